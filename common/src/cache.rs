@@ -0,0 +1,319 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		RwLock,
+	},
+};
+
+use crate::{models::PrimaryId, utils};
+
+#[derive(Display, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CacheKey {
+	#[display("n{_0}_bitcoin_tx_index_t{_1}_v{_2}")]
+	BitcoinTxIndex(PrimaryId, String, u32),
+	#[display("idempotency_k{_0}_i{_1}")]
+	Idempotency(String, String),
+	/// Keyed by the info version too (see `Config::get_info_version()`), so bumping it makes
+	/// every cached response stale at once, across processes
+	#[display("info_v{_0}_a{_1}")]
+	Info(String, String),
+	#[display("info_totals")]
+	InfoTotals,
+}
+
+impl CacheKey {
+	/// Prefix shared by every key that belongs to `network_id`
+	pub fn network_prefix(network_id: PrimaryId) -> String {
+		format!("n{network_id}_")
+	}
+
+	/// Same key with everything past its prefix digested into a fixed length. The prefix is
+	/// kept so `Cache::delete_by_prefix()` still works
+	pub fn to_hashed_string(&self) -> String {
+		let prefix = match self {
+			Self::BitcoinTxIndex(network_id, ..) => Self::network_prefix(*network_id),
+			Self::Idempotency(..) => "idempotency_".to_string(),
+			Self::Info(..) => "info_".to_string(),
+			Self::InfoTotals => return self.to_string(),
+		};
+
+		let digest = utils::sha256(&self.to_string());
+		format!("{prefix}h{}", digest[..16].iter().map(|b| format!("{b:02x}")).collect::<String>())
+	}
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	pub evictions: u64,
+}
+
+#[derive(Default)]
+struct CacheData {
+	values: HashMap<String, String>,
+	order: VecDeque<String>,
+}
+
+pub struct Cache {
+	capacity: usize,
+	hash_keys: bool,
+	data: RwLock<CacheData>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+	evictions: AtomicU64,
+}
+
+impl Cache {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			hash_keys: false,
+			data: RwLock::new(CacheData::default()),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+			evictions: AtomicU64::new(0),
+		}
+	}
+
+	pub fn with_hashed_keys(mut self, hash_keys: bool) -> Self {
+		self.hash_keys = hash_keys;
+		self
+	}
+
+	fn get_key(&self, cache_key: &CacheKey) -> String {
+		if self.hash_keys {
+			cache_key.to_hashed_string()
+		} else {
+			cache_key.to_string()
+		}
+	}
+
+	pub fn get<T>(&self, cache_key: &CacheKey) -> Option<T>
+	where
+		T: for<'a> Deserialize<'a>,
+	{
+		let hit = self
+			.data
+			.read()
+			.unwrap()
+			.values
+			.get(&self.get_key(cache_key))
+			.and_then(|v| serde_json::from_str(v).ok());
+
+		if hit.is_some() {
+			self.hits.fetch_add(1, Ordering::Relaxed);
+		} else {
+			self.misses.fetch_add(1, Ordering::Relaxed);
+		}
+
+		hit
+	}
+
+	pub fn set<T>(&self, cache_key: &CacheKey, value: T)
+	where
+		T: Serialize,
+	{
+		if self.capacity == 0 {
+			return;
+		}
+
+		if let Ok(value) = serde_json::to_string(&value) {
+			let key = self.get_key(cache_key);
+			self.insert(&mut self.data.write().unwrap(), key, value);
+		}
+	}
+
+	/// Sets `value` unless `cache_key` holds one that `is_valid` accepts, both under the
+	/// same lock, so concurrent callers can use it to claim a key. Returns the value that
+	/// was kept instead, if any
+	pub fn set_if_absent<T, F>(&self, cache_key: &CacheKey, value: T, is_valid: F) -> Option<T>
+	where
+		T: Serialize + for<'a> Deserialize<'a>,
+		F: Fn(&T) -> bool,
+	{
+		if self.capacity == 0 {
+			return None;
+		}
+
+		let key = self.get_key(cache_key);
+		let mut data = self.data.write().unwrap();
+
+		let existing = data.values.get(&key).and_then(|v| serde_json::from_str::<T>(v).ok());
+		if let Some(existing) = existing.filter(|v| is_valid(v)) {
+			self.hits.fetch_add(1, Ordering::Relaxed);
+			return Some(existing);
+		}
+
+		self.misses.fetch_add(1, Ordering::Relaxed);
+		if let Ok(value) = serde_json::to_string(&value) {
+			self.insert(&mut data, key, value);
+		}
+
+		None
+	}
+
+	fn insert(&self, data: &mut CacheData, key: String, value: String) {
+		if data.values.insert(key.clone(), value).is_none() {
+			data.order.push_back(key);
+		}
+
+		// evict oldest entries when over capacity
+		while data.values.len() > self.capacity {
+			match data.order.pop_front() {
+				Some(oldest_key) => {
+					if data.values.remove(&oldest_key).is_some() {
+						self.evictions.fetch_add(1, Ordering::Relaxed);
+					}
+				}
+				None => break,
+			}
+		}
+	}
+
+	pub fn delete(&self, cache_key: &CacheKey) {
+		let key = self.get_key(cache_key);
+		let mut data = self.data.write().unwrap();
+
+		if data.values.remove(&key).is_some() {
+			data.order.retain(|k| *k != key);
+		}
+	}
+
+	pub fn delete_by_prefix(&self, prefix: &str) -> usize {
+		let mut data = self.data.write().unwrap();
+
+		let before = data.values.len();
+		data.values.retain(|k, _| !k.starts_with(prefix));
+		data.order.retain(|k| !k.starts_with(prefix));
+
+		before - data.values.len()
+	}
+
+	pub fn len(&self) -> usize {
+		self.data.read().unwrap().values.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+			evictions: self.evictions.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_cache_key_str() {
+		assert_eq!(
+			CacheKey::BitcoinTxIndex(123, "abc".to_string(), 4).to_string(),
+			"n123_bitcoin_tx_index_tabc_v4"
+		);
+
+		assert_eq!(
+			CacheKey::Idempotency("key_abc".to_string(), "123".to_string()).to_string(),
+			"idempotency_kkey_abc_i123"
+		);
+
+		assert_eq!(
+			CacheKey::Info("123".to_string(), "abc".to_string()).to_string(),
+			"info_v123_aabc"
+		);
+		assert_eq!(CacheKey::InfoTotals.to_string(), "info_totals");
+	}
+
+	#[test]
+	fn test_delete_by_prefix() {
+		let cache = Cache::new(10);
+		let (key_a, key_b, key_c) = (
+			CacheKey::BitcoinTxIndex(1, "a".to_string(), 0),
+			CacheKey::BitcoinTxIndex(1, "b".to_string(), 0),
+			CacheKey::BitcoinTxIndex(12, "a".to_string(), 0),
+		);
+
+		cache.set(&key_a, 1u64);
+		cache.set(&key_b, 2u64);
+		cache.set(&key_c, 3u64);
+
+		assert_eq!(cache.delete_by_prefix(&CacheKey::network_prefix(1)), 2);
+		assert_eq!(cache.len(), 1);
+		assert_eq!(cache.get::<u64>(&key_a), None);
+		assert_eq!(cache.get::<u64>(&key_c), Some(3));
+	}
+
+	#[test]
+	fn test_stats() {
+		let cache = Cache::new(1);
+		let (key_a, key_b) = (
+			CacheKey::BitcoinTxIndex(1, "a".to_string(), 0),
+			CacheKey::BitcoinTxIndex(1, "b".to_string(), 0),
+		);
+
+		cache.set(&key_a, 1u64);
+		assert_eq!(cache.get::<u64>(&key_a), Some(1));
+		assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 0, evictions: 0 });
+
+		assert_eq!(cache.get::<u64>(&key_b), None);
+		assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, evictions: 0 });
+
+		cache.set(&key_b, 2u64);
+		assert_eq!(cache.len(), 1);
+		assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, evictions: 1 });
+	}
+
+	#[test]
+	fn test_set_if_absent() {
+		let cache = Cache::new(10);
+		let key = CacheKey::Idempotency("a".to_string(), "b".to_string());
+
+		// first caller claims the key, the next one gets what's there
+		assert_eq!(cache.set_if_absent(&key, 1u64, |_| true), None);
+		assert_eq!(cache.set_if_absent(&key, 2u64, |_| true), Some(1));
+		assert_eq!(cache.get::<u64>(&key), Some(1));
+
+		// values that are no longer valid get replaced
+		assert_eq!(cache.set_if_absent(&key, 3u64, |v| *v > 1), None);
+		assert_eq!(cache.get::<u64>(&key), Some(3));
+	}
+
+	#[test]
+	fn test_hashed_keys() {
+		let (key_a, key_b) = (
+			CacheKey::BitcoinTxIndex(1, "a".repeat(64), 0),
+			CacheKey::BitcoinTxIndex(1, "a".repeat(64), 1),
+		);
+
+		// fixed length, and distinct keys stay distinct
+		assert_eq!(key_a.to_hashed_string(), key_a.to_hashed_string());
+		assert_eq!(key_a.to_hashed_string().len(), "n1_h".len() + 32);
+		assert_ne!(key_a.to_hashed_string(), key_b.to_hashed_string());
+
+		for hash_keys in [false, true] {
+			let cache = Cache::new(10).with_hashed_keys(hash_keys);
+
+			cache.set(&key_a, 1u64);
+			cache.set(&key_b, 2u64);
+			assert_eq!(cache.get::<u64>(&key_a), Some(1));
+			assert_eq!(cache.get::<u64>(&key_b), Some(2));
+
+			cache.delete(&key_a);
+			assert_eq!(cache.get::<u64>(&key_a), None);
+			assert_eq!(cache.get::<u64>(&key_b), Some(2));
+
+			assert_eq!(cache.delete_by_prefix(&CacheKey::network_prefix(1)), 1);
+			assert!(cache.is_empty());
+		}
+	}
+}
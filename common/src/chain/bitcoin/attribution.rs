@@ -0,0 +1,134 @@
+use crate::Attribution;
+
+/// Splits a transaction's value between its `(address, amount)` inputs and outputs, as
+/// `(from, to, amount)` triples. Both sides come with unique addresses, in the order they
+/// first show up in the transaction
+pub trait AttributionTrait: Send + Sync {
+	fn attribute(
+		&self,
+		inputs: &[(String, u64)],
+		outputs: &[(String, u64)],
+	) -> Vec<(String, String, u64)>;
+}
+
+pub struct Proportional;
+
+impl AttributionTrait for Proportional {
+	fn attribute(
+		&self,
+		inputs: &[(String, u64)],
+		outputs: &[(String, u64)],
+	) -> Vec<(String, String, u64)> {
+		let input_amount_total: u64 = inputs.iter().map(|(_, v)| v).sum();
+
+		let mut ret = vec![];
+		for (from, input_amount) in inputs.iter() {
+			for (to, output_amount) in outputs.iter() {
+				let amount = match input_amount_total > 0 {
+					true => ((*input_amount as f64 / input_amount_total as f64) *
+						*output_amount as f64)
+						.round() as u64,
+					_ => 0,
+				};
+
+				ret.push((from.clone(), to.clone(), amount));
+			}
+		}
+
+		ret
+	}
+}
+
+pub struct Fifo;
+
+impl AttributionTrait for Fifo {
+	fn attribute(
+		&self,
+		inputs: &[(String, u64)],
+		outputs: &[(String, u64)],
+	) -> Vec<(String, String, u64)> {
+		let mut ret = vec![];
+		let mut outputs = outputs.iter().map(|(to, v)| (to, *v));
+		let mut output = outputs.next();
+
+		for (from, input_amount) in inputs.iter() {
+			let mut left = *input_amount;
+
+			while let Some((to, unpaid)) = output.as_mut() {
+				let amount = left.min(*unpaid);
+				if amount > 0 {
+					ret.push((from.clone(), to.to_string(), amount));
+				}
+
+				left -= amount;
+				*unpaid -= amount;
+				if *unpaid == 0 {
+					output = outputs.next();
+				}
+
+				if left == 0 {
+					break;
+				}
+			}
+		}
+
+		// whatever's left over on the inputs is the fee
+		ret
+	}
+}
+
+impl From<Attribution> for Box<dyn AttributionTrait> {
+	fn from(attribution: Attribution) -> Self {
+		match attribution {
+			Attribution::Proportional => Box::new(Proportional),
+			Attribution::Fifo => Box::new(Fifo),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn new_pairs(pairs: &[(&str, u64)]) -> Vec<(String, u64)> {
+		pairs.iter().map(|(address, v)| (address.to_string(), *v)).collect()
+	}
+
+	fn new_triples(triples: &[(&str, &str, u64)]) -> Vec<(String, String, u64)> {
+		triples.iter().map(|(from, to, v)| (from.to_string(), to.to_string(), *v)).collect()
+	}
+
+	#[test]
+	fn test_attribution() {
+		// two inputs, two outputs and a fee of 5
+		let inputs = new_pairs(&[("a", 100), ("b", 50)]);
+		let outputs = new_pairs(&[("x", 120), ("y", 25)]);
+
+		// everyone pays everyone
+		assert_eq!(
+			Proportional.attribute(&inputs, &outputs),
+			new_triples(&[("a", "x", 80), ("a", "y", 17), ("b", "x", 40), ("b", "y", 8)])
+		);
+
+		// "a" covers most of "x", "b" tops it up and pays "y"
+		assert_eq!(
+			Fifo.attribute(&inputs, &outputs),
+			new_triples(&[("a", "x", 100), ("b", "x", 20), ("b", "y", 25)])
+		);
+
+		// an input running past several outputs, and zero-value ones (eg: `OP_RETURN`) skipped
+		let inputs = new_pairs(&[("a", 100)]);
+		let outputs = new_pairs(&[("x", 30), ("z", 0), ("y", 60)]);
+		assert_eq!(
+			Fifo.attribute(&inputs, &outputs),
+			new_triples(&[("a", "x", 30), ("a", "y", 60)])
+		);
+
+		// nothing to split
+		assert!(Fifo.attribute(&[], &outputs).is_empty());
+		assert_eq!(
+			Proportional.attribute(&new_pairs(&[("a", 0)]), &outputs),
+			new_triples(&[("a", "x", 0), ("a", "z", 0), ("a", "y", 0)])
+		);
+	}
+}
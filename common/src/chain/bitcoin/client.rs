@@ -1,15 +1,26 @@
 use base64::{engine::general_purpose, Engine as _};
-use bitcoin::{consensus::encode, Block, BlockHash};
+use bitcoin::{consensus::encode, Block, BlockHash, Transaction, Txid};
 use bitcoincore_rpc_json::GetBlockchainInfoResult;
 use derive_more::{Display, Error};
 use eyre::Result;
-use reqwest::header::AUTHORIZATION;
+use reqwest::{header::AUTHORIZATION, RequestBuilder};
 use serde::Deserialize;
 use serde_json::{json, Value as JsonValue};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use tokio::time::{sleep, Duration};
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+};
+use tokio::{
+	sync::{Semaphore, SemaphorePermit},
+	time::{sleep, Duration},
+};
 
 // source: `https://github.com/bitcoin/bitcoin/blob/master/src/rpc/protocol.h`
+const RPC_MISC_ERROR: i32 = -1;
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
 const RPC_IN_WARMUP: i32 = -28;
 
 const RETRY_ATTEMPTS: u32 = 13;
@@ -25,6 +36,47 @@ pub enum ClientError {
 	Rpc { message: String },
 	#[display("Nonce mismatch")]
 	NonceMismatch,
+	#[display("Block not available (pruned data)")]
+	Pruned,
+	#[display("Not found")]
+	NotFound,
+	#[display("RPC request timed out")]
+	Timeout,
+}
+
+impl From<RpcError> for ClientError {
+	fn from(error: RpcError) -> Self {
+		// pruned nodes only keep recent blocks around
+		if error.code == RPC_MISC_ERROR && error.message.contains("pruned data") {
+			return ClientError::Pruned;
+		}
+
+		// eg: unknown transactions
+		if error.code == RPC_INVALID_ADDRESS_OR_KEY {
+			return ClientError::NotFound;
+		}
+
+		ClientError::Rpc { message: error.message }
+	}
+}
+
+/// Caps how many rpc calls can be in flight at once, across every client sharing it. Calls
+/// in a batch count one by one, up to the whole cap (so a batch bigger than it still goes)
+pub struct RpcSemaphore {
+	semaphore: Semaphore,
+	permits: u32,
+}
+
+impl RpcSemaphore {
+	pub fn new(permits: usize) -> Self {
+		let permits = permits.clamp(1, u32::MAX as usize) as u32;
+		Self { semaphore: Semaphore::new(permits as usize), permits }
+	}
+
+	async fn acquire(&self, calls: usize) -> Option<SemaphorePermit<'_>> {
+		let calls = calls.clamp(1, self.permits as usize) as u32;
+		self.semaphore.acquire_many(calls).await.ok()
+	}
 }
 
 #[derive(Clone)]
@@ -39,6 +91,13 @@ struct RpcError {
 	message: String,
 }
 
+/// A transaction along with where it was confirmed, if it was
+pub struct TransactionInfo {
+	pub tx: Transaction,
+	pub block_hash: Option<BlockHash>,
+	pub block_time: Option<u32>,
+}
+
 #[derive(Debug, Deserialize)]
 struct Response {
 	result: JsonValue,
@@ -53,15 +112,38 @@ pub struct Client {
 	auth: Auth,
 	id: AtomicUsize,
 	with_retry: bool,
+	timeout: Option<Duration>,
+	semaphore: Option<Arc<RpcSemaphore>>,
 }
 
 impl Client {
 	pub fn new(url: &str, auth: Auth) -> Self {
-		Self { url: url.to_string(), auth, id: AtomicUsize::new(1), with_retry: true }
+		Self {
+			url: url.to_string(),
+			auth,
+			id: AtomicUsize::new(1),
+			with_retry: true,
+			timeout: None,
+			semaphore: None,
+		}
 	}
 
 	pub fn new_without_retry(url: &str, auth: Auth) -> Self {
-		Self { url: url.to_string(), auth, id: AtomicUsize::new(1), with_retry: false }
+		Self { with_retry: false, ..Self::new(url, auth) }
+	}
+
+	/// Fails requests that take longer than `timeout`, so a stuck node can't hang the
+	/// caller. Timed out requests are not retried
+	pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.timeout = timeout;
+		self
+	}
+
+	/// Caps how many calls can be in flight at once. The semaphore can be shared, so the
+	/// cap holds across every client talking to the same node
+	pub fn with_semaphore(mut self, semaphore: Option<Arc<RpcSemaphore>>) -> Self {
+		self.semaphore = semaphore;
+		self
 	}
 
 	pub async fn get_blockchain_info(&self) -> Result<GetBlockchainInfoResult> {
@@ -85,15 +167,88 @@ impl Client {
 		Ok(encode::deserialize_hex(result.as_str().unwrap())?)
 	}
 
-	async fn request(&self, method: &str, params: &[JsonValue]) -> Result<JsonValue> {
-		let client = reqwest::Client::new();
-		let mut req = client.post(&self.url);
+	pub async fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+		let result = self
+			.request("getrawtransaction", &[JsonValue::from(txid.to_string()), false.into()])
+			.await?;
+		Ok(encode::deserialize_hex(result.as_str().unwrap())?)
+	}
+
+	pub async fn get_raw_transaction_info(&self, txid: &Txid) -> Result<TransactionInfo> {
+		#[derive(Deserialize)]
+		struct VerboseTransaction {
+			hex: String,
+			blockhash: Option<BlockHash>,
+			blocktime: Option<u32>,
+		}
+
+		let result = self
+			.request("getrawtransaction", &[JsonValue::from(txid.to_string()), true.into()])
+			.await?;
+		let verbose: VerboseTransaction = serde_json::from_value(result)?;
+
+		Ok(TransactionInfo {
+			tx: encode::deserialize_hex(&verbose.hex)?,
+			block_hash: verbose.blockhash,
+			block_time: verbose.blocktime,
+		})
+	}
+
+	pub async fn get_block_height_by_hash(&self, hash: &BlockHash) -> Result<u64> {
+		let result = self
+			.request("getblockheader", &[JsonValue::from(hash.to_string()), true.into()])
+			.await?;
+		Ok(serde_json::from_value(result["height"].clone())?)
+	}
+
+	pub async fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+		let result = self.request("getrawmempool", &[]).await?;
+		Ok(serde_json::from_value(result)?)
+	}
+
+	/// Fetches all `txids` with a single (batched) rpc call, in the same order
+	pub async fn get_raw_transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>> {
+		let params = txids
+			.iter()
+			.map(|txid| vec![JsonValue::from(txid.to_string()), false.into()])
+			.collect::<Vec<_>>();
+
+		self.batch_request("getrawtransaction", &params)
+			.await?
+			.into_iter()
+			.map(|result| -> Result<Transaction> {
+				Ok(encode::deserialize_hex(result.as_str().unwrap())?)
+			})
+			.collect()
+	}
+
+	fn new_request(&self) -> RequestBuilder {
+		let mut req = reqwest::Client::new().post(&self.url);
+
+		if let Some(timeout) = self.timeout {
+			req = req.timeout(timeout);
+		}
 
 		if let Auth::UserPass(username, password) = &self.auth {
 			let token = general_purpose::STANDARD.encode(format!("{username}:{password}"));
 			req = req.header(AUTHORIZATION, format!("Basic {token}"));
 		}
 
+		req
+	}
+
+	/// Waits for enough free slots for `calls` when in-flight calls are capped; they're held
+	/// until the returned permit is dropped (which has to happen before any backoff, so a
+	/// waiting client doesn't hold up the rest)
+	async fn acquire(&self, calls: usize) -> Option<SemaphorePermit<'_>> {
+		match &self.semaphore {
+			Some(semaphore) => semaphore.acquire(calls).await,
+			None => None,
+		}
+	}
+
+	async fn request(&self, method: &str, params: &[JsonValue]) -> Result<JsonValue> {
+		let req = self.new_request();
 		let retry_attempts = if self.with_retry { RETRY_ATTEMPTS } else { 1 };
 
 		for attempt in 0..retry_attempts {
@@ -107,24 +262,27 @@ impl Client {
 				"id": id,
 			});
 
+			let permit = self.acquire(1).await;
 			match req.try_clone().unwrap().json(&body).send().await {
 				Ok(response) => {
 					let json = response.json::<Response>().await?;
+					drop(permit);
+
 					match json.error {
 						Some(error) if error.code == RPC_IN_WARMUP => {
 							sleep(timeout).await;
 							continue;
 						}
-						Some(error) => {
-							return Err(ClientError::Rpc { message: error.message }.into())
-						}
+						Some(error) => return Err(ClientError::from(error).into()),
 						None if json.id.is_none() || json.id.unwrap() != id => {
 							return Err(ClientError::NonceMismatch.into())
 						}
 						None => return Ok(json.result),
 					}
 				}
+				Err(e) if e.is_timeout() => return Err(ClientError::Timeout.into()),
 				Err(e) if e.is_connect() => {
+					drop(permit);
 					sleep(timeout).await;
 					continue;
 				}
@@ -134,4 +292,168 @@ impl Client {
 
 		Err(ClientError::Connection.into())
 	}
+
+	async fn batch_request(
+		&self,
+		method: &str,
+		params: &[Vec<JsonValue>],
+	) -> Result<Vec<JsonValue>> {
+		if params.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let req = self.new_request();
+		let retry_attempts = if self.with_retry { RETRY_ATTEMPTS } else { 1 };
+
+		for attempt in 0..retry_attempts {
+			let ids = params
+				.iter()
+				.map(|_| self.id.fetch_add(1, Ordering::Relaxed).to_string())
+				.collect::<Vec<_>>();
+			let timeout = Duration::from_millis(RPC_TIMEOUT * 2_i32.pow(attempt) as u64);
+
+			let body = ids
+				.iter()
+				.zip(params.iter())
+				.map(|(id, params)| {
+					json!({
+						"jsonrpc": "2.0",
+						"method": method,
+						"params": params,
+						"id": id,
+					})
+				})
+				.collect::<Vec<_>>();
+
+			let permit = self.acquire(params.len()).await;
+			match req.try_clone().unwrap().json(&body).send().await {
+				Ok(response) => {
+					// responses are not guaranteed to come back in order
+					let mut responses = response
+						.json::<Vec<Response>>()
+						.await?
+						.into_iter()
+						.filter_map(|r| r.id.clone().map(|id| (id, r)))
+						.collect::<HashMap<_, _>>();
+					drop(permit);
+
+					if responses
+						.values()
+						.any(|r| r.error.as_ref().is_some_and(|e| e.code == RPC_IN_WARMUP))
+					{
+						sleep(timeout).await;
+						continue;
+					}
+
+					return ids
+						.iter()
+						.map(|id| match responses.remove(id) {
+							Some(Response { error: Some(error), .. }) => {
+								Err(ClientError::from(error).into())
+							}
+							Some(response) => Ok(response.result),
+							None => Err(ClientError::NonceMismatch.into()),
+						})
+						.collect();
+				}
+				Err(e) if e.is_timeout() => return Err(ClientError::Timeout.into()),
+				Err(e) if e.is_connect() => {
+					drop(permit);
+					sleep(timeout).await;
+					continue;
+				}
+				Err(e) => return Err(ClientError::General { message: e.to_string() }.into()),
+			}
+		}
+
+		Err(ClientError::Connection.into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::MockRpc;
+	use futures::future::try_join_all;
+	use std::{sync::atomic::AtomicBool, time::Instant};
+
+	#[test]
+	fn test_client_error_from_rpc_error() {
+		let pruned = RpcError { code: -1, message: "Block not available (pruned data)".into() };
+		assert!(matches!(ClientError::from(pruned), ClientError::Pruned));
+
+		let not_found = RpcError { code: -5, message: "No such transaction".into() };
+		assert!(matches!(ClientError::from(not_found), ClientError::NotFound));
+
+		let other = RpcError { code: -8, message: "Block height out of range".into() };
+		assert!(matches!(ClientError::from(other), ClientError::Rpc { .. }));
+	}
+
+	#[tokio::test]
+	async fn test_timeout() -> Result<()> {
+		// takes far longer to answer than the client waits
+		let rpc = MockRpc::start_with_delay(Duration::from_secs(10), |_, _| Ok(json!(100))).await?;
+		let client =
+			Client::new(&rpc.url, Auth::None).with_timeout(Some(Duration::from_millis(100)));
+
+		let started_at = Instant::now();
+		let err = client.get_block_count().await.unwrap_err();
+		assert!(matches!(err.downcast_ref::<ClientError>(), Some(ClientError::Timeout)));
+		assert!(started_at.elapsed() < Duration::from_secs(1));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_semaphore() -> Result<()> {
+		// answers slowly, keeping track of how many calls overlap
+		let rpc =
+			MockRpc::start_with_delay(Duration::from_millis(50), |_, _| Ok(json!(100))).await?;
+
+		let client =
+			Client::new(&rpc.url, Auth::None).with_semaphore(Some(Arc::new(RpcSemaphore::new(3))));
+		let results = try_join_all((0..12).map(|_| client.get_block_count())).await?;
+
+		assert_eq!(results, vec![100; 12]);
+		assert!(rpc.max_in_flight.load(Ordering::SeqCst) <= 3);
+
+		// batched calls count one by one, and a batch bigger than the cap still goes through
+		let rpc =
+			MockRpc::start_with_delay(Duration::from_millis(50), |_, _| Ok(json!(100))).await?;
+		let client =
+			Client::new(&rpc.url, Auth::None).with_semaphore(Some(Arc::new(RpcSemaphore::new(4))));
+		let params = vec![vec![]; 2];
+		try_join_all((0..3).map(|_| client.batch_request("getblockcount", &params))).await?;
+		assert!(rpc.max_in_flight.load(Ordering::SeqCst) <= 4);
+
+		assert_eq!(client.batch_request("getblockcount", &vec![vec![]; 6]).await?.len(), 6);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_semaphore_backoff() -> Result<()> {
+		// the first call finds the node still warming up, and backs off before retrying
+		let is_warming_up = AtomicBool::new(true);
+		let rpc = MockRpc::start(move |_, _| match is_warming_up.swap(false, Ordering::SeqCst) {
+			true => Err(json!({ "code": RPC_IN_WARMUP, "message": "Loading block index" })),
+			false => Ok(json!(100)),
+		})
+		.await?;
+
+		let client =
+			Client::new(&rpc.url, Auth::None).with_semaphore(Some(Arc::new(RpcSemaphore::new(1))));
+		let ((backed_off, backed_off_at), (other, other_at)) =
+			tokio::join!(async { (client.get_block_count().await, Instant::now()) }, async {
+				sleep(Duration::from_millis(50)).await;
+				(client.get_block_count().await, Instant::now())
+			});
+
+		// the other call doesn't have to wait the backoff out
+		assert_eq!(backed_off?, 100);
+		assert_eq!(other?, 100);
+		assert!(other_at < backed_off_at);
+
+		Ok(())
+	}
 }
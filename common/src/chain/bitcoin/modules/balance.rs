@@ -32,8 +32,9 @@ impl BitcoinModuleTrait for BitcoinBalance {
 		block_height: BlockHeight,
 		block_time: u32,
 		tx: ParquetTransaction,
-		inputs: HashMap<String, u64>,
-		outputs: HashMap<String, u64>,
+		asset_address: Option<String>,
+		inputs: Vec<(String, u64)>,
+		outputs: Vec<(String, u64)>,
 	) -> Result<WarehouseData> {
 		let mut ret = WarehouseData::new();
 		let mut balance_map = HashMap::<String, (u64, u64)>::new();
@@ -65,7 +66,7 @@ impl BitcoinModuleTrait for BitcoinBalance {
 				block_height,
 				&tx_hash.clone(),
 				&address,
-				None,
+				asset_address.clone(),
 				U256::from_str_radix(&amount_in.to_string(), 10)?,
 				U256::from_str_radix(&amount_out.to_string(), 10)?,
 				block_time,
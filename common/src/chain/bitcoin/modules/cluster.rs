@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use eyre::Result;
+use std::collections::BTreeSet;
+
+use crate::{
+	chain::{
+		bitcoin::{modules::BitcoinModuleTrait, schema::Transaction as ParquetTransaction},
+		ModuleId, ModuleTrait, WarehouseData,
+	},
+	models::PrimaryId,
+	BlockHeight,
+};
+
+/// Common-input-ownership heuristic: all input addresses of a transaction are assumed to
+/// be controlled by the same wallet. Coinjoins break that assumption and will merge
+/// unrelated clusters
+pub struct BitcoinCluster {
+	network_id: PrimaryId,
+}
+
+impl ModuleTrait for BitcoinCluster {
+	fn new(network_id: PrimaryId) -> Self {
+		Self { network_id }
+	}
+
+	fn get_id(&self) -> ModuleId {
+		ModuleId::BitcoinCluster
+	}
+}
+
+#[async_trait]
+impl BitcoinModuleTrait for BitcoinCluster {
+	async fn run(
+		&self,
+		_block_height: BlockHeight,
+		_block_time: u32,
+		tx: ParquetTransaction,
+		_asset_address: Option<String>,
+		inputs: Vec<(String, u64)>,
+		_outputs: Vec<(String, u64)>,
+	) -> Result<WarehouseData> {
+		let mut ret = WarehouseData::new();
+
+		// non-standard scripts are keyed by outpoint (`txid:vout`) or by script
+		// (`script:<hash>`), neither of which says anything about who owns them
+		let addresses = inputs
+			.into_iter()
+			.map(|(a, _)| a)
+			.filter(|a| !a.contains(':'))
+			.collect::<BTreeSet<_>>();
+
+		if !tx.is_coinbase && addresses.len() > 1 {
+			ret.clusters.insert((self.network_id, addresses));
+		}
+
+		Ok(ret)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::{
+		absolute::LockTime,
+		hashes::{sha256d, Hash},
+		transaction::Version,
+	};
+
+	#[tokio::test]
+	async fn test_run() -> Result<()> {
+		let tx = ParquetTransaction {
+			hash: sha256d::Hash::all_zeros(),
+			version: Version::ONE,
+			lock_time: LockTime::ZERO,
+			input_count: 3,
+			output_count: 1,
+			is_coinbase: false,
+		};
+		let inputs =
+			vec![("a".to_string(), 10), ("b".to_string(), 20), (format!("{}:0", tx.hash), 30)];
+
+		let module = BitcoinCluster::new(1);
+		let ret = module.run(1, 0, tx.clone(), None, inputs, vec![]).await?;
+		assert_eq!(
+			ret.clusters,
+			[(1, BTreeSet::from(["a".to_string(), "b".to_string()]))].into_iter().collect()
+		);
+
+		// a single input address has nothing to cluster with
+		let inputs = vec![("a".to_string(), 10)];
+		assert!(module.run(1, 0, tx, None, inputs, vec![]).await?.clusters.is_empty());
+
+		Ok(())
+	}
+}
@@ -1,6 +1,5 @@
 use async_trait::async_trait;
 use eyre::Result;
-use std::collections::HashMap;
 
 use crate::{
 	chain::{
@@ -32,14 +31,15 @@ impl BitcoinModuleTrait for BitcoinCoinbase {
 		block_height: BlockHeight,
 		block_time: u32,
 		tx: ParquetTransaction,
-		_inputs: HashMap<String, u64>,
-		outputs: HashMap<String, u64>,
+		asset_address: Option<String>,
+		_inputs: Vec<(String, u64)>,
+		outputs: Vec<(String, u64)>,
 	) -> Result<WarehouseData> {
 		let mut ret = WarehouseData::new();
 
 		if tx.is_coinbase {
 			let tx_hash = tx.hash.to_string();
-			let output_amount_total: u64 = outputs.clone().into_values().sum();
+			let output_amount_total: u64 = outputs.iter().map(|(_, v)| v).sum();
 			let batch_amount = U256::from_str_radix(&output_amount_total.to_string(), 10)?;
 
 			for (to, amount) in outputs.into_iter() {
@@ -50,7 +50,7 @@ impl BitcoinModuleTrait for BitcoinCoinbase {
 					&tx_hash.clone(),
 					"",
 					&to,
-					None,
+					asset_address.clone(),
 					U256::from_str_radix(&amount.to_string(), 10)?,
 					batch_amount,
 					block_time,
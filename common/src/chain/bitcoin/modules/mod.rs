@@ -1,27 +1,32 @@
 use async_trait::async_trait;
 use eyre::Result;
-use std::collections::HashMap;
 
 use crate::{
 	chain::{bitcoin::schema::Transaction as ParquetTransaction, ModuleTrait, WarehouseData},
 	BlockHeight,
 };
 pub use balance::BitcoinBalance;
+pub use cluster::BitcoinCluster;
 pub use coinbase::BitcoinCoinbase;
 pub use transfer::BitcoinTransfer;
 
 mod balance;
+mod cluster;
 mod coinbase;
 mod transfer;
 
 #[async_trait]
 pub trait BitcoinModuleTrait: ModuleTrait + Send + Sync {
+	/// `inputs` and `outputs` are `(address, amount)` with unique addresses (amounts summed
+	/// up), in the order they first show up in the transaction. They're all of the same
+	/// `asset_address` (`None` for the chain's own coin)
 	async fn run(
 		&self,
 		block_height: BlockHeight,
 		block_time: u32,
 		tx: ParquetTransaction,
-		inputs: HashMap<String, u64>,
-		outputs: HashMap<String, u64>,
+		asset_address: Option<String>,
+		inputs: Vec<(String, u64)>,
+		outputs: Vec<(String, u64)>,
 	) -> Result<WarehouseData>;
 }
@@ -1,23 +1,46 @@
 use async_trait::async_trait;
 use eyre::Result;
-use std::collections::HashMap;
 
 use crate::{
 	chain::{
-		bitcoin::{modules::BitcoinModuleTrait, schema::Transaction as ParquetTransaction},
+		bitcoin::{
+			attribution::AttributionTrait, modules::BitcoinModuleTrait,
+			schema::Transaction as ParquetTransaction,
+		},
 		ModuleId, ModuleTrait, WarehouseData, U256,
 	},
 	models::{PrimaryId, Transfer},
-	BlockHeight,
+	Attribution, BlockHeight,
 };
 
 pub struct BitcoinTransfer {
 	network_id: PrimaryId,
+	skip_change_outputs: bool,
+	attribution: Box<dyn AttributionTrait>,
+}
+
+impl BitcoinTransfer {
+	/// Treats outputs paying back to one of the transaction's input addresses as change,
+	/// leaving them out of the transfers (and of the proportional split).
+	///
+	/// This only catches change sent to a reused address; most wallets send change to a
+	/// fresh one, which stays indistinguishable from a payment. It can also hide a real
+	/// payment when a sender pays an address that happens to be among the inputs (eg:
+	/// coinjoins)
+	pub fn with_skip_change_outputs(mut self, skip_change_outputs: bool) -> Self {
+		self.skip_change_outputs = skip_change_outputs;
+		self
+	}
+
+	pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+		self.attribution = attribution.into();
+		self
+	}
 }
 
 impl ModuleTrait for BitcoinTransfer {
 	fn new(network_id: PrimaryId) -> Self {
-		Self { network_id }
+		Self { network_id, skip_change_outputs: false, attribution: Attribution::default().into() }
 	}
 
 	fn get_id(&self) -> ModuleId {
@@ -32,8 +55,9 @@ impl BitcoinModuleTrait for BitcoinTransfer {
 		block_height: BlockHeight,
 		block_time: u32,
 		tx: ParquetTransaction,
-		inputs: HashMap<String, u64>,
-		outputs: HashMap<String, u64>,
+		asset_address: Option<String>,
+		inputs: Vec<(String, u64)>,
+		mut outputs: Vec<(String, u64)>,
 	) -> Result<WarehouseData> {
 		let mut ret = WarehouseData::new();
 
@@ -42,36 +66,165 @@ impl BitcoinModuleTrait for BitcoinTransfer {
 		}
 
 		let tx_hash = tx.hash.to_string();
-		let input_amount_total: u64 = inputs.clone().into_values().sum();
-		let output_amount_total: u64 = outputs.clone().into_values().sum();
+		let input_amount_total: u64 = inputs.iter().map(|(_, v)| v).sum();
+		let fee = U256::from(
+			input_amount_total.saturating_sub(outputs.iter().map(|(_, v)| v).sum::<u64>()),
+		);
+
+		// change goes back to the sender, it's not a transfer
+		if self.skip_change_outputs {
+			outputs.retain(|(address, _)| !inputs.iter().any(|(a, _)| a == address));
+		}
+
+		let output_amount_total: u64 = outputs.iter().map(|(_, v)| v).sum();
 		let batch_amount = U256::from_str_radix(&output_amount_total.to_string(), 10)?;
 
-		for input in inputs.iter() {
-			for output in outputs.iter() {
-				let (from, to) = (input.0.clone(), output.0.clone());
-				if from != to {
-					let amount = match input_amount_total > 0 {
-						true => ((*input.1 as f64 / input_amount_total as f64) * *output.1 as f64)
-							.round(),
-						_ => 0.0,
-					};
-
-					ret.transfers.insert(Transfer::new(
+		for (from, to, amount) in self.attribution.attribute(&inputs, &outputs).into_iter() {
+			if from != to {
+				ret.transfers.insert(
+					Transfer::new(
 						self.get_id(),
 						self.network_id,
 						block_height,
 						&tx_hash.clone(),
 						&from,
 						&to,
-						None,
-						U256::from_str_radix(&amount.to_string(), 10)?,
+						asset_address.clone(),
+						U256::from(amount),
 						batch_amount,
 						block_time,
-					));
-				}
+					)
+					.with_fee(fee),
+				);
 			}
 		}
 
 		Ok(ret)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::{
+		absolute::LockTime,
+		hashes::{sha256d, Hash},
+		transaction::Version,
+	};
+
+	fn new_tx(is_coinbase: bool) -> ParquetTransaction {
+		ParquetTransaction {
+			hash: sha256d::Hash::all_zeros(),
+			version: Version::ONE,
+			lock_time: LockTime::ZERO,
+			input_count: 1,
+			output_count: 2,
+			is_coinbase,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_fee() -> Result<()> {
+		let module = BitcoinTransfer::new(1);
+		let inputs = vec![("a".to_string(), 100)];
+		let outputs = vec![("b".to_string(), 60), ("c".to_string(), 30)];
+
+		// inputs minus outputs, on every transfer of the transaction
+		let ret = module.run(1, 0, new_tx(false), None, inputs.clone(), outputs.clone()).await?;
+		assert_eq!(ret.transfers.len(), 2);
+		assert!(ret.transfers.iter().all(|t| t.fee == U256::from(10)));
+
+		// coinbase transactions have no real inputs (and aren't transfers)
+		assert!(module.run(1, 0, new_tx(true), None, inputs, outputs).await?.transfers.is_empty());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_skip_change_outputs() -> Result<()> {
+		let inputs = vec![("a".to_string(), 100), ("b".to_string(), 50)];
+		let outputs = vec![("x".to_string(), 120), ("a".to_string(), 25)];
+
+		let get_pairs = |data: WarehouseData| {
+			let mut pairs = data
+				.transfers
+				.into_iter()
+				.map(|t| (t.from_address, t.to_address, t.relative_amount.as_u64()))
+				.collect::<Vec<_>>();
+			pairs.sort();
+			pairs
+		};
+
+		// by default, "b" looks like it's paying "a" as well
+		let module = BitcoinTransfer::new(1);
+		let ret = module.run(1, 0, new_tx(false), None, inputs.clone(), outputs.clone()).await?;
+		assert_eq!(
+			get_pairs(ret),
+			vec![
+				("a".to_string(), "x".to_string(), 80),
+				("b".to_string(), "a".to_string(), 8),
+				("b".to_string(), "x".to_string(), 40),
+			]
+		);
+
+		// with the flag, the output back to "a" is change
+		let module = BitcoinTransfer::new(1).with_skip_change_outputs(true);
+		let ret = module.run(1, 0, new_tx(false), None, inputs, outputs).await?;
+		assert!(ret.transfers.iter().all(|t| t.batch_amount == U256::from(120)));
+		assert!(ret.transfers.iter().all(|t| t.fee == U256::from(5)));
+		assert_eq!(
+			get_pairs(ret),
+			vec![("a".to_string(), "x".to_string(), 80), ("b".to_string(), "x".to_string(), 40)]
+		);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_attribution() -> Result<()> {
+		let inputs = vec![("a".to_string(), 100), ("b".to_string(), 50)];
+		let outputs = vec![("x".to_string(), 120), ("y".to_string(), 25)];
+
+		let get_amounts = |data: WarehouseData| {
+			let mut amounts = data
+				.transfers
+				.into_iter()
+				.map(|t| (t.from_address, t.to_address, t.relative_amount.as_u64()))
+				.collect::<Vec<_>>();
+			amounts.sort();
+			amounts
+		};
+
+		let module = BitcoinTransfer::new(1);
+		let proportional =
+			module.run(1, 0, new_tx(false), None, inputs.clone(), outputs.clone()).await?;
+
+		let module = BitcoinTransfer::new(1).with_attribution(Attribution::Fifo);
+		let fifo = module.run(1, 0, new_tx(false), None, inputs, outputs).await?;
+
+		// same transaction, so the same fee and batch either way
+		for t in proportional.transfers.iter().chain(fifo.transfers.iter()) {
+			assert_eq!((t.fee, t.batch_amount), (U256::from(5), U256::from(145)));
+		}
+
+		assert_eq!(
+			get_amounts(proportional),
+			vec![
+				("a".to_string(), "x".to_string(), 80),
+				("a".to_string(), "y".to_string(), 17),
+				("b".to_string(), "x".to_string(), 40),
+				("b".to_string(), "y".to_string(), 8),
+			]
+		);
+		assert_eq!(
+			get_amounts(fifo),
+			vec![
+				("a".to_string(), "x".to_string(), 100),
+				("b".to_string(), "x".to_string(), 20),
+				("b".to_string(), "y".to_string(), 25),
+			]
+		);
+
+		Ok(())
+	}
+}
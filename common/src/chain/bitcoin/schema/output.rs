@@ -14,6 +14,8 @@ pub struct Output {
 	pub tx_hash: Hash,
 	pub value: Amount,
 	pub script_pubkey: ScriptBuf,
+	/// Asset id on asset-aware chains (eg: Liquid), `None` for plain bitcoin
+	pub asset: Option<String>,
 }
 
 impl Output {
@@ -37,6 +39,8 @@ impl Output {
 					tx_hash: hashes::Hash::from_slice(&tx_hash)?,
 					value: Amount::from_sat(row.get(1)?),
 					script_pubkey: ScriptBuf::from_bytes(script_pubkey),
+					// files stored before assets were recorded don't have the column
+					asset: row.get(3).ok().flatten(),
 				});
 			}
 		}
@@ -51,7 +55,8 @@ impl StorageModelTrait for Output {
 			r#"CREATE TEMP TABLE IF NOT EXISTS {} (
                 tx_hash BLOB NOT NULL,
                 value UINT64 NOT NULL,
-                script_pubkey BLOB NOT NULL
+                script_pubkey BLOB NOT NULL,
+                asset VARCHAR
             );"#,
 			ParquetFile::Outputs
 		))?;
@@ -65,9 +70,9 @@ impl StorageModelTrait for Output {
 		db.execute(
 			&format!(
 				r#"INSERT INTO {} (
-                    tx_hash, value, script_pubkey
+                    tx_hash, value, script_pubkey, asset
                 ) VALUES (
-                    ?, ?, ?
+                    ?, ?, ?, ?
                 );"#,
 				ParquetFile::Outputs
 			),
@@ -75,6 +80,7 @@ impl StorageModelTrait for Output {
 				<Hash as AsRef<[u8]>>::as_ref(&self.tx_hash),
 				self.value.to_sat(),
 				self.script_pubkey.clone().into_bytes(),
+				self.asset.as_deref(),
 			],
 		)?;
 
@@ -7,12 +7,12 @@ use ethers::{
 	utils::hex::ToHex,
 };
 use eyre::Result;
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use crate::{
-	chain::{ChainTrait, ModuleId, ModuleTrait, WarehouseData},
-	models::Network,
-	utils, BlockHeight, RateLimiter, Storage,
+	chain::{ChainError, ChainTrait, ModuleId, ModuleTrait, WarehouseData},
+	models::{Network, Transfer},
+	utils, Architecture, BlockHeight, Cache, RateLimiter, Storage,
 };
 use modules::{EvmBalance, EvmModuleTrait, EvmTokenBalance, EvmTokenTransfer, EvmTransfer};
 use schema::{
@@ -109,6 +109,10 @@ impl ChainTrait for Evm {
 		address.to_string()
 	}
 
+	fn is_valid_address(&self, address: &str) -> bool {
+		address.len() == 42 && address.starts_with("0x") && address[2..].parse::<Address>().is_ok()
+	}
+
 	async fn get_block_height(&self) -> Result<BlockHeight> {
 		self.rate_limit().await;
 		Ok(self.provider.as_ref().unwrap().get_block_number().await?.as_u64())
@@ -117,14 +121,15 @@ impl ChainTrait for Evm {
 	async fn process_block(
 		&self,
 		_storage: Arc<Storage>,
+		_cache: Arc<Cache>,
 		block_height: BlockHeight,
 		module_ids: Vec<ModuleId>,
-	) -> Result<Option<WarehouseData>> {
+	) -> Result<Option<WarehouseData>, ChainError> {
 		let mut ret = None;
 		let provider = self.provider.as_ref().unwrap();
 
 		self.rate_limit().await;
-		match provider.get_block_with_txs(block_height).await? {
+		match provider.get_block_with_txs(block_height).await.map_err(ChainError::rpc)? {
 			Some(block) if block.number.is_some() => {
 				let mut warehouse_data = WarehouseData::new();
 
@@ -136,7 +141,11 @@ impl ChainTrait for Evm {
 
 					// process tx only if receipt exists
 					self.rate_limit().await;
-					if let Some(receipt) = provider.get_transaction_receipt(tx.hash()).await? {
+					if let Some(receipt) = provider
+						.get_transaction_receipt(tx.hash())
+						.await
+						.map_err(ChainError::rpc)?
+					{
 						// skip if tx reverted
 						if let Some(status) = receipt.status {
 							if status == U64::zero() {
@@ -153,7 +162,8 @@ impl ChainTrait for Evm {
 								receipt,
 								module_ids.clone(),
 							)
-							.await?;
+							.await
+							.map_err(ChainError::decode)?;
 					}
 				}
 
@@ -165,30 +175,74 @@ impl ChainTrait for Evm {
 		Ok(ret)
 	}
 
+	async fn get_transfers_by_tx_hash(
+		&self,
+		_cache: Arc<Cache>,
+		tx_hash: &str,
+	) -> Result<Option<Vec<Transfer>>> {
+		let Ok(hash) = H256::from_str(tx_hash) else {
+			return Ok(None);
+		};
+		let provider = self.provider.as_ref().unwrap();
+
+		self.rate_limit().await;
+		let Some(tx) = provider.get_transaction(hash).await? else {
+			return Ok(None);
+		};
+
+		// pending ones have no receipt yet, and reverted ones transfer nothing
+		self.rate_limit().await;
+		let receipt = match provider.get_transaction_receipt(hash).await? {
+			Some(receipt) if receipt.status != Some(U64::zero()) => receipt,
+			_ => return Ok(Some(vec![])),
+		};
+
+		let block_height = receipt.block_number.map(|v| v.as_u64()).unwrap_or(0);
+
+		self.rate_limit().await;
+		let block_time =
+			provider.get_block(block_height).await?.map(|b| b.timestamp.as_u32()).unwrap_or(0);
+
+		let warehouse_data = self
+			.process_transaction(
+				block_height,
+				block_time,
+				tx,
+				receipt,
+				ModuleId::get_transfer_module_ids(Architecture::Evm),
+			)
+			.await?;
+
+		Ok(Some(warehouse_data.transfers.into_iter().collect()))
+	}
+
 	async fn extract_block(
 		&self,
 		storage: Arc<Storage>,
 		block_height: BlockHeight,
-	) -> Result<bool> {
-		let storage_db = storage.get(self.network.network_id, block_height)?;
+	) -> Result<bool, ChainError> {
+		let storage_db =
+			storage.get(self.network.network_id, block_height).map_err(ChainError::storage)?;
 		let provider = self.provider.as_ref().unwrap();
 
 		self.rate_limit().await;
-		match provider.get_block_with_txs(block_height).await? {
+		match provider.get_block_with_txs(block_height).await.map_err(ChainError::rpc)? {
 			Some(block) if block.number.is_some() => {
-				storage_db.insert(ParquetBlock {
-					hash: block.hash,
-					parent_hash: block.parent_hash,
-					author: block.author,
-					state_root: block.state_root,
-					transactions_root: block.transactions_root,
-					receipts_root: block.receipts_root,
-					number: block.number.map(|v| v.as_u64()),
-					gas_used: block.gas_used,
-					timestamp: block.timestamp.as_u64(),
-					total_difficulty: block.total_difficulty,
-					base_fee_per_gas: block.base_fee_per_gas,
-				})?;
+				storage_db
+					.insert(ParquetBlock {
+						hash: block.hash,
+						parent_hash: block.parent_hash,
+						author: block.author,
+						state_root: block.state_root,
+						transactions_root: block.transactions_root,
+						receipts_root: block.receipts_root,
+						number: block.number.map(|v| v.as_u64()),
+						gas_used: block.gas_used,
+						timestamp: block.timestamp.as_u64(),
+						total_difficulty: block.total_difficulty,
+						base_fee_per_gas: block.base_fee_per_gas,
+					})
+					.map_err(ChainError::storage)?;
 
 				for tx in block.transactions.into_iter() {
 					// skip if pending
@@ -198,7 +252,11 @@ impl ChainTrait for Evm {
 
 					// process tx only if receipt exists
 					self.rate_limit().await;
-					if let Some(receipt) = provider.get_transaction_receipt(tx.hash()).await? {
+					if let Some(receipt) = provider
+						.get_transaction_receipt(tx.hash())
+						.await
+						.map_err(ChainError::rpc)?
+					{
 						// skip if tx reverted
 						if let Some(status) = receipt.status {
 							if status == U64::zero() {
@@ -206,48 +264,54 @@ impl ChainTrait for Evm {
 							}
 						}
 
-						storage_db.insert(ParquetTransaction {
-							hash: tx.hash,
-							nonce: tx.nonce,
-							transaction_index: tx.transaction_index.map(|v| v.as_u64()),
-							from_address: tx.from,
-							to_address: tx.to,
-							value: tx.value,
-							gas_price: tx.gas_price,
-							gas: tx.gas,
-							transaction_type: tx.transaction_type.map(|v| v.as_u64()),
-							chain_id: tx.chain_id,
-						})?;
-
-						storage_db.insert(ParquetReceipt {
-							transaction_hash: receipt.transaction_hash,
-							transaction_index: receipt.transaction_index.as_u64(),
-							block_hash: receipt.block_hash,
-							block_number: receipt.block_number.map(|v| v.as_u64()),
-							from_address: receipt.from,
-							to_address: receipt.to,
-							cumulative_gas_used: receipt.cumulative_gas_used,
-							gas_used: receipt.gas_used,
-							contract_address: receipt.contract_address,
-							logs: receipt.logs.len() as u32,
-							status: receipt.status.map(|v| v.as_u64()),
-							root: receipt.root,
-							transaction_type: receipt.transaction_type.map(|v| v.as_u64()),
-							effective_gas_price: receipt.effective_gas_price,
-						})?;
+						storage_db
+							.insert(ParquetTransaction {
+								hash: tx.hash,
+								nonce: tx.nonce,
+								transaction_index: tx.transaction_index.map(|v| v.as_u64()),
+								from_address: tx.from,
+								to_address: tx.to,
+								value: tx.value,
+								gas_price: tx.gas_price,
+								gas: tx.gas,
+								transaction_type: tx.transaction_type.map(|v| v.as_u64()),
+								chain_id: tx.chain_id,
+							})
+							.map_err(ChainError::storage)?;
+
+						storage_db
+							.insert(ParquetReceipt {
+								transaction_hash: receipt.transaction_hash,
+								transaction_index: receipt.transaction_index.as_u64(),
+								block_hash: receipt.block_hash,
+								block_number: receipt.block_number.map(|v| v.as_u64()),
+								from_address: receipt.from,
+								to_address: receipt.to,
+								cumulative_gas_used: receipt.cumulative_gas_used,
+								gas_used: receipt.gas_used,
+								contract_address: receipt.contract_address,
+								logs: receipt.logs.len() as u32,
+								status: receipt.status.map(|v| v.as_u64()),
+								root: receipt.root,
+								transaction_type: receipt.transaction_type.map(|v| v.as_u64()),
+								effective_gas_price: receipt.effective_gas_price,
+							})
+							.map_err(ChainError::storage)?;
 
 						for log in receipt.logs.into_iter() {
-							storage_db.insert(ParquetLog {
-								address: log.address,
-								topics: log.topics,
-								data: log.data,
-								transaction_hash: log.transaction_hash,
-								transaction_index: log.transaction_index.map(|v| v.as_u64()),
-								log_index: log.log_index,
-								transaction_log_index: log.transaction_log_index,
-								log_type: log.log_type,
-								removed: log.removed,
-							})?;
+							storage_db
+								.insert(ParquetLog {
+									address: log.address,
+									topics: log.topics,
+									data: log.data,
+									transaction_hash: log.transaction_hash,
+									transaction_index: log.transaction_index.map(|v| v.as_u64()),
+									log_index: log.log_index,
+									transaction_log_index: log.transaction_log_index,
+									log_type: log.log_type,
+									removed: log.removed,
+								})
+								.map_err(ChainError::storage)?;
 						}
 					}
 				}
@@ -255,12 +319,14 @@ impl ChainTrait for Evm {
 			_ => {}
 		};
 
-		storage_db.commit(vec![
-			ParquetFile::Blocks.to_string(),
-			ParquetFile::Transactions.to_string(),
-			ParquetFile::Receipts.to_string(),
-			ParquetFile::Logs.to_string(),
-		])?;
+		storage_db
+			.commit(vec![
+				ParquetFile::Blocks.to_string(),
+				ParquetFile::Transactions.to_string(),
+				ParquetFile::Receipts.to_string(),
+				ParquetFile::Logs.to_string(),
+			])
+			.map_err(ChainError::storage)?;
 
 		Ok(true)
 	}
@@ -1,17 +1,23 @@
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
-use derive_more::Display;
+use derive_more::{Display, Error};
 use eyre::Result;
-use std::{collections::HashSet, ops::AddAssign, sync::Arc};
+use sea_orm::DatabaseConnection;
+use std::{
+	collections::{BTreeSet, HashSet},
+	fmt, io,
+	ops::AddAssign,
+	sync::Arc,
+};
 use tokio::task::JoinSet;
 
 pub use crate::chain::bitcoin::Bitcoin;
 use crate::{
-	models::{Amount, AmountTable, Link, LinkTable, Network, Transfer, TransferTable},
-	utils, BlockHeight, PrimaryId, RateLimiter, Storage, Warehouse,
+	models::{Amount, Link, LinkTable, Network, Transfer},
+	utils, Architecture, BlockHeight, Cache, PrimaryId, RateLimiter, Storage, Warehouse,
 };
 pub use evm::Evm;
-pub use u256::U256;
+pub use u256::{JsonAmount, U256};
 
 pub mod bitcoin;
 pub mod evm;
@@ -25,12 +31,85 @@ pub enum ModuleId {
 	BitcoinCoinbase = 101,
 	BitcoinTransfer = 102,
 	BitcoinBalance = 103,
+	BitcoinCluster = 104,
 	EvmTransfer = 201,
 	EvmBalance = 202,
 	EvmTokenTransfer = 203,
 	EvmTokenBalance = 204,
 }
 
+/// Why extracting or processing a block failed. Rpc, storage and warehouse failures are usually
+/// a node or a store that's (temporarily) unavailable; decoding ones fail the same way every
+/// time, and so do blocks a pruned node no longer has
+#[derive(Debug, Display, Error)]
+pub enum ChainError {
+	#[display("rpc: {message}")]
+	Rpc { message: String },
+	#[display("could not decode: {message}")]
+	Decode { message: String },
+	#[display("warehouse: {message}")]
+	Warehouse { message: String },
+	#[display("storage: {message}")]
+	Storage { message: String },
+	#[display("{message}")]
+	Pruned { message: String },
+	#[display("wrong chain: {message}")]
+	Mismatch { message: String },
+}
+
+impl ChainError {
+	pub fn rpc(e: impl fmt::Display) -> Self {
+		Self::Rpc { message: e.to_string() }
+	}
+
+	pub fn decode(e: impl fmt::Display) -> Self {
+		Self::Decode { message: e.to_string() }
+	}
+
+	pub fn warehouse(e: impl fmt::Display) -> Self {
+		Self::Warehouse { message: e.to_string() }
+	}
+
+	pub fn storage(e: impl fmt::Display) -> Self {
+		Self::Storage { message: e.to_string() }
+	}
+
+	pub fn pruned(e: impl fmt::Display) -> Self {
+		Self::Pruned { message: e.to_string() }
+	}
+
+	/// Reading a stored block failed. Not getting to it (disk, s3...) is worth retrying, but
+	/// failing on what was read would fail the same way every time
+	pub fn from_storage(e: eyre::Report) -> Self {
+		let is_io = e.chain().any(|e| {
+			let message = e.to_string();
+			e.is::<io::Error>() ||
+				message.starts_with("IO Error") ||
+				message.starts_with("HTTP Error")
+		});
+
+		match is_io {
+			true => Self::storage(e),
+			_ => Self::decode(e),
+		}
+	}
+
+	/// Whether trying again (later) could go through
+	pub fn is_transient(&self) -> bool {
+		!matches!(self, Self::Decode { .. } | Self::Pruned { .. } | Self::Mismatch { .. })
+	}
+}
+
+impl ModuleId {
+	/// Modules that output transfers (as opposed to balances or clusters) on `architecture`
+	pub fn get_transfer_module_ids(architecture: Architecture) -> Vec<Self> {
+		match architecture {
+			Architecture::Bitcoin => vec![Self::BitcoinCoinbase, Self::BitcoinTransfer],
+			Architecture::Evm => vec![Self::EvmTransfer, Self::EvmTokenTransfer],
+		}
+	}
+}
+
 #[async_trait]
 pub trait ChainTrait: Send + Sync {
 	async fn connect(&mut self) -> Result<bool>;
@@ -40,6 +119,7 @@ pub trait ChainTrait: Send + Sync {
 	fn get_rpc(&self) -> Option<String>;
 	fn get_module_ids(&self) -> Vec<ModuleId>;
 	fn format_address(&self, address: &str) -> String;
+	fn is_valid_address(&self, address: &str) -> bool;
 	fn get_rate_limiter(&self) -> Option<Arc<RateLimiter>>;
 
 	async fn get_block_height(&self) -> Result<BlockHeight>;
@@ -47,18 +127,57 @@ pub trait ChainTrait: Send + Sync {
 	async fn process_block(
 		&self,
 		storage: Arc<Storage>,
+		cache: Arc<Cache>,
 		block_height: BlockHeight,
 		modules: Vec<ModuleId>,
-	) -> Result<Option<WarehouseData>>;
+	) -> Result<Option<WarehouseData>, ChainError>;
+
+	async fn extract_block(
+		&self,
+		storage: Arc<Storage>,
+		block_height: BlockHeight,
+	) -> Result<bool, ChainError>;
 
-	async fn extract_block(&self, storage: Arc<Storage>, block_height: BlockHeight)
-		-> Result<bool>;
+	/// Transfers of a single transaction straight from the node, for ones that aren't indexed
+	/// (yet). `None` when the node doesn't know about the transaction, and a block height of
+	/// `0` while it's unconfirmed
+	async fn get_transfers_by_tx_hash(
+		&self,
+		cache: Arc<Cache>,
+		tx_hash: &str,
+	) -> Result<Option<Vec<Transfer>>>;
+
+	/// Records transfers of new mempool transactions as pending (see `PendingTransfer`), and
+	/// drops the ones of transactions that left the mempool
+	async fn process_mempool(&self, _c: &DatabaseConnection, _cache: Arc<Cache>) -> Result<()> {
+		Ok(())
+	}
 
 	async fn rate_limit(&self) {
 		if let Some(rate_limiter) = &self.get_rate_limiter() {
 			rate_limiter.until_ready().await;
 		}
 	}
+
+	/// Same as `rate_limit`, but for a batched request of `calls` calls (one token each)
+	async fn rate_limit_calls(&self, calls: usize) {
+		for _ in 0..calls {
+			self.rate_limit().await;
+		}
+	}
+}
+
+/// Normalizes `address` with whichever chain it's valid on, since each has its own rules:
+/// bech32 is lowercased, base58 is case-sensitive (so it's left as is) and EVM hex is
+/// checksummed (EIP-55)
+pub fn normalize_address<'a>(
+	chains: impl IntoIterator<Item = &'a Arc<BoxedChain>>,
+	address: &str,
+) -> Option<String> {
+	chains
+		.into_iter()
+		.find(|chain| chain.is_valid_address(address))
+		.map(|chain| chain.format_address(address))
 }
 
 #[async_trait]
@@ -75,6 +194,8 @@ pub struct WarehouseData {
 	pub transfers: HashSet<Transfer>,
 	pub amounts: HashSet<Amount>,
 	pub links: HashSet<Link>,
+	/// Co-spent input addresses per network; merged into db clusters, not the warehouse
+	pub clusters: HashSet<(PrimaryId, BTreeSet<String>)>,
 }
 
 impl WarehouseData {
@@ -83,7 +204,7 @@ impl WarehouseData {
 	}
 
 	pub fn len(&self) -> usize {
-		self.transfers.len() + self.amounts.len() + self.links.len()
+		self.transfers.len() + self.amounts.len() + self.links.len() + self.clusters.len()
 	}
 
 	pub fn is_empty(&self) -> bool {
@@ -100,7 +221,7 @@ impl WarehouseData {
 		manually_required || lengthy_break || buffer_is_full
 	}
 
-	pub async fn commit(&mut self, warehouse: Arc<Warehouse>) -> Result<()> {
+	pub async fn commit(&mut self, warehouse: Arc<Warehouse>) -> Result<(), ChainError> {
 		let mut set = JoinSet::new();
 
 		if !self.transfers.is_empty() {
@@ -109,7 +230,7 @@ impl WarehouseData {
 				let t: Vec<_> = self.transfers.clone().into_iter().collect();
 
 				async move {
-					w.insert(TransferTable, &t).await?;
+					Transfer::create_many(&w, t).await?;
 					Ok::<_, eyre::Error>(())
 				}
 			});
@@ -120,7 +241,7 @@ impl WarehouseData {
 				let a: Vec<_> = self.amounts.clone().into_iter().collect();
 
 				async move {
-					w.insert(AmountTable, &a).await?;
+					Amount::create_many(&w, a).await?;
 					Ok::<_, eyre::Error>(())
 				}
 			});
@@ -138,7 +259,7 @@ impl WarehouseData {
 		}
 
 		while let Some(res) = set.join_next().await {
-			res??;
+			res.map_err(ChainError::warehouse)?.map_err(ChainError::warehouse)?;
 		}
 
 		self.clear();
@@ -151,6 +272,7 @@ impl WarehouseData {
 		self.transfers.clear();
 		self.amounts.clear();
 		self.links.clear();
+		self.clusters.clear();
 	}
 }
 
@@ -159,5 +281,39 @@ impl AddAssign for WarehouseData {
 		self.transfers.extend(rhs.transfers);
 		self.amounts.extend(rhs.amounts);
 		self.links.extend(rhs.links);
+		self.clusters.extend(rhs.clusters);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_normalize_address() {
+		let bitcoin: BoxedChain =
+			Box::new(Bitcoin::new(Network { chain_id: 0, ..Default::default() }).unwrap());
+		let evm: BoxedChain = Box::new(Evm::new(Network { chain_id: 1, ..Default::default() }));
+		let chains = [Arc::new(bitcoin), Arc::new(evm)];
+
+		// bech32 is case-insensitive, so it's lowercased
+		assert_eq!(
+			normalize_address(&chains, "BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ"),
+			Some("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_string())
+		);
+
+		// base58 is case-sensitive, so it's kept as is
+		assert_eq!(
+			normalize_address(&chains, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"),
+			Some("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_string())
+		);
+
+		// evm addresses are checksummed
+		assert_eq!(
+			normalize_address(&chains, "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+			Some("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string())
+		);
+
+		assert_eq!(normalize_address(&chains, "not-an-address"), None);
 	}
 }
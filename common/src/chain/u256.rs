@@ -1,8 +1,17 @@
 pub use ethers::types::U256;
+use schemars::{
+	gen::SchemaGenerator,
+	schema::{InstanceType, Metadata, NumberValidation, Schema, SchemaObject, SubschemaValidation},
+	JsonSchema,
+};
 use serde::{
-	de::{Deserialize, Deserializer},
+	de::{self, Deserialize, Deserializer, Visitor},
 	ser::{Serialize, Serializer},
 };
+use std::fmt;
+
+/// Largest integer js numbers hold exactly (2^53 - 1)
+const MAX_SAFE_INTEGER: u64 = 9_007_199_254_740_991;
 
 pub fn serialize<S: Serializer>(u: &U256, serializer: S) -> Result<S::Ok, S::Error> {
 	let mut buf: [u8; 32] = [0; 32];
@@ -17,3 +26,150 @@ where
 	let u: [u8; 32] = Deserialize::deserialize(deserializer)?;
 	Ok(U256::from_little_endian(&u))
 }
+
+/// An amount as it shows up in api responses: a decimal string, or a json number when
+/// numeric amounts were opted into (see `Settings::numeric_amounts`) and js can hold it
+/// exactly. Reads back either form
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct JsonAmount {
+	pub amount: U256,
+	pub is_numeric: bool,
+}
+
+impl JsonAmount {
+	pub fn new(amount: U256, is_numeric: bool) -> Self {
+		Self { amount, is_numeric }
+	}
+}
+
+impl From<U256> for JsonAmount {
+	fn from(u: U256) -> Self {
+		Self::new(u, false)
+	}
+}
+
+impl fmt::Display for JsonAmount {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.amount)
+	}
+}
+
+impl Serialize for JsonAmount {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self.is_numeric && self.amount <= U256::from(MAX_SAFE_INTEGER) {
+			true => serializer.serialize_u64(self.amount.as_u64()),
+			_ => serializer.serialize_str(&self.amount.to_string()),
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for JsonAmount {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct JsonAmountVisitor;
+
+		impl Visitor<'_> for JsonAmountVisitor {
+			type Value = JsonAmount;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				write!(f, "a non-negative integer, or a string of one")
+			}
+
+			fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+				Ok(JsonAmount::new(U256::from(v), true))
+			}
+
+			fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+				U256::from_dec_str(v).map(JsonAmount::from).map_err(E::custom)
+			}
+		}
+
+		deserializer.deserialize_any(JsonAmountVisitor)
+	}
+}
+
+impl JsonSchema for JsonAmount {
+	fn schema_name() -> String {
+		"Amount".to_string()
+	}
+
+	fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+		let string =
+			SchemaObject { instance_type: Some(InstanceType::String.into()), ..Default::default() };
+		let number = SchemaObject {
+			instance_type: Some(InstanceType::Integer.into()),
+			number: Some(Box::new(NumberValidation {
+				minimum: Some(0.0),
+				maximum: Some(MAX_SAFE_INTEGER as f64),
+				..Default::default()
+			})),
+			..Default::default()
+		};
+
+		SchemaObject {
+			metadata: Some(Box::new(Metadata {
+				description: Some(
+					"Decimal integer string, or a json number when the server runs with \
+					 `--numeric-amounts` and it's at most 2^53 - 1"
+						.to_string(),
+				),
+				..Default::default()
+			})),
+			subschemas: Some(Box::new(SubschemaValidation {
+				one_of: Some(vec![string.into(), number.into()]),
+				..Default::default()
+			})),
+			..Default::default()
+		}
+		.into()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn test_json_amount() {
+		// past 2^53, where js numbers stop being exact
+		let amount = JsonAmount::from(U256::from(2u64.pow(53) + 1));
+
+		assert_eq!(serde_json::to_value(amount).unwrap(), json!("9007199254740993"));
+		assert_eq!(
+			serde_json::from_value::<JsonAmount>(json!("9007199254740993")).unwrap(),
+			amount
+		);
+		assert_eq!(
+			serde_json::from_value::<JsonAmount>(json!(9007199254740993u64)).unwrap().amount,
+			amount.amount
+		);
+
+		let max = JsonAmount::from(U256::MAX);
+		assert_eq!(
+			serde_json::from_str::<JsonAmount>(&serde_json::to_string(&max).unwrap()).unwrap(),
+			max
+		);
+
+		assert!(serde_json::from_value::<JsonAmount>(json!("-1")).is_err());
+		assert!(serde_json::from_value::<JsonAmount>(json!(1.5)).is_err());
+	}
+
+	#[test]
+	fn test_numeric_json_amount() {
+		let numeric = |v: U256| serde_json::to_value(JsonAmount::new(v, true)).unwrap();
+
+		assert_eq!(numeric(U256::from(MAX_SAFE_INTEGER)), json!(9007199254740991u64));
+
+		// past what js holds exactly, and past u64, they stay strings
+		assert_eq!(numeric(U256::from(MAX_SAFE_INTEGER + 1)), json!("9007199254740992"));
+		assert_eq!(numeric(U256::from(u64::MAX)), json!("18446744073709551615"));
+		assert_eq!(numeric(U256::MAX), json!(U256::MAX.to_string()));
+
+		// reads back the same way it was written
+		let amount = JsonAmount::new(U256::from(10), true);
+		assert_eq!(
+			serde_json::from_str::<JsonAmount>(&serde_json::to_string(&amount).unwrap()).unwrap(),
+			amount
+		);
+	}
+}
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.create_table(
+				Table::create()
+					.table(Clusters::Table)
+					.if_not_exists()
+					.col(
+						ColumnDef::new(Clusters::ClusterAddressId)
+							.big_integer()
+							.not_null()
+							.auto_increment()
+							.primary_key(),
+					)
+					.col(ColumnDef::new(Clusters::NetworkId).big_integer().not_null())
+					.col(ColumnDef::new(Clusters::Address).string().not_null())
+					.col(ColumnDef::new(Clusters::Cluster).string().not_null())
+					.col(ColumnDef::new(Clusters::UpdatedAt).date_time().null())
+					.col(
+						ColumnDef::new(Clusters::CreatedAt)
+							.date_time()
+							.not_null()
+							.extra("DEFAULT CURRENT_TIMESTAMP".to_owned()),
+					)
+					.foreign_key(
+						&mut sea_query::ForeignKey::create()
+							.name("fk_clusters_network_id")
+							.from(Clusters::Table, Clusters::NetworkId)
+							.to(Alias::new("networks"), Alias::new("network_id"))
+							.on_delete(ForeignKeyAction::Cascade)
+							.to_owned(),
+					)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ux_clusters_network_id_address")
+					.table(Clusters::Table)
+					.unique()
+					.col(Clusters::NetworkId)
+					.col(Clusters::Address)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ix_clusters_network_id_cluster")
+					.table(Clusters::Table)
+					.col(Clusters::NetworkId)
+					.col(Clusters::Cluster)
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager.drop_table(Table::drop().table(Clusters::Table).to_owned()).await
+	}
+}
+
+#[derive(Iden)]
+enum Clusters {
+	#[iden = "clusters"]
+	Table,
+	ClusterAddressId,
+	NetworkId,
+	Address,
+	Cluster,
+	UpdatedAt,
+	CreatedAt,
+}
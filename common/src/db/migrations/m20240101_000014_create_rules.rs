@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.create_table(
+				Table::create()
+					.table(Rules::Table)
+					.if_not_exists()
+					.col(
+						ColumnDef::new(Rules::RuleId)
+							.big_integer()
+							.not_null()
+							.auto_increment()
+							.primary_key(),
+					)
+					.col(ColumnDef::new(Rules::EntityId).big_integer().not_null())
+					.col(ColumnDef::new(Rules::Id).unique_key().string().not_null())
+					.col(ColumnDef::new(Rules::Entity).string().not_null())
+					.col(ColumnDef::new(Rules::Kind).small_integer().not_null())
+					.col(ColumnDef::new(Rules::UpdatedAt).date_time().null())
+					.col(
+						ColumnDef::new(Rules::CreatedAt)
+							.date_time()
+							.not_null()
+							.extra("DEFAULT CURRENT_TIMESTAMP".to_owned()),
+					)
+					.foreign_key(
+						&mut sea_query::ForeignKey::create()
+							.name("fk_rules_entity_id")
+							.from(Rules::Table, Rules::EntityId)
+							.to(Alias::new("entities"), Alias::new("entity_id"))
+							.on_delete(ForeignKeyAction::Cascade)
+							.to_owned(),
+					)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ux_rules_entity_id_kind")
+					.table(Rules::Table)
+					.unique()
+					.col(Rules::EntityId)
+					.col(Rules::Kind)
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager.drop_table(Table::drop().table(Rules::Table).to_owned()).await
+	}
+}
+
+#[derive(Iden)]
+enum Rules {
+	#[iden = "rules"]
+	Table,
+	RuleId,
+	EntityId,
+	Id,
+	Entity,
+	Kind,
+	UpdatedAt,
+	CreatedAt,
+}
@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.create_table(
+				Table::create()
+					.table(RuleRejections::Table)
+					.if_not_exists()
+					.col(
+						ColumnDef::new(RuleRejections::RuleRejectionId)
+							.big_integer()
+							.not_null()
+							.auto_increment()
+							.primary_key(),
+					)
+					.col(ColumnDef::new(RuleRejections::EntityId).big_integer().not_null())
+					.col(ColumnDef::new(RuleRejections::NetworkId).big_integer().not_null())
+					.col(ColumnDef::new(RuleRejections::Address).string().not_null())
+					.col(ColumnDef::new(RuleRejections::UpdatedAt).date_time().null())
+					.col(
+						ColumnDef::new(RuleRejections::CreatedAt)
+							.date_time()
+							.not_null()
+							.extra("DEFAULT CURRENT_TIMESTAMP".to_owned()),
+					)
+					.foreign_key(
+						&mut sea_query::ForeignKey::create()
+							.name("fk_rule_rejections_entity_id")
+							.from(RuleRejections::Table, RuleRejections::EntityId)
+							.to(Alias::new("entities"), Alias::new("entity_id"))
+							.on_delete(ForeignKeyAction::Cascade)
+							.to_owned(),
+					)
+					.foreign_key(
+						&mut sea_query::ForeignKey::create()
+							.name("fk_rule_rejections_network_id")
+							.from(RuleRejections::Table, RuleRejections::NetworkId)
+							.to(Alias::new("networks"), Alias::new("network_id"))
+							.on_delete(ForeignKeyAction::Cascade)
+							.to_owned(),
+					)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ux_rule_rejections_entity_id_network_id_address")
+					.table(RuleRejections::Table)
+					.unique()
+					.col(RuleRejections::EntityId)
+					.col(RuleRejections::NetworkId)
+					.col(RuleRejections::Address)
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager.drop_table(Table::drop().table(RuleRejections::Table).to_owned()).await
+	}
+}
+
+#[derive(Iden)]
+enum RuleRejections {
+	#[iden = "rule_rejections"]
+	Table,
+	RuleRejectionId,
+	EntityId,
+	NetworkId,
+	Address,
+	UpdatedAt,
+	CreatedAt,
+}
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.create_table(
+				Table::create()
+					.table(PendingTransfers::Table)
+					.if_not_exists()
+					.col(
+						ColumnDef::new(PendingTransfers::PendingTransferId)
+							.big_integer()
+							.not_null()
+							.auto_increment()
+							.primary_key(),
+					)
+					.col(ColumnDef::new(PendingTransfers::NetworkId).big_integer().not_null())
+					.col(ColumnDef::new(PendingTransfers::TxHash).string().not_null())
+					.col(ColumnDef::new(PendingTransfers::FromAddress).string().not_null())
+					.col(ColumnDef::new(PendingTransfers::ToAddress).string().not_null())
+					.col(ColumnDef::new(PendingTransfers::AssetAddress).string().not_null())
+					.col(ColumnDef::new(PendingTransfers::Amount).string().not_null())
+					.col(ColumnDef::new(PendingTransfers::Fee).string().not_null())
+					.col(ColumnDef::new(PendingTransfers::UpdatedAt).date_time().null())
+					.col(
+						ColumnDef::new(PendingTransfers::CreatedAt)
+							.date_time()
+							.not_null()
+							.extra("DEFAULT CURRENT_TIMESTAMP".to_owned()),
+					)
+					.foreign_key(
+						&mut sea_query::ForeignKey::create()
+							.name("fk_pending_transfers_network_id")
+							.from(PendingTransfers::Table, PendingTransfers::NetworkId)
+							.to(Alias::new("networks"), Alias::new("network_id"))
+							.on_delete(ForeignKeyAction::Cascade)
+							.to_owned(),
+					)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ix_pending_transfers_network_id_tx_hash")
+					.table(PendingTransfers::Table)
+					.col(PendingTransfers::NetworkId)
+					.col(PendingTransfers::TxHash)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ix_pending_transfers_from_address")
+					.table(PendingTransfers::Table)
+					.col(PendingTransfers::FromAddress)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.if_not_exists()
+					.name("ix_pending_transfers_to_address")
+					.table(PendingTransfers::Table)
+					.col(PendingTransfers::ToAddress)
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager.drop_table(Table::drop().table(PendingTransfers::Table).to_owned()).await
+	}
+}
+
+#[derive(Iden)]
+enum PendingTransfers {
+	#[iden = "pending_transfers"]
+	Table,
+	PendingTransferId,
+	NetworkId,
+	TxHash,
+	FromAddress,
+	ToAddress,
+	AssetAddress,
+	Amount,
+	Fee,
+	UpdatedAt,
+	CreatedAt,
+}
@@ -9,6 +9,17 @@ mod m20240101_000006_create_addresses;
 mod m20240101_000007_create_tags;
 mod m20240101_000008_create_entity_tags;
 mod m20240101_000009_create_tokens;
+mod m20240101_000010_create_webhooks;
+mod m20240101_000011_add_networks_confirmations;
+mod m20240101_000012_add_networks_skip_change_outputs;
+mod m20240101_000013_create_clusters;
+mod m20240101_000014_create_rules;
+mod m20240101_000015_add_addresses_is_locked;
+mod m20240101_000016_add_api_keys_last_used_at;
+mod m20240101_000017_add_networks_attribution;
+mod m20240101_000018_create_rule_rejections;
+mod m20240101_000019_create_pending_transfers;
+mod m20240101_000020_add_networks_script_addresses;
 
 pub struct Migrator;
 
@@ -24,6 +35,17 @@ impl MigratorTrait for Migrator {
 			Box::new(m20240101_000007_create_tags::Migration),
 			Box::new(m20240101_000008_create_entity_tags::Migration),
 			Box::new(m20240101_000009_create_tokens::Migration),
+			Box::new(m20240101_000010_create_webhooks::Migration),
+			Box::new(m20240101_000011_add_networks_confirmations::Migration),
+			Box::new(m20240101_000012_add_networks_skip_change_outputs::Migration),
+			Box::new(m20240101_000013_create_clusters::Migration),
+			Box::new(m20240101_000014_create_rules::Migration),
+			Box::new(m20240101_000015_add_addresses_is_locked::Migration),
+			Box::new(m20240101_000016_add_api_keys_last_used_at::Migration),
+			Box::new(m20240101_000017_add_networks_attribution::Migration),
+			Box::new(m20240101_000018_create_rule_rejections::Migration),
+			Box::new(m20240101_000019_create_pending_transfers::Migration),
+			Box::new(m20240101_000020_add_networks_script_addresses::Migration),
 		]
 	}
 }
@@ -1,16 +1,18 @@
+use clap::{builder::PossibleValue, ValueEnum};
 use console::style;
 use derive_more::Display;
-use eyre::Result;
-use log::LevelFilter;
+use eyre::{Report, Result};
 use sea_orm::{
-	ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DatabaseTransaction, DbBackend,
-	Statement, TransactionTrait,
+	sqlx, ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DatabaseTransaction,
+	DbBackend, DbErr, RuntimeErr, SqlErr, Statement, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
-use std::{str::FromStr, sync::Arc, time::Duration};
-use tracing::info;
+use std::{future::Future, path::Path, pin::Pin, str::FromStr, sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{info, warn};
+use url::Url;
 
-use crate::{utils, AppError, Settings};
+use crate::{utils, AppError, Settings, DB_TX_MAX_ATTEMPTS};
 use migrations::{Migrator, MigratorTrait};
 
 mod migrations;
@@ -27,21 +29,129 @@ pub enum Driver {
 	#[serde(rename = "mysql")]
 	#[display("MySQL")]
 	MySQL,
+	#[serde(rename = "cockroachdb")]
+	#[display("CockroachDB")]
+	CockroachDB,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SslMode {
+	Require,
+	VerifyCa,
+	VerifyFull,
+}
+
+impl ValueEnum for SslMode {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Require, Self::VerifyCa, Self::VerifyFull]
+	}
+
+	fn to_possible_value<'a>(&self) -> Option<PossibleValue> {
+		match self {
+			Self::Require => Some(PossibleValue::new("require")),
+			Self::VerifyCa => Some(PossibleValue::new("verify-ca")),
+			Self::VerifyFull => Some(PossibleValue::new("verify-full")),
+		}
+	}
 }
 
 impl FromStr for Driver {
-	type Err = ();
+	type Err = AppError<'static>;
 
 	fn from_str(d: &str) -> Result<Self, Self::Err> {
 		match d {
 			"sqlite" => Ok(Self::SQLite),
 			"postgres" | "postgresql" => Ok(Self::PostgreSQL),
 			"mysql" => Ok(Self::MySQL),
-			_ => Err(()),
+			"cockroachdb" | "cockroach" => Ok(Self::CockroachDB),
+			_ => Err(AppError::Database {
+				error: format!(
+					"unknown driver `{d}` (supported: sqlite, postgres, mysql, cockroachdb)"
+				)
+				.into(),
+			}),
 		}
 	}
 }
 
+impl Driver {
+	/// Statements that create `db_name` if it doesn't exist yet. PostgreSQL has no
+	/// `IF NOT EXISTS` for databases, so it checks the catalog first (the create statement
+	/// only runs when the lookup returns no rows)
+	pub fn create_database_statements(&self, db_name: &str) -> Vec<Statement> {
+		match self {
+			Self::SQLite => vec![],
+			Self::MySQL => vec![Statement::from_string(
+				DbBackend::MySql,
+				format!("CREATE DATABASE IF NOT EXISTS `{db_name}`;"),
+			)],
+			Self::PostgreSQL => vec![
+				Statement::from_string(
+					DbBackend::Postgres,
+					format!(
+						"SELECT datname FROM pg_catalog.pg_database WHERE datname='{db_name}';"
+					),
+				),
+				Statement::from_string(
+					DbBackend::Postgres,
+					format!(r#"CREATE DATABASE "{db_name}";"#),
+				),
+			],
+			Self::CockroachDB => vec![Statement::from_string(
+				DbBackend::Postgres,
+				format!(r#"CREATE DATABASE IF NOT EXISTS "{db_name}";"#),
+			)],
+		}
+	}
+
+	/// Sets tls params on the connection url in the form sqlx expects for each driver
+	/// (PostgreSQL's `sslmode`, MySQL's `ssl-mode`), replacing any already in it. SQLite has
+	/// no notion of tls
+	pub fn set_ssl(&self, url: &mut Url, ssl_mode: SslMode, root_cert: Option<&Path>) {
+		let (mode_key, cert_key, mode) = match self {
+			Self::SQLite => return,
+			Self::PostgreSQL | Self::CockroachDB => (
+				"sslmode",
+				"sslrootcert",
+				match ssl_mode {
+					SslMode::Require => "require",
+					SslMode::VerifyCa => "verify-ca",
+					SslMode::VerifyFull => "verify-full",
+				},
+			),
+			Self::MySQL => (
+				"ssl-mode",
+				"ssl-ca",
+				match ssl_mode {
+					SslMode::Require => "REQUIRED",
+					SslMode::VerifyCa => "VERIFY_CA",
+					SslMode::VerifyFull => "VERIFY_IDENTITY",
+				},
+			),
+		};
+
+		let replaced = [Some(mode_key), root_cert.map(|_| cert_key)];
+		let kept = url
+			.query_pairs()
+			.filter(|(key, _)| !replaced.contains(&Some(key.as_ref())))
+			.map(|(key, value)| (key.into_owned(), value.into_owned()))
+			.collect::<Vec<_>>();
+
+		let mut query = url.query_pairs_mut();
+		query.clear().extend_pairs(kept).append_pair(mode_key, mode);
+		if let Some(root_cert) = root_cert {
+			query.append_pair(cert_key, &root_cert.to_string_lossy());
+		}
+	}
+
+	/// Whether a per-statement timeout can be set when connecting. MySQL only supports it
+	/// through `max_execution_time` on the server or per-query hints, and SQLite has no
+	/// equivalent, so settings reject it for both
+	pub fn supports_statement_timeout(&self) -> bool {
+		matches!(self, Self::PostgreSQL | Self::CockroachDB)
+	}
+}
+
 pub struct Db {
 	db: DatabaseConnection,
 }
@@ -51,26 +161,9 @@ impl Db {
 		let (url_without_credentials, has_credentials) =
 			utils::without_credentials(settings.database_uri.as_ref().unwrap().as_str());
 
-		let with_options = |url: String| -> ConnectOptions {
-			let mut opt = ConnectOptions::new(url);
-
-			// @TODO for sqlite, max out at 1 connection otherwise
-			// writes are not guaranteed to be executed serially
-			let (min_connections, max_connections) = match settings.database_driver {
-				Driver::SQLite => (1, 1),
-				_ => (settings.database_min_connections, settings.database_max_connections),
-			};
-
-			opt.max_connections(max_connections)
-				.min_connections(min_connections)
-				.connect_timeout(Duration::from_secs(settings.database_connect_timeout))
-				.idle_timeout(Duration::from_secs(settings.database_idle_timeout))
-				.max_lifetime(Duration::from_secs(settings.database_max_lifetime))
-				.sqlx_logging(false)
-				.sqlx_logging_level(LevelFilter::Warn);
-
-			opt
-		};
+		if settings.database_schema.is_some() && settings.database_driver != Driver::PostgreSQL {
+			warn!("database schema is not supported by {}; ignoring", settings.database_driver);
+		}
 
 		let url = settings.database_uri.as_ref().unwrap().as_str().to_string();
 		let (url_without_database, db_name) = match settings.database_driver {
@@ -80,62 +173,64 @@ impl Db {
 		let url_with_database = url.clone();
 
 		let conn =
-			Database::connect(with_options(url_without_database.clone())).await.map_err(|_| {
-				if has_credentials {
-					AppError::ConnectionWithCredentials {
-						service: settings.database_driver.to_string().into(),
-						url: url_without_credentials.to_string().into(),
-					}
-				} else {
-					AppError::Connection {
-						service: settings.database_driver.to_string().into(),
-						url: url.to_string().into(),
-					}
-				}
-			})?;
-
-		let db = match conn.get_database_backend() {
-			DbBackend::MySql => {
-				conn.execute(Statement::from_string(
-					DbBackend::MySql,
-					format!("CREATE DATABASE IF NOT EXISTS `{db_name}`;"),
-				))
+			Database::connect(Self::connect_options(&settings, url_without_database.clone()))
 				.await
-				.map_err(|_| AppError::Database { error: "could not create database".into() })?;
+				.map_err(|_| {
+					if has_credentials {
+						AppError::ConnectionWithCredentials {
+							service: settings.database_driver.to_string().into(),
+							url: url_without_credentials.to_string().into(),
+						}
+					} else {
+						AppError::Connection {
+							service: settings.database_driver.to_string().into(),
+							url: url.to_string().into(),
+						}
+					}
+				})?;
 
-				Database::connect(with_options(url_with_database.clone()))
-					.await
-					.map_err(|_| AppError::Database { error: "could not connect".into() })?
-			}
-			DbBackend::Postgres => {
-				let result = conn
-					.execute(Statement::from_string(
-						DbBackend::Postgres,
-						format!(
-							"SELECT datname FROM pg_catalog.pg_database WHERE datname='{db_name}';"
-						),
-					))
-					.await
-					.map_err(|_| AppError::Database {
-						error: "could not confirm database creation".into(),
-					})?;
+		let statements = settings.database_driver.create_database_statements(&db_name);
+		let db = match settings.database_driver {
+			Driver::SQLite => conn,
+			Driver::PostgreSQL => {
+				let result = conn.execute(statements[0].clone()).await.map_err(|_| {
+					AppError::Database { error: "could not confirm database creation".into() }
+				})?;
 
 				if result.rows_affected() == 0 {
-					conn.execute(Statement::from_string(
+					conn.execute(statements[1].clone()).await.map_err(|_| AppError::Database {
+						error: "could not create database".into(),
+					})?;
+				}
+
+				let db =
+					Database::connect(Self::connect_options(&settings, url_with_database.clone()))
+						.await
+						.map_err(|_| AppError::Database { error: "could not connect".into() })?;
+
+				// create the schema before anything else (eg: migrations) touches it
+				if let Some(schema) = &settings.database_schema {
+					db.execute(Statement::from_string(
 						DbBackend::Postgres,
-						format!(r#"CREATE DATABASE "{db_name}";"#),
+						format!(r#"CREATE SCHEMA IF NOT EXISTS "{schema}";"#),
 					))
 					.await
-					.map_err(|_| AppError::Database {
+					.map_err(|_| AppError::Database { error: "could not create schema".into() })?;
+				}
+
+				db
+			}
+			Driver::MySQL | Driver::CockroachDB => {
+				for statement in statements.into_iter() {
+					conn.execute(statement).await.map_err(|_| AppError::Database {
 						error: "could not create database".into(),
 					})?;
 				}
 
-				Database::connect(with_options(url_with_database.clone()))
+				Database::connect(Self::connect_options(&settings, url_with_database.clone()))
 					.await
 					.map_err(|_| AppError::Database { error: "could not connect".into() })?
 			}
-			DbBackend::Sqlite => conn,
 		};
 
 		info!(
@@ -148,6 +243,76 @@ impl Db {
 		Ok(Self { db })
 	}
 
+	fn connect_options(settings: &Settings, url: String) -> ConnectOptions {
+		let mut opt = ConnectOptions::new(Self::with_postgres_options(settings, url));
+
+		// @TODO for sqlite, max out at 1 connection otherwise
+		// writes are not guaranteed to be executed serially
+		let (min_connections, max_connections) = match settings.database_driver {
+			Driver::SQLite => (1, 1),
+			_ => (settings.database_min_connections, settings.database_max_connections),
+		};
+
+		opt.max_connections(max_connections)
+			.min_connections(min_connections)
+			.connect_timeout(Duration::from_secs(settings.database_connect_timeout))
+			.idle_timeout(Duration::from_secs(settings.database_idle_timeout))
+			.max_lifetime(Duration::from_secs(settings.database_max_lifetime))
+			.sqlx_logging(settings.database_log_queries)
+			.sqlx_logging_level(settings.database_log_level);
+
+		opt
+	}
+
+	/// Adds `postgres_options` to the url, where sqlx picks them up from as `options[..]`
+	fn with_postgres_options(settings: &Settings, url: String) -> String {
+		let options = Self::postgres_options(settings);
+
+		match Url::parse(&url) {
+			Ok(mut parsed) if !options.is_empty() => {
+				for (key, value) in options.into_iter() {
+					parsed.query_pairs_mut().append_pair(&format!("options[{key}]"), &value);
+				}
+
+				parsed.to_string()
+			}
+			_ => url,
+		}
+	}
+
+	/// Runtime parameters set on every new postgres connection
+	fn postgres_options(settings: &Settings) -> Vec<(&'static str, String)> {
+		let mut ret = vec![];
+
+		// abort any single statement running past the limit
+		if settings.database_statement_timeout > 0 &&
+			settings.database_driver.supports_statement_timeout()
+		{
+			ret.push(("statement_timeout", format!("{}s", settings.database_statement_timeout)));
+		}
+
+		// keep all tables (migrations included) in the configured schema. Quoted the same as
+		// when it's created, or mixed-case names would get folded to lowercase
+		if let Some(schema) = &settings.database_schema {
+			if settings.database_driver == Driver::PostgreSQL {
+				ret.push(("search_path", format!(r#""{schema}""#)));
+			}
+		}
+
+		ret
+	}
+
+	#[cfg(any(test, feature = "testing"))]
+	pub async fn new_in_memory() -> Result<Self> {
+		let mut opt = ConnectOptions::new("sqlite::memory:");
+		opt.max_connections(1).min_connections(1).sqlx_logging(false);
+
+		let db = Database::connect(opt).await?;
+		Migrator::up(&db, None).await?;
+
+		Ok(Self { db })
+	}
+
 	pub async fn run_migrations(&self) -> Result<()> {
 		Migrator::up(&self.db, None).await?;
 		Ok(())
@@ -160,4 +325,293 @@ impl Db {
 	pub async fn get_tx(&self) -> Result<DatabaseTransaction> {
 		Ok(self.db.begin().await?)
 	}
+
+	/// Runs `f` in a transaction, starting over (with backoff) when the database gives up
+	/// on it because of concurrent writes, eg: serialization failures or deadlocks
+	pub async fn transaction_with_retry<F, T>(&self, f: F) -> Result<T>
+	where
+		F: for<'c> Fn(
+				&'c DatabaseTransaction,
+			) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'c>>
+			+ Send
+			+ Sync,
+		T: Send,
+	{
+		let mut attempt = 1;
+
+		loop {
+			let tx = self.db.begin().await?;
+			let result = match f(&tx).await {
+				Ok(value) => tx.commit().await.map(|_| value).map_err(Report::from),
+				Err(e) => {
+					tx.rollback().await.ok();
+					Err(e)
+				}
+			};
+
+			match result {
+				Err(e) if attempt < DB_TX_MAX_ATTEMPTS && Self::is_retryable(&e) => {
+					warn!(attempt, error = %e, "retrying transaction");
+					sleep(Duration::from_millis(50 << attempt)).await;
+					attempt += 1;
+				}
+				result => return result,
+			}
+		}
+	}
+
+	fn is_retryable(e: &Report) -> bool {
+		let code = match e.downcast_ref::<DbErr>() {
+			Some(DbErr::Exec(RuntimeErr::SqlxError(sqlx::Error::Database(e)))) |
+			Some(DbErr::Query(RuntimeErr::SqlxError(sqlx::Error::Database(e)))) => {
+				e.code().map(|c| c.to_string())
+			}
+			_ => None,
+		};
+
+		// serialization failure & deadlock (postgres, cockroachdb), deadlock & lock wait
+		// timeout (mysql), busy (sqlite)
+		matches!(code.as_deref(), Some("40001" | "40P01" | "1213" | "1205" | "5"))
+	}
+
+	/// Whether the database rejected a write because of a unique constraint
+	pub fn is_unique_violation(e: &Report) -> bool {
+		matches!(
+			e.downcast_ref::<DbErr>().and_then(|e| e.sql_err()),
+			Some(SqlErr::UniqueConstraintViolation(_))
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use clap::Parser;
+	use log::LevelFilter;
+	use sqlx::error::{DatabaseError, ErrorKind};
+	use std::{
+		borrow::Cow,
+		collections::HashSet,
+		error::Error,
+		fmt,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	#[derive(Debug)]
+	struct SerializationFailure;
+
+	impl fmt::Display for SerializationFailure {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			write!(f, "could not serialize access due to concurrent update")
+		}
+	}
+
+	impl Error for SerializationFailure {}
+
+	impl DatabaseError for SerializationFailure {
+		fn message(&self) -> &str {
+			"could not serialize access due to concurrent update"
+		}
+
+		fn code(&self) -> Option<Cow<'_, str>> {
+			Some("40001".into())
+		}
+
+		fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+			self
+		}
+
+		fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+			self
+		}
+
+		fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+			self
+		}
+
+		fn kind(&self) -> ErrorKind {
+			ErrorKind::Other
+		}
+	}
+
+	#[tokio::test]
+	async fn test_transaction_with_retry() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		// fails with a serialization failure on the first attempt only
+		let attempts = AtomicUsize::new(0);
+		let ret = db
+			.transaction_with_retry(|_| {
+				let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+				Box::pin(async move {
+					if attempt == 0 {
+						return Err(DbErr::Exec(RuntimeErr::SqlxError(sqlx::Error::Database(
+							Box::new(SerializationFailure),
+						)))
+						.into());
+					}
+
+					Ok(attempt)
+				})
+			})
+			.await?;
+
+		assert_eq!(ret, 1);
+		assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+		// other errors are not retried
+		let attempts = AtomicUsize::new(0);
+		let ret = db
+			.transaction_with_retry(|_| {
+				attempts.fetch_add(1, Ordering::SeqCst);
+				Box::pin(async { Err::<(), _>(eyre::eyre!("nope")) })
+			})
+			.await;
+
+		assert!(ret.is_err());
+		assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_driver_from_str() {
+		assert_eq!("cockroachdb".parse::<Driver>().ok(), Some(Driver::CockroachDB));
+		assert_eq!("cockroach".parse::<Driver>().ok(), Some(Driver::CockroachDB));
+		assert_eq!("postgresql".parse::<Driver>().ok(), Some(Driver::PostgreSQL));
+		assert!("oracle".parse::<Driver>().is_err());
+
+		assert_eq!(
+			"postgre".parse::<Driver>().unwrap_err().to_string(),
+			"database: unknown driver `postgre` (supported: sqlite, postgres, mysql, cockroachdb)"
+		);
+	}
+
+	#[test]
+	fn test_connect_options_logging() {
+		let settings = Settings::parse_from(["barreleye"]);
+		let opt = Db::connect_options(&settings, "sqlite::memory:".to_string());
+		assert!(!opt.get_sqlx_logging());
+
+		let settings = Settings::parse_from([
+			"barreleye",
+			"--database-log-queries",
+			"--database-log-level",
+			"debug",
+		]);
+		let opt = Db::connect_options(&settings, "sqlite::memory:".to_string());
+		assert!(opt.get_sqlx_logging());
+		assert_eq!(opt.get_sqlx_logging_level(), LevelFilter::Debug);
+	}
+
+	#[test]
+	fn test_postgres_options() {
+		let mut settings = Settings::parse_from([
+			"barreleye",
+			"--database-schema",
+			"barreleye",
+			"--database-statement-timeout",
+			"30",
+		]);
+		assert!(Db::postgres_options(&settings).is_empty());
+
+		settings.database_driver = Driver::PostgreSQL;
+		assert_eq!(
+			Db::postgres_options(&settings),
+			vec![
+				("statement_timeout", "30s".to_string()),
+				("search_path", r#""barreleye""#.to_string())
+			]
+		);
+
+		// cockroachdb only gets the statement timeout
+		settings.database_driver = Driver::CockroachDB;
+		assert_eq!(Db::postgres_options(&settings), vec![("statement_timeout", "30s".to_string())]);
+		assert_eq!(
+			Db::with_postgres_options(&settings, "postgres://localhost:26257/db".to_string()),
+			"postgres://localhost:26257/db?options%5Bstatement_timeout%5D=30s"
+		);
+	}
+
+	/// Needs a PostgreSQL server, so it only runs when `BARRELEYE_TEST_POSTGRES_URI` points
+	/// at one (eg: `postgres://postgres@localhost:5432/barreleye_test`)
+	#[tokio::test]
+	async fn test_postgres_schema() -> Result<()> {
+		let Ok(uri) = std::env::var("BARRELEYE_TEST_POSTGRES_URI") else {
+			return Ok(());
+		};
+
+		let mut settings = Settings::parse_from(["barreleye", "--database-schema", "Barreleye"]);
+		settings.database_driver = Driver::PostgreSQL;
+		settings.database_uri = Some(Url::parse(&uri)?);
+
+		let db = Db::new(Arc::new(settings)).await?;
+		db.run_migrations().await?;
+
+		// migrations (and their own bookkeeping) land in the schema, case and all
+		let tables = db
+			.get()
+			.query_all(Statement::from_string(
+				DbBackend::Postgres,
+				r#"
+					SELECT table_name
+					FROM information_schema.tables
+					WHERE table_schema = 'Barreleye'
+				"#,
+			))
+			.await?
+			.into_iter()
+			.map(|row| row.try_get::<String>("", "table_name"))
+			.collect::<Result<HashSet<_>, _>>()?;
+		assert!(tables.contains("seaql_migrations"));
+		assert!(tables.contains("networks"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_set_ssl() {
+		let mut url = Url::parse("postgres://localhost:5432/barreleye").unwrap();
+		Driver::PostgreSQL.set_ssl(&mut url, SslMode::VerifyFull, Some(Path::new("/ca.pem")));
+		assert_eq!(
+			url.as_str(),
+			"postgres://localhost:5432/barreleye?sslmode=verify-full&sslrootcert=%2Fca.pem"
+		);
+
+		let mut url = Url::parse("mysql://localhost:3306/barreleye").unwrap();
+		Driver::MySQL.set_ssl(&mut url, SslMode::Require, None);
+		assert_eq!(url.as_str(), "mysql://localhost:3306/barreleye?ssl-mode=REQUIRED");
+
+		// ones already in the url are replaced rather than duplicated
+		let mut url = Url::parse(
+			"postgres://localhost:5432/barreleye?sslmode=disable&application_name=barreleye",
+		)
+		.unwrap();
+		Driver::PostgreSQL.set_ssl(&mut url, SslMode::Require, None);
+		assert_eq!(
+			url.as_str(),
+			"postgres://localhost:5432/barreleye?application_name=barreleye&sslmode=require"
+		);
+
+		let mut url = Url::parse("sqlite:///barreleye.db?mode=rwc").unwrap();
+		Driver::SQLite.set_ssl(&mut url, SslMode::VerifyFull, None);
+		assert_eq!(url.as_str(), "sqlite:///barreleye.db?mode=rwc");
+	}
+
+	#[test]
+	fn test_supports_statement_timeout() {
+		assert!(Driver::PostgreSQL.supports_statement_timeout());
+		assert!(Driver::CockroachDB.supports_statement_timeout());
+		assert!(!Driver::MySQL.supports_statement_timeout());
+		assert!(!Driver::SQLite.supports_statement_timeout());
+	}
+
+	#[test]
+	fn test_cockroachdb_create_database_statements() {
+		let statements = Driver::CockroachDB.create_database_statements("barreleye");
+
+		assert_eq!(statements.len(), 1);
+		assert_eq!(statements[0].db_backend, DbBackend::Postgres);
+		assert_eq!(statements[0].sql, r#"CREATE DATABASE IF NOT EXISTS "barreleye";"#);
+	}
 }
@@ -30,6 +30,12 @@ pub enum AppError<'a> {
 	#[display("indexing failed: {error}")]
 	Indexing { error: Cow<'a, str> },
 
+	#[display(
+		"block {block_height} is pruned on the {network} node; indexing historical ranges \
+		 requires an archive (non-pruned) node"
+	)]
+	PrunedBlock { network: Cow<'a, str>, block_height: u64 },
+
 	#[display("unexpected error: {error}")]
 	Unexpected { error: Cow<'a, str> },
 }
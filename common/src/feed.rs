@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::models::{PrimaryId, Transfer};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedEvent {
+	Transfer(Box<Transfer>),
+	Lagged(u64),
+}
+
+/// Broadcasts newly indexed transfers to live subscribers. Publishing never blocks: a
+/// subscriber that falls more than `capacity` transfers behind skips ahead and gets a
+/// `FeedEvent::Lagged` with the number of transfers it missed
+pub struct TransferFeed {
+	sender: broadcast::Sender<Transfer>,
+}
+
+impl TransferFeed {
+	pub fn new(capacity: usize) -> Self {
+		let (sender, _) = broadcast::channel(capacity.max(1));
+		Self { sender }
+	}
+
+	pub fn has_subscribers(&self) -> bool {
+		self.sender.receiver_count() > 0
+	}
+
+	pub fn publish(&self, transfers: impl IntoIterator<Item = Transfer>) {
+		for transfer in transfers.into_iter() {
+			// only fails when there are no subscribers
+			if self.sender.send(transfer).is_err() {
+				break;
+			}
+		}
+	}
+
+	pub fn subscribe(&self, network_ids: Option<HashSet<PrimaryId>>) -> TransferSubscription {
+		TransferSubscription { receiver: self.sender.subscribe(), network_ids }
+	}
+}
+
+pub struct TransferSubscription {
+	receiver: broadcast::Receiver<Transfer>,
+	network_ids: Option<HashSet<PrimaryId>>,
+}
+
+impl TransferSubscription {
+	pub async fn next(&mut self) -> Option<FeedEvent> {
+		loop {
+			match self.receiver.recv().await {
+				Ok(transfer) if self.is_match(&transfer) => {
+					return Some(FeedEvent::Transfer(Box::new(transfer)))
+				}
+				Ok(_) => continue,
+				Err(RecvError::Lagged(n)) => return Some(FeedEvent::Lagged(n)),
+				Err(RecvError::Closed) => return None,
+			}
+		}
+	}
+
+	fn is_match(&self, transfer: &Transfer) -> bool {
+		self.network_ids
+			.as_ref()
+			.is_none_or(|network_ids| network_ids.contains(&(transfer.network_id as PrimaryId)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain::{ModuleId, U256};
+
+	fn new_transfer(network_id: PrimaryId) -> Transfer {
+		Transfer::new(
+			ModuleId::BitcoinTransfer,
+			network_id,
+			1,
+			"tx",
+			"from",
+			"to",
+			None,
+			U256::from(1),
+			U256::from(1),
+			0,
+		)
+	}
+
+	#[tokio::test]
+	async fn test_subscribe() {
+		let feed = TransferFeed::new(10);
+		assert!(!feed.has_subscribers());
+
+		let mut all = feed.subscribe(None);
+		let mut filtered = feed.subscribe(Some(HashSet::from([2])));
+		assert!(feed.has_subscribers());
+
+		let (t1, t2) = (new_transfer(1), new_transfer(2));
+		feed.publish(vec![t1.clone(), t2.clone()]);
+
+		assert_eq!(all.next().await, Some(FeedEvent::Transfer(Box::new(t1))));
+		assert_eq!(all.next().await, Some(FeedEvent::Transfer(Box::new(t2.clone()))));
+		assert_eq!(filtered.next().await, Some(FeedEvent::Transfer(Box::new(t2))));
+	}
+
+	#[tokio::test]
+	async fn test_lagged() {
+		let feed = TransferFeed::new(1);
+		let mut subscription = feed.subscribe(None);
+
+		let t = new_transfer(1);
+		feed.publish(vec![new_transfer(1), t.clone()]);
+
+		assert_eq!(subscription.next().await, Some(FeedEvent::Lagged(1)));
+		assert_eq!(subscription.next().await, Some(FeedEvent::Transfer(Box::new(t))));
+	}
+}
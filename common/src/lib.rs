@@ -8,45 +8,90 @@ use governor::{
 	state::{direct::NotKeyed, InMemoryState},
 	RateLimiter as GovernorRateLimiter,
 };
+use schemars::JsonSchema;
 use sea_orm::{entity::prelude::*, DatabaseTransaction, TransactionTrait};
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	fmt::Debug,
+	future::Future,
+	pin::Pin,
 	process,
+	str::FromStr,
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
 	},
 };
-use tokio::sync::RwLock;
+use tokio::{signal, sync::RwLock};
 use tracing::{error, info, warn};
 
 use crate::{
 	chain::{Bitcoin, BoxedChain, Evm},
 	models::{Config, ConfigKey, Network, PrimaryId, SoftDeleteModel},
 };
+pub use cache::Cache;
 pub use db::Db;
 pub use errors::AppError;
+pub use feed::TransferFeed;
+pub use notifier::Notifier;
 pub use s3::{Service as S3Service, S3};
 pub use settings::Settings;
 pub use storage::Storage;
 pub use warehouse::Warehouse;
 
+pub mod cache;
 pub mod chain;
 pub mod db;
 pub mod errors;
+pub mod feed;
 pub mod models;
+pub mod notifier;
+pub mod progress;
 pub mod s3;
 pub mod settings;
 pub mod storage;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod utils;
 pub mod warehouse;
 
 mod banner;
 
+pub const API_KEY_LAST_USED_INTERVAL: i64 = 60;
+pub const DB_CHUNK_SIZE: usize = 1_000;
+pub const DB_TX_MAX_ATTEMPTS: usize = 5;
+pub const ID_MIN_LENGTH: usize = 8;
+pub const ID_DEFAULT_LENGTH: usize = 12;
+pub const ID_MAX_LENGTH: usize = 32;
+pub const ID_MAX_ATTEMPTS: usize = 5;
+pub const INFO_CACHE_TTL: i64 = 60;
+pub const INFO_ENTITIES_MAX: usize = 100;
+pub const INFO_SOURCES_DEFAULT_LIMIT: u64 = 100;
+pub const INFO_SOURCES_MAX_LIMIT: u64 = 1_000;
 pub const INDEXER_PROMOTION_TIMEOUT: u64 = 20;
 pub const INDEXER_HEARTBEAT_INTERVAL: u64 = 2;
+pub const INDEXER_MEMPOOL_BATCH_SIZE: usize = 100;
+pub const INDEXER_RECONNECT_INTERVAL: u64 = 30;
+pub const INDEXER_RETRY_MAX_BACKOFF: u64 = 60;
+pub const INDEXER_RULES_BATCH_SIZE: u64 = 1_000;
+pub const INDEXER_RULES_INTERVAL: u64 = 60;
+pub const INDEXER_SHUTDOWN_TIMEOUT: u64 = 30;
+pub const LINK_PATH_MAX_DEPTH: usize = 6;
+pub const IDEMPOTENCY_KEY_TTL: i64 = 86_400;
+pub const IDEMPOTENCY_KEYS_MAX: usize = 100_000;
+pub const PROGRESS_INTERVAL: u64 = 5;
+pub const PROGRESS_RATE_WINDOW: usize = 6;
+pub const PENDING_TRANSFERS_MAX_AGE: u64 = 1_209_600; // same as bitcoin core's mempool expiry
+pub const RPC_BATCH_SIZE: usize = 100;
+pub const TRANSFER_FEED_CAPACITY: usize = 10_000;
+pub const TRANSFERS_BY_UUIDS_CHUNK_SIZE: usize = 1_000;
+pub const TRANSFERS_DEFAULT_LIMIT: u64 = 100;
+pub const TRANSFERS_MAX_LIMIT: u64 = 1_000;
+pub const WEBHOOK_CONCURRENCY: usize = 10;
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+pub const WEBHOOK_QUEUE_CAPACITY: usize = 1_000;
+pub const WEBHOOK_TIMEOUT: u64 = 10;
 
 pub type BlockHeight = u64;
 pub type RateLimiter = GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>;
@@ -57,6 +102,12 @@ pub struct App {
 	pub networks: Arc<RwLock<HashMap<PrimaryId, Arc<BoxedChain>>>>,
 	pub settings: Arc<Settings>,
 	pub storage: Arc<Storage>,
+	pub cache: Arc<Cache>,
+	/// Claims and responses of `Idempotency-Key`s, kept apart from `cache` so they aren't
+	/// evicted by whatever else fills it up (eg: utxos while syncing)
+	pub idempotency_cache: Arc<Cache>,
+	pub feed: Arc<TransferFeed>,
+	pub notifier: Arc<Notifier>,
 	db: Arc<Db>,
 	pub warehouse: Arc<Warehouse>,
 	is_ready: Arc<AtomicBool>,
@@ -77,6 +128,14 @@ impl App {
 			networks: Arc::new(RwLock::new(HashMap::new())),
 			settings: settings.clone(),
 			storage,
+			cache: Arc::new(
+				Cache::new(settings.cache_size).with_hashed_keys(settings.hash_cache_keys),
+			),
+			idempotency_cache: Arc::new(
+				Cache::new(IDEMPOTENCY_KEYS_MAX).with_hashed_keys(settings.hash_cache_keys),
+			),
+			feed: Arc::new(TransferFeed::new(TRANSFER_FEED_CAPACITY)),
+			notifier: Arc::new(Notifier::new().with_numeric_amounts(settings.numeric_amounts)),
 			db,
 			warehouse,
 			is_ready: Arc::new(AtomicBool::new(false)),
@@ -114,23 +173,80 @@ impl App {
 		Ok(self.db().begin().await?)
 	}
 
+	/// See `Db::transaction_with_retry()`
+	pub async fn db_tx_with_retry<F, T>(&self, f: F) -> Result<T>
+	where
+		F: for<'c> Fn(
+				&'c DatabaseTransaction,
+			) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'c>>
+			+ Send
+			+ Sync,
+		T: Send,
+	{
+		self.db.transaction_with_retry(f).await
+	}
+
 	pub async fn get_networks(&self) -> Result<HashMap<PrimaryId, Arc<BoxedChain>>> {
 		let mut ret = HashMap::new();
 
 		for n in Network::get_all_existing(self.db(), Some(false)).await?.into_iter() {
-			let network_id = n.network_id;
+			ret.insert(n.network_id, Arc::new(self.new_chain(n)?));
+		}
+
+		Ok(ret)
+	}
+
+	/// Connects to a single network and registers it, leaving all the others untouched.
+	/// Returns `false` (without registering) if its rpc endpoint could not be reached
+	pub async fn add_network(&self, network: Network) -> Result<bool> {
+		let network_id = network.network_id;
+
+		let mut boxed_chain = self.new_chain(network)?;
+		if !boxed_chain.connect().await.unwrap_or(false) {
+			return Ok(false);
+		}
+
+		self.networks.write().await.insert(network_id, Arc::new(boxed_chain));
+
+		Ok(true)
+	}
+
+	/// Retries connecting the networks that aren't registered (eg: their rpc endpoint was down
+	/// when connecting), leaving the registered ones untouched. Returns whether any of them
+	/// got registered
+	pub async fn connect_missing_networks(&self) -> Result<bool> {
+		let registered = self.networks.read().await.keys().copied().collect::<HashSet<_>>();
+		let mut ret = false;
 
-			let boxed_chain: BoxedChain = match n.architecture {
-				Architecture::Bitcoin => Box::new(Bitcoin::new(n)),
-				Architecture::Evm => Box::new(Evm::new(n)),
-			};
+		for n in Network::get_all_existing(self.db(), Some(false)).await?.into_iter() {
+			if !registered.contains(&n.network_id) {
+				let (name, id) = (n.name.clone(), n.id.clone());
 
-			ret.insert(network_id, Arc::new(boxed_chain));
+				match self.add_network(n).await {
+					Ok(true) => {
+						info!("connected to {name} ({id})");
+						ret = true;
+					}
+					Ok(false) => warn!("could not connect to {name} ({id})"),
+					Err(e) => warn!("could not connect to {name} ({id}): {e}"),
+				}
+			}
 		}
 
 		Ok(ret)
 	}
 
+	fn new_chain(&self, n: Network) -> Result<BoxedChain> {
+		Ok(match n.architecture {
+			Architecture::Bitcoin => Box::new(
+				Bitcoin::new(n)?
+					.with_rpc_timeout(self.settings.rpc_timeout)
+					.with_rpc_concurrency(self.settings.rpc_concurrency),
+			),
+			Architecture::Evm => Box::new(Evm::new(n)),
+		})
+	}
+
 	pub async fn should_reconnect(&self) -> Result<bool> {
 		Ok(match *self.connected_at.read().await {
 			Some(connected_at) => {
@@ -156,11 +272,7 @@ impl App {
 					info!("connecting to {} ({})…", n.name, n.id);
 				}
 
-				let mut boxed_chain: BoxedChain = match n.architecture {
-					Architecture::Bitcoin => Box::new(Bitcoin::new(n.clone())),
-					Architecture::Evm => Box::new(Evm::new(n.clone())),
-				};
-
+				let mut boxed_chain = self.new_chain(n.clone())?;
 				if boxed_chain.connect().await? {
 					Ok(Arc::new(boxed_chain))
 				} else {
@@ -188,16 +300,17 @@ impl App {
 			}
 		}
 
-		if !failures.is_empty() {
-			return Err(Report::msg(failures.join("\n")));
-		}
-
+		// keep whatever did connect, so one bad rpc endpoint doesn't take down the rest
 		let mut networks = self.networks.write().await;
 		*networks = connected_networks;
 
 		let mut connected_at = self.connected_at.write().await;
 		*connected_at = Some(utils::now());
 
+		if !failures.is_empty() {
+			return Err(Report::msg(failures.join("\n")));
+		}
+
 		Ok(())
 	}
 
@@ -225,15 +338,16 @@ impl App {
 		Ok(())
 	}
 
+	/// Normalizes `address` for the network it belongs to (see `chain::normalize_address()`).
+	/// Addresses that aren't valid on any configured network are returned as they are
 	pub async fn format_address(&self, address: &str) -> Result<String> {
-		for (_, chain) in self.networks.read().await.iter() {
-			let formatted_address = chain.format_address(address);
-			if formatted_address != address {
-				return Ok(formatted_address);
-			}
-		}
+		Ok(self.format_valid_address(address).await.unwrap_or_else(|| address.to_string()))
+	}
 
-		Ok(address.to_string())
+	/// Like `format_address()`, but returns `None` if the address isn't valid on any of
+	/// the configured networks
+	pub async fn format_valid_address(&self, address: &str) -> Option<String> {
+		chain::normalize_address(self.networks.read().await.values(), address)
 	}
 }
 
@@ -251,6 +365,10 @@ pub enum IdPrefix {
 	Tag,
 	#[display("tok")]
 	Token,
+	#[display("whk")]
+	Webhook,
+	#[display("rul")]
+	Rule,
 }
 
 #[derive(
@@ -266,6 +384,7 @@ pub enum IdPrefix {
 	Serialize,
 	Deserialize,
 	EnumIter,
+	JsonSchema,
 )]
 #[sea_orm(rs_type = "i16", db_type = "SmallInteger")]
 #[serde(rename_all = "camelCase")]
@@ -276,7 +395,62 @@ pub enum RiskLevel {
 	Critical = 3,
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
+/// Risk score (0-100) a tag's risk level is worth, parsed from eg: `low=0,high=50,critical=100`
+/// (levels left out keep their default)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RiskWeights {
+	pub low: u8,
+	pub high: u8,
+	pub critical: u8,
+}
+
+impl Default for RiskWeights {
+	fn default() -> Self {
+		Self { low: 0, high: 50, critical: 100 }
+	}
+}
+
+impl RiskWeights {
+	pub fn get(&self, risk_level: RiskLevel) -> u8 {
+		match risk_level {
+			RiskLevel::Low => self.low,
+			RiskLevel::High => self.high,
+			RiskLevel::Critical => self.critical,
+		}
+	}
+}
+
+impl FromStr for RiskWeights {
+	type Err = AppError<'static>;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let config_error =
+			|error: String| AppError::Config { config: "risk_weights".into(), error: error.into() };
+
+		let mut ret = Self::default();
+		for pair in s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+			let (risk_level, weight) = pair
+				.split_once('=')
+				.ok_or_else(|| config_error(format!("expected `level=weight`, got `{pair}`")))?;
+
+			let weight =
+				weight.trim().parse::<u8>().ok().filter(|w| *w <= 100).ok_or_else(|| {
+					config_error(format!("weight must be between 0 and 100: {pair}"))
+				})?;
+
+			match risk_level.trim() {
+				"low" => ret.low = weight,
+				"high" => ret.high = weight,
+				"critical" => ret.critical = weight,
+				v => return Err(config_error(format!("unknown risk level `{v}`"))),
+			}
+		}
+
+		Ok(ret)
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum RiskReason {
 	Entity,
@@ -294,6 +468,32 @@ pub enum Architecture {
 	Evm = 2,
 }
 
+/// How a Bitcoin transaction's inputs are matched up with its outputs (ie: who paid whom).
+/// There's no way to tell from the chain, so it's a heuristic analysts pick per network
+#[derive(
+	Default, Debug, DeriveActiveEnum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, EnumIter,
+)]
+#[sea_orm(rs_type = "i16", db_type = "SmallInteger")]
+#[serde(rename_all = "camelCase")]
+pub enum Attribution {
+	/// Every input pays every output, in proportion to its share of the inputs
+	#[default]
+	Proportional = 1,
+	/// Inputs pay outputs in order, each input's value going to the next unpaid output(s)
+	Fifo = 2,
+}
+
+#[derive(
+	Default, Debug, DeriveActiveEnum, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, EnumIter,
+)]
+#[sea_orm(rs_type = "i16", db_type = "SmallInteger")]
+#[serde(rename_all = "camelCase")]
+pub enum RuleKind {
+	/// Addresses that share a cluster with one of the entity's addresses
+	#[default]
+	Cluster = 1,
+}
+
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Mode {
@@ -317,6 +517,26 @@ impl ValueEnum for Mode {
 	}
 }
 
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+	#[default]
+	Pretty,
+	Json,
+}
+
+impl ValueEnum for LogFormat {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Pretty, Self::Json]
+	}
+
+	fn to_possible_value<'a>(&self) -> Option<PossibleValue> {
+		match self {
+			Self::Pretty => Some(PossibleValue::new("pretty")),
+			Self::Json => Some(PossibleValue::new("json")),
+		}
+	}
+}
+
 pub fn quit(app_error: AppError) -> ! {
 	error!("{}", app_error.to_string());
 
@@ -326,3 +546,30 @@ pub fn quit(app_error: AppError) -> ! {
 		_ => exitcode::UNAVAILABLE,
 	})
 }
+
+/// Resolves on SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+	let ctrl_c = async {
+		if signal::ctrl_c().await.is_err() {
+			quit(AppError::SignalHandler);
+		}
+	};
+
+	#[cfg(unix)]
+	let terminate = async {
+		match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+			Ok(mut signal) => {
+				signal.recv().await;
+			}
+			_ => quit(AppError::SignalHandler),
+		};
+	};
+
+	#[cfg(not(unix))]
+	let terminate = std::future::pending::<()>();
+
+	tokio::select! {
+		_ = ctrl_c => {},
+		_ = terminate => {},
+	}
+}
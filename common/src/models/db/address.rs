@@ -11,7 +11,7 @@ use std::collections::HashSet;
 
 use crate::{
 	models::{db::entity, BasicModel, EntityColumn, PrimaryId, PrimaryIds, SoftDeleteModel},
-	utils, IdPrefix,
+	utils, IdPrefix, DB_CHUNK_SIZE, ID_DEFAULT_LENGTH,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -30,6 +30,8 @@ pub struct Model {
 	pub address: String,
 	pub description: String,
 	pub data: Json,
+	/// Locked addresses can't be deleted
+	pub is_locked: bool,
 	#[serde(skip_serializing)]
 	pub is_deleted: bool,
 	#[sea_orm(nullable)]
@@ -83,10 +85,11 @@ impl Model {
 			entity_id: Set(entity_id),
 			network_id: Set(network_id),
 			network: Set(network.to_string()),
-			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Address))),
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Address, ID_DEFAULT_LENGTH))),
 			address: Set(address.to_string()),
 			description: Set(description.to_string()),
 			data: Set(data.unwrap_or(json!({}))),
+			is_locked: Set(false),
 			is_deleted: Set(false),
 			..Default::default()
 		}
@@ -117,13 +120,18 @@ impl Model {
 		addresses.sort_unstable();
 		addresses.dedup();
 
-		let mut q = Entity::find().filter(Column::Address.is_in(addresses));
+		let mut ret = vec![];
+		for chunk in addresses.chunks(DB_CHUNK_SIZE) {
+			let mut q = Entity::find().filter(Column::Address.is_in(chunk.to_vec()));
 
-		if let Some(is_deleted) = is_deleted {
-			q = q.filter(Column::IsDeleted.eq(is_deleted))
+			if let Some(is_deleted) = is_deleted {
+				q = q.filter(Column::IsDeleted.eq(is_deleted))
+			}
+
+			ret.extend(q.all(c).await?);
 		}
 
-		Ok(q.all(c).await?)
+		Ok(ret)
 	}
 
 	pub async fn get_all_by_entity_ids<C>(
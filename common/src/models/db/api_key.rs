@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
 	models::{BasicModel, PrimaryId},
-	utils, IdPrefix,
+	utils, IdPrefix, API_KEY_LAST_USED_INTERVAL, ID_DEFAULT_LENGTH,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -27,6 +27,9 @@ pub struct Model {
 	#[sea_orm(nullable)]
 	#[serde(skip_serializing)]
 	pub updated_at: Option<DateTime>,
+	/// Throttled, so it can be up to `API_KEY_LAST_USED_INTERVAL` seconds behind
+	#[sea_orm(nullable)]
+	pub last_used_at: Option<DateTime>,
 	pub created_at: DateTime,
 
 	#[sea_orm(ignore)]
@@ -50,7 +53,7 @@ impl Model {
 		let (secret_key, secret_key_hash) = Self::generate_key();
 
 		ActiveModel {
-			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::ApiKey))),
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::ApiKey, ID_DEFAULT_LENGTH))),
 			secret_key: Set(Some(format!("sk_{secret_key}"))),
 			secret_key_hash: Set(secret_key_hash),
 			is_active: Set(true),
@@ -98,6 +101,51 @@ impl Model {
 		(format!("sk_{secret_key}"), secret_key_hash)
 	}
 
+	pub async fn rotate_key<C>(c: &C, api_key_id: PrimaryId) -> Result<()>
+	where
+		C: ConnectionTrait,
+	{
+		let (secret_key, secret_key_hash) = Self::generate_key();
+
+		// single update, so the old secret stops working the moment the new one is stored
+		Entity::update(ActiveModel {
+			api_key_id: Set(api_key_id),
+			secret_key: Set(Some(secret_key)),
+			secret_key_hash: Set(secret_key_hash),
+			updated_at: Set(Some(utils::now())),
+			..Default::default()
+		})
+		.exec(c)
+		.await?;
+
+		Ok(())
+	}
+
+	/// Records that the key was just used, unless that was already done within the last
+	/// `API_KEY_LAST_USED_INTERVAL` seconds, so authenticating doesn't write on every request.
+	/// Returns whether it wrote
+	pub async fn touch<C>(c: &C, api_key: &Self) -> Result<bool>
+	where
+		C: ConnectionTrait,
+	{
+		let now = utils::now();
+		if api_key.last_used_at.is_some_and(|last_used_at| {
+			(now - last_used_at).num_seconds() < API_KEY_LAST_USED_INTERVAL
+		}) {
+			return Ok(false);
+		}
+
+		Entity::update(ActiveModel {
+			api_key_id: Set(api_key.api_key_id),
+			last_used_at: Set(Some(now)),
+			..Default::default()
+		})
+		.exec(c)
+		.await?;
+
+		Ok(true)
+	}
+
 	pub async fn hide_key<C>(c: &C, api_key_id: PrimaryId) -> Result<()>
 	where
 		C: ConnectionTrait,
@@ -114,3 +162,59 @@ impl Model {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Db;
+
+	#[tokio::test]
+	async fn test_rotate_key() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let api_key_id = ApiKey::create(db.get(), ApiKey::new_model(None)).await?;
+		let old_key =
+			<ApiKey as BasicModel>::get(db.get(), api_key_id).await?.unwrap().secret_key.unwrap();
+		assert!(ApiKey::get_by_hashing(db.get(), &old_key).await?.is_some());
+
+		ApiKey::rotate_key(db.get(), api_key_id).await?;
+		let rotated = <ApiKey as BasicModel>::get(db.get(), api_key_id).await?.unwrap();
+		let new_key = rotated.secret_key.clone().unwrap();
+
+		assert!(ApiKey::get_by_hashing(db.get(), &old_key).await?.is_none());
+		assert_eq!(ApiKey::get_by_hashing(db.get(), &new_key).await?, Some(rotated));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_touch() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let api_key_id = ApiKey::create(db.get(), ApiKey::new_model(None)).await?;
+		let get_api_key = || async {
+			<ApiKey as BasicModel>::get(db.get(), api_key_id).await.map(|k| k.unwrap())
+		};
+		assert!(get_api_key().await?.last_used_at.is_none());
+
+		// first use is recorded
+		assert!(ApiKey::touch(db.get(), &get_api_key().await?).await?);
+		let last_used_at = get_api_key().await?.last_used_at;
+		assert!(last_used_at.is_some());
+
+		// rapid reuse isn't
+		for _ in 0..5 {
+			assert!(!ApiKey::touch(db.get(), &get_api_key().await?).await?);
+		}
+		assert_eq!(get_api_key().await?.last_used_at, last_used_at);
+
+		// until the interval has passed
+		let mut api_key = get_api_key().await?;
+		api_key.last_used_at =
+			last_used_at.map(|t| t - chrono::Duration::seconds(API_KEY_LAST_USED_INTERVAL));
+		assert!(ApiKey::touch(db.get(), &api_key).await?);
+		assert!(get_api_key().await?.last_used_at > api_key.last_used_at);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,332 @@
+use eyre::Result;
+use sea_orm::{
+	entity::{prelude::*, *},
+	ConnectionTrait, QueryOrder, QuerySelect,
+};
+use sea_orm_migration::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+	models::{BasicModel, PrimaryId, PrimaryIds},
+	DB_CHUNK_SIZE,
+};
+
+/// Address clusters (common-input-ownership): addresses spent together as inputs of the
+/// same transaction are assumed to share an owner. Every address points at its cluster's
+/// label (one of its addresses), so merging clusters is a relabel of the smaller ones
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
+#[sea_orm(table_name = "clusters")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	#[serde(skip_serializing, skip_deserializing)]
+	pub cluster_address_id: PrimaryId,
+	#[serde(skip_serializing)]
+	pub network_id: PrimaryId,
+	pub address: String,
+	pub cluster: String,
+	#[sea_orm(nullable)]
+	#[serde(skip_serializing)]
+	pub updated_at: Option<DateTime>,
+	pub created_at: DateTime,
+}
+
+pub use ActiveModel as ClusterActiveModel;
+pub use Model as Cluster;
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl BasicModel for Model {
+	type ActiveModel = ActiveModel;
+}
+
+impl Model {
+	pub fn new_model(network_id: PrimaryId, address: &str, cluster: &str) -> ActiveModel {
+		ActiveModel {
+			network_id: Set(network_id),
+			address: Set(address.to_string()),
+			cluster: Set(cluster.to_string()),
+			..Default::default()
+		}
+	}
+
+	/// Puts each of the co-spent address `sets` in one cluster, merging whichever clusters
+	/// they already belong to. Overlapping sets are joined up front, so every resulting
+	/// cluster is only merged once per call
+	pub async fn merge<'a, C, I>(c: &C, network_id: PrimaryId, sets: I) -> Result<()>
+	where
+		C: ConnectionTrait,
+		I: IntoIterator<Item = &'a BTreeSet<String>>,
+	{
+		for addresses in Self::join_overlapping(sets).into_iter() {
+			Self::merge_one(c, network_id, &addresses).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Union-find over the sets' addresses, returning the connected groups (of 2+)
+	fn join_overlapping<'a, I>(sets: I) -> Vec<BTreeSet<String>>
+	where
+		I: IntoIterator<Item = &'a BTreeSet<String>>,
+	{
+		fn find(parents: &mut [usize], mut i: usize) -> usize {
+			while parents[i] != i {
+				parents[i] = parents[parents[i]];
+				i = parents[i];
+			}
+			i
+		}
+
+		let mut indexes = HashMap::<&str, usize>::new();
+		let mut parents = vec![];
+		for addresses in sets.into_iter() {
+			let mut root = None;
+			for address in addresses.iter() {
+				let i = *indexes.entry(address).or_insert_with(|| {
+					parents.push(parents.len());
+					parents.len() - 1
+				});
+
+				let i = find(&mut parents, i);
+				match root {
+					Some(root) => parents[i] = root,
+					None => root = Some(i),
+				}
+			}
+		}
+
+		let mut groups = HashMap::<usize, BTreeSet<String>>::new();
+		for (address, i) in indexes.into_iter() {
+			groups.entry(find(&mut parents, i)).or_default().insert(address.to_string());
+		}
+
+		groups.into_values().filter(|addresses| addresses.len() > 1).collect()
+	}
+
+	/// Relabels the smaller clusters into the largest one (union by size), so an address is
+	/// relabeled at most `log2(n)` times over the lifetime of a cluster of `n` addresses
+	async fn merge_one<C>(c: &C, network_id: PrimaryId, addresses: &BTreeSet<String>) -> Result<()>
+	where
+		C: ConnectionTrait,
+	{
+		let addresses = addresses.iter().cloned().collect::<Vec<_>>();
+
+		let mut existing = vec![];
+		for chunk in addresses.chunks(DB_CHUNK_SIZE) {
+			existing.extend(
+				Entity::find()
+					.filter(Column::NetworkId.eq(network_id))
+					.filter(Column::Address.is_in(chunk.iter().cloned()))
+					.all(c)
+					.await?,
+			);
+		}
+
+		// brand new clusters are labeled after their smallest address
+		let clusters = existing.iter().map(|m| m.cluster.clone()).collect::<BTreeSet<_>>();
+		let mut sizes = vec![];
+		for chunk in clusters.iter().cloned().collect::<Vec<_>>().chunks(DB_CHUNK_SIZE) {
+			sizes.extend(
+				Entity::find()
+					.select_only()
+					.column(Column::Cluster)
+					.column_as(Column::ClusterAddressId.count(), "count")
+					.filter(Column::NetworkId.eq(network_id))
+					.filter(Column::Cluster.is_in(chunk.iter().cloned()))
+					.group_by(Column::Cluster)
+					.into_tuple::<(String, i64)>()
+					.all(c)
+					.await?,
+			);
+		}
+		let label = match sizes.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))) {
+			Some((label, _)) => label,
+			_ => addresses[0].clone(),
+		};
+
+		let stale_clusters = clusters.into_iter().filter(|c| *c != label).collect::<Vec<_>>();
+		for chunk in stale_clusters.chunks(DB_CHUNK_SIZE) {
+			Self::update_all_where(
+				c,
+				Condition::all()
+					.add(Column::NetworkId.eq(network_id))
+					.add(Column::Cluster.is_in(chunk.iter().cloned())),
+				ActiveModel { cluster: Set(label.clone()), ..Default::default() },
+			)
+			.await?;
+		}
+
+		let known = existing.into_iter().map(|m| m.address).collect::<HashSet<_>>();
+		let new_addresses = addresses
+			.iter()
+			.filter(|a| !known.contains(*a))
+			.map(|a| Self::new_model(network_id, a, &label))
+			.collect::<Vec<_>>();
+
+		for chunk in new_addresses.chunks(DB_CHUNK_SIZE) {
+			Entity::insert_many(chunk.to_vec())
+				.on_conflict(
+					OnConflict::columns([Column::NetworkId, Column::Address])
+						.do_nothing()
+						.to_owned(),
+				)
+				.do_nothing()
+				.exec(c)
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	/// Members of the cluster `address` belongs to on `network_id`, sorted by address
+	pub async fn get_all_paginated_by_address<C>(
+		c: &C,
+		network_id: PrimaryId,
+		address: &str,
+		offset: Option<u64>,
+		limit: Option<u64>,
+	) -> Result<Vec<Self>>
+	where
+		C: ConnectionTrait,
+	{
+		let Some(m) = Entity::find()
+			.filter(Column::NetworkId.eq(network_id))
+			.filter(Column::Address.eq(address))
+			.one(c)
+			.await?
+		else {
+			return Ok(vec![]);
+		};
+
+		Self::get_all_sorted_paginated_where(
+			c,
+			Condition::all()
+				.add(Column::NetworkId.eq(network_id))
+				.add(Column::Cluster.eq(m.cluster)),
+			Column::Address,
+			Order::Asc,
+			offset,
+			limit,
+		)
+		.await
+	}
+
+	/// Every address sharing a cluster with `address`, across all networks it was seen on
+	pub async fn get_all_by_address<C>(c: &C, address: &str) -> Result<Vec<Self>>
+	where
+		C: ConnectionTrait,
+	{
+		let clusters = Entity::find().filter(Column::Address.eq(address)).all(c).await?;
+		if clusters.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let mut condition = Condition::any();
+		for m in clusters.into_iter() {
+			condition = condition.add(
+				Condition::all()
+					.add(Column::NetworkId.eq(m.network_id))
+					.add(Column::Cluster.eq(m.cluster)),
+			);
+		}
+
+		Ok(Entity::find()
+			.filter(condition)
+			.order_by_asc(Column::NetworkId)
+			.order_by_asc(Column::Address)
+			.all(c)
+			.await?)
+	}
+
+	pub async fn delete_all_by_network_ids<C>(c: &C, network_ids: PrimaryIds) -> Result<u64>
+	where
+		C: ConnectionTrait,
+	{
+		Self::delete_all_where(c, Column::NetworkId.is_in(network_ids)).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{testing, Db};
+
+	async fn merge(db: &Db, network_id: PrimaryId, addresses: &[&str]) -> Result<()> {
+		let addresses = addresses.iter().map(|a| a.to_string()).collect();
+		Cluster::merge(db.get(), network_id, [&addresses]).await
+	}
+
+	async fn get_members(db: &Db, address: &str) -> Result<Vec<(String, String)>> {
+		Ok(Cluster::get_all_by_address(db.get(), address)
+			.await?
+			.into_iter()
+			.map(|m| (m.address, m.cluster))
+			.collect())
+	}
+
+	#[tokio::test]
+	async fn test_merge() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network_id = testing::create_network(db.get(), "net_a", 0).await?.network_id;
+
+		// a lone input says nothing about ownership
+		merge(&db, network_id, &["a"]).await?;
+		assert!(get_members(&db, "a").await?.is_empty());
+
+		merge(&db, network_id, &["c", "b"]).await?;
+		merge(&db, network_id, &["e", "d"]).await?;
+		assert_eq!(
+			get_members(&db, "c").await?,
+			[("b", "b"), ("c", "b")].map(|(a, c)| (a.to_string(), c.to_string()))
+		);
+
+		// spending from both clusters at once joins them
+		merge(&db, network_id, &["c", "e"]).await?;
+		let members = get_members(&db, "d").await?;
+		assert_eq!(members.len(), 4);
+		assert!(members.iter().all(|(_, cluster)| cluster == "b"));
+		assert_eq!(members, get_members(&db, "b").await?);
+
+		assert!(get_members(&db, "unknown").await?.is_empty());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_merge_many() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network_id = testing::create_network(db.get(), "net_a", 0).await?.network_id;
+		let sets = |sets: &[&[&str]]| -> Vec<BTreeSet<String>> {
+			sets.iter().map(|set| set.iter().map(|a| a.to_string()).collect()).collect()
+		};
+
+		// overlapping sets of a batch end up in one cluster
+		Cluster::merge(db.get(), network_id, &sets(&[&["x", "y"], &["z", "y"], &["a", "b"]]))
+			.await?;
+		assert_eq!(get_members(&db, "x").await?.len(), 3);
+		assert_eq!(get_members(&db, "a").await?.len(), 2);
+
+		// the smaller cluster is the one relabeled, even with the smaller label
+		merge(&db, network_id, &["a", "z"]).await?;
+		let members = get_members(&db, "b").await?;
+		assert_eq!(members.len(), 5);
+		assert!(members.iter().all(|(_, cluster)| cluster == "x"));
+
+		let page =
+			Cluster::get_all_paginated_by_address(db.get(), network_id, "y", Some(1), Some(2))
+				.await?
+				.into_iter()
+				.map(|m| m.address)
+				.collect::<Vec<_>>();
+		assert_eq!(page, ["b", "x"]);
+
+		Ok(())
+	}
+}
@@ -34,6 +34,8 @@ pub enum ConfigKey {
 	IndexerProcessModuleDone(PrimaryId, u16),
 	#[display("indexer_process_progress_n{_0}")]
 	IndexerProcessProgress(PrimaryId),
+	#[display("indexer_process_errors_n{_0}")]
+	IndexerProcessErrors(PrimaryId),
 	#[display("indexer_link_n{_0}_a{_1}")]
 	IndexerLink(PrimaryId, PrimaryId),
 	#[display("block_height_n{_0}")]
@@ -42,6 +44,10 @@ pub enum ConfigKey {
 	NetworksUpdated,
 	#[display("newly_added_address_n{_0}_a{_1}")]
 	NewlyAddedAddress(PrimaryId, PrimaryId),
+	#[display("indexer_paused_n{_0}")]
+	IndexerPaused(PrimaryId),
+	#[display("info_updated")]
+	InfoUpdated,
 }
 
 impl From<String> for ConfigKey {
@@ -69,10 +75,13 @@ impl From<String> for ConfigKey {
 				Self::IndexerProcessModuleDone(n[0], n[1] as u16)
 			}
 			"indexer_process_progress_n{}" if n.len() == 1 => Self::IndexerProcessProgress(n[0]),
+			"indexer_process_errors_n{}" if n.len() == 1 => Self::IndexerProcessErrors(n[0]),
 			"indexer_link_n{}_a{}" if n.len() == 2 => Self::IndexerLink(n[0], n[1]),
 			"block_height_n{}" if n.len() == 1 => Self::BlockHeight(n[0]),
 			"networks_updated" => Self::NetworksUpdated,
 			"newly_added_address_n{}_a{}" if n.len() == 2 => Self::NewlyAddedAddress(n[0], n[1]),
+			"indexer_paused_n{}" if n.len() == 1 => Self::IndexerPaused(n[0]),
+			"info_updated" => Self::InfoUpdated,
 			_ => panic!("no match in From<String> for ConfigKey: {s:?}"),
 		}
 	}
@@ -97,10 +106,13 @@ mod tests {
 				"indexer_process_module_done_n123_m456",
 			),
 			(ConfigKey::IndexerProcessProgress(123), "indexer_process_progress_n123"),
+			(ConfigKey::IndexerProcessErrors(123), "indexer_process_errors_n123"),
 			(ConfigKey::IndexerLink(123, 456), "indexer_link_n123_a456"),
 			(ConfigKey::BlockHeight(123), "block_height_n123"),
 			(ConfigKey::NetworksUpdated, "networks_updated"),
 			(ConfigKey::NewlyAddedAddress(123, 456), "newly_added_address_n123_a456"),
+			(ConfigKey::IndexerPaused(123), "indexer_paused_n123"),
+			(ConfigKey::InfoUpdated, "info_updated"),
 		]);
 
 		for (config_key, config_key_str) in config_keys.into_iter() {
@@ -108,6 +120,21 @@ mod tests {
 			assert_eq!(Into::<ConfigKey>::into(config_key_str.to_string()), config_key);
 		}
 	}
+
+	#[tokio::test]
+	async fn test_info_version() -> Result<()> {
+		let db = crate::Db::new_in_memory().await?;
+		assert_eq!(Config::get_info_version(db.get()).await?, "");
+
+		Config::bump_info_version(db.get()).await?;
+		let version = Config::get_info_version(db.get()).await?;
+		assert!(!version.is_empty());
+
+		Config::bump_info_version(db.get()).await?;
+		assert_ne!(Config::get_info_version(db.get()).await?, version);
+
+		Ok(())
+	}
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -222,6 +249,27 @@ impl Model {
 		}))
 	}
 
+	/// Version cached info responses are keyed by (see `CacheKey::Info`), so a change made
+	/// by any process (eg: the indexer, when http runs separately) makes them stale
+	pub async fn get_info_version<C>(c: &C) -> Result<String>
+	where
+		C: ConnectionTrait,
+	{
+		Ok(Self::get::<_, String>(c, ConfigKey::InfoUpdated)
+			.await?
+			.map(|v| v.value)
+			.unwrap_or_default())
+	}
+
+	/// Makes every cached info response stale. Versions are unique rather than incremented,
+	/// so two processes bumping at once can't end up on the same one
+	pub async fn bump_info_version<C>(c: &C) -> Result<()>
+	where
+		C: ConnectionTrait,
+	{
+		Self::set::<_, String>(c, ConfigKey::InfoUpdated, utils::new_uuid().to_string()).await
+	}
+
 	pub async fn get_many<C, T>(
 		c: &C,
 		mut keys: Vec<ConfigKey>,
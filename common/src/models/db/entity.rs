@@ -1,4 +1,5 @@
 use eyre::Result;
+use schemars::JsonSchema;
 use sea_orm::{
 	entity::prelude::*,
 	sea_query::{func::Func, Expr},
@@ -11,7 +12,7 @@ use std::collections::HashSet;
 
 use crate::{
 	models::{db::entity_tag, BasicModel, EntityTagColumn, PrimaryId, PrimaryIds, SoftDeleteModel},
-	utils, IdPrefix,
+	utils, IdPrefix, RiskLevel, RiskWeights, ID_DEFAULT_LENGTH,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -39,6 +40,9 @@ pub struct Model {
 	#[sea_orm(ignore)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub addresses: Option<Vec<String>>,
+	#[sea_orm(ignore)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub risk_score: Option<u8>,
 }
 
 impl From<Vec<Model>> for PrimaryIds {
@@ -74,6 +78,7 @@ impl From<JoinedModel> for Model {
 			created_at: m.created_at,
 			tags: None,
 			addresses: None,
+			risk_score: None,
 		}
 	}
 }
@@ -89,7 +94,7 @@ impl From<Vec<JoinedModel>> for PrimaryIds {
 	}
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SanitizedEntity {
 	pub id: String,
@@ -97,6 +102,7 @@ pub struct SanitizedEntity {
 	pub description: String,
 	pub data: Json,
 	pub tags: Option<Vec<String>>,
+	pub risk_score: Option<u8>,
 }
 
 impl From<Model> for SanitizedEntity {
@@ -107,6 +113,7 @@ impl From<Model> for SanitizedEntity {
 			description: m.description,
 			data: m.data,
 			tags: m.tags,
+			risk_score: m.risk_score,
 		}
 	}
 }
@@ -143,7 +150,7 @@ impl Model {
 		data: Option<Json>,
 	) -> ActiveModel {
 		ActiveModel {
-			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Entity))),
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Entity, ID_DEFAULT_LENGTH))),
 			name: Set(name),
 			description: Set(description.to_string()),
 			data: Set(data.unwrap_or(json!({}))),
@@ -152,6 +159,15 @@ impl Model {
 		}
 	}
 
+	/// 0-100 score from the risk levels of the entity's tags, weighed by `risk_weights`. The
+	/// riskiest tag decides, so piling up low-risk tags never adds up to a high-risk one
+	pub fn risk_score(
+		risk_levels: impl IntoIterator<Item = RiskLevel>,
+		risk_weights: &RiskWeights,
+	) -> u8 {
+		risk_levels.into_iter().map(|v| risk_weights.get(v)).max().unwrap_or(0)
+	}
+
 	pub async fn get_by_name<C>(c: &C, name: &str, is_deleted: Option<bool>) -> Result<Option<Self>>
 	where
 		C: ConnectionTrait,
@@ -168,6 +184,38 @@ impl Model {
 		Ok(q.one(c).await?)
 	}
 
+	/// Another active entity already using `name` (case-insensitive). Soft-deleted ones don't
+	/// count, see `release_deleted_name()`. Shared by create & update so both follow the same
+	/// policy
+	pub async fn get_name_conflict<C>(
+		c: &C,
+		name: &str,
+		except_entity_id: Option<PrimaryId>,
+	) -> Result<Option<Self>>
+	where
+		C: ConnectionTrait,
+	{
+		Ok(Self::get_by_name(c, name, Some(false))
+			.await?
+			.filter(|e| Some(e.entity_id) != except_entity_id))
+	}
+
+	/// Takes `name` off soft-deleted entities, so an active one can reuse it right away
+	/// (names are unique across the table, and lookups by name shouldn't match two rows)
+	pub async fn release_deleted_name<C>(c: &C, name: &str) -> Result<u64>
+	where
+		C: ConnectionTrait,
+	{
+		Self::update_all_where(
+			c,
+			Condition::all().add(Column::IsDeleted.eq(true)).add(
+				Expr::expr(Func::lower(Expr::col(Column::Name))).eq(name.trim().to_lowercase()),
+			),
+			ActiveModel { name: Set(None), ..Default::default() },
+		)
+		.await
+	}
+
 	pub async fn get_all_by_entity_ids<C>(
 		c: &C,
 		entity_ids: PrimaryIds,
@@ -205,3 +253,110 @@ impl Model {
 		Ok(q.into_model::<JoinedModel>().all(c).await?)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		models::{EntityTag, Tag},
+		Db,
+	};
+	use std::str::FromStr;
+
+	#[tokio::test]
+	async fn test_get_name_conflict() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let entity_id = LabeledEntity::create(
+			db.get(),
+			LabeledEntity::new_model(None, Some("Alpha".into()), "", None),
+		)
+		.await?;
+		let entity = <LabeledEntity as BasicModel>::get(db.get(), entity_id).await?.unwrap();
+
+		// active names collide (case-insensitively), except with the entity itself
+		assert_eq!(
+			LabeledEntity::get_name_conflict(db.get(), "alpha", None).await?,
+			Some(entity.clone())
+		);
+		assert_eq!(
+			LabeledEntity::get_name_conflict(db.get(), "alpha", Some(entity_id)).await?,
+			None
+		);
+		assert_eq!(LabeledEntity::get_name_conflict(db.get(), "beta", None).await?, None);
+
+		// soft-deleted names are free to reuse...
+		LabeledEntity::update_by_id(
+			db.get(),
+			&entity.id,
+			ActiveModel { is_deleted: Set(true), ..Default::default() },
+		)
+		.await?;
+		assert_eq!(LabeledEntity::get_name_conflict(db.get(), "alpha", None).await?, None);
+
+		// ...once released by the deleted entity
+		assert_eq!(LabeledEntity::release_deleted_name(db.get(), "ALPHA").await?, 1);
+		let new_entity_id = LabeledEntity::create(
+			db.get(),
+			LabeledEntity::new_model(None, Some("Alpha".into()), "", None),
+		)
+		.await?;
+		assert_eq!(
+			LabeledEntity::get_by_name(db.get(), "alpha", None).await?.map(|e| e.entity_id),
+			Some(new_entity_id)
+		);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_risk_score() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let sanctioned =
+			Tag::create(db.get(), Tag::new_model(None, "Sanctioned", RiskLevel::Critical)).await?;
+		let exchange =
+			Tag::create(db.get(), Tag::new_model(None, "Exchange", RiskLevel::Low)).await?;
+
+		let entity_a =
+			LabeledEntity::create(db.get(), LabeledEntity::new_model(None, None, "", None)).await?;
+		let entity_b =
+			LabeledEntity::create(db.get(), LabeledEntity::new_model(None, None, "", None)).await?;
+
+		EntityTag::create_many(
+			db.get(),
+			vec![
+				EntityTag::new_model(entity_a, sanctioned),
+				EntityTag::new_model(entity_a, exchange),
+				EntityTag::new_model(entity_b, exchange),
+			],
+		)
+		.await?;
+
+		let joined_tags =
+			Tag::get_all_by_entity_ids(db.get(), vec![entity_a, entity_b].into()).await?;
+		let score = |entity_id: PrimaryId, risk_weights: &RiskWeights| {
+			LabeledEntity::risk_score(
+				joined_tags.iter().filter(|t| t.entity_id == entity_id).map(|t| t.risk_level),
+				risk_weights,
+			)
+		};
+
+		let risk_weights = RiskWeights::default();
+		assert_eq!(score(entity_a, &risk_weights), 100);
+		assert_eq!(score(entity_b, &risk_weights), 0);
+		assert_eq!(LabeledEntity::risk_score([RiskLevel::Low; 0], &risk_weights), 0);
+
+		// weights are configurable, and the riskiest tag still wins
+		let risk_weights = RiskWeights::from_str("low=30, critical=90")?;
+		assert_eq!(risk_weights, RiskWeights { low: 30, high: 50, critical: 90 });
+		assert_eq!(score(entity_a, &risk_weights), 90);
+		assert_eq!(score(entity_b, &risk_weights), 30);
+
+		assert!(RiskWeights::from_str("low=101").is_err());
+		assert!(RiskWeights::from_str("severe=10").is_err());
+		assert!(RiskWeights::from_str("low").is_err());
+
+		Ok(())
+	}
+}
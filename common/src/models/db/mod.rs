@@ -1,5 +1,6 @@
 pub use address::{Address, AddressActiveModel, Column as AddressColumn};
 pub use api_key::{ApiKey, ApiKeyActiveModel, Column as ApiKeyColumn};
+pub use cluster::{Cluster, ClusterActiveModel, Column as ClusterColumn};
 pub use config::{Config, ConfigKey};
 pub use entity::{
 	Column as EntityColumn, JoinedEntity, LabeledEntity as Entity,
@@ -7,14 +8,25 @@ pub use entity::{
 };
 pub use entity_tag::{Column as EntityTagColumn, EntityTag};
 pub use network::{Column as NetworkColumn, Network, NetworkActiveModel, SanitizedNetwork};
+pub use pending_transfer::{
+	Column as PendingTransferColumn, PendingTransfer, PendingTransferActiveModel,
+};
+pub use rule::{Column as RuleColumn, Rule, RuleActiveModel, RuleProposal};
+pub use rule_rejection::{Column as RuleRejectionColumn, RuleRejection, RuleRejectionActiveModel};
 pub use tag::{Column as TagColumn, JoinedTag, SanitizedTag, Tag, TagActiveModel};
 pub use token::{Column as TokenColumn, Token, TokenActiveModel};
+pub use webhook::{Column as WebhookColumn, Webhook, WebhookActiveModel};
 
 mod address;
 mod api_key;
+mod cluster;
 mod config;
 mod entity;
 mod entity_tag;
 mod network;
+mod pending_transfer;
+mod rule;
+mod rule_rejection;
 mod tag;
 mod token;
+mod webhook;
@@ -1,4 +1,5 @@
 use eyre::Result;
+use schemars::JsonSchema;
 use sea_orm::{
 	entity::prelude::*,
 	sea_query::{func::Func, Expr},
@@ -8,8 +9,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 use crate::{
-	models::{BasicModel, PrimaryId, PrimaryIds, SoftDeleteModel},
-	utils, Architecture, IdPrefix,
+	models::{BasicModel, Config, ConfigKey, PrimaryId, PrimaryIds, SoftDeleteModel},
+	utils, Architecture, Attribution, BlockHeight, IdPrefix, ID_DEFAULT_LENGTH,
 };
 
 #[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -26,6 +27,10 @@ pub struct Model {
 	pub block_time: i64,
 	pub rpc_endpoint: String,
 	pub rps: i32,
+	pub confirmations: i32,
+	pub skip_change_outputs: bool,
+	pub attribution: Attribution,
+	pub script_addresses: bool,
 	#[serde(skip_serializing)]
 	pub is_deleted: bool,
 	#[sea_orm(nullable)]
@@ -41,7 +46,7 @@ impl From<Vec<Model>> for PrimaryIds {
 	}
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SanitizedNetwork {
 	pub id: String,
@@ -80,9 +85,11 @@ impl Model {
 		block_time: i64,
 		rpc_endpoint: String,
 		rps: i32,
+		confirmations: i32,
+		skip_change_outputs: bool,
 	) -> ActiveModel {
 		ActiveModel {
-			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Network))),
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Network, ID_DEFAULT_LENGTH))),
 			name: Set(name.to_string()),
 			architecture: Set(architecture),
 			chain_id: Set(chain_id),
@@ -90,10 +97,18 @@ impl Model {
 			rpc_endpoint: Set(rpc_endpoint),
 			is_deleted: Set(false),
 			rps: Set(rps),
+			confirmations: Set(confirmations),
+			skip_change_outputs: Set(skip_change_outputs),
 			..Default::default()
 		}
 	}
 
+	/// Highest block that's at least `confirmations` deep, given the chain's tip. Blocks
+	/// above it could still get reorged away, so they're not indexed yet
+	pub fn get_confirmed_block_height(&self, block_height: BlockHeight) -> BlockHeight {
+		block_height.saturating_sub(self.confirmations.max(0) as BlockHeight)
+	}
+
 	pub async fn get_all_by_network_ids<C>(
 		c: &C,
 		network_ids: PrimaryIds,
@@ -146,4 +161,110 @@ impl Model {
 
 		Ok(q.one(c).await?)
 	}
+
+	pub async fn is_paused<C>(c: &C, network_id: PrimaryId) -> Result<bool>
+	where
+		C: ConnectionTrait,
+	{
+		Ok(Config::get::<_, bool>(c, ConfigKey::IndexerPaused(network_id)).await?.is_some())
+	}
+
+	pub async fn set_paused<C>(c: &C, network_id: PrimaryId, is_paused: bool) -> Result<()>
+	where
+		C: ConnectionTrait,
+	{
+		let config_key = ConfigKey::IndexerPaused(network_id);
+
+		if is_paused {
+			Config::set::<_, bool>(c, config_key, true).await
+		} else {
+			Config::delete(c, config_key).await
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Db;
+
+	#[tokio::test]
+	async fn test_set_paused() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		Network::set_paused(db.get(), 1, true).await?;
+		assert!(Network::is_paused(db.get(), 1).await?);
+		assert!(!Network::is_paused(db.get(), 2).await?);
+
+		Network::set_paused(db.get(), 1, false).await?;
+		assert!(!Network::is_paused(db.get(), 1).await?);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_lookups() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network_id = Network::create(
+			db.get(),
+			Network::new_model(
+				Some("net_a".to_string()),
+				"Ethereum",
+				Architecture::Evm,
+				1,
+				12_000,
+				String::new(),
+				0,
+				0,
+				false,
+			),
+		)
+		.await?;
+
+		let hit = Network::get_by_id(db.get(), "net_a").await?;
+		assert_eq!(hit.map(|n| n.network_id), Some(network_id));
+		assert!(Network::get_by_id(db.get(), "net_missing").await?.is_none());
+
+		let hit =
+			Network::get_by_architecture_and_chain_id(db.get(), Architecture::Evm, 1, None).await?;
+		assert_eq!(hit.map(|n| n.network_id), Some(network_id));
+
+		// chain ids are only unique within an architecture
+		assert!(Network::get_by_architecture_and_chain_id(
+			db.get(),
+			Architecture::Bitcoin,
+			1,
+			None
+		)
+		.await?
+		.is_none());
+		assert!(Network::get_by_architecture_and_chain_id(db.get(), Architecture::Evm, 5, None)
+			.await?
+			.is_none());
+
+		// soft-deleted ones can be left out
+		Network::soft_delete_all_where(db.get(), Column::NetworkId.eq(network_id)).await?;
+		assert!(Network::get_by_architecture_and_chain_id(
+			db.get(),
+			Architecture::Evm,
+			1,
+			Some(false)
+		)
+		.await?
+		.is_none());
+		assert!(Network::get_existing_by_id(db.get(), "net_a").await?.is_none());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_get_confirmed_block_height() {
+		let network = Network { confirmations: 6, ..Default::default() };
+		assert_eq!(network.get_confirmed_block_height(100), 94);
+		assert_eq!(network.get_confirmed_block_height(3), 0);
+
+		// no confirmations means indexing right up to the tip
+		assert_eq!(Network::default().get_confirmed_block_height(100), 100);
+	}
 }
@@ -0,0 +1,212 @@
+use eyre::Result;
+use sea_orm::{
+	entity::{prelude::*, *},
+	Condition, ConnectionTrait, Order, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::{
+	models::{BasicModel, PrimaryId, Transfer},
+	utils, DB_CHUNK_SIZE,
+};
+
+/// A transfer of an unconfirmed (mempool) transaction. Kept in the db rather than in memory so
+/// the http process sees what the indexer's mempool poller found. Rows are dropped once their
+/// tx leaves the mempool (confirmed or evicted), or once they're too old
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
+#[sea_orm(table_name = "pending_transfers")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	#[serde(skip_serializing, skip_deserializing)]
+	pub pending_transfer_id: PrimaryId,
+	pub network_id: PrimaryId,
+	pub tx_hash: String,
+	pub from_address: String,
+	pub to_address: String,
+	pub asset_address: String,
+	/// Decimal string, since amounts don't fit any db integer type
+	pub amount: String,
+	pub fee: String,
+	#[sea_orm(nullable)]
+	#[serde(skip_serializing)]
+	pub updated_at: Option<DateTime>,
+	pub created_at: DateTime,
+}
+
+pub use ActiveModel as PendingTransferActiveModel;
+pub use Model as PendingTransfer;
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl BasicModel for Model {
+	type ActiveModel = ActiveModel;
+}
+
+impl Model {
+	pub fn new_model(transfer: &Transfer) -> ActiveModel {
+		ActiveModel {
+			network_id: Set(transfer.network_id as PrimaryId),
+			tx_hash: Set(transfer.tx_hash.clone()),
+			from_address: Set(transfer.from_address.clone()),
+			to_address: Set(transfer.to_address.clone()),
+			asset_address: Set(transfer.asset_address.clone()),
+			amount: Set(transfer.relative_amount.to_string()),
+			fee: Set(transfer.fee.to_string()),
+			..Default::default()
+		}
+	}
+
+	/// Hashes of the transactions with pending transfers on `network_id`
+	pub async fn get_tx_hashes<C>(c: &C, network_id: PrimaryId) -> Result<HashSet<String>>
+	where
+		C: ConnectionTrait,
+	{
+		Ok(Entity::find()
+			.select_only()
+			.column(Column::TxHash)
+			.distinct()
+			.filter(Column::NetworkId.eq(network_id))
+			.into_tuple::<String>()
+			.all(c)
+			.await?
+			.into_iter()
+			.collect())
+	}
+
+	pub async fn delete_all_by_tx_hashes<C>(
+		c: &C,
+		network_id: PrimaryId,
+		tx_hashes: &[String],
+	) -> Result<u64>
+	where
+		C: ConnectionTrait,
+	{
+		let mut ret = 0;
+
+		for chunk in tx_hashes.chunks(DB_CHUNK_SIZE) {
+			ret += Self::delete_all_where(
+				c,
+				Condition::all()
+					.add(Column::NetworkId.eq(network_id))
+					.add(Column::TxHash.is_in(chunk.iter().cloned())),
+			)
+			.await?;
+		}
+
+		Ok(ret)
+	}
+
+	/// Drops transfers of transactions that didn't confirm within `max_age` seconds
+	pub async fn delete_expired<C>(c: &C, max_age: u64) -> Result<u64>
+	where
+		C: ConnectionTrait,
+	{
+		Self::delete_all_where(c, Column::CreatedAt.lt(utils::ago_in_seconds(max_age))).await
+	}
+
+	/// Pending transfers to or from `address`, newest first
+	pub async fn get_all_by_address<C>(
+		c: &C,
+		address: &str,
+		network_id: Option<PrimaryId>,
+		offset: Option<u64>,
+		limit: Option<u64>,
+	) -> Result<Vec<Self>>
+	where
+		C: ConnectionTrait,
+	{
+		let mut filter = Condition::all().add(
+			Condition::any()
+				.add(Column::FromAddress.eq(address))
+				.add(Column::ToAddress.eq(address)),
+		);
+		if let Some(network_id) = network_id {
+			filter = filter.add(Column::NetworkId.eq(network_id));
+		}
+
+		Self::get_all_sorted_paginated_where(
+			c,
+			filter,
+			Column::CreatedAt,
+			Order::Desc,
+			offset,
+			limit,
+		)
+		.await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		chain::{ModuleId, U256},
+		testing, Db,
+	};
+
+	#[tokio::test]
+	async fn test_pending_transfers() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let network_id = testing::create_network(db.get(), "net_a", 0).await?.network_id;
+
+		let new_transfer = |tx_hash: &str, to: &str| {
+			Transfer::new(
+				ModuleId::BitcoinTransfer,
+				network_id,
+				0,
+				tx_hash,
+				"from",
+				to,
+				None,
+				U256::from(1),
+				U256::from(1),
+				0,
+			)
+		};
+
+		PendingTransfer::create_many(
+			db.get(),
+			vec![
+				PendingTransfer::new_model(&new_transfer("tx_a", "to_a")),
+				PendingTransfer::new_model(&new_transfer("tx_a", "to_b")),
+				PendingTransfer::new_model(&new_transfer("tx_b", "to_a")),
+			],
+		)
+		.await?;
+
+		assert_eq!(
+			PendingTransfer::get_tx_hashes(db.get(), network_id).await?,
+			HashSet::from(["tx_a".to_string(), "tx_b".to_string()])
+		);
+		assert_eq!(
+			PendingTransfer::get_all_by_address(db.get(), "to_a", Some(network_id), None, None)
+				.await?
+				.len(),
+			2
+		);
+		assert!(PendingTransfer::get_all_by_address(db.get(), "to_a", Some(0), None, None)
+			.await?
+			.is_empty());
+
+		// tx_a left the mempool
+		assert_eq!(
+			PendingTransfer::delete_all_by_tx_hashes(db.get(), network_id, &["tx_a".to_string()])
+				.await?,
+			2
+		);
+		assert_eq!(
+			PendingTransfer::get_tx_hashes(db.get(), network_id).await?,
+			HashSet::from(["tx_b".to_string()])
+		);
+
+		// still fresh
+		assert_eq!(PendingTransfer::delete_expired(db.get(), 60).await?, 0);
+
+		Ok(())
+	}
+}
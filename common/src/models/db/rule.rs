@@ -0,0 +1,322 @@
+use eyre::Result;
+use sea_orm::{
+	entity::{prelude::*, *},
+	ConnectionTrait, FromQueryResult,
+};
+use sea_orm_migration::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+	models::{
+		db::{address, cluster, rule_rejection},
+		AddressColumn, BasicModel, ClusterColumn, PrimaryId, RuleRejectionColumn,
+	},
+	utils, IdPrefix, RuleKind, ID_DEFAULT_LENGTH,
+};
+
+/// Explicit opt-in for an entity to claim addresses it's linked to. Nothing gets
+/// assigned automatically unless an entity has a rule for it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
+#[sea_orm(table_name = "rules")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	#[serde(skip_serializing, skip_deserializing)]
+	pub rule_id: PrimaryId,
+	#[serde(skip_serializing)]
+	pub entity_id: PrimaryId,
+	pub id: String,
+	pub entity: String,
+	pub kind: RuleKind,
+	#[sea_orm(nullable)]
+	#[serde(skip_serializing)]
+	pub updated_at: Option<DateTime>,
+	pub created_at: DateTime,
+}
+
+/// An unassigned address that a rule matched
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RuleProposal {
+	pub rule: String,
+	pub entity_id: PrimaryId,
+	pub network_id: PrimaryId,
+	pub address: String,
+}
+
+pub use ActiveModel as RuleActiveModel;
+pub use Model as Rule;
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl BasicModel for Model {
+	type ActiveModel = ActiveModel;
+}
+
+impl Model {
+	pub fn new_model(
+		id: Option<String>,
+		entity_id: PrimaryId,
+		entity: &str,
+		kind: RuleKind,
+	) -> ActiveModel {
+		ActiveModel {
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Rule, ID_DEFAULT_LENGTH))),
+			entity_id: Set(entity_id),
+			entity: Set(entity.to_string()),
+			kind: Set(kind),
+			..Default::default()
+		}
+	}
+
+	pub async fn get_by_entity_id_and_kind<C>(
+		c: &C,
+		entity_id: PrimaryId,
+		kind: RuleKind,
+	) -> Result<Option<Self>>
+	where
+		C: ConnectionTrait,
+	{
+		Ok(Entity::find()
+			.filter(Column::EntityId.eq(entity_id))
+			.filter(Column::Kind.eq(kind))
+			.one(c)
+			.await?)
+	}
+
+	/// Addresses that share a cluster with one of a rule entity's addresses, but aren't
+	/// assigned to anyone yet (nor rejected by that entity). Addresses matching more than
+	/// one entity are left alone. Runs as a single query returning at most `limit` of them
+	pub async fn get_proposals<C>(c: &C, limit: u64) -> Result<Vec<RuleProposal>>
+	where
+		C: ConnectionTrait,
+	{
+		#[derive(FromQueryResult)]
+		struct Candidate {
+			network_id: PrimaryId,
+			address: String,
+			entity_id: PrimaryId,
+		}
+
+		let rules = Entity::find().filter(Column::Kind.eq(RuleKind::Cluster)).all(c).await?;
+		if rules.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let rule_ids = rules.into_iter().map(|r| (r.entity_id, r.id)).collect::<HashMap<_, _>>();
+
+		// owned addresses -> their clusters -> the other members of those clusters
+		let (owned, owned_cluster, member, assigned, rejected) = (
+			Alias::new("owned"),
+			Alias::new("owned_cluster"),
+			Alias::new("member"),
+			Alias::new("assigned"),
+			Alias::new("rejected"),
+		);
+		let query = Query::select()
+			.expr_as(
+				Expr::col((member.clone(), ClusterColumn::NetworkId)),
+				Alias::new("network_id"),
+			)
+			.expr_as(Expr::col((member.clone(), ClusterColumn::Address)), Alias::new("address"))
+			.expr_as(
+				Expr::col((owned.clone(), AddressColumn::EntityId)).min(),
+				Alias::new("entity_id"),
+			)
+			.from_as(address::Entity, owned.clone())
+			.join_as(
+				JoinType::InnerJoin,
+				cluster::Entity,
+				owned_cluster.clone(),
+				Condition::all()
+					.add(
+						Expr::col((owned_cluster.clone(), ClusterColumn::NetworkId))
+							.equals((owned.clone(), AddressColumn::NetworkId)),
+					)
+					.add(
+						Expr::col((owned_cluster.clone(), ClusterColumn::Address))
+							.equals((owned.clone(), AddressColumn::Address)),
+					),
+			)
+			.join_as(
+				JoinType::InnerJoin,
+				cluster::Entity,
+				member.clone(),
+				Condition::all()
+					.add(
+						Expr::col((member.clone(), ClusterColumn::NetworkId))
+							.equals((owned_cluster.clone(), ClusterColumn::NetworkId)),
+					)
+					.add(
+						Expr::col((member.clone(), ClusterColumn::Cluster))
+							.equals((owned_cluster.clone(), ClusterColumn::Cluster)),
+					),
+			)
+			// whatever an owning entity had deleted after it was assigned. Checked once grouped,
+			// so a rejection doesn't leave the address looking like only another entity's
+			.join_as(
+				JoinType::LeftJoin,
+				rule_rejection::Entity,
+				rejected.clone(),
+				Condition::all()
+					.add(
+						Expr::col((rejected.clone(), RuleRejectionColumn::EntityId))
+							.equals((owned.clone(), AddressColumn::EntityId)),
+					)
+					.add(
+						Expr::col((rejected.clone(), RuleRejectionColumn::NetworkId))
+							.equals((member.clone(), ClusterColumn::NetworkId)),
+					)
+					.add(
+						Expr::col((rejected.clone(), RuleRejectionColumn::Address))
+							.equals((member.clone(), ClusterColumn::Address)),
+					),
+			)
+			.and_where(Expr::col((owned.clone(), AddressColumn::IsDeleted)).eq(false))
+			.and_where(
+				Expr::col((owned.clone(), AddressColumn::EntityId)).in_subquery(
+					Query::select()
+						.column(Column::EntityId)
+						.from(Entity)
+						.and_where(Column::Kind.eq(RuleKind::Cluster))
+						.to_owned(),
+				),
+			)
+			// skip anything already assigned (soft-deleted ones included)
+			.and_where(
+				Expr::exists(
+					Query::select()
+						.expr(Expr::val(1))
+						.from_as(address::Entity, assigned.clone())
+						.and_where(
+							Expr::col((assigned.clone(), AddressColumn::NetworkId))
+								.equals((member.clone(), ClusterColumn::NetworkId)),
+						)
+						.and_where(
+							Expr::col((assigned.clone(), AddressColumn::Address))
+								.equals((member.clone(), ClusterColumn::Address)),
+						)
+						.to_owned(),
+				)
+				.not(),
+			)
+			.group_by_col((member.clone(), ClusterColumn::NetworkId))
+			.group_by_col((member.clone(), ClusterColumn::Address))
+			.and_having(Expr::col((owned.clone(), AddressColumn::EntityId)).count_distinct().eq(1))
+			.and_having(Expr::col((rejected.clone(), RuleRejectionColumn::Address)).count().eq(0))
+			.order_by((member.clone(), ClusterColumn::NetworkId), Order::Asc)
+			.order_by((member.clone(), ClusterColumn::Address), Order::Asc)
+			.limit(limit)
+			.to_owned();
+
+		Ok(Candidate::find_by_statement(c.get_database_backend().build(&query))
+			.all(c)
+			.await?
+			.into_iter()
+			.filter_map(|m| {
+				rule_ids.get(&m.entity_id).map(|rule| RuleProposal {
+					rule: rule.clone(),
+					entity_id: m.entity_id,
+					network_id: m.network_id,
+					address: m.address,
+				})
+			})
+			.collect())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		models::{Address, Cluster, Entity as LabeledEntity, RuleRejection},
+		testing, Db,
+	};
+	use serde_json::json;
+	use std::collections::BTreeSet;
+
+	#[tokio::test]
+	async fn test_get_proposals() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let network_id = network.network_id;
+
+		let create_entity = |address: &'static str| {
+			let (db, network) = (&db, &network);
+			async move {
+				let entity_id =
+					LabeledEntity::create(db.get(), LabeledEntity::new_model(None, None, "", None))
+						.await?;
+				let entity =
+					<LabeledEntity as BasicModel>::get(db.get(), entity_id).await?.unwrap();
+				Address::create(
+					db.get(),
+					Address::new_model(None, entity_id, network_id, &network.id, address, "", None),
+				)
+				.await?;
+
+				Ok::<_, eyre::Error>(entity)
+			}
+		};
+		let entity = create_entity("owned").await?;
+		let entity_id = entity.entity_id;
+
+		// "owned", "other" & "more" were spent together
+		let co_spent = ["owned", "other", "more"].map(|a| a.to_string());
+		Cluster::merge(db.get(), network_id, [&BTreeSet::from(co_spent)]).await?;
+
+		// nothing is proposed without an explicit rule
+		assert!(Rule::get_proposals(db.get(), 10).await?.is_empty());
+
+		let rule_id =
+			Rule::create(db.get(), Rule::new_model(None, entity_id, &entity.id, RuleKind::Cluster))
+				.await?;
+		let rule = <Rule as BasicModel>::get(db.get(), rule_id).await?.unwrap().id;
+		let proposal = |address: &str| RuleProposal {
+			rule: rule.clone(),
+			entity_id,
+			network_id,
+			address: address.to_string(),
+		};
+
+		assert_eq!(
+			Rule::get_proposals(db.get(), 10).await?,
+			vec![proposal("more"), proposal("other")]
+		);
+		assert_eq!(Rule::get_proposals(db.get(), 1).await?, vec![proposal("more")]);
+
+		// "other" was assigned by the rule before, and then deleted (and pruned)
+		let assigned = Address::new_model(
+			None,
+			entity_id,
+			network_id,
+			&network.id,
+			"other",
+			"",
+			Some(json!({ "rule": rule })),
+		);
+		let address_id = Address::create(db.get(), assigned).await?;
+		let assigned = <Address as BasicModel>::get(db.get(), address_id).await?.unwrap();
+		RuleRejection::create_many_by_addresses(db.get(), &[assigned]).await?;
+		<Address as BasicModel>::delete(db.get(), address_id).await?;
+		assert_eq!(Rule::get_proposals(db.get(), 10).await?, vec![proposal("more")]);
+
+		// "more" is also co-spent with another rule entity's address, which joins the two
+		// clusters, so it's no longer clear whose any of them are
+		let other_entity = create_entity("owned_elsewhere").await?;
+		Rule::create(
+			db.get(),
+			Rule::new_model(None, other_entity.entity_id, &other_entity.id, RuleKind::Cluster),
+		)
+		.await?;
+		let co_spent = ["more", "owned_elsewhere"].map(|a| a.to_string());
+		Cluster::merge(db.get(), network_id, [&BTreeSet::from(co_spent)]).await?;
+		assert!(Rule::get_proposals(db.get(), 10).await?.is_empty());
+
+		Ok(())
+	}
+}
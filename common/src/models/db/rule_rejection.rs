@@ -0,0 +1,79 @@
+use eyre::Result;
+use sea_orm::{
+	entity::{prelude::*, *},
+	ConnectionTrait,
+};
+use sea_orm_migration::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	models::{Address, BasicModel, PrimaryId},
+	DB_CHUNK_SIZE,
+};
+
+/// An address that was assigned to an entity by a rule and then deleted by a user. Rules
+/// never propose it for that entity again, even after the deleted address is pruned
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
+#[sea_orm(table_name = "rule_rejections")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	#[serde(skip_serializing, skip_deserializing)]
+	pub rule_rejection_id: PrimaryId,
+	pub entity_id: PrimaryId,
+	pub network_id: PrimaryId,
+	pub address: String,
+	#[sea_orm(nullable)]
+	#[serde(skip_serializing)]
+	pub updated_at: Option<DateTime>,
+	pub created_at: DateTime,
+}
+
+pub use ActiveModel as RuleRejectionActiveModel;
+pub use Model as RuleRejection;
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl BasicModel for Model {
+	type ActiveModel = ActiveModel;
+}
+
+impl Model {
+	pub fn new_model(entity_id: PrimaryId, network_id: PrimaryId, address: &str) -> ActiveModel {
+		ActiveModel {
+			entity_id: Set(entity_id),
+			network_id: Set(network_id),
+			address: Set(address.to_string()),
+			..Default::default()
+		}
+	}
+
+	/// Rejects those of `addresses` that were assigned by a rule, skipping known rejections
+	pub async fn create_many_by_addresses<C>(c: &C, addresses: &[Address]) -> Result<()>
+	where
+		C: ConnectionTrait,
+	{
+		let models = addresses
+			.iter()
+			.filter(|a| a.data.get("rule").is_some())
+			.map(|a| Self::new_model(a.entity_id, a.network_id, &a.address))
+			.collect::<Vec<_>>();
+
+		for chunk in models.chunks(DB_CHUNK_SIZE) {
+			Entity::insert_many(chunk.to_vec())
+				.on_conflict(
+					OnConflict::columns([Column::EntityId, Column::NetworkId, Column::Address])
+						.do_nothing()
+						.to_owned(),
+				)
+				.do_nothing()
+				.exec(c)
+				.await?;
+		}
+
+		Ok(())
+	}
+}
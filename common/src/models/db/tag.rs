@@ -1,4 +1,5 @@
 use eyre::Result;
+use schemars::JsonSchema;
 use sea_orm::{
 	entity::{prelude::*, *},
 	ConnectionTrait, FromQueryResult, QuerySelect,
@@ -9,7 +10,7 @@ use std::collections::HashSet;
 
 use crate::{
 	models::{db::entity_tag, BasicModel, EntityTagColumn, PrimaryId, PrimaryIds},
-	utils, IdPrefix, RiskLevel,
+	utils, IdPrefix, RiskLevel, ID_DEFAULT_LENGTH,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -75,7 +76,7 @@ impl From<JoinedModel> for Model {
 	}
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SanitizedTag {
 	pub id: String,
@@ -112,7 +113,7 @@ impl BasicModel for Model {
 impl Model {
 	pub fn new_model(id: Option<String>, name: &str, risk_level: RiskLevel) -> ActiveModel {
 		ActiveModel {
-			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Tag))),
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Tag, ID_DEFAULT_LENGTH))),
 			name: Set(name.to_string()),
 			risk_level: Set(risk_level),
 			..Default::default()
@@ -9,7 +9,7 @@ use std::collections::HashSet;
 
 use crate::{
 	models::{BasicModel, PrimaryId, PrimaryIds},
-	utils, IdPrefix,
+	utils, IdPrefix, ID_DEFAULT_LENGTH,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
@@ -61,7 +61,7 @@ impl Model {
 		decimals: i16,
 	) -> ActiveModel {
 		ActiveModel {
-			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Token))),
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Token, ID_DEFAULT_LENGTH))),
 			network_id: Set(network_id),
 			name: Set(name.to_string()),
 			symbol: Set(symbol.to_string()),
@@ -0,0 +1,66 @@
+use base58::ToBase58;
+use eyre::Result;
+use sea_orm::{
+	entity::{prelude::*, *},
+	ConnectionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	models::{BasicModel, PrimaryId},
+	utils, IdPrefix, ID_DEFAULT_LENGTH,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DeriveEntityModel)]
+#[sea_orm(table_name = "webhooks")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	#[serde(skip_serializing, skip_deserializing)]
+	pub webhook_id: PrimaryId,
+	pub id: String,
+	pub url: String,
+	#[serde(skip_serializing)]
+	pub secret: String,
+	pub is_active: bool,
+	#[sea_orm(nullable)]
+	#[serde(skip_serializing)]
+	pub updated_at: Option<DateTime>,
+	pub created_at: DateTime,
+}
+
+pub use ActiveModel as WebhookActiveModel;
+pub use Model as Webhook;
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl BasicModel for Model {
+	type ActiveModel = ActiveModel;
+}
+
+impl Model {
+	pub fn new_model(id: Option<String>, url: &str) -> ActiveModel {
+		ActiveModel {
+			id: Set(id.unwrap_or(utils::new_unique_id(IdPrefix::Webhook, ID_DEFAULT_LENGTH))),
+			url: Set(url.to_string()),
+			secret: Set(Self::generate_secret()),
+			is_active: Set(true),
+			..Default::default()
+		}
+	}
+
+	pub fn generate_secret() -> String {
+		let input = utils::new_uuid().to_string();
+		format!("whsec_{}", utils::sha256(&input).to_base58())
+	}
+
+	pub async fn get_all_active<C>(c: &C) -> Result<Vec<Self>>
+	where
+		C: ConnectionTrait,
+	{
+		Ok(Entity::find().filter(Column::IsActive.eq(true)).all(c).await?)
+	}
+}
@@ -3,12 +3,12 @@ use eyre::Result;
 use sea_orm::{
 	entity::prelude::*,
 	sea_query::{types::*, Expr},
-	ActiveValue, QuerySelect,
+	ActiveValue, Iterable, Order, PrimaryKeyToColumn, QueryOrder, QuerySelect, TransactionTrait,
 };
 use sea_orm_migration::prelude::IntoCondition;
 use std::ops::{Deref, DerefMut};
 
-use crate::{utils, IdPrefix};
+use crate::{utils, Db, IdPrefix, ID_MAX_ATTEMPTS, ID_MAX_LENGTH};
 pub use db::*;
 pub use warehouse::*;
 
@@ -80,7 +80,7 @@ pub fn is_valid_id(id: &str, id_prefix: IdPrefix) -> bool {
 
 	// check min & max lengths
 	let contents = &id[id_prefix.to_string().len() + 1..];
-	if contents.is_empty() || contents.len() > 32 {
+	if contents.is_empty() || contents.len() > ID_MAX_LENGTH {
 		return false;
 	}
 
@@ -135,6 +135,63 @@ pub trait BasicModel {
 		Ok(<Self::ActiveModel as ActiveModelTrait>::Entity::find_by_id(primary_id).one(c).await?)
 	}
 
+	/// Inserts the row `f` builds around `id`, or around a newly generated public id if
+	/// there's none. A generated id that's taken already is replaced with a new one, up to
+	/// `ID_MAX_ATTEMPTS` times
+	async fn create_with_id<C, F>(
+		c: &C,
+		id: Option<String>,
+		prefix: IdPrefix,
+		id_length: usize,
+		f: F,
+	) -> Result<<<<Self::ActiveModel as ActiveModelTrait>::Entity as EntityTrait>::PrimaryKey as
+	PrimaryKeyTrait>::ValueType>
+	where
+		C: ConnectionTrait + TransactionTrait,
+		F: Fn(String) -> Self::ActiveModel + Send + Sync,
+	{
+		if let Some(id) = id {
+			return Self::create(c, f(id)).await;
+		}
+
+		Self::create_many_with_ids(c, 1, prefix, id_length, |mut ids| vec![f(ids.remove(0))]).await
+	}
+
+	/// Inserts the rows `f` builds around `n` newly generated public ids, starting over
+	/// with new ones (up to `ID_MAX_ATTEMPTS` times) if any of them is taken already
+	async fn create_many_with_ids<C, F>(
+		c: &C,
+		n: usize,
+		prefix: IdPrefix,
+		id_length: usize,
+		f: F,
+	) -> Result<<<<Self::ActiveModel as ActiveModelTrait>::Entity as EntityTrait>::PrimaryKey as
+	PrimaryKeyTrait>::ValueType>
+	where
+		C: ConnectionTrait + TransactionTrait,
+		F: Fn(Vec<String>) -> Vec<Self::ActiveModel> + Send + Sync,
+	{
+		let mut attempt = 1;
+
+		loop {
+			let ids = (0..n).map(|_| utils::new_unique_id(prefix.clone(), id_length)).collect();
+
+			// savepoint when nested, so a failed insert leaves the outer transaction usable
+			let tx = c.begin().await?;
+			match Self::create_many(&tx, f(ids)).await {
+				Ok(last_insert_id) => {
+					tx.commit().await?;
+					return Ok(last_insert_id);
+				}
+				Err(e) if attempt < ID_MAX_ATTEMPTS && Db::is_unique_violation(&e) => {
+					tx.rollback().await?;
+					attempt += 1;
+				}
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
 	async fn get_by_id<C>(
 		c: &C,
 		id: &str,
@@ -210,6 +267,40 @@ pub trait BasicModel {
 		Ok(q.all(c).await?)
 	}
 
+	/// Like `get_all_paginated_where`, but sorted by `order_by` and then by primary key, so
+	/// rows with equal sort values don't shift between pages
+	async fn get_all_sorted_paginated_where<C, F>(
+		c: &C,
+		filter: F,
+		order_by: <<Self::ActiveModel as ActiveModelTrait>::Entity as EntityTrait>::Column,
+		order: Order,
+		offset: Option<u64>,
+		limit: Option<u64>,
+	) -> Result<Vec<<<Self::ActiveModel as ActiveModelTrait>::Entity as EntityTrait>::Model>>
+	where
+		C: ConnectionTrait,
+		F: IntoCondition + Send,
+	{
+		let mut q = <Self::ActiveModel as ActiveModelTrait>::Entity::find()
+			.filter(filter)
+			.order_by(order_by, order.clone());
+
+		for key in
+			<<Self::ActiveModel as ActiveModelTrait>::Entity as EntityTrait>::PrimaryKey::iter()
+		{
+			q = q.order_by(key.into_column(), order.clone());
+		}
+
+		if let Some(v) = offset {
+			q = q.offset(v);
+		}
+		if let Some(v) = limit {
+			q = q.limit(v);
+		}
+
+		Ok(q.all(c).await?)
+	}
+
 	async fn update_by_id<C>(c: &C, id: &str, data: Self::ActiveModel) -> Result<bool>
 	where
 		C: ConnectionTrait,
@@ -314,6 +405,55 @@ pub trait SoftDeleteModel {
 		Ok(q.all(c).await?)
 	}
 
+	/// Same as `BasicModel::get_all_where`, minus the soft-deleted rows
+	async fn get_all_existing_where<C, F>(
+		c: &C,
+		filter: F,
+	) -> Result<Vec<<<Self::ActiveModel as ActiveModelTrait>::Entity as EntityTrait>::Model>>
+	where
+		C: ConnectionTrait,
+		F: IntoCondition + Send,
+	{
+		Ok(<Self::ActiveModel as ActiveModelTrait>::Entity::find()
+			.filter(Expr::col(Alias::new("is_deleted")).eq(false))
+			.filter(filter)
+			.all(c)
+			.await?)
+	}
+
+	async fn count_existing<C>(c: &C, is_deleted: Option<bool>) -> Result<u64>
+	where
+		C: ConnectionTrait,
+	{
+		let mut q = <Self::ActiveModel as ActiveModelTrait>::Entity::find()
+			.select_only()
+			.column_as(Expr::cust("COUNT(*)"), "count");
+
+		if let Some(is_deleted) = is_deleted {
+			q = q.filter(Expr::col(Alias::new("is_deleted")).eq(is_deleted))
+		}
+
+		Ok(q.into_tuple::<i64>().one(c).await?.unwrap_or(0) as u64)
+	}
+
+	/// Marks matching rows as deleted (bumping `updated_at`), so they can be pruned later.
+	/// Rows that are deleted already are left as they are
+	async fn soft_delete_all_where<C, F>(c: &C, filter: F) -> Result<u64>
+	where
+		C: ConnectionTrait,
+		F: IntoCondition + Send,
+	{
+		let res = <Self::ActiveModel as ActiveModelTrait>::Entity::update_many()
+			.col_expr(Alias::new("is_deleted"), Expr::value(true))
+			.col_expr(Alias::new("updated_at"), Expr::value(utils::now()))
+			.filter(Expr::col(Alias::new("is_deleted")).eq(false))
+			.filter(filter)
+			.exec(c)
+			.await?;
+
+		Ok(res.rows_affected)
+	}
+
 	async fn prune_all<C>(c: &C) -> Result<u64>
 	where
 		C: ConnectionTrait,
@@ -344,6 +484,7 @@ pub trait SoftDeleteModel {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::testing;
 	use std::collections::HashMap;
 
 	#[test]
@@ -360,7 +501,123 @@ mod tests {
 		]);
 
 		for (input, output) in data.into_iter() {
-			assert_eq!(is_valid_id(&input.0, input.1), output)
+			assert_eq!(is_valid_id(input.0, input.1), output)
+		}
+	}
+
+	#[tokio::test]
+	async fn test_get_all_sorted_paginated_where() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let network_id = network.network_id;
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+
+		// equal descriptions, so only the primary key keeps pages apart
+		for address in ["a", "b", "c", "d", "e"] {
+			Address::create(
+				db.get(),
+				Address::new_model(None, entity_id, network_id, &network.id, address, "", None),
+			)
+			.await?;
+		}
+
+		let get_page = |order: Order, offset: u64| {
+			Address::get_all_sorted_paginated_where(
+				db.get(),
+				AddressColumn::EntityId.eq(entity_id),
+				AddressColumn::Description,
+				order,
+				Some(offset),
+				Some(2),
+			)
+		};
+
+		let mut pages = vec![];
+		for offset in [0, 2, 4] {
+			let page = get_page(Order::Asc, offset).await?;
+			pages.push(page.into_iter().map(|a| a.address).collect::<Vec<_>>());
 		}
+		assert_eq!(pages, [vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+
+		// same window every time
+		for _ in 0..3 {
+			let page = get_page(Order::Desc, 1).await?;
+			assert_eq!(page.into_iter().map(|a| a.address).collect::<Vec<_>>(), ["d", "c"]);
+		}
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_soft_delete_all_where() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+		assert_eq!(
+			<Entity as BasicModel>::get(db.get(), entity_id).await?.unwrap().updated_at,
+			None
+		);
+
+		let filter = || EntityColumn::EntityId.eq(entity_id);
+
+		assert_eq!(Entity::soft_delete_all_where(db.get(), filter()).await?, 1);
+		let deleted = <Entity as BasicModel>::get(db.get(), entity_id).await?.unwrap();
+		assert!(deleted.is_deleted);
+		assert!(deleted.updated_at.is_some());
+
+		// nothing left to delete
+		assert_eq!(Entity::soft_delete_all_where(db.get(), filter()).await?, 0);
+		assert!(Entity::get_all_existing_where(db.get(), filter()).await?.is_empty());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_create_with_id() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let new_entity = |id| Entity::new_model(Some(id), None, "", None);
+
+		// ids that were asked for are kept
+		let entity_id = Entity::create_with_id(
+			db.get(),
+			Some("ent_a".to_string()),
+			IdPrefix::Entity,
+			8,
+			new_entity,
+		)
+		.await?;
+		assert_eq!(<Entity as BasicModel>::get(db.get(), entity_id).await?.unwrap().id, "ent_a");
+
+		// generated ones come with the configured length
+		let entity_id =
+			Entity::create_with_id(db.get(), None, IdPrefix::Entity, 20, new_entity).await?;
+		let id = <Entity as BasicModel>::get(db.get(), entity_id).await?.unwrap().id;
+		assert!(is_valid_id(&id, IdPrefix::Entity));
+		assert_eq!(id.len(), "ent_".len() + 20);
+
+		// taken ids that were asked for are not replaced
+		let err = Entity::create_with_id(
+			db.get(),
+			Some("ent_a".to_string()),
+			IdPrefix::Entity,
+			8,
+			new_entity,
+		)
+		.await
+		.unwrap_err();
+		assert!(Db::is_unique_violation(&err));
+
+		// bulk inserts work inside transactions as well
+		let tx = db.get().begin().await?;
+		Entity::create_many_with_ids(&tx, 3, IdPrefix::Entity, 8, |ids| {
+			ids.into_iter().map(new_entity).collect()
+		})
+		.await?;
+		tx.commit().await?;
+
+		assert_eq!(Entity::get_all(db.get()).await?.len(), 5);
+
+		Ok(())
 	}
 }
@@ -1,11 +1,15 @@
 use clickhouse::Row;
 use eyre::Result;
+use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 
 use crate::{
 	chain::{u256, ModuleId, U256},
 	models::{PrimaryId, PrimaryIds},
+	utils,
 	warehouse::Warehouse,
+	BlockHeight,
 };
 
 pub static TABLE: &str = "amounts";
@@ -27,6 +31,13 @@ pub struct Model {
 
 pub use Model as Amount;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressBalance {
+	pub network_id: PrimaryId,
+	pub asset_address: String,
+	pub balance: U256,
+}
+
 impl Model {
 	pub fn new(
 		module_id: ModuleId,
@@ -52,6 +63,72 @@ impl Model {
 		}
 	}
 
+	pub fn get_natural_key(&self) -> String {
+		format!(
+			"{}:{}:{}:{}:{}:{}:{}:{}",
+			self.module_id,
+			self.network_id,
+			self.block_height,
+			self.tx_hash,
+			self.address,
+			self.asset_address,
+			self.amount_in,
+			self.amount_out
+		)
+	}
+
+	/// Skips amounts that are already stored (or repeated within `models`), the same way
+	/// `Transfer::create_many` does, so reprocessing a block doesn't count them (and the
+	/// balances summed from them) twice
+	pub async fn create_many(warehouse: &Warehouse, models: Vec<Self>) -> Result<()> {
+		if models.is_empty() {
+			return Ok(());
+		}
+
+		let mut block_ranges = BTreeMap::<(u16, u64), (u64, u64)>::new();
+		for m in models.iter() {
+			block_ranges
+				.entry((m.module_id, m.network_id))
+				.and_modify(|(min, max)| {
+					*min = (*min).min(m.block_height);
+					*max = (*max).max(m.block_height);
+				})
+				.or_insert((m.block_height, m.block_height));
+		}
+
+		let queries = block_ranges
+			.into_iter()
+			.map(|((module_id, network_id), (block_height_min, block_height_max))| {
+				format!(
+					r#"
+						SELECT *
+						FROM {TABLE}
+						WHERE
+							module_id = {module_id} AND
+							network_id = {network_id} AND
+							block_height >= {block_height_min} AND
+							block_height <= {block_height_max}
+					"#
+				)
+			})
+			.collect::<Vec<_>>();
+
+		let mut existing = try_join_all(queries.iter().map(|q| warehouse.select::<Self>(q)))
+			.await?
+			.into_iter()
+			.flatten()
+			.map(|a| a.get_natural_key())
+			.collect::<HashSet<_>>();
+
+		let models =
+			models.into_iter().filter(|m| existing.insert(m.get_natural_key())).collect::<Vec<_>>();
+		if models.is_empty() {
+			return Ok(());
+		}
+
+		warehouse.insert(TABLE, &models).await
+	}
+
 	pub async fn get_all_network_ids_by_addresses(
 		warehouse: &Warehouse,
 		mut addresses: Vec<String>,
@@ -73,15 +150,110 @@ impl Model {
 					SELECT DISTINCT network_id
 					FROM {TABLE}
 					WHERE address IN ({formatted_addresses})
-                "#
+				"#
 			))
 			.await?
 			.into_iter()
-			.map(|d: Self| d.network_id as PrimaryId)
+			.map(|d: Data| d.network_id as PrimaryId)
 			.collect::<Vec<PrimaryId>>()
 			.into())
 	}
 
+	/// Current balance of `address` per network and asset, computed by netting all of its
+	/// inflows and outflows. Addresses without any activity get an empty list
+	pub async fn get_balances_by_address(
+		warehouse: &Warehouse,
+		address: &str,
+	) -> Result<Vec<AddressBalance>> {
+		let address = utils::quote_sql(address);
+
+		let results: Vec<Self> = warehouse
+			.select(&format!(
+				r#"
+					SELECT *
+					FROM {TABLE}
+					WHERE address = {address}
+				"#
+			))
+			.await?;
+
+		Ok(Self::get_net_balances(results))
+	}
+
+	/// Balance of `address` on a single network as of `block_height` (inclusive), for
+	/// point-in-time reporting
+	pub async fn get_balance_at_block(
+		warehouse: &Warehouse,
+		address: &str,
+		network_id: PrimaryId,
+		block_height: BlockHeight,
+	) -> Result<Vec<AddressBalance>> {
+		let conditions = Self::get_balance_at_block_conditions(address, network_id, block_height);
+
+		let results: Vec<Self> = warehouse
+			.select(&format!(
+				r#"
+					SELECT *
+					FROM {TABLE}
+					WHERE {conditions}
+				"#
+			))
+			.await?;
+
+		Ok(Self::get_net_balances(results))
+	}
+
+	fn get_balance_at_block_conditions(
+		address: &str,
+		network_id: PrimaryId,
+		block_height: BlockHeight,
+	) -> String {
+		[
+			format!("address = {}", utils::quote_sql(address)),
+			format!("network_id = {network_id}"),
+			format!("block_height <= {block_height}"),
+		]
+		.join(" AND ")
+	}
+
+	fn get_net_balances(amounts: Vec<Self>) -> Vec<AddressBalance> {
+		let mut totals = BTreeMap::<(u64, String), (U256, U256)>::new();
+
+		for amount in amounts.into_iter() {
+			let total = totals.entry((amount.network_id, amount.asset_address)).or_default();
+			total.0 = total.0.saturating_add(amount.amount_in);
+			total.1 = total.1.saturating_add(amount.amount_out);
+		}
+
+		totals
+			.into_iter()
+			.map(|((network_id, asset_address), (amount_in, amount_out))| AddressBalance {
+				network_id: network_id as PrimaryId,
+				asset_address,
+				balance: amount_in.saturating_sub(amount_out),
+			})
+			.collect()
+	}
+
+	/// Both ends of the range are inclusive
+	pub async fn delete_all_by_block_range(
+		warehouse: &Warehouse,
+		network_id: PrimaryId,
+		(block_height_min, block_height_max): (BlockHeight, BlockHeight),
+	) -> Result<()> {
+		warehouse
+			.delete(&format!(
+				r#"
+					DELETE FROM {TABLE}
+					WHERE
+						network_id = {network_id} AND
+						block_height >= {block_height_min} AND
+						block_height <= {block_height_max}
+				"#
+			))
+			.await
+	}
+
 	pub async fn delete_all_by_network_id(
 		warehouse: &Warehouse,
 		network_ids: PrimaryIds,
@@ -92,10 +264,129 @@ impl Model {
 		warehouse
 			.delete(&format!(
 				r#"
-					SET allow_experimental_lightweight_delete = true;
 					DELETE FROM {TABLE} WHERE network_id IN ({network_ids_string})
-                "#
+				"#
 			))
 			.await
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::MockDriver;
+
+	#[test]
+	fn test_get_balance_at_block_conditions() {
+		assert_eq!(
+			Amount::get_balance_at_block_conditions("a'b", 1, 100),
+			"address = 'a''b' AND network_id = 1 AND block_height <= 100"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_get_balance_at_block() -> Result<()> {
+		// narrows rows down the way the query's conditions say to
+		let driver = MockDriver::default().with_select(TABLE, |query, amounts: Vec<Amount>| {
+			let value = |condition: &str| -> String {
+				let (_, rest) = query.split_once(condition).unwrap();
+				rest.split(" AND ").next().unwrap().trim().to_string()
+			};
+
+			amounts
+				.into_iter()
+				.filter(|a| {
+					utils::quote_sql(&a.address) == value("address = ") &&
+						a.network_id.to_string() == value("network_id = ") &&
+						a.block_height <= value("block_height <= ").parse::<u64>().unwrap()
+				})
+				.collect()
+		});
+		let warehouse = driver.warehouse(10);
+
+		let amount = |network_id, block_height, address: &str, amount_in: u64, amount_out: u64| {
+			Amount::new(
+				ModuleId::BitcoinBalance,
+				network_id,
+				block_height,
+				"tx",
+				address,
+				None,
+				U256::from(amount_in),
+				U256::from(amount_out),
+				0,
+			)
+		};
+		warehouse
+			.insert(
+				TABLE,
+				&[
+					amount(1, 10, "address", 100, 0),
+					amount(1, 20, "address", 0, 30),
+					amount(1, 30, "address", 50, 0),
+					amount(1, 40, "address", 0, 120),
+					amount(1, 15, "other", 1_000, 0),
+					amount(2, 15, "address", 1_000, 0),
+				],
+			)
+			.await?;
+
+		let balance_at = |block_height| {
+			let warehouse = &warehouse;
+			async move {
+				let balances =
+					Amount::get_balance_at_block(warehouse, "address", 1, block_height).await?;
+				Ok::<_, eyre::Report>(balances.first().map(|b| b.balance))
+			}
+		};
+
+		assert_eq!(balance_at(5).await?, None);
+		assert_eq!(balance_at(10).await?, Some(U256::from(100)));
+		assert_eq!(balance_at(29).await?, Some(U256::from(70)));
+		assert_eq!(balance_at(30).await?, Some(U256::from(120)));
+		assert_eq!(balance_at(40).await?, Some(U256::zero()));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_get_net_balances() {
+		let amount = |network_id, asset_address: Option<&str>, amount_in: u64, amount_out: u64| {
+			Amount::new(
+				ModuleId::BitcoinBalance,
+				network_id,
+				1,
+				"tx",
+				"address",
+				asset_address.map(|a| a.to_string()),
+				U256::from(amount_in),
+				U256::from(amount_out),
+				0,
+			)
+		};
+
+		assert!(Amount::get_net_balances(vec![]).is_empty());
+
+		assert_eq!(
+			Amount::get_net_balances(vec![
+				amount(1, None, 100, 0),
+				amount(1, None, 0, 30),
+				amount(1, None, 5, 0),
+				amount(2, Some("token"), 10, 0),
+				amount(2, Some("token"), 0, 10),
+			]),
+			vec![
+				AddressBalance {
+					network_id: 1,
+					asset_address: "".to_string(),
+					balance: U256::from(75),
+				},
+				AddressBalance {
+					network_id: 2,
+					asset_address: "token".to_string(),
+					balance: U256::zero(),
+				},
+			]
+		);
+	}
+}
@@ -65,7 +65,6 @@ impl Model {
 		warehouse
 			.delete(&format!(
 				r#"
-					SET allow_experimental_lightweight_delete = true;
 					DELETE FROM {TABLE} WHERE network_id IN ({network_ids_string})
                 "#
 			))
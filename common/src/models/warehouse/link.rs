@@ -1,13 +1,17 @@
 use clickhouse::Row;
 use eyre::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::{
+	collections::{HashMap, HashSet},
+	future::Future,
+};
 use uuid::Uuid;
 
 use crate::{
 	models::{warehouse::transfer::TABLE as TRANSFERS_TABLE, PrimaryId, PrimaryIds},
+	utils,
 	warehouse::Warehouse,
-	BlockHeight,
+	BlockHeight, LINK_PATH_MAX_DEPTH,
 };
 
 pub static TABLE: &str = "links";
@@ -23,6 +27,10 @@ pub struct Model {
 	pub from_address: String,
 	pub to_address: String,
 	pub transfer_uuids: Vec<LinkUuid>,
+	/// Transfers of every link from the same source, when `get_all_distinct_by_addresses`
+	/// collapsed them into this one (never stored)
+	#[serde(default, skip_serializing)]
+	pub merged_transfer_uuids: Vec<LinkUuid>,
 	pub created_at: u32,
 }
 
@@ -43,6 +51,7 @@ impl Model {
 			from_address: from_address.to_string(),
 			to_address: to_address.to_string(),
 			transfer_uuids,
+			merged_transfer_uuids: vec![],
 			created_at,
 		}
 	}
@@ -68,7 +77,11 @@ impl Model {
 			.await
 	}
 
-	pub async fn get_all_disinct_by_addresses(
+	/// Same as `get_all_by_addresses`, but with a single link per source (`network_id` +
+	/// `from_address`): its shortest one, with the transfers of all of them (once each) in
+	/// `merged_transfer_uuids`. Ties, uuids and rows are ordered, so the same data always
+	/// comes back the same way
+	pub async fn get_all_distinct_by_addresses(
 		warehouse: &Warehouse,
 		mut addresses: Vec<String>,
 	) -> Result<Vec<Self>> {
@@ -76,20 +89,127 @@ impl Model {
 		addresses.dedup();
 
 		let formatted_addresses =
-			addresses.iter().map(|addr| format!("'{}'", addr)).collect::<Vec<_>>().join(", ");
+			addresses.iter().map(|addr| utils::quote_sql(addr)).collect::<Vec<_>>().join(", ");
+
+		warehouse
+			.select(&format!(
+				r#"
+					SELECT
+						network_id,
+						shortest.1 AS block_height,
+						from_address,
+						shortest.2 AS to_address,
+						shortest.3 AS transfer_uuids,
+						merged_transfer_uuids,
+						shortest.4 AS created_at
+					FROM (
+						SELECT
+							network_id,
+							from_address,
+							argMin(
+								(block_height, to_address, transfer_uuids, created_at),
+								(length(transfer_uuids), block_height, to_address)
+							) AS shortest,
+							arraySort(groupUniqArrayArray(transfer_uuids)) AS merged_transfer_uuids
+						FROM {TABLE}
+						WHERE to_address IN ({formatted_addresses})
+						GROUP BY network_id, from_address
+					)
+					ORDER BY network_id ASC, from_address ASC
+				"#
+			))
+			.await
+	}
+
+	pub async fn get_all_by_from_addresses(
+		warehouse: &Warehouse,
+		network_id: Option<PrimaryId>,
+		mut addresses: Vec<String>,
+	) -> Result<Vec<Self>> {
+		addresses.sort_unstable();
+		addresses.dedup();
+
+		let formatted_addresses =
+			addresses.iter().map(|addr| utils::quote_sql(addr)).collect::<Vec<_>>().join(", ");
+		let network_condition =
+			network_id.map(|id| format!("network_id = {id} AND")).unwrap_or_default();
 
 		warehouse
 			.select(&format!(
 				r#"
-					SELECT DISTINCT ON (network_id, from_address) *
+					SELECT *
 					FROM {TABLE}
-					WHERE to_address IN ({formatted_addresses})
-					ORDER BY LENGTH(transfer_uuids) ASC
+					WHERE {network_condition} from_address IN ({formatted_addresses})
+					ORDER BY block_height ASC
 				"#
 			))
 			.await
 	}
 
+	/// Shortest chain of links going from `from` to `to`, searched breadth-first one level
+	/// (one warehouse query) at a time. Depth is capped at `LINK_PATH_MAX_DEPTH`
+	pub async fn shortest_path(
+		warehouse: &Warehouse,
+		network_id: Option<PrimaryId>,
+		(from, to): (&str, &str),
+		max_depth: usize,
+	) -> Result<Option<Vec<Self>>> {
+		Self::find_shortest_path((from, to), max_depth, |addresses| {
+			Self::get_all_by_from_addresses(warehouse, network_id, addresses)
+		})
+		.await
+	}
+
+	async fn find_shortest_path<F, Fut>(
+		(from, to): (&str, &str),
+		max_depth: usize,
+		mut get_links: F,
+	) -> Result<Option<Vec<Self>>>
+	where
+		F: FnMut(Vec<String>) -> Fut,
+		Fut: Future<Output = Result<Vec<Self>>>,
+	{
+		if from == to {
+			return Ok(Some(vec![]));
+		}
+
+		let mut visited = HashSet::from([from.to_string()]);
+		let mut parents = HashMap::<String, Self>::new(); // to_address -> link
+		let mut frontier = vec![from.to_string()];
+
+		for _ in 0..max_depth.min(LINK_PATH_MAX_DEPTH) {
+			if frontier.is_empty() {
+				break;
+			}
+
+			let mut next_frontier = vec![];
+			for link in get_links(frontier).await?.into_iter() {
+				if visited.insert(link.to_address.clone()) {
+					next_frontier.push(link.to_address.clone());
+					parents.insert(link.to_address.clone(), link);
+				}
+			}
+
+			// walk back from the target to build the ordered hops
+			if parents.contains_key(to) {
+				let mut path = vec![];
+				let mut address = to.to_string();
+
+				while let Some(link) = parents.remove(&address) {
+					address = link.from_address.clone();
+					path.push(link);
+				}
+
+				path.reverse();
+				return Ok(Some(path));
+			}
+
+			frontier = next_frontier;
+		}
+
+		Ok(None)
+	}
+
 	pub async fn get_all_to_seed_blocks(
 		warehouse: &Warehouse,
 		network_id: PrimaryId,
@@ -124,7 +244,6 @@ impl Model {
 			warehouse
 				.delete(&format!(
 					r#"
-						SET allow_experimental_lightweight_delete = true;
 						DELETE FROM {TABLE} WHERE {}
 					"#,
 					Self::get_network_id_address_tuples(sources, "from_address"),
@@ -135,6 +254,23 @@ impl Model {
 		Ok(())
 	}
 
+	/// Drops every link that ends at `block_height` or later. Links only ever extend forward,
+	/// so those are all the links that could go through a transfer at or after it
+	pub async fn delete_all_from_block_height(
+		warehouse: &Warehouse,
+		network_id: PrimaryId,
+		block_height: BlockHeight,
+	) -> Result<()> {
+		warehouse
+			.delete(&format!(
+				r#"
+					DELETE FROM {TABLE}
+					WHERE network_id = {network_id} AND block_height >= {block_height}
+				"#
+			))
+			.await
+	}
+
 	pub async fn delete_all_by_network_id(
 		warehouse: &Warehouse,
 		network_ids: PrimaryIds,
@@ -145,7 +281,6 @@ impl Model {
 		warehouse
 			.delete(&format!(
 				r#"
-					SET allow_experimental_lightweight_delete = true;
 					DELETE FROM {TABLE} WHERE network_id IN ({network_ids_string})
                 "#
 			))
@@ -179,7 +314,6 @@ impl Model {
 			warehouse
 				.delete(&format!(
 					r#"
-						SET allow_experimental_lightweight_delete = true;
 						DELETE FROM {TABLE}
 						WHERE
 							length(transfer_uuids) > 2 AND
@@ -218,3 +352,90 @@ impl Model {
 			.join(" OR ")
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::MockDriver;
+	use futures::future;
+	use serde_json::json;
+
+	async fn find(links: &[Link], (from, to): (&str, &str), max_depth: usize) -> Option<Vec<Link>> {
+		Link::find_shortest_path((from, to), max_depth, |addresses| {
+			future::ready(Ok(links
+				.iter()
+				.filter(|l| addresses.contains(&l.from_address))
+				.cloned()
+				.collect()))
+		})
+		.await
+		.unwrap()
+	}
+
+	#[test]
+	fn test_merged_transfer_uuids_are_not_stored() -> Result<()> {
+		let mut link = Link::new(1, 1, "from", "to", vec![LinkUuid(utils::new_uuid())], 0);
+		link.merged_transfer_uuids = vec![LinkUuid(utils::new_uuid())];
+
+		let stored = serde_json::to_string(&link)?;
+		assert!(!stored.contains("merged_transfer_uuids"));
+
+		// plain links (`SELECT *`) come back without any
+		let read = serde_json::from_str::<Link>(&stored)?;
+		assert!(read.merged_transfer_uuids.is_empty());
+		assert_eq!(read.transfer_uuids, link.transfer_uuids);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_get_all_distinct_by_addresses() -> Result<()> {
+		let driver = MockDriver::default();
+		let warehouse = driver.warehouse(10);
+
+		let uuids = (0..3).map(|_| LinkUuid(utils::new_uuid())).collect::<Vec<_>>();
+		let row = json!({
+			"network_id": 1,
+			"block_height": 2,
+			"from_address": "a",
+			"to_address": "to",
+			"transfer_uuids": [uuids[0]],
+			"merged_transfer_uuids": uuids,
+			"created_at": 0,
+		});
+		warehouse.insert(TABLE, &[row]).await?;
+
+		// collapsed by the warehouse, one row per source
+		let links = Link::get_all_distinct_by_addresses(&warehouse, vec!["to".to_string()]).await?;
+		let query = driver.queries.lock().unwrap().pop().unwrap();
+		assert!(query.contains("GROUP BY network_id, from_address"));
+		assert!(query.contains("argMin("));
+		assert!(query.contains("arraySort(groupUniqArrayArray(transfer_uuids))"));
+		assert!(query.contains("ORDER BY network_id ASC, from_address ASC"));
+
+		// hops still come from the shortest link, transfers from all of them
+		assert_eq!(links.len(), 1);
+		assert_eq!(links[0].transfer_uuids, vec![uuids[0].clone()]);
+		assert_eq!(links[0].merged_transfer_uuids, uuids);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_find_shortest_path() {
+		let link = |from, to| Link::new(1, 1, from, to, vec![], 0);
+		let links = vec![
+			link("a", "b"),
+			link("a", "d"),
+			link("b", "c"),
+			link("d", "e"),
+			link("e", "c"),
+			link("x", "y"),
+		];
+
+		assert_eq!(find(&links, ("a", "c"), 5).await, Some(vec![link("a", "b"), link("b", "c")]));
+		assert_eq!(find(&links, ("a", "c"), 1).await, None);
+		assert_eq!(find(&links, ("a", "y"), 5).await, None);
+		assert_eq!(find(&links, ("a", "a"), 5).await, Some(vec![]));
+	}
+}
@@ -1,7 +1,7 @@
-pub use amount::{Amount, TABLE as AmountTable};
+pub use amount::{AddressBalance, Amount, TABLE as AmountTable};
 pub use balance::{Balance, TABLE as BalanceTable};
 pub use link::{Link, LinkUuid, TABLE as LinkTable};
-pub use transfer::{Transfer, TABLE as TransferTable};
+pub use transfer::{Transfer, TransferCursor, TransferVolume, TABLE as TransferTable};
 
 mod amount;
 mod balance;
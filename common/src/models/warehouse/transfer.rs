@@ -1,6 +1,10 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::NaiveDate;
 use clickhouse::Row;
 use eyre::Result;
+use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use uuid::Uuid;
 
 use crate::{
@@ -13,8 +17,12 @@ use crate::{
 
 pub static TABLE: &str = "transfers";
 
+/// A transfer is identified by its natural key: module, network, block height, tx hash, from,
+/// to, asset and both amounts (the warehouse's sorting key too). There's no output index: a
+/// module emits a single transfer per `from -> to` pair of a transaction
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Row, Serialize, Deserialize)]
 pub struct Model {
+	/// Derived from the natural key, see `Model::get_natural_key()`
 	#[serde(with = "clickhouse::serde::uuid")]
 	pub uuid: Uuid,
 	pub module_id: u16,
@@ -28,11 +36,50 @@ pub struct Model {
 	pub relative_amount: U256,
 	#[serde(with = "u256")]
 	pub batch_amount: U256,
+	/// Fee paid by the whole transaction (`0` when it's not known, eg: coinbase)
+	#[serde(with = "u256")]
+	pub fee: U256,
+	/// Header time of the block the transfer was included in (unix seconds)
 	pub created_at: u32,
 }
 
 pub use Model as Transfer;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferVolume {
+	pub date: NaiveDate,
+	pub count: u64,
+	pub total_amount: U256,
+}
+
+/// Position right after a transfer in `(block_height, uuid)` order, handed out as an opaque
+/// string. Unlike offsets, it doesn't shift when newer transfers are inserted and doesn't make
+/// the warehouse skip over everything before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferCursor {
+	pub block_height: BlockHeight,
+	pub uuid: Uuid,
+}
+
+impl TransferCursor {
+	pub fn decode(cursor: &str) -> Option<Self> {
+		let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+		let (block_height, uuid) = decoded.split_once('_')?;
+
+		Some(Self { block_height: block_height.parse().ok()?, uuid: Uuid::parse_str(uuid).ok()? })
+	}
+
+	pub fn encode(&self) -> String {
+		URL_SAFE_NO_PAD.encode(format!("{}_{}", self.block_height, self.uuid))
+	}
+}
+
+impl From<&Model> for TransferCursor {
+	fn from(transfer: &Model) -> Self {
+		Self { block_height: transfer.block_height, uuid: transfer.uuid }
+	}
+}
+
 impl Model {
 	pub fn new(
 		module_id: ModuleId,
@@ -46,8 +93,8 @@ impl Model {
 		batch_amount: U256,
 		created_at: u32,
 	) -> Self {
-		Self {
-			uuid: utils::new_uuid(),
+		let mut ret = Self {
+			uuid: Uuid::nil(),
 			module_id: module_id as u16,
 			network_id: network_id as u64,
 			block_height,
@@ -57,8 +104,135 @@ impl Model {
 			asset_address: asset_address.unwrap_or_default(),
 			relative_amount,
 			batch_amount,
+			fee: U256::zero(),
 			created_at,
+		};
+
+		// processing the same block again yields the same uuids
+		ret.uuid = utils::new_uuid_from(&ret.get_natural_key());
+
+		ret
+	}
+
+	pub fn get_natural_key(&self) -> String {
+		format!(
+			"{}:{}:{}:{}:{}:{}:{}:{}:{}",
+			self.module_id,
+			self.network_id,
+			self.block_height,
+			self.tx_hash,
+			self.from_address,
+			self.to_address,
+			self.asset_address,
+			self.relative_amount,
+			self.batch_amount
+		)
+	}
+
+	pub fn with_fee(mut self, fee: U256) -> Self {
+		self.fee = fee;
+		self
+	}
+
+	/// Skips transfers that are already stored (or repeated within `models`), so reprocessing
+	/// a block (eg: after a crash between saving transfers and its checkpoint, or a retry)
+	/// doesn't insert them twice. Stored uuids are looked up by block range per module and
+	/// network, which the warehouse's sorting key serves (unlike a lookup by uuid)
+	pub async fn create_many(warehouse: &Warehouse, models: Vec<Self>) -> Result<()> {
+		#[derive(Debug, Clone, Serialize, Deserialize)]
+		struct Data {
+			#[serde(with = "clickhouse::serde::uuid")]
+			uuid: Uuid,
+		}
+
+		if models.is_empty() {
+			return Ok(());
+		}
+
+		let mut block_ranges = BTreeMap::<(u16, u64), (u64, u64)>::new();
+		for m in models.iter() {
+			block_ranges
+				.entry((m.module_id, m.network_id))
+				.and_modify(|(min, max)| {
+					*min = (*min).min(m.block_height);
+					*max = (*max).max(m.block_height);
+				})
+				.or_insert((m.block_height, m.block_height));
+		}
+
+		let queries = block_ranges
+			.into_iter()
+			.map(|((module_id, network_id), (block_height_min, block_height_max))| {
+				format!(
+					r#"
+						SELECT uuid
+						FROM {TABLE}
+						WHERE
+							module_id = {module_id} AND
+							network_id = {network_id} AND
+							block_height >= {block_height_min} AND
+							block_height <= {block_height_max}
+					"#
+				)
+			})
+			.collect::<Vec<_>>();
+
+		let mut existing = try_join_all(queries.iter().map(|q| warehouse.select::<Data>(q)))
+			.await?
+			.into_iter()
+			.flatten()
+			.map(|d| d.uuid)
+			.collect::<HashSet<_>>();
+
+		let models = models.into_iter().filter(|m| existing.insert(m.uuid)).collect::<Vec<_>>();
+		if models.is_empty() {
+			return Ok(());
 		}
+
+		warehouse.insert(TABLE, &models).await
+	}
+
+	pub async fn count(warehouse: &Warehouse) -> Result<u64> {
+		#[derive(Debug, Clone, Serialize, Deserialize)]
+		struct Data {
+			count: u64,
+		}
+
+		let results: Vec<Data> =
+			warehouse.select(&format!("SELECT count(*) AS count FROM {TABLE}")).await?;
+
+		Ok(results.first().map(|d| d.count).unwrap_or(0))
+	}
+
+	/// Looks up transfers by uuid, `chunk_size` uuids per query (run concurrently), so hot
+	/// addresses don't end up with a single huge `IN (...)` clause
+	pub async fn get_all_by_uuids(
+		warehouse: &Warehouse,
+		mut uuids: Vec<Uuid>,
+		chunk_size: usize,
+	) -> Result<Vec<Self>> {
+		uuids.sort_unstable();
+		uuids.dedup();
+
+		let queries = uuids
+			.chunks(chunk_size.max(1))
+			.map(|chunk| {
+				let formatted_uuids =
+					chunk.iter().map(|uuid| format!("'{uuid}'")).collect::<Vec<_>>().join(", ");
+
+				format!(
+					r#"
+						SELECT *
+						FROM {TABLE}
+						WHERE uuid IN ({formatted_uuids})
+					"#
+				)
+			})
+			.collect::<Vec<_>>();
+
+		let results =
+			try_join_all(queries.iter().map(|query| warehouse.select::<Self>(query))).await?;
+		Ok(results.concat())
 	}
 
 	pub async fn get_first_by_source(
@@ -74,7 +248,7 @@ impl Model {
 					WHERE network_id = {network_id} AND from_address = {address}
 					ORDER BY created_at ASC
 					LIMIT 1
-                "#
+				"#
 			))
 			.await?;
 
@@ -99,7 +273,171 @@ impl Model {
 						block_height >= {block_height_min} AND
 						block_height <= {block_height_max}
 					ORDER BY block_height ASC
-                "#
+				"#
+			))
+			.await
+	}
+
+	pub async fn get_all_by_tx_hash(
+		warehouse: &Warehouse,
+		network_id: PrimaryId,
+		tx_hash: &str,
+	) -> Result<Vec<Self>> {
+		let tx_hash = utils::quote_sql(tx_hash);
+
+		warehouse
+			.select(&format!(
+				r#"
+					SELECT *
+					FROM {TABLE}
+					WHERE network_id = {network_id} AND tx_hash = {tx_hash}
+					ORDER BY module_id ASC
+				"#
+			))
+			.await
+	}
+
+	/// Direct transfers sent from `from_address` to `to_address`, optionally narrowed down
+	/// to a network and to block height and/or time ranges (both inclusive). They're paged
+	/// through `limit` at a time starting after `cursor`, and the returned cursor is set
+	/// while there are more
+	pub async fn get_between_addresses(
+		warehouse: &Warehouse,
+		(from_address, to_address): (&str, &str),
+		network_id: Option<PrimaryId>,
+		block_range: Option<(BlockHeight, BlockHeight)>,
+		time_range: Option<(u32, u32)>,
+		cursor: Option<TransferCursor>,
+		limit: u64,
+	) -> Result<(Vec<Self>, Option<TransferCursor>)> {
+		let conditions = Self::get_between_addresses_conditions(
+			(from_address, to_address),
+			network_id,
+			block_range,
+			time_range,
+			cursor,
+		);
+
+		// one extra row tells whether there's another page
+		let mut transfers: Vec<Self> = warehouse
+			.select(&format!(
+				r#"
+					SELECT *
+					FROM {TABLE}
+					WHERE {conditions}
+					ORDER BY block_height ASC, uuid ASC
+					LIMIT {}
+				"#,
+				limit.saturating_add(1)
+			))
+			.await?;
+
+		let next_cursor = match transfers.len() as u64 > limit {
+			true => {
+				transfers.truncate(limit as usize);
+				transfers.last().map(TransferCursor::from)
+			}
+			_ => None,
+		};
+
+		Ok((transfers, next_cursor))
+	}
+
+	fn get_between_addresses_conditions(
+		(from_address, to_address): (&str, &str),
+		network_id: Option<PrimaryId>,
+		block_range: Option<(BlockHeight, BlockHeight)>,
+		time_range: Option<(u32, u32)>,
+		cursor: Option<TransferCursor>,
+	) -> String {
+		let mut conditions = vec![
+			format!("from_address = {}", utils::quote_sql(from_address)),
+			format!("to_address = {}", utils::quote_sql(to_address)),
+		];
+
+		if let Some(network_id) = network_id {
+			conditions.push(format!("network_id = {network_id}"));
+		}
+
+		if let Some((block_height_min, block_height_max)) = block_range {
+			conditions.push(format!("block_height >= {block_height_min}"));
+			conditions.push(format!("block_height <= {block_height_max}"));
+		}
+
+		if let Some((created_at_min, created_at_max)) = time_range {
+			conditions.push(format!("created_at >= {created_at_min}"));
+			conditions.push(format!("created_at <= {created_at_max}"));
+		}
+
+		if let Some(TransferCursor { block_height, uuid }) = cursor {
+			conditions.push(format!(
+				"(block_height > {block_height} OR (block_height = {block_height} AND uuid > \
+				 '{uuid}'))"
+			));
+		}
+
+		conditions.join(" AND ")
+	}
+
+	/// Daily (UTC) transfer counts and totals for `[from, to)` (unix timestamps)
+	pub async fn aggregate_by_day(
+		warehouse: &Warehouse,
+		network_id: PrimaryId,
+		(from, to): (u32, u32),
+	) -> Result<Vec<TransferVolume>> {
+		#[derive(Debug, Clone, Serialize, Deserialize)]
+		struct Data {
+			date: String,
+			count: u64,
+			#[serde(with = "u256")]
+			total_amount: U256,
+		}
+
+		let results: Vec<Data> = warehouse
+			.select(&format!(
+				r#"
+					SELECT
+						toString(toDate(created_at, 'UTC')) AS date,
+						count() AS count,
+						sum(relative_amount) AS total_amount
+					FROM {TABLE}
+					WHERE
+						network_id = {network_id} AND
+						created_at >= {from} AND
+						created_at < {to}
+					GROUP BY date
+					ORDER BY date ASC
+				"#
+			))
+			.await?;
+
+		results
+			.into_iter()
+			.map(|d| {
+				Ok(TransferVolume {
+					date: NaiveDate::parse_from_str(&d.date, "%Y-%m-%d")?,
+					count: d.count,
+					total_amount: d.total_amount,
+				})
+			})
+			.collect()
+	}
+
+	/// Both ends of the range are inclusive
+	pub async fn delete_all_by_block_range(
+		warehouse: &Warehouse,
+		network_id: PrimaryId,
+		(block_height_min, block_height_max): (BlockHeight, BlockHeight),
+	) -> Result<()> {
+		warehouse
+			.delete(&format!(
+				r#"
+					DELETE FROM {TABLE}
+					WHERE
+						network_id = {network_id} AND
+						block_height >= {block_height_min} AND
+						block_height <= {block_height_max}
+				"#
 			))
 			.await
 	}
@@ -114,10 +452,362 @@ impl Model {
 		warehouse
 			.delete(&format!(
 				r#"
-					SET allow_experimental_lightweight_delete = true;
 					DELETE FROM {TABLE} WHERE network_id IN ({network_ids_string})
-                "#
+				"#
 			))
 			.await
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		chain::WarehouseData,
+		models::{Amount, AmountTable},
+		testing::MockDriver,
+	};
+	use std::{ops::Range, sync::Arc};
+
+	/// Serves the transfers (incl inserted ones) whose uuids show up in a query. Lookups
+	/// between addresses honor the cursor and limit, and lookups by block range the range
+	fn new_driver() -> MockDriver {
+		MockDriver::default().with_select(TABLE, |query, mut transfers: Vec<Transfer>| {
+			if query.contains("from_address = ") {
+				let cursor = query.split_once("uuid > '").map(|(head, tail)| {
+					let (_, block_height) = head.rsplit_once("block_height = ").unwrap();
+					let block_height = block_height.split_whitespace().next().unwrap();
+					(block_height.parse::<u64>().unwrap(), Uuid::parse_str(&tail[..36]).unwrap())
+				});
+				let limit = query.split_once("LIMIT ").map(|(_, tail)| {
+					tail.split_whitespace().next().unwrap().parse::<usize>().unwrap()
+				});
+
+				transfers.retain(|t| !matches!(cursor, Some(c) if (t.block_height, t.uuid) <= c));
+				transfers.sort_by_key(|t| (t.block_height, t.uuid));
+				transfers.truncate(limit.unwrap_or(usize::MAX));
+			} else if query.contains("block_height >= ") {
+				let value = |key: &str| -> u64 {
+					let (_, tail) = query.split_once(key).unwrap();
+					tail.split_whitespace().next().unwrap().parse().unwrap()
+				};
+				transfers.retain(|t| {
+					t.module_id == value("module_id = ") as u16 &&
+						t.network_id == value("network_id = ") &&
+						(value("block_height >= ")..=value("block_height <= "))
+							.contains(&t.block_height)
+				});
+			} else {
+				transfers.retain(|t| query.contains(&format!("'{}'", t.uuid)));
+			}
+
+			transfers
+		})
+	}
+
+	#[tokio::test]
+	async fn test_get_all_by_uuids() -> Result<()> {
+		let transfers = (0..25)
+			.map(|block_height| {
+				Transfer::new(
+					ModuleId::BitcoinTransfer,
+					1,
+					block_height,
+					"tx",
+					"from",
+					"to",
+					None,
+					U256::from(1),
+					U256::from(1),
+					0,
+				)
+			})
+			.collect::<Vec<_>>();
+
+		let driver = new_driver();
+		let warehouse = driver.warehouse(10);
+		warehouse.insert(TABLE, &transfers).await?;
+
+		// out of order, with duplicates and an unknown uuid
+		let uuids = transfers
+			.iter()
+			.rev()
+			.chain(transfers.iter())
+			.map(|t| t.uuid)
+			.chain([utils::new_uuid()])
+			.collect::<Vec<_>>();
+
+		let mut ret = Transfer::get_all_by_uuids(&warehouse, uuids, 10).await?;
+		ret.sort_by_key(|t| t.block_height);
+
+		assert_eq!(driver.queries.lock().unwrap().len(), 3);
+		assert_eq!(ret, transfers);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_create_many_twice() -> Result<()> {
+		let driver = new_driver();
+		let warehouse = driver.warehouse(10);
+
+		// processing the same block builds new (but identical) transfers every time
+		let process_block = || {
+			["a", "b"]
+				.into_iter()
+				.map(|to| {
+					Transfer::new(
+						ModuleId::BitcoinTransfer,
+						1,
+						1,
+						"tx",
+						"from",
+						to,
+						None,
+						U256::from(1),
+						U256::from(1),
+						0,
+					)
+				})
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(process_block(), process_block());
+
+		Transfer::create_many(&warehouse, process_block()).await?;
+		Transfer::create_many(&warehouse, process_block()).await?;
+
+		assert_eq!(driver.rows::<Transfer>(TABLE), process_block());
+
+		// stored transfers are looked up by block range, never by uuid
+		let queries = driver.queries.lock().unwrap().clone();
+		assert_eq!(queries.len(), 2);
+		assert!(queries.iter().all(|q| q.contains("block_height <= 1") && !q.contains("uuid IN")));
+
+		// committing the block's data again doesn't duplicate its amounts either (which
+		// balances are summed from)
+		let driver = new_driver().with_select(AmountTable, |query, mut amounts: Vec<Amount>| {
+			let value = |key: &str| -> u64 {
+				let (_, tail) = query.split_once(key).unwrap();
+				tail.split_whitespace().next().unwrap().parse().unwrap()
+			};
+			amounts.retain(|a| {
+				a.module_id == value("module_id = ") as u16 &&
+					a.network_id == value("network_id = ") &&
+					(value("block_height >= ")..=value("block_height <= "))
+						.contains(&a.block_height)
+			});
+			amounts
+		});
+		let warehouse = Arc::new(driver.warehouse(10));
+
+		let process_block = || {
+			let mut data = WarehouseData::new();
+			data.transfers.extend(process_block());
+			data.amounts.extend(["from", "to"].into_iter().map(|address| {
+				Amount::new(
+					ModuleId::BitcoinBalance,
+					1,
+					1,
+					"tx",
+					address,
+					None,
+					U256::from(1),
+					U256::zero(),
+					0,
+				)
+			}));
+			data
+		};
+
+		process_block().commit(warehouse.clone()).await?;
+		process_block().commit(warehouse.clone()).await?;
+
+		let (data, transfers, amounts) =
+			(process_block(), driver.rows::<Transfer>(TABLE), driver.rows::<Amount>(AmountTable));
+		assert_eq!(transfers.len(), data.transfers.len());
+		assert_eq!(transfers.into_iter().collect::<HashSet<_>>(), data.transfers);
+		assert_eq!(amounts.len(), data.amounts.len());
+		assert_eq!(amounts.into_iter().collect::<HashSet<_>>(), data.amounts);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_create_many_duplicates() -> Result<()> {
+		let driver = new_driver();
+		let warehouse = driver.warehouse(10);
+
+		let new_transfer = |fee: u64| {
+			Transfer::new(
+				ModuleId::BitcoinTransfer,
+				1,
+				1,
+				"tx",
+				"from",
+				"to",
+				None,
+				U256::from(1),
+				U256::from(1),
+				0,
+			)
+			.with_fee(U256::from(fee))
+		};
+
+		// the same logical transfer (fee is not part of the key)
+		let transfer = new_transfer(1);
+		assert_eq!(transfer.get_natural_key(), "102:1:1:tx:from:to::1:1");
+		assert_eq!(transfer.uuid, new_transfer(2).uuid);
+
+		Transfer::create_many(&warehouse, vec![transfer.clone(), new_transfer(2)]).await?;
+		assert_eq!(driver.rows::<Transfer>(TABLE), vec![transfer]);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_get_between_addresses_paging() -> Result<()> {
+		let driver = new_driver();
+		let warehouse = driver.warehouse(10);
+
+		// a couple per block, so pages end mid-block too
+		let new_transfers = |block_heights: Range<u64>| {
+			block_heights
+				.flat_map(|block_height| {
+					["tx_a", "tx_b"].map(|tx_hash| {
+						Transfer::new(
+							ModuleId::BitcoinTransfer,
+							1,
+							block_height,
+							tx_hash,
+							"from",
+							"to",
+							None,
+							U256::from(1),
+							U256::from(1),
+							0,
+						)
+					})
+				})
+				.collect::<Vec<_>>()
+		};
+
+		Transfer::create_many(&warehouse, new_transfers(0..5)).await?;
+
+		let (mut seen, mut cursor) = (vec![], None);
+		for page in 0.. {
+			let (transfers, next_cursor) = Transfer::get_between_addresses(
+				&warehouse,
+				("from", "to"),
+				None,
+				None,
+				None,
+				cursor,
+				3,
+			)
+			.await?;
+			seen.extend(transfers);
+
+			// new blocks keep coming in while paging
+			if page == 0 {
+				Transfer::create_many(&warehouse, new_transfers(5..7)).await?;
+			}
+
+			// clients only ever get to see the encoded cursor
+			match next_cursor {
+				Some(next_cursor) => cursor = TransferCursor::decode(&next_cursor.encode()),
+				None => break,
+			}
+		}
+
+		let mut all = driver.rows::<Transfer>(TABLE);
+		all.sort_by_key(|t| (t.block_height, t.uuid));
+		assert_eq!(all.len(), 14);
+		assert_eq!(seen, all);
+
+		assert_eq!(TransferCursor::decode("not a cursor"), None);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_get_between_addresses_conditions() {
+		assert_eq!(
+			Transfer::get_between_addresses_conditions(("a", "b"), None, None, None, None),
+			"from_address = 'a' AND to_address = 'b'"
+		);
+
+		assert_eq!(
+			Transfer::get_between_addresses_conditions(
+				("a", "b'"),
+				Some(1),
+				Some((10, 20)),
+				Some((100, 200)),
+				None
+			),
+			"from_address = 'a' AND to_address = 'b''' AND network_id = 1 AND \
+			 block_height >= 10 AND block_height <= 20 AND created_at >= 100 AND created_at <= 200"
+		);
+
+		let uuid = Uuid::nil();
+		assert_eq!(
+			Transfer::get_between_addresses_conditions(
+				("a", "b"),
+				None,
+				None,
+				None,
+				Some(TransferCursor { block_height: 5, uuid })
+			),
+			format!(
+				"from_address = 'a' AND to_address = 'b' AND \
+				 (block_height > 5 OR (block_height = 5 AND uuid > '{uuid}'))"
+			)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_aggregate_by_day() -> Result<()> {
+		#[derive(Serialize, Deserialize)]
+		struct Data {
+			date: String,
+			count: u64,
+			#[serde(with = "u256")]
+			total_amount: U256,
+		}
+
+		// rows as the warehouse would have grouped them
+		let driver = MockDriver::default().with_select(TABLE, |_, _: Vec<Data>| {
+			[("2023-11-14", 2, 15), ("2023-11-15", 1, 7)]
+				.map(|(date, count, total_amount)| Data {
+					date: date.to_string(),
+					count,
+					total_amount: U256::from(total_amount),
+				})
+				.into_iter()
+				.collect()
+		});
+		let warehouse = driver.warehouse(10);
+
+		assert_eq!(
+			Transfer::aggregate_by_day(&warehouse, 1, (1_700_000_000, 1_700_172_800)).await?,
+			vec![
+				TransferVolume {
+					date: NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+					count: 2,
+					total_amount: U256::from(15),
+				},
+				TransferVolume {
+					date: NaiveDate::from_ymd_opt(2023, 11, 15).unwrap(),
+					count: 1,
+					total_amount: U256::from(7),
+				},
+			]
+		);
+
+		// bucketed by the warehouse, not in memory
+		let query = driver.queries.lock().unwrap().pop().unwrap();
+		assert!(query.contains("GROUP BY date"));
+		assert!(query.contains("toDate(created_at, 'UTC')"));
+
+		Ok(())
+	}
+}
@@ -0,0 +1,352 @@
+use eyre::{eyre, Result};
+use futures::stream::{self, StreamExt};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use sea_orm::ConnectionTrait;
+use serde_json::json;
+use std::{
+	collections::{HashMap, HashSet},
+	net::IpAddr,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::{
+	net::lookup_host,
+	sync::{mpsc, Mutex},
+	time::sleep,
+};
+use tracing::warn;
+use url::{Host, Url};
+
+use crate::{
+	chain::JsonAmount,
+	models::{Address, PrimaryId, Transfer, Webhook},
+	utils, WEBHOOK_CONCURRENCY, WEBHOOK_MAX_ATTEMPTS, WEBHOOK_QUEUE_CAPACITY, WEBHOOK_TIMEOUT,
+};
+
+pub static SIGNATURE_HEADER: &str = "X-Barreleye-Signature";
+
+/// Delivers webhooks for transfers that touch watched (locked) addresses. Each payload
+/// is signed with the subscription's secret; failed deliveries are retried with
+/// exponential backoff
+///
+/// Transfers are queued up and delivered by a single worker (see `run`), so a burst of
+/// blocks or slow endpoints can't pile up unbounded work. Webhooks can only reach public
+/// addresses
+pub struct Notifier {
+	client: reqwest::Client,
+	max_attempts: u32,
+	public_only: bool,
+	numeric_amounts: bool,
+	queue_tx: mpsc::Sender<Vec<Transfer>>,
+	queue_rx: Mutex<mpsc::Receiver<Vec<Transfer>>>,
+}
+
+impl Notifier {
+	pub fn new() -> Self {
+		Self::new_with(true)
+	}
+
+	fn new_with(public_only: bool) -> Self {
+		let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(WEBHOOK_TIMEOUT));
+		if public_only {
+			builder = builder.dns_resolver(Arc::new(PublicResolver));
+		}
+
+		let (queue_tx, queue_rx) = mpsc::channel(WEBHOOK_QUEUE_CAPACITY);
+
+		Self {
+			client: builder.build().unwrap_or_default(),
+			max_attempts: WEBHOOK_MAX_ATTEMPTS,
+			public_only,
+			numeric_amounts: false,
+			queue_tx,
+			queue_rx: Mutex::new(queue_rx),
+		}
+	}
+
+	/// Amounts in payloads as json numbers (see `Settings::numeric_amounts`)
+	pub fn with_numeric_amounts(mut self, numeric_amounts: bool) -> Self {
+		self.numeric_amounts = numeric_amounts;
+		self
+	}
+
+	/// Queues up transfers for delivery without waiting on it. When the queue is full the
+	/// transfers are dropped (and `false` is returned), rather than holding up indexing
+	pub fn enqueue(&self, transfers: Vec<Transfer>) -> bool {
+		if transfers.is_empty() {
+			return true;
+		}
+
+		match self.queue_tx.try_send(transfers) {
+			Ok(_) => true,
+			Err(e) => {
+				warn!(error = %e, "webhook queue is full; dropping transfers");
+				false
+			}
+		}
+	}
+
+	/// Works through queued up transfers, one batch at a time
+	pub async fn run<C>(&self, c: &C) -> Result<()>
+	where
+		C: ConnectionTrait,
+	{
+		let mut queue_rx = self.queue_rx.lock().await;
+
+		while let Some(transfers) = queue_rx.recv().await {
+			if let Err(e) = self.notify(c, transfers).await {
+				warn!(error = %e, "could not deliver webhooks");
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns how many deliveries were attempted
+	pub async fn notify<C>(&self, c: &C, transfers: Vec<Transfer>) -> Result<usize>
+	where
+		C: ConnectionTrait,
+	{
+		if transfers.is_empty() {
+			return Ok(0);
+		}
+
+		let webhooks = Webhook::get_all_active(c).await?;
+		if webhooks.is_empty() {
+			return Ok(0);
+		}
+
+		// find transfers touching a watched address
+		let addresses = Address::get_all_by_addresses(
+			c,
+			transfers.iter().flat_map(|t| [t.from_address.clone(), t.to_address.clone()]).collect(),
+			Some(false),
+		)
+		.await?;
+
+		let watched = addresses
+			.iter()
+			.filter(|a| a.is_locked)
+			.map(|a| ((a.network_id, a.address.as_str()), a))
+			.collect::<HashMap<_, _>>();
+
+		let mut payloads = vec![];
+		for t in transfers.iter() {
+			let network_id = t.network_id as PrimaryId;
+
+			let mut seen = HashSet::new();
+			for address in [&t.from_address, &t.to_address] {
+				if let Some(a) = watched.get(&(network_id, address.as_str())) {
+					if seen.insert(a.address_id) {
+						payloads.push(json!({
+							"address": {
+								"id": a.id,
+								"network": a.network,
+								"address": a.address,
+							},
+							"transfer": {
+								"blockHeight": t.block_height,
+								"txHash": t.tx_hash,
+								"from": t.from_address,
+								"to": t.to_address,
+								"asset": t.asset_address,
+								"amount": JsonAmount::new(t.relative_amount, self.numeric_amounts),
+								"createdAt": t.created_at,
+							},
+						}));
+					}
+				}
+			}
+		}
+
+		let deliveries = webhooks
+			.iter()
+			.flat_map(|w| {
+				payloads.iter().map(move |p| {
+					let mut payload = p.clone();
+					payload["webhook"] = json!(w.id);
+					self.deliver(w, payload.to_string())
+				})
+			})
+			.collect::<Vec<_>>();
+
+		let ret = deliveries.len();
+		stream::iter(deliveries).buffer_unordered(WEBHOOK_CONCURRENCY).collect::<Vec<_>>().await;
+
+		Ok(ret)
+	}
+
+	async fn deliver(&self, webhook: &Webhook, payload: String) -> bool {
+		// hostnames are checked when resolved, ip addresses have to be checked upfront
+		if self.public_only && !is_public_url(&webhook.url) {
+			warn!(webhook = %webhook.id, "webhook url is not public; skipping");
+			return false;
+		}
+
+		let signature = format!("sha256={}", utils::hmac_sha256(&webhook.secret, &payload));
+
+		for attempt in 0..self.max_attempts {
+			if attempt > 0 {
+				sleep(Duration::from_secs(1 << (attempt - 1))).await;
+			}
+
+			let response = self
+				.client
+				.post(&webhook.url)
+				.header("Content-Type", "application/json")
+				.header(SIGNATURE_HEADER, &signature)
+				.body(payload.clone())
+				.send()
+				.await;
+
+			match response {
+				Ok(r) if r.status().is_success() => return true,
+				Ok(r) => {
+					warn!(webhook = %webhook.id, status = %r.status(), "webhook delivery failed")
+				}
+				Err(e) => warn!(webhook = %webhook.id, error = %e, "webhook delivery failed"),
+			}
+		}
+
+		false
+	}
+}
+
+impl Default for Notifier {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Whether `url` is an http(s) url that isn't pointing at a non-public ip address. Hostnames
+/// are left to `PublicResolver`
+pub fn is_public_url(url: &str) -> bool {
+	let Ok(url) = Url::parse(url) else { return false };
+	if !["http", "https"].contains(&url.scheme()) {
+		return false;
+	}
+
+	match url.host() {
+		Some(Host::Ipv4(ip)) => utils::is_public_ip(IpAddr::V4(ip)),
+		Some(Host::Ipv6(ip)) => utils::is_public_ip(IpAddr::V6(ip)),
+		Some(Host::Domain(_)) => true,
+		None => false,
+	}
+}
+
+/// Resolves hostnames like the system does, but never to non-public addresses, so webhooks
+/// can't be pointed at internal services (not even by changing dns records later on)
+pub struct PublicResolver;
+
+impl PublicResolver {
+	pub async fn lookup(host: &str) -> Result<Vec<std::net::SocketAddr>> {
+		let addrs = lookup_host((host, 0))
+			.await?
+			.filter(|addr| utils::is_public_ip(addr.ip()))
+			.collect::<Vec<_>>();
+		if addrs.is_empty() {
+			return Err(eyre!("{host} does not resolve to a public address"));
+		}
+
+		Ok(addrs)
+	}
+}
+
+impl Resolve for PublicResolver {
+	fn resolve(&self, name: Name) -> Resolving {
+		Box::pin(async move {
+			let addrs = Self::lookup(name.as_str()).await.map_err(|e| e.to_string())?;
+			Ok(Box::new(addrs.into_iter()) as Addrs)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		chain::{ModuleId, U256},
+		models::{set, BasicModel, Entity},
+		testing::{self, MockRpc},
+		Db,
+	};
+	use serde_json::Value as JsonValue;
+	use std::sync::atomic::Ordering;
+
+	#[tokio::test]
+	async fn test_notify() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		// count incoming requests
+		let receiver = MockRpc::start(|_, _| Ok(JsonValue::Null)).await?;
+		let (url, requests) = (receiver.url, receiver.requests);
+
+		// watch an address, next to one that's not locked
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let network_id = network.network_id;
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+		for (address, is_locked) in [("watched", true), ("unlocked", false)] {
+			let mut model =
+				Address::new_model(None, entity_id, network_id, &network.id, address, "", None);
+			model.is_locked = set(is_locked);
+			Address::create(db.get(), model).await?;
+		}
+		Webhook::create(db.get(), Webhook::new_model(None, &url)).await?;
+
+		let new_transfer = |to: &str| {
+			Transfer::new(
+				ModuleId::BitcoinTransfer,
+				network_id,
+				1,
+				"tx",
+				"from",
+				to,
+				None,
+				U256::from(1),
+				U256::from(1),
+				0,
+			)
+		};
+
+		let transfers =
+			vec![new_transfer("watched"), new_transfer("unlocked"), new_transfer("other")];
+		assert_eq!(Notifier::new_with(false).notify(db.get(), transfers.clone()).await?, 1);
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+		// local endpoints are off limits by default
+		assert_eq!(Notifier::new().notify(db.get(), transfers.clone()).await?, 1);
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+		// queued up transfers are delivered by the worker
+		let notifier = Arc::new(Notifier::new_with(false));
+		assert!(notifier.enqueue(transfers));
+		let worker = tokio::spawn({
+			let notifier = notifier.clone();
+			async move { notifier.run(db.get()).await }
+		});
+		while requests.load(Ordering::SeqCst) < 2 {
+			sleep(Duration::from_millis(10)).await;
+		}
+		worker.abort();
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_is_public_url() {
+		let data = HashMap::from([
+			("https://example.com/hook", true),
+			("http://1.1.1.1/hook", true),
+			("http://127.0.0.1:8080/hook", false),
+			("http://[::1]/hook", false),
+			("http://169.254.169.254/latest/meta-data", false),
+			("http://10.0.0.1/hook", false),
+			("ftp://example.com/hook", false),
+			("not a url", false),
+		]);
+
+		for (url, is_public) in data.into_iter() {
+			assert_eq!(is_public_url(url), is_public, "{url}");
+		}
+	}
+}
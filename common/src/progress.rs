@@ -0,0 +1,258 @@
+use eyre::Result;
+use sea_orm::ConnectionTrait;
+use serde::Serialize;
+use std::{
+	collections::{HashMap, VecDeque},
+	time::{Duration, Instant},
+};
+
+use crate::{
+	models::{Config, ConfigKey, Network, PrimaryId, SoftDeleteModel},
+	BlockHeight,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+	pub network: String,
+	pub node_height: BlockHeight,
+	pub last_processed: BlockHeight,
+	pub blocks_behind: BlockHeight,
+	pub rate_per_min: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+	Healthy,
+	Degraded,
+}
+
+impl Health {
+	/// Networks more than `max_blocks_behind` blocks behind their node's tip are degraded
+	/// (eg: a stalled rpc or a slow warehouse). A max of `0` disables the check
+	pub fn new(blocks_behind: BlockHeight, max_blocks_behind: BlockHeight) -> Self {
+		match max_blocks_behind > 0 && blocks_behind > max_blocks_behind {
+			true => Health::Degraded,
+			_ => Health::Healthy,
+		}
+	}
+}
+
+/// How far a network's indexing is, as recorded by the indexer (both `0` until known)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Heights {
+	pub node_height: BlockHeight,
+	pub last_processed: BlockHeight,
+}
+
+impl Heights {
+	pub async fn get<C>(c: &C, network_id: PrimaryId) -> Result<Self>
+	where
+		C: ConnectionTrait,
+	{
+		let node_height = Config::get::<_, BlockHeight>(c, ConfigKey::BlockHeight(network_id))
+			.await?
+			.map(|v| v.value)
+			.unwrap_or(0);
+
+		let last_processed =
+			Config::get::<_, BlockHeight>(c, ConfigKey::IndexerProcessTail(network_id))
+				.await?
+				.map(|v| v.value)
+				.unwrap_or(0);
+
+		Ok(Self { node_height, last_processed })
+	}
+
+	pub fn blocks_behind(&self) -> BlockHeight {
+		self.node_height.saturating_sub(self.last_processed)
+	}
+}
+
+/// Indexing progress per network. The processing rate is measured between consecutive
+/// calls, so it reads as `0` on the first one
+#[derive(Default)]
+pub struct ProgressTracker {
+	samples: HashMap<PrimaryId, (BlockHeight, Instant)>,
+}
+
+impl ProgressTracker {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Networks that are being indexed (deleted and paused ones aren't moving on purpose)
+	pub async fn get_all<C>(&mut self, c: &C) -> Result<Vec<Progress>>
+	where
+		C: ConnectionTrait,
+	{
+		let mut ret = vec![];
+
+		for network in Network::get_all_existing(c, Some(false)).await?.into_iter() {
+			let nid = network.network_id;
+			if Network::is_paused(c, nid).await? {
+				continue;
+			}
+
+			let heights = Heights::get(c, nid).await?;
+			ret.push(self.update(nid, &network.id, heights, Instant::now()));
+		}
+
+		Ok(ret)
+	}
+
+	fn update(
+		&mut self,
+		network_id: PrimaryId,
+		network: &str,
+		heights: Heights,
+		now: Instant,
+	) -> Progress {
+		let Heights { node_height, last_processed } = heights;
+
+		let rate_per_min = match self.samples.insert(network_id, (last_processed, now)) {
+			Some((prev_processed, prev_at)) if now > prev_at => {
+				let blocks = last_processed.saturating_sub(prev_processed) as f64;
+				let rate = blocks * 60.0 / now.duration_since(prev_at).as_secs_f64();
+				(rate * 100.0).round() / 100.0
+			}
+			_ => 0.0,
+		};
+
+		Progress {
+			network: network.to_string(),
+			node_height,
+			last_processed,
+			blocks_behind: heights.blocks_behind(),
+			rate_per_min,
+		}
+	}
+}
+
+/// Blocks-per-second over the last few samples, so a single slow (or fast) interval
+/// doesn't throw off the estimated time left
+pub struct RollingRate {
+	window: usize,
+	samples: VecDeque<(BlockHeight, Instant)>,
+}
+
+impl RollingRate {
+	pub fn new(window: usize) -> Self {
+		Self { window: window.max(2), samples: VecDeque::new() }
+	}
+
+	/// Records the current height and returns the rate across the window
+	pub fn push(&mut self, height: BlockHeight, at: Instant) -> f64 {
+		self.samples.push_back((height, at));
+		while self.samples.len() > self.window {
+			self.samples.pop_front();
+		}
+
+		self.rate()
+	}
+
+	pub fn rate(&self) -> f64 {
+		match (self.samples.front(), self.samples.back()) {
+			(Some((first_height, first_at)), Some((last_height, last_at)))
+				if last_at > first_at =>
+			{
+				let blocks = last_height.saturating_sub(*first_height) as f64;
+				blocks / last_at.duration_since(*first_at).as_secs_f64()
+			}
+			_ => 0.0,
+		}
+	}
+
+	/// Time left to cover `remaining` blocks at the current rate, if there is one
+	pub fn eta(&self, remaining: BlockHeight) -> Option<Duration> {
+		let rate = self.rate();
+		(rate > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / rate))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{models::NetworkColumn, testing, Db};
+	use sea_orm::ColumnTrait;
+	use serde_json::json;
+
+	#[test]
+	fn test_update() {
+		let mut tracker = ProgressTracker::new();
+		let now = Instant::now();
+
+		let heights = |node_height, last_processed| Heights { node_height, last_processed };
+
+		let progress = tracker.update(1, "net_a", heights(100, 40), now);
+		assert_eq!(progress.blocks_behind, 60);
+		assert_eq!(progress.rate_per_min, 0.0);
+
+		let progress = tracker.update(1, "net_a", heights(110, 70), now + Duration::from_secs(30));
+		assert_eq!(progress.blocks_behind, 40);
+		assert_eq!(progress.rate_per_min, 60.0);
+
+		// other networks are tracked separately
+		assert_eq!(tracker.update(2, "net_b", heights(10, 10), now).rate_per_min, 0.0);
+	}
+
+	#[test]
+	fn test_health() {
+		assert_eq!(Health::new(100, 100), Health::Healthy);
+		assert_eq!(Health::new(101, 100), Health::Degraded);
+
+		// no threshold
+		assert_eq!(Health::new(1_000_000, 0), Health::Healthy);
+	}
+
+	#[test]
+	fn test_rolling_rate() {
+		let mut rate = RollingRate::new(3);
+		let now = Instant::now();
+
+		assert_eq!(rate.push(100, now), 0.0);
+		assert_eq!(rate.eta(100), None);
+
+		assert_eq!(rate.push(110, now + Duration::from_secs(10)), 1.0);
+		assert_eq!(rate.push(150, now + Duration::from_secs(20)), 2.5);
+
+		// oldest sample falls out of the window
+		assert_eq!(rate.push(160, now + Duration::from_secs(30)), 2.5);
+		assert_eq!(rate.eta(100), Some(Duration::from_secs(40)));
+	}
+
+	#[tokio::test]
+	async fn test_get_all() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let mut network_ids = vec![];
+		for id in ["net_a", "net_b", "net_c"] {
+			let network_id = testing::create_network(db.get(), id, 0).await?.network_id;
+			Config::set::<_, BlockHeight>(db.get(), ConfigKey::BlockHeight(network_id), 100)
+				.await?;
+			Config::set::<_, BlockHeight>(db.get(), ConfigKey::IndexerProcessTail(network_id), 40)
+				.await?;
+			network_ids.push(network_id);
+		}
+
+		// deleted and paused networks are left out
+		Network::soft_delete_all_where(db.get(), NetworkColumn::NetworkId.eq(network_ids[1]))
+			.await?;
+		Network::set_paused(db.get(), network_ids[2], true).await?;
+
+		let progress = ProgressTracker::new().get_all(db.get()).await?;
+		assert_eq!(
+			serde_json::to_value(progress)?,
+			json!([{
+				"network": "net_a",
+				"nodeHeight": 100,
+				"lastProcessed": 40,
+				"blocksBehind": 60,
+				"ratePerMin": 0.0,
+			}])
+		);
+
+		Ok(())
+	}
+}
@@ -115,7 +115,7 @@ mod tests {
 		]);
 
 		for (url, s3) in data.into_iter() {
-			assert_eq!(S3::from_str(&url).unwrap(), s3);
+			assert_eq!(S3::from_str(url).unwrap(), s3);
 		}
 	}
 }
@@ -1,6 +1,7 @@
 use clap::{Parser, ValueHint};
 use dirs::home_dir;
 use eyre::Result;
+use log::LevelFilter;
 use regex::Regex;
 use std::{
 	fs,
@@ -8,11 +9,15 @@ use std::{
 	path::{Path, PathBuf},
 	str::FromStr,
 };
+use tracing::Level;
 use url::Url;
 
 use crate::{
-	banner, db::Driver as DatabaseDriver, warehouse::Driver as WarehouseDriver, AppError, Mode,
-	S3Service, S3,
+	banner,
+	db::{Driver as DatabaseDriver, SslMode as DatabaseSslMode},
+	warehouse::Driver as WarehouseDriver,
+	AppError, LogFormat, Mode, RiskWeights, S3Service, ID_DEFAULT_LENGTH, ID_MAX_LENGTH,
+	ID_MIN_LENGTH, S3,
 };
 
 #[derive(Parser, Debug)]
@@ -25,7 +30,13 @@ use crate::{
 Barreleye is a powerful tool for indexing and exploring data from multiple blockchains.
 It provides a flexible storage system and supports various database options for efficient
 data management and analysis."#),
-	after_help = "For more information and examples, visit: https://barreleye.org"
+	after_help = r#"Rpc credentials can be kept out of a network's endpoint by setting
+BARRELEYE_RPC_USER_<NAME> and BARRELEYE_RPC_PASSWORD_<NAME>, where <NAME> is the network's
+name uppercased with anything but letters and digits as `_` (eg: a network named
+"Bitcoin Mainnet" reads BARRELEYE_RPC_USER_BITCOIN_MAINNET). Renaming a network renames
+its variables too.
+
+For more information and examples, visit: https://barreleye.org"#
 )]
 pub struct Settings {
 	/// Specify the operation mode
@@ -36,15 +47,83 @@ pub struct Settings {
 	#[arg(skip)]
 	pub is_server: bool,
 
+	/// Maximum number of entries kept in the in-memory cache (0 disables it)
+	#[arg(
+		help_heading = "Runtime Options",
+		long,
+		default_value_t = 1_000_000,
+		value_name = "NUMBER"
+	)]
+	pub cache_size: usize,
+
+	/// Digest the variable part of cache keys (eg: txids) into a fixed length, so keys stay
+	/// short when there are lots of them
+	#[arg(help_heading = "Runtime Options", long)]
+	pub hash_cache_keys: bool,
+
+	/// Entity risk score (0-100) each tag risk level is worth; entities score as their riskiest
+	/// tag
+	#[arg(
+		help_heading = "Runtime Options",
+		long,
+		default_value = "low=0,high=50,critical=100",
+		value_name = "WEIGHTS"
+	)]
+	pub risk_weights: String,
+	#[arg(skip)]
+	pub risk_score_weights: RiskWeights,
+
+	/// Length of the random part of generated ids (eg: `ent_<random>`), up to 32
+	#[arg(
+		help_heading = "Runtime Options",
+		long,
+		default_value_t = ID_DEFAULT_LENGTH,
+		value_name = "NUMBER"
+	)]
+	pub id_length: usize,
+
+	/// Give up on a single rpc request after this many seconds (0 disables it)
+	#[arg(help_heading = "Runtime Options", long, default_value_t = 30, value_name = "SECONDS")]
+	pub rpc_timeout: u64,
+
+	/// Most rpc calls a single network can have in flight at once, batched ones counted one
+	/// by one (0 disables it)
+	#[arg(help_heading = "Runtime Options", long, default_value_t = 16, value_name = "NUMBER")]
+	pub rpc_concurrency: usize,
+
+	/// Save processing progress at least every this many blocks, even mid-batch (0 disables it)
+	#[arg(help_heading = "Runtime Options", long, default_value_t = 1_000, value_name = "BLOCKS")]
+	pub checkpoint_interval: u64,
+
+	/// Return amounts as json numbers instead of strings. Amounts past 2^53 - 1, which js
+	/// can't hold exactly (eg: 18-decimal tokens), stay strings
+	#[arg(help_heading = "Runtime Options", long)]
+	pub numeric_amounts: bool,
+
+	/// Also track unconfirmed transfers from the mempool (Bitcoin only)
+	#[arg(help_heading = "Runtime Options", long)]
+	pub mempool: bool,
+
+	/// Log output format (`json` is meant for log aggregation pipelines)
+	#[arg(
+		help_heading = "Runtime Options",
+		long,
+		env = "BARRELEYE_LOG_FORMAT",
+		default_value = "pretty",
+		value_name = "FORMAT"
+	)]
+	pub log_format: LogFormat,
+
 	/// Specify the database connection URI
-	/// Supported databases: SQLite, PostgreSQL, MySQL:
+	/// Supported databases: SQLite, PostgreSQL, MySQL, CockroachDB:
 	/// - SQLite: sqlite:///path/to/your/database.db
 	/// - PostgreSQL: postgres://localhost:5432/database_name
 	/// - MySQL: mysql://localhost:3306/database_name
+	/// - CockroachDB: cockroachdb://localhost:26257/database_name
 	///
 	/// The following environment variables can be used to configure credentials
-	/// - BARRELEYE_DB_USER: PostgreSQL and MySQL user
-	/// - BARRELEYE_DB_PASSWORD: PostgreSQL and MySQL password
+	/// - BARRELEYE_DB_USER: PostgreSQL, MySQL and CockroachDB user
+	/// - BARRELEYE_DB_PASSWORD: PostgreSQL, MySQL and CockroachDB password
 	#[arg(
 		help_heading = "Database Options",
 		short,
@@ -82,6 +161,61 @@ pub struct Settings {
 	#[arg(help_heading = "Database Options", long, default_value_t = 8, value_name = "SECONDS")]
 	pub database_max_lifetime: u64,
 
+	/// Abort any single query running longer than this (0 disables it)
+	/// Only supported by PostgreSQL and CockroachDB
+	#[arg(
+		help_heading = "Database Options",
+		long,
+		verbatim_doc_comment,
+		default_value_t = 0,
+		value_name = "SECONDS"
+	)]
+	pub database_statement_timeout: u64,
+
+	/// Schema to keep all tables in, created if missing (PostgreSQL only)
+	#[arg(
+		help_heading = "Database Options",
+		long,
+		env = "BARRELEYE_DB_SCHEMA",
+		value_name = "NAME"
+	)]
+	pub database_schema: Option<String>,
+
+	/// Connect over tls (`require`, `verify-ca` or `verify-full`)
+	/// Only applies to PostgreSQL, MySQL and CockroachDB
+	#[arg(
+		help_heading = "Database Options",
+		long,
+		verbatim_doc_comment,
+		env = "BARRELEYE_DB_SSL_MODE",
+		value_name = "MODE"
+	)]
+	pub database_ssl_mode: Option<DatabaseSslMode>,
+
+	/// CA certificate used to verify the server when `--database-ssl-mode` is `verify-*`
+	#[arg(
+		help_heading = "Database Options",
+		long,
+		env = "BARRELEYE_DB_SSL_ROOT_CERT",
+		value_hint = ValueHint::FilePath,
+		value_name = "PATH"
+	)]
+	pub database_ssl_root_cert: Option<PathBuf>,
+
+	/// Log every executed query (useful when debugging slow queries)
+	#[arg(help_heading = "Database Options", long, env = "BARRELEYE_DB_LOG_QUERIES")]
+	pub database_log_queries: bool,
+
+	/// Level that queries are logged at when `--database-log-queries` is on
+	#[arg(
+		help_heading = "Database Options",
+		long,
+		env = "BARRELEYE_DB_LOG_LEVEL",
+		default_value = "warn",
+		value_name = "LEVEL"
+	)]
+	pub database_log_level: LevelFilter,
+
 	/// Specify the storage location for blockchain data:
 	/// - Local folder: /path/to/your/storage/folder
 	/// - Amazon S3: https://s3.<region>.amazonaws.com/bucket_name/
@@ -143,6 +277,15 @@ pub struct Settings {
 	#[arg(long, env = "BARRELEYE_WAREHOUSE_PASSWORD", hide = true)]
 	pub warehouse_password: Option<String>,
 
+	/// Maximum number of rows sent to the warehouse in a single insert
+	#[arg(
+		help_heading = "Warehouse Options",
+		long,
+		default_value_t = 10_000,
+		value_name = "NUMBER"
+	)]
+	pub warehouse_batch_size: usize,
+
 	#[arg(
 		help_heading = "Server Options",
 		long,
@@ -157,11 +300,95 @@ pub struct Settings {
 	/// Port number for the HTTP server
 	#[arg(help_heading = "Server Options", long, default_value_t = 2277, value_name = "PORT")]
 	pub port: u16,
+
+	/// Maximum size of a request body; larger requests are rejected with a 413
+	#[arg(
+		help_heading = "Server Options",
+		long,
+		default_value_t = 10_485_760,
+		value_name = "BYTES"
+	)]
+	pub max_body_size: usize,
+
+	/// On shutdown, wait this long for in-flight requests before abandoning them (0 waits
+	/// indefinitely)
+	#[arg(help_heading = "Server Options", long, default_value_t = 30, value_name = "SECONDS")]
+	pub shutdown_timeout: u64,
+
+	/// Report networks this many blocks behind their node's tip as degraded, in `/v1/stats`
+	/// and `/v1/heartbeat` (0 disables it)
+	#[arg(help_heading = "Server Options", long, default_value_t = 0, value_name = "BLOCKS")]
+	pub max_blocks_behind: u64,
+
+	/// Level of the span opened for each request
+	#[arg(help_heading = "Server Options", long, default_value = "info", value_name = "LEVEL")]
+	pub trace_level: Level,
+
+	/// Request headers included in request spans, comma-separated. Anything else (eg:
+	/// credentials in `authorization` or `cookie`) is left out
+	#[arg(
+		help_heading = "Server Options",
+		long,
+		value_delimiter = ',',
+		default_value = "user-agent,content-type,content-length,accept",
+		value_name = "HEADERS"
+	)]
+	pub logged_headers: Vec<String>,
+
+	/// Require an api key for all non-public endpoints, even before any key exists (by default
+	/// they're open until the first one is created)
+	#[arg(help_heading = "Server Options", long)]
+	pub require_auth: bool,
+
+	/// Most ids a single bulk request (eg: deleting addresses) can contain (0 disables it)
+	#[arg(help_heading = "Server Options", long, default_value_t = 1_000, value_name = "NUMBER")]
+	pub max_bulk_size: usize,
+
+	/// Include the underlying cause of internal errors in responses (not for production)
+	#[arg(help_heading = "Server Options", long)]
+	pub debug_errors: bool,
+
+	/// Origins allowed to make cross-origin requests, comma-separated (`*` allows any)
+	/// No cross-origin access is allowed by default
+	#[arg(
+		help_heading = "Server Options",
+		long,
+		verbatim_doc_comment,
+		value_delimiter = ',',
+		value_name = "ORIGINS"
+	)]
+	pub allowed_origins: Vec<String>,
+
+	/// HTTP methods allowed in cross-origin requests, comma-separated
+	#[arg(
+		help_heading = "Server Options",
+		long,
+		value_delimiter = ',',
+		default_value = "GET,POST,PUT,DELETE",
+		value_name = "METHODS"
+	)]
+	pub allowed_methods: Vec<String>,
+
+	/// Request headers allowed in cross-origin requests, comma-separated
+	#[arg(
+		help_heading = "Server Options",
+		long,
+		value_delimiter = ',',
+		default_value = "authorization,content-type,idempotency-key,x-request-id",
+		value_name = "HEADERS"
+	)]
+	pub allowed_headers: Vec<String>,
 }
 
 impl Settings {
-	pub async fn new() -> Result<Self> {
-		let mut settings = Self::parse();
+	/// Raw command-line arguments, before any validation (see `init()`). Split out so
+	/// logging can be set up before settings are validated
+	pub fn from_args() -> Self {
+		Self::parse()
+	}
+
+	pub async fn init(self) -> Result<Self> {
+		let mut settings = self;
 
 		// show banner
 		banner::show()?;
@@ -213,9 +440,33 @@ impl Settings {
 
 		// set the database driver
 		settings.database_driver =
-			database_parsed_uri.scheme().to_ascii_lowercase().parse::<DatabaseDriver>().map_err(
-				|_| AppError::Config { config: "database".into(), error: "invalid URI".into() },
-			)?;
+			database_parsed_uri.scheme().to_ascii_lowercase().parse::<DatabaseDriver>()?;
+
+		// check statement timeout (rather than silently running without one)
+		if settings.database_statement_timeout > 0 &&
+			!settings.database_driver.supports_statement_timeout()
+		{
+			return Err(AppError::Config {
+				config: "database_statement_timeout".into(),
+				error: format!("not supported by {}", settings.database_driver).into(),
+			}
+			.into());
+		}
+
+		// check schema name (it ends up quoted in sql, so keep it to a plain identifier)
+		if let Some(schema) = &settings.database_schema {
+			let is_valid =
+				schema.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') &&
+					schema.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+			if !is_valid {
+				return Err(AppError::Config {
+					config: "database_schema".into(),
+					error: "must be a plain identifier (letters, digits and underscores)".into(),
+				}
+				.into());
+			}
+		}
 
 		match settings.database_driver {
 			DatabaseDriver::SQLite => {
@@ -233,11 +484,11 @@ impl Settings {
 				// store the processed URI
 				settings.database_uri = Some(database_parsed_uri);
 			}
-			DatabaseDriver::PostgreSQL | DatabaseDriver::MySQL => {
+			DatabaseDriver::PostgreSQL | DatabaseDriver::MySQL | DatabaseDriver::CockroachDB => {
 				// check if "database_name" is set in the path
 				let database_name = database_parsed_uri
 					.path_segments()
-					.and_then(|segments| segments.last())
+					.and_then(|mut segments| segments.next_back())
 					.filter(|name| !name.is_empty());
 
 				if database_name.is_none() {
@@ -248,6 +499,26 @@ impl Settings {
 					.into());
 				}
 
+				// cockroachdb speaks the postgres wire protocol
+				if settings.database_driver == DatabaseDriver::CockroachDB &&
+					database_parsed_uri.set_scheme("postgres").is_err()
+				{
+					return Err(AppError::Config {
+						config: "database".into(),
+						error: "invalid URI".into(),
+					}
+					.into());
+				}
+
+				// connect over tls
+				if let Some(ssl_mode) = settings.database_ssl_mode {
+					settings.database_driver.set_ssl(
+						&mut database_parsed_uri,
+						ssl_mode,
+						settings.database_ssl_root_cert.as_deref(),
+					);
+				}
+
 				// store the valid URI
 				settings.database_uri = Some(database_parsed_uri);
 			}
@@ -274,7 +545,7 @@ impl Settings {
 		} else if let Ok(parsed_url) = Url::parse(&settings.storage) {
 			// ensure the URL has a bucket name
 			let has_bucket_name =
-				parsed_url.path_segments().and_then(|segments| segments.last()).is_some();
+				parsed_url.path_segments().and_then(|mut segments| segments.next_back()).is_some();
 
 			if !has_bucket_name {
 				return Err(AppError::Config {
@@ -375,6 +646,18 @@ impl Settings {
 			}
 		}
 
+		// check id length
+		if settings.id_length < ID_MIN_LENGTH || settings.id_length > ID_MAX_LENGTH {
+			return Err(AppError::Config {
+				config: "id_length".into(),
+				error: format!("must be between {ID_MIN_LENGTH} and {ID_MAX_LENGTH}").into(),
+			}
+			.into());
+		}
+
+		// parse risk weights
+		settings.risk_score_weights = RiskWeights::from_str(&settings.risk_weights)?;
+
 		// parse ip address
 		settings.ip_addr = Some(IpAddr::V4(settings.ip.parse().map_err(|_| AppError::Config {
 			config: "ip".into(),
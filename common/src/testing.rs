@@ -0,0 +1,335 @@
+//! Fixtures and mocks shared by tests across crates (enabled with the `testing` feature)
+
+use async_trait::async_trait;
+use clap::Parser;
+use eyre::Result;
+use sea_orm::ConnectionTrait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value as JsonValue};
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpListener,
+	time::sleep,
+};
+
+use crate::{
+	models::{BasicModel, Network, NetworkActiveModel},
+	warehouse::DriverTrait,
+	App, Architecture, BlockHeight, Db, Settings, Storage, Warehouse,
+};
+
+type SelectFn = dyn Fn(&str, &[String]) -> Vec<String> + Send + Sync;
+type DeleteFn = dyn Fn(&str, &str) -> bool + Send + Sync;
+type RpcFn = dyn Fn(&str, &JsonValue) -> Result<JsonValue, JsonValue> + Send + Sync;
+
+/// A bitcoin network named after its id, for tests that need one to hang things off of
+pub fn new_network_model(id: &str, confirmations: i32) -> NetworkActiveModel {
+	Network::new_model(
+		Some(id.to_string()),
+		id,
+		Architecture::Bitcoin,
+		0,
+		600,
+		String::new(),
+		0,
+		confirmations,
+		false,
+	)
+}
+
+/// Stores `new_network_model` and returns it
+pub async fn create_network<C>(c: &C, id: &str, confirmations: i32) -> Result<Network>
+where
+	C: ConnectionTrait,
+{
+	let network_id = Network::create(c, new_network_model(id, confirmations)).await?;
+	Ok(Network::get(c, network_id).await?.unwrap())
+}
+
+/// App on `db`, with default settings and an in-memory warehouse
+pub async fn new_app(db: Arc<Db>) -> Result<App> {
+	new_app_with_settings(db, |_| {}).await
+}
+
+/// Same as `new_app`, with `f` tweaking the default settings first
+pub async fn new_app_with_settings<F>(db: Arc<Db>, f: F) -> Result<App>
+where
+	F: FnOnce(&mut Settings),
+{
+	let mut settings = Settings::parse_from(["barreleye"]);
+	f(&mut settings);
+
+	let settings = Arc::new(settings);
+	let storage = Arc::new(Storage::new(settings.clone())?);
+	let warehouse = Arc::new(MockDriver::default().warehouse(10));
+
+	App::new(settings, storage, db, warehouse).await
+}
+
+/// What bitcoind on `chain` (eg: `regtest`) answers `getblockchaininfo` with, at
+/// `block_height`
+pub fn bitcoind_blockchain_info(chain: &str, block_height: BlockHeight) -> JsonValue {
+	json!({
+		"chain": chain,
+		"blocks": block_height,
+		"headers": block_height,
+		"bestblockhash": "0".repeat(64),
+		"difficulty": 1.0,
+		"mediantime": 0,
+		"verificationprogress": 1.0,
+		"initialblockdownload": false,
+		"chainwork": "00",
+		"size_on_disk": 0,
+		"pruned": false,
+		"warnings": "",
+	})
+}
+
+/// Warehouse driver that keeps rows in memory, per table. Selects return the rows of the
+/// table they read from, narrowed down by that table's `with_select` (all of them by
+/// default), and deletes drop the rows its `with_delete` matches (none by default). Clones
+/// share their rows, so a handle can be kept around after boxing one up
+#[derive(Clone, Default)]
+pub struct MockDriver {
+	pub batches: Arc<Mutex<Vec<Vec<String>>>>,
+	pub queries: Arc<Mutex<Vec<String>>>,
+	tables: Arc<Mutex<HashMap<String, Vec<String>>>>,
+	selects: HashMap<String, Arc<SelectFn>>,
+	deletes: HashMap<String, Arc<DeleteFn>>,
+}
+
+impl MockDriver {
+	/// Narrows down selects from `table` with `f`, which gets the query and all its rows
+	pub fn with_select<T, F>(mut self, table: &str, f: F) -> Self
+	where
+		T: Serialize + DeserializeOwned,
+		F: Fn(&str, Vec<T>) -> Vec<T> + Send + Sync + 'static,
+	{
+		let f: Arc<SelectFn> = Arc::new(move |query, rows| {
+			let rows = rows.iter().map(|row| serde_json::from_str(row).unwrap()).collect();
+			f(query, rows).iter().map(|row| serde_json::to_string(row).unwrap()).collect()
+		});
+
+		self.selects.insert(table.to_string(), f);
+		self
+	}
+
+	/// Deletes the rows of `table` that `f` returns `true` for, given the query
+	pub fn with_delete<T, F>(mut self, table: &str, f: F) -> Self
+	where
+		T: DeserializeOwned,
+		F: Fn(&str, &T) -> bool + Send + Sync + 'static,
+	{
+		let f: Arc<DeleteFn> =
+			Arc::new(move |query, row| f(query, &serde_json::from_str(row).unwrap()));
+
+		self.deletes.insert(table.to_string(), f);
+		self
+	}
+
+	pub fn warehouse(&self, batch_size: usize) -> Warehouse {
+		Warehouse::with_driver(Box::new(self.clone()), batch_size)
+	}
+
+	/// Stored rows of `table`, in insertion order
+	pub fn rows<T>(&self, table: &str) -> Vec<T>
+	where
+		T: DeserializeOwned,
+	{
+		self.tables
+			.lock()
+			.unwrap()
+			.get(table)
+			.map(|rows| rows.iter().map(|row| serde_json::from_str(row).unwrap()).collect())
+			.unwrap_or_default()
+	}
+
+	/// First table selected from, past any subqueries
+	fn get_table(query: &str) -> String {
+		query
+			.split_whitespace()
+			.collect::<Vec<_>>()
+			.windows(2)
+			.find(|w| w[0].eq_ignore_ascii_case("FROM") && !w[1].starts_with('('))
+			.map(|w| w[1].to_string())
+			.unwrap_or_default()
+	}
+}
+
+#[async_trait]
+impl DriverTrait for MockDriver {
+	async fn new(_settings: Arc<Settings>) -> Result<Self> {
+		Ok(Self::default())
+	}
+
+	async fn run_migrations(&self) -> Result<()> {
+		Ok(())
+	}
+
+	async fn insert(&self, table: &str, serialized_data: &[String]) -> Result<()> {
+		self.batches.lock().unwrap().push(serialized_data.to_vec());
+		self.tables
+			.lock()
+			.unwrap()
+			.entry(table.to_string())
+			.or_default()
+			.extend_from_slice(serialized_data);
+
+		Ok(())
+	}
+
+	async fn select(&self, query: &str) -> Result<Vec<String>> {
+		self.queries.lock().unwrap().push(query.to_string());
+
+		let table = Self::get_table(query);
+		let rows = self.tables.lock().unwrap().get(&table).cloned().unwrap_or_default();
+
+		Ok(match self.selects.get(&table) {
+			Some(f) => f(query, &rows),
+			None => rows,
+		})
+	}
+
+	async fn delete(&self, query: &str) -> Result<()> {
+		self.queries.lock().unwrap().push(query.to_string());
+
+		let table = Self::get_table(query);
+		if let Some(f) = self.deletes.get(&table) {
+			if let Some(rows) = self.tables.lock().unwrap().get_mut(&table) {
+				rows.retain(|row| !f(query, row));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Minimal json-rpc server. Every call (single or batched) is answered with what the
+/// handler returns for its method and params: a result, or an error object
+pub struct MockRpc {
+	pub url: String,
+	/// Http requests served
+	pub requests: Arc<AtomicUsize>,
+	/// Calls answered, counting batched ones one by one
+	pub calls: Arc<AtomicUsize>,
+	/// Most calls that were ever being served at the same time, counting batched ones one
+	/// by one
+	pub max_in_flight: Arc<AtomicUsize>,
+}
+
+impl MockRpc {
+	pub async fn start<F>(f: F) -> Result<Self>
+	where
+		F: Fn(&str, &JsonValue) -> Result<JsonValue, JsonValue> + Send + Sync + 'static,
+	{
+		Self::start_with_delay(Duration::ZERO, f).await
+	}
+
+	/// Same as `start`, but takes `delay` to answer each request
+	pub async fn start_with_delay<F>(delay: Duration, f: F) -> Result<Self>
+	where
+		F: Fn(&str, &JsonValue) -> Result<JsonValue, JsonValue> + Send + Sync + 'static,
+	{
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		let url = format!("http://{}/", listener.local_addr()?);
+
+		let f: Arc<RpcFn> = Arc::new(f);
+		let requests = Arc::new(AtomicUsize::new(0));
+		let calls = Arc::new(AtomicUsize::new(0));
+		let in_flight = Arc::new(AtomicUsize::new(0));
+		let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+		tokio::spawn({
+			let (requests, calls) = (requests.clone(), calls.clone());
+			let max_in_flight = max_in_flight.clone();
+
+			async move {
+				while let Ok((mut stream, _)) = listener.accept().await {
+					let (f, requests, calls) = (f.clone(), requests.clone(), calls.clone());
+					let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+
+					tokio::spawn(async move {
+						let Some(body) = Self::read_body(&mut stream).await else { return };
+
+						let batch_size = body.as_array().map_or(1, |reqs| reqs.len());
+						requests.fetch_add(1, Ordering::SeqCst);
+						let n = in_flight.fetch_add(batch_size, Ordering::SeqCst) + batch_size;
+						max_in_flight.fetch_max(n, Ordering::SeqCst);
+						sleep(delay).await;
+						in_flight.fetch_sub(batch_size, Ordering::SeqCst);
+
+						let respond = |req: &JsonValue| {
+							calls.fetch_add(1, Ordering::SeqCst);
+							let method = req["method"].as_str().unwrap_or_default();
+							match f(method, &req["params"]) {
+								Ok(result) => {
+									json!({ "result": result, "error": null, "id": req["id"] })
+								}
+								Err(error) => {
+									json!({ "result": null, "error": error, "id": req["id"] })
+								}
+							}
+						};
+
+						let response = match body {
+							JsonValue::Array(reqs) => {
+								json!(reqs.iter().map(respond).collect::<Vec<_>>())
+							}
+							req => respond(&req),
+						}
+						.to_string();
+
+						let head = format!(
+							"HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+							response.len()
+						);
+						let _ = stream.write_all(format!("{head}{response}").as_bytes()).await;
+					});
+				}
+			}
+		});
+
+		Ok(Self { url, requests, calls, max_in_flight })
+	}
+
+	/// Url of a port nothing listens on
+	pub async fn unreachable_url() -> Result<String> {
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		Ok(format!("http://{}/", listener.local_addr()?))
+	}
+
+	async fn read_body(stream: &mut tokio::net::TcpStream) -> Option<JsonValue> {
+		let mut data = vec![];
+		let mut buf = [0; 4096];
+
+		loop {
+			match stream.read(&mut buf).await {
+				Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+				_ => return None,
+			}
+
+			let text = String::from_utf8_lossy(&data).to_string();
+			if let Some((head, body)) = text.split_once("\r\n\r\n") {
+				let content_length = head
+					.lines()
+					.find_map(|l| {
+						l.to_lowercase()
+							.strip_prefix("content-length:")
+							.and_then(|v| v.trim().parse::<usize>().ok())
+					})
+					.unwrap_or(0);
+				if body.len() >= content_length {
+					return serde_json::from_str(body).ok();
+				}
+			}
+		}
+	}
+}
@@ -62,6 +62,7 @@ impl DriverTrait for ClickHouse {
                         asset_address String,
                         relative_amount UInt256,
                         batch_amount UInt256,
+                        fee UInt256,
                         created_at DateTime
                     )
                     ENGINE = ReplacingMergeTree
@@ -84,6 +85,19 @@ impl DriverTrait for ClickHouse {
 			.await
 			.wrap_err(self.url_without_database.clone())?;
 
+		// tables created before fees were recorded
+		self.client
+			.query(&format!(
+				r#"
+					ALTER TABLE {}.transfers
+					ADD COLUMN IF NOT EXISTS fee UInt256 DEFAULT 0 AFTER batch_amount;
+				"#,
+				self.db_name
+			))
+			.execute()
+			.await
+			.wrap_err(self.url_without_database.clone())?;
+
 		self.client
 			.query(&format!(
 				r#"
@@ -189,9 +203,13 @@ impl DriverTrait for ClickHouse {
 		Ok(rows.into_iter().map(|row| row.network_id.to_string()).collect())
 	}
 
+	/// Runs as a lightweight delete that only returns once it's done, so rows inserted right
+	/// after (eg: when reindexing) aren't caught by it
 	async fn delete(&self, query: &str) -> Result<()> {
 		self.client
 			.query(query)
+			.with_option("allow_experimental_lightweight_delete", "1")
+			.with_option("mutations_sync", "2")
 			.execute()
 			.await
 			.map_err(|e| eyre!("Failed to execute delete query: {}", e))?;
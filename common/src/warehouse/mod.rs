@@ -38,6 +38,7 @@ pub trait DriverTrait: Send + Sync {
 
 pub struct Warehouse {
 	driver: Box<dyn DriverTrait>,
+	batch_size: usize,
 }
 
 impl Warehouse {
@@ -70,7 +71,13 @@ impl Warehouse {
 			info!("{} is connected to {}", settings.warehouse_driver, style(url).bold());
 		}
 
-		Ok(Self { driver })
+		Ok(Self::with_driver(driver, settings.warehouse_batch_size))
+	}
+
+	/// Wraps any other columnar store; `new` picks one of the built-in drivers based on
+	/// the `warehouse` setting
+	pub fn with_driver(driver: Box<dyn DriverTrait>, batch_size: usize) -> Self {
+		Self { driver, batch_size: batch_size.max(1) }
 	}
 
 	pub async fn run_migrations(&self) -> Result<()> {
@@ -84,7 +91,12 @@ impl Warehouse {
 			.collect::<Result<Vec<_>, _>>()
 			.map_err(|e| eyre!(e))?;
 
-		self.driver.insert(table, &serialized_data).await
+		// split large inserts so a single dense block doesn't exceed warehouse limits
+		for batch in serialized_data.chunks(self.batch_size) {
+			self.driver.insert(table, batch).await?;
+		}
+
+		Ok(())
 	}
 
 	pub async fn select<T: for<'de> Deserialize<'de>>(&self, query: &str) -> Result<Vec<T>> {
@@ -102,3 +114,60 @@ impl Warehouse {
 		self.driver.delete(query).await
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		chain::{ModuleId, U256},
+		models::Transfer,
+		testing::MockDriver,
+	};
+
+	#[tokio::test]
+	async fn test_insert_in_batches() -> Result<()> {
+		let driver = MockDriver::default();
+		let warehouse = driver.warehouse(2);
+
+		warehouse.insert("transfers", &[1, 2, 3, 4, 5]).await?;
+
+		let batches = driver.batches.lock().unwrap();
+		assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![2, 2, 1]);
+		assert_eq!(batches.concat(), vec!["1", "2", "3", "4", "5"]);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_create_and_get() -> Result<()> {
+		let warehouse = MockDriver::default().warehouse(10);
+
+		let transfers = (1..=3)
+			.map(|block_height| {
+				Transfer::new(
+					ModuleId::BitcoinTransfer,
+					1,
+					block_height,
+					"tx",
+					"from",
+					"to",
+					None,
+					U256::from(block_height),
+					U256::MAX,
+					0,
+				)
+			})
+			.collect::<Vec<_>>();
+
+		Transfer::create_many(&warehouse, transfers.clone()).await?;
+
+		// rows survive the round trip through the driver as they were
+		assert_eq!(Transfer::get_all_by_block_range(&warehouse, 1, (1, 3)).await?, transfers);
+		assert_eq!(
+			Transfer::get_first_by_source(&warehouse, 1, "from").await?,
+			transfers.first().cloned()
+		);
+
+		Ok(())
+	}
+}
@@ -6,24 +6,29 @@ use std::{
 	time::SystemTime,
 };
 use tokio::{
-	signal,
 	sync::watch,
 	task::JoinSet,
-	time::{sleep, Duration},
+	time::{sleep, timeout, Duration},
 };
-use tracing::{info, span, trace, Level};
+use tracing::{info, span, trace, warn, Level};
 use uuid::Uuid;
 
 use barreleye_common::{
+	cache::CacheKey,
 	models::{
-		Address, AddressColumn, Amount, Balance, Config, ConfigKey, Entity, Link, Network,
-		NetworkColumn, PrimaryId, PrimaryIds, SoftDeleteModel, Transfer,
+		Address, AddressColumn, Amount, Balance, BasicModel, Cluster, Config, ConfigKey, Entity,
+		Link, Network, NetworkColumn, PendingTransfer, PendingTransferColumn, PrimaryId,
+		PrimaryIds, SoftDeleteModel, Transfer,
 	},
-	utils, App, AppError, BlockHeight, INDEXER_HEARTBEAT_INTERVAL, INDEXER_PROMOTION_TIMEOUT,
+	shutdown_signal, utils, App, AppError, BlockHeight, INDEXER_HEARTBEAT_INTERVAL,
+	INDEXER_PROMOTION_TIMEOUT, INDEXER_RECONNECT_INTERVAL, INDEXER_SHUTDOWN_TIMEOUT,
 };
 
 mod link;
+mod mempool;
 mod process;
+mod retry;
+mod rules;
 mod sync;
 
 #[derive(Clone)]
@@ -47,17 +52,19 @@ impl Indexer {
 
 			let mut set = JoinSet::new();
 			let (tx, rx) = watch::channel(SystemTime::now());
+			let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
 			set.spawn({
 				let s = self.clone();
 				let r = rx.clone();
-				async move { s.sync(r).await }
+				let shutdown = shutdown_rx.clone();
+				async move { s.sync(r, shutdown).await }
 			});
 
 			set.spawn({
 				let s = self.clone();
 				let r = rx.clone();
-				async move { s.process(r).await }
+				async move { s.process(r, shutdown_rx).await }
 			});
 
 			set.spawn({
@@ -66,8 +73,36 @@ impl Indexer {
 				async move { s.link(r).await }
 			});
 
+			set.spawn({
+				let s = self.clone();
+				let r = rx.clone();
+				async move { s.mempool(r).await }
+			});
+
+			set.spawn({
+				let s = self.clone();
+				async move { s.rules().await }
+			});
+
+			set.spawn({
+				let s = self.clone();
+				async move { s.app.notifier.run(s.app.db()).await }
+			});
+
 			let ret = tokio::select! {
-				_ = signal::ctrl_c() => break Ok(()),
+				_ = shutdown_signal() => {
+					// let processing finish its in-flight blocks, so nothing is left half-saved
+					info!("shutting down… (finishing in-flight blocks)");
+					// ...but don't wait on them forever
+					let drain_timeout = Duration::from_secs(INDEXER_SHUTDOWN_TIMEOUT);
+					if shutdown_tx.send(true).is_ok() &&
+						timeout(drain_timeout, shutdown_tx.closed()).await.is_err()
+					{
+						warn!("in-flight blocks did not finish in time; shutting down anyway");
+					}
+
+					break Ok(());
+				}
 				v = self.primary_check() => v,
 				v = self.networks_check(tx) => v,
 				v = self.show_progress() => v,
@@ -128,6 +163,7 @@ impl Indexer {
 				.await?
 				.map(|v| v.updated_at)
 				.unwrap_or_else(utils::now);
+		let mut reconnected_at = utils::now();
 
 		loop {
 			match Config::get::<_, u8>(self.app.db(), ConfigKey::NetworksUpdated).await? {
@@ -138,6 +174,15 @@ impl Indexer {
 				_ => {}
 			}
 
+			// networks whose rpc endpoint was down when connecting are retried every so often
+			if utils::ago_in_seconds(INDEXER_RECONNECT_INTERVAL) > reconnected_at {
+				reconnected_at = utils::now();
+
+				if self.app.connect_missing_networks().await? {
+					tx.send(SystemTime::now())?;
+				}
+			}
+
 			sleep(Duration::from_secs(1)).await;
 		}
 	}
@@ -228,6 +273,14 @@ impl Indexer {
 			)
 			.await?;
 
+			// delete all address clusters
+			Cluster::delete_all_by_network_ids(self.app.db(), network_ids.clone()).await?;
+			PendingTransfer::delete_all_where(
+				self.app.db(),
+				PendingTransferColumn::NetworkId.is_in(network_ids.clone()),
+			)
+			.await?;
+
 			// delete from warehouse
 			let (transfers_deleted, balances_deleted, amounts_deleted, links_deleted) = tokio::join!(
 				Transfer::delete_all_by_network_id(&self.app.warehouse, network_ids.clone()),
@@ -238,6 +291,11 @@ impl Indexer {
 
 			transfers_deleted.and(balances_deleted).and(amounts_deleted).and(links_deleted)?;
 
+			// drop cached entries
+			for network_id in network_ids.iter() {
+				self.app.cache.delete_by_prefix(&CacheKey::network_prefix(*network_id));
+			}
+
 			// finally delete only the networks we grabbed earlier
 			Network::prune_all_where(self.app.db(), NetworkColumn::NetworkId.is_in(network_ids))
 				.await?;
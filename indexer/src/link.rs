@@ -319,6 +319,9 @@ impl Indexer {
 				if warehouse_data.should_commit(is_caught_up) {
 					trace!(warehouse = "pushing", records = warehouse_data.len());
 					warehouse_data.commit(self.app.warehouse.clone()).await?;
+
+					// new links change sources and risk of cached info responses
+					Config::bump_info_version(self.app.db()).await?;
 				}
 
 				// commit config marker updates
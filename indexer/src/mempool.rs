@@ -0,0 +1,50 @@
+use eyre::Result;
+use std::time::SystemTime;
+use tokio::{
+	sync::watch::Receiver,
+	time::{sleep, Duration},
+};
+use tracing::{trace, warn};
+
+use crate::Indexer;
+use barreleye_common::{
+	models::{Network, PendingTransfer},
+	PENDING_TRANSFERS_MAX_AGE,
+};
+
+const MEMPOOL_POLL_INTERVAL: u64 = 5;
+
+impl Indexer {
+	#[tracing::instrument(name = "indexer::mempool", skip_all)]
+	pub async fn mempool(&self, mut networks_updated: Receiver<SystemTime>) -> Result<()> {
+		loop {
+			if !self.app.settings.mempool || !self.app.is_leading() {
+				sleep(Duration::from_secs(1)).await;
+				continue;
+			}
+
+			let networks = self.app.networks.read().await.clone();
+			for (network_id, chain) in networks.into_iter() {
+				if !chain.is_connected() || Network::is_paused(self.app.db(), network_id).await? {
+					continue;
+				}
+
+				// a flaky rpc shouldn't stop confirmed indexing
+				if let Err(e) = chain.process_mempool(self.app.db(), self.app.cache.clone()).await {
+					warn!("{}: {e}", chain.get_network().name);
+				}
+			}
+
+			let evicted =
+				PendingTransfer::delete_expired(self.app.db(), PENDING_TRANSFERS_MAX_AGE).await?;
+			if evicted > 0 {
+				trace!(message = "evicted pending transactions", count = evicted);
+			}
+
+			tokio::select! {
+				_ = networks_updated.changed() => {}
+				_ = sleep(Duration::from_secs(MEMPOOL_POLL_INTERVAL)) => {}
+			}
+		}
+	}
+}
@@ -1,26 +1,29 @@
-use eyre::{ErrReport, Result};
+use eyre::Result;
+use sea_orm::ConnectionTrait;
 use serde_json::{from_value as json_parse, json, Value as JsonValue};
 use std::{
 	cmp,
 	collections::HashMap,
+	future::Future,
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
 	},
-	time::SystemTime,
+	time::{Instant, SystemTime},
 };
 use tokio::{
 	sync::{broadcast, mpsc, mpsc::Sender, watch::Receiver},
 	task::JoinSet,
 	time::{sleep, Duration},
 };
-use tracing::{debug, info, trace};
+use tracing::{debug, info, trace, warn};
 
-use crate::Indexer;
+use crate::{retry::retry_block, Indexer};
 use barreleye_common::{
-	chain::{ModuleId, WarehouseData},
-	models::{Config, ConfigKey, PrimaryId},
-	BlockHeight,
+	chain::{ChainError, ModuleId, WarehouseData},
+	models::{Cluster, Config, ConfigKey, Network, PrimaryId},
+	progress::RollingRate,
+	BlockHeight, PROGRESS_RATE_WINDOW,
 };
 
 #[derive(Clone, Debug)]
@@ -77,25 +80,40 @@ impl Pipe {
 
 impl Indexer {
 	#[tracing::instrument(name = "indexer::process", skip_all)]
-	pub async fn process(&self, mut networks_updated: Receiver<SystemTime>) -> Result<()> {
+	pub async fn process(
+		&self,
+		mut networks_updated: Receiver<SystemTime>,
+		mut shutdown: Receiver<bool>,
+	) -> Result<()> {
 		let mut warehouse_data = WarehouseData::new();
 		let mut config_key_map = HashMap::<ConfigKey, serde_json::Value>::new();
 		let mut blocked_and_notified = false;
 
 		'indexing: loop {
+			if *shutdown.borrow() {
+				break Ok(());
+			}
+
 			if !self.app.is_leading() {
 				sleep(Duration::from_secs(1)).await;
 				continue;
 			}
 
 			if self.app.should_reconnect().await? {
-				self.app.connect_networks(true).await?;
+				if let Err(e) = self.app.connect_networks(true).await {
+					warn!("{e}");
+				}
 			}
 
 			let mut network_params_map = HashMap::new();
 			for (network_id, chain) in self.app.networks.read().await.iter() {
 				let nid = *network_id;
 
+				// skip paused networks
+				if Network::is_paused(self.app.db(), nid).await? {
+					continue;
+				}
+
 				// skip if "sync" step is not done yet
 				let copy_step_started = matches!(Config::get::<_, BlockHeight>(
                     self.app.db(),
@@ -274,11 +292,10 @@ impl Indexer {
 				receipts.insert(config_key, rtx);
 
 				futures.spawn({
-					let nid = network_params.network_id;
 					let networks = self.app.networks.read().await;
 					let chain = networks[&network_params.network_id].clone();
 					let should_keep_going = should_keep_going.clone();
-					let mut pipe = Pipe::new(
+					let pipe = Pipe::new(
 						config_key,
 						pipe_sender.clone(),
 						receipt,
@@ -286,105 +303,31 @@ impl Indexer {
 					);
 					let db = self.app.db().clone();
 					let storage = self.app.storage.clone();
+					let cache = self.app.cache.clone();
+					let checkpoint_interval = self.app.settings.checkpoint_interval;
+					let block_time = chain.get_network().block_time;
+					let idle_wait = Duration::from_millis(cmp::min(block_time, 5_000) as u64);
 
 					async move {
-						let mut warehouse_data = WarehouseData::new();
-
-						let mut block_height = network_params.range.0;
-						let block_height_max = network_params.range.1;
-
-						let config_value = |block_height| match config_key {
-							ConfigKey::IndexerProcessTail(_) => {
-								json!(block_height)
-							}
-							ConfigKey::IndexerProcessChunk(_, _) |
-							ConfigKey::IndexerProcessModule(_, _)
-								if block_height_max.is_some() =>
-							{
-								json!((block_height, block_height_max.unwrap()))
-							}
-							_ => panic!("no return value for {config_key}"),
-						};
-
-						while should_keep_going.load(Ordering::SeqCst) {
-							match block_height_max {
-								Some(block_height_max) if block_height + 1 > block_height_max => {
-									// push no matter what (even if no warehouse
-									// data) so that config keys get updated
-									pipe.push(
-										config_value(block_height),
-										warehouse_data.clone(),
-										true,
-									)
-									.await?;
-
-									break;
-								}
-								None => {
-									let last_synced_block_height = Config::get::<_, BlockHeight>(
-										&db,
-										ConfigKey::IndexerSyncTail(nid),
-									)
-									.await?
-									.map(|v| v.value)
-									.unwrap_or(0);
-
-									if block_height + 1 > last_synced_block_height {
-										// push only if have some warehouse
-										// data; otherwise, it's ok
-										// if config keys get updated later
-										if !warehouse_data.is_empty() {
-											pipe.push(
-												config_value(block_height),
-												warehouse_data.clone(),
-												true,
-											)
-											.await?;
-										}
-
-										// wait a bit
-										let timeout =
-											cmp::min(chain.get_network().block_time, 5_000);
-										sleep(Duration::from_millis(timeout as u64)).await;
-										continue;
-									}
-								}
-								_ => {}
-							}
-
-							block_height += 1;
-
-							let is_done = tokio::select! {
-								_ = pipe.abort.recv() => true,
-								new_data = chain.process_block(
+						let modules = network_params.modules.clone();
+
+						process_range(
+							&db,
+							pipe,
+							network_params,
+							should_keep_going,
+							checkpoint_interval,
+							idle_wait,
+							|block_height| {
+								chain.process_block(
 									storage.clone(),
+									cache.clone(),
 									block_height,
-									network_params.modules.clone(),
-								) => match new_data? {
-									Some(new_data) => {
-										warehouse_data += new_data;
-										false
-									},
-									None => true,
-								},
-							};
-
-							if is_done || warehouse_data.len() > 100 {
-								pipe.push(
-									config_value(block_height),
-									warehouse_data.clone(),
-									false,
+									modules.clone(),
 								)
-								.await?;
-								warehouse_data.clear();
-							}
-
-							if is_done {
-								break;
-							}
-						}
-
-						Ok::<_, ErrReport>(())
+							},
+						)
+						.await
 					}
 				});
 			}
@@ -404,8 +347,16 @@ impl Indexer {
 				abort_sender.send(())?;
 				Ok(())
 			};
+			let mut is_draining = false;
 			loop {
 				tokio::select! {
+					_ = shutdown.changed(), if !is_draining => {
+						// threads stop after their in-flight block; keep receiving until
+						// they're all done, so every block they finished gets saved
+						debug!("draining… (shutting down)");
+						should_keep_going.store(false, Ordering::SeqCst);
+						is_draining = true;
+					}
 					_ = networks_updated.changed() => {
 						debug!("restarting… (networks updated)");
 						abort()?;
@@ -430,72 +381,46 @@ impl Indexer {
 						warehouse_data += new_data;
 						config_key_map.insert(config_key, config_value);
 
-						// batch save in warehouse
-						if warehouse_data.should_commit(force_commit) {
+						// batch save in warehouse (forced pushes save progress even when
+						// there's no new data)
+						if force_commit || warehouse_data.should_commit(false) {
 							trace!(warehouse = "pushing", records = warehouse_data.len());
 
+							// keep a copy for subscribers & webhooks
+							let transfers = warehouse_data.transfers.clone();
+
+							// merge co-spent addresses into clusters (these live in the db),
+							// all of a network's at once
+							let mut clusters = HashMap::<_, Vec<_>>::new();
+							for (network_id, addresses) in warehouse_data.clusters.iter() {
+								clusters.entry(*network_id).or_default().push(addresses);
+							}
+							for (network_id, sets) in clusters.into_iter() {
+								Cluster::merge(self.app.db(), network_id, sets).await?;
+							}
+
 							// push to warehouse
+							let has_data = !warehouse_data.is_empty();
 							warehouse_data.commit(self.app.warehouse.clone()).await?;
 
-							// commit config marker updates
-							for (config_key, config_value) in config_key_map.iter() {
-								let db = self.app.db();
-								let key = *config_key;
-								let value = config_value.clone();
-
-								match config_key {
-									ConfigKey::IndexerProcessTail(_) => {
-										let value = json_parse::<BlockHeight>(value)?;
-										Config::set::<_, BlockHeight>(db, key, value).await?;
-									}
-									ConfigKey::IndexerProcessChunk(_, _) => {
-										let (block_range_min, block_range_max) =
-											json_parse::<(BlockHeight, BlockHeight)>(value)?;
-
-										if block_range_min < block_range_max {
-											Config::set::<_, (BlockHeight, BlockHeight)>(
-												db,
-												key,
-												(block_range_min, block_range_max),
-											)
-											.await?;
-										} else {
-											Config::delete(db, key).await?;
-										}
-									}
-									ConfigKey::IndexerProcessModule(nid, mid) => {
-										let value =
-											json_parse::<(BlockHeight, BlockHeight)>(value)?;
-										Config::set::<_, (BlockHeight, BlockHeight)>(db, key, value)
-											.await?;
-
-										if value.0 >= value.1 {
-											Config::set::<_, u8>(
-												db,
-												ConfigKey::IndexerProcessModuleDone(*nid, *mid),
-												1,
-											)
-											.await?;
-										}
-									}
-									ConfigKey::IndexerProcessModuleDone(_, _) => {
-										let value = json_parse::<u8>(value)?;
-										Config::set::<_, u8>(db, key, value).await?;
-									}
-									_ => {}
-								}
+							// drop stale cached info responses (only once the data they'd
+							// be rebuilt from is in)
+							if has_data {
+								Config::bump_info_version(self.app.db()).await?;
 							}
 
-							// cleanup: if `config_key_map` contains a key indicating a certain
-							// module has been fully synced, it's safe to delete config for its
-							// range markers
-							for (config_key, _) in config_key_map.iter() {
-								if let ConfigKey::IndexerProcessModuleDone(nid, mid) = config_key {
-									let ck_block_range = ConfigKey::IndexerProcessModule(*nid, *mid);
-									Config::delete(self.app.db(), ck_block_range).await?;
-								}
+							// notify live subscribers
+							if self.app.feed.has_subscribers() {
+								self.app.feed.publish(transfers.clone());
 							}
 
+							// deliver webhooks in the background, so slow endpoints
+							// don't hold up indexing
+							self.app.notifier.enqueue(transfers.into_iter().collect());
+
+							// commit config marker updates
+							save_checkpoints(self.app.db(), &config_key_map).await?;
+
 							// reset config key markers
 							config_key_map.clear();
 						}
@@ -511,12 +436,15 @@ impl Indexer {
 	}
 
 	async fn show_process_progress(&self, secs: u64) -> Result<()> {
+		let mut rates = HashMap::new();
+
 		loop {
 			sleep(Duration::from_secs(secs)).await;
 
 			for (network_id, chain) in self.app.networks.read().await.clone().into_iter() {
 				let nid = network_id;
 				let mut scores = vec![];
+				let mut done_blocks = 0;
 
 				let block_height =
 					Config::get::<_, BlockHeight>(self.app.db(), ConfigKey::BlockHeight(nid))
@@ -535,7 +463,7 @@ impl Indexer {
 					.map(|v| v.value)
 					.unwrap_or(0);
 
-					let mut done_blocks = tail_block;
+					done_blocks = tail_block;
 					for (_, block_range) in Config::get_many::<_, (BlockHeight, BlockHeight)>(
 						self.app.db(),
 						vec![ConfigKey::IndexerProcessChunk(nid, 0)],
@@ -556,11 +484,306 @@ impl Indexer {
 				)
 				.await?;
 
+				// rate & eta over the last few intervals
+				let rate =
+					rates.entry(nid).or_insert_with(|| RollingRate::new(PROGRESS_RATE_WINDOW));
+				let blocks_per_sec = rate.push(done_blocks, Instant::now());
+				let eta = rate.eta(block_height.saturating_sub(done_blocks));
+
 				info!(
 					network = chain.get_network().name,
-					progress = (progress * 1000000.0).round() / 1000000.0
+					progress = (progress * 1000000.0).round() / 1000000.0,
+					height = done_blocks,
+					target = block_height,
+					blocks_per_sec = (blocks_per_sec * 100.0).round() / 100.0,
+					eta_secs = eta.map(|d| d.as_secs()),
 				);
 			}
 		}
 	}
 }
+
+/// Processes `network_params`' blocks one at a time, pushing new data (and the block it's good
+/// up to) through `pipe`. Once `should_keep_going` is off, the block in flight is still
+/// finished and saved before returning
+async fn process_range<C, F, Fut>(
+	c: &C,
+	mut pipe: Pipe,
+	network_params: NetworkRange,
+	should_keep_going: Arc<AtomicBool>,
+	checkpoint_interval: u64,
+	idle_wait: Duration,
+	process_block: F,
+) -> Result<()>
+where
+	C: ConnectionTrait,
+	F: Fn(BlockHeight) -> Fut,
+	Fut: Future<Output = Result<Option<WarehouseData>, ChainError>>,
+{
+	let nid = network_params.network_id;
+	let config_key = pipe.config_key;
+	let mut warehouse_data = WarehouseData::new();
+
+	let mut block_height = network_params.range.0;
+	let mut last_checkpoint = block_height;
+	let block_height_max = network_params.range.1;
+
+	let config_value = |block_height| match config_key {
+		ConfigKey::IndexerProcessTail(_) => {
+			json!(block_height)
+		}
+		ConfigKey::IndexerProcessChunk(_, _) | ConfigKey::IndexerProcessModule(_, _)
+			if block_height_max.is_some() =>
+		{
+			json!((block_height, block_height_max.unwrap()))
+		}
+		_ => panic!("no return value for {config_key}"),
+	};
+
+	loop {
+		// when shutting down, the block in flight still went through, so save it (along with
+		// its checkpoint) before stopping
+		if !should_keep_going.load(Ordering::SeqCst) {
+			pipe.push(config_value(block_height), warehouse_data.clone(), true).await?;
+			break;
+		}
+
+		match block_height_max {
+			Some(block_height_max) if block_height + 1 > block_height_max => {
+				// push no matter what (even if no warehouse
+				// data) so that config keys get updated
+				pipe.push(config_value(block_height), warehouse_data.clone(), true).await?;
+
+				break;
+			}
+			None => {
+				let last_synced_block_height =
+					Config::get::<_, BlockHeight>(c, ConfigKey::IndexerSyncTail(nid))
+						.await?
+						.map(|v| v.value)
+						.unwrap_or(0);
+
+				if block_height + 1 > last_synced_block_height {
+					// push only if have some warehouse
+					// data; otherwise, it's ok
+					// if config keys get updated later
+					if !warehouse_data.is_empty() {
+						pipe.push(config_value(block_height), warehouse_data.clone(), true).await?;
+					}
+
+					// wait a bit
+					sleep(idle_wait).await;
+					continue;
+				}
+			}
+			_ => {}
+		}
+
+		let is_done = tokio::select! {
+			_ = pipe.abort.recv() => true,
+			new_data = retry_block(
+				c,
+				nid,
+				&should_keep_going,
+				|| process_block(block_height + 1),
+			) => {
+				match new_data {
+					Some(Some(new_data)) => {
+						block_height += 1;
+						warehouse_data += new_data;
+						false
+					},
+					Some(None) => {
+						block_height += 1;
+						true
+					},
+					// held at a block that needs attention until shutting down, and that
+					// block isn't done
+					None => true,
+				}
+			},
+		};
+
+		// on large batches, make sure progress is saved every once in a while
+		// so a crash doesn't throw away the whole batch
+		let is_checkpoint = is_checkpoint_due(checkpoint_interval, last_checkpoint, block_height);
+
+		if is_done || is_checkpoint || warehouse_data.len() > 100 {
+			pipe.push(config_value(block_height), warehouse_data.clone(), is_checkpoint).await?;
+			warehouse_data.clear();
+
+			if is_checkpoint {
+				last_checkpoint = block_height;
+			}
+		}
+
+		if is_done {
+			break;
+		}
+	}
+
+	Ok(())
+}
+
+/// Whether `interval` blocks went by since the last checkpoint (`0` disables interval checkpoints)
+fn is_checkpoint_due(interval: u64, last: BlockHeight, block_height: BlockHeight) -> bool {
+	interval > 0 && block_height.saturating_sub(last) >= interval
+}
+
+/// Persists processing markers (tail, chunk and per-module ranges) pushed by the threads
+async fn save_checkpoints<C>(c: &C, config_key_map: &HashMap<ConfigKey, JsonValue>) -> Result<()>
+where
+	C: ConnectionTrait,
+{
+	for (config_key, config_value) in config_key_map.iter() {
+		let key = *config_key;
+		let value = config_value.clone();
+
+		match config_key {
+			ConfigKey::IndexerProcessTail(_) => {
+				let value = json_parse::<BlockHeight>(value)?;
+				Config::set::<_, BlockHeight>(c, key, value).await?;
+			}
+			ConfigKey::IndexerProcessChunk(_, _) => {
+				let (block_range_min, block_range_max) =
+					json_parse::<(BlockHeight, BlockHeight)>(value)?;
+
+				if block_range_min < block_range_max {
+					Config::set::<_, (BlockHeight, BlockHeight)>(
+						c,
+						key,
+						(block_range_min, block_range_max),
+					)
+					.await?;
+				} else {
+					Config::delete(c, key).await?;
+				}
+			}
+			ConfigKey::IndexerProcessModule(nid, mid) => {
+				let value = json_parse::<(BlockHeight, BlockHeight)>(value)?;
+				Config::set::<_, (BlockHeight, BlockHeight)>(c, key, value).await?;
+
+				if value.0 >= value.1 {
+					Config::set::<_, u8>(c, ConfigKey::IndexerProcessModuleDone(*nid, *mid), 1)
+						.await?;
+				}
+			}
+			ConfigKey::IndexerProcessModuleDone(_, _) => {
+				let value = json_parse::<u8>(value)?;
+				Config::set::<_, u8>(c, key, value).await?;
+			}
+			_ => {}
+		}
+	}
+
+	// cleanup: if `config_key_map` contains a key indicating a certain module has been fully
+	// synced, it's safe to delete config for its range markers
+	for (config_key, _) in config_key_map.iter() {
+		if let ConfigKey::IndexerProcessModuleDone(nid, mid) = config_key {
+			Config::delete(c, ConfigKey::IndexerProcessModule(*nid, *mid)).await?;
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::Db;
+
+	#[tokio::test]
+	async fn test_checkpoint_interval() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let chunk = ConfigKey::IndexerProcessChunk(1, 100);
+		Config::set::<_, (BlockHeight, BlockHeight)>(db.get(), chunk, (0, 100)).await?;
+
+		// process a batch of blocks, but "crash" at block 25 before the batch is done
+		let mut config_key_map = HashMap::new();
+		let mut last_checkpoint = 0;
+		for block_height in 1..=25 {
+			config_key_map.insert(chunk, json!((block_height, 100)));
+
+			if is_checkpoint_due(10, last_checkpoint, block_height) {
+				save_checkpoints(db.get(), &config_key_map).await?;
+				config_key_map.clear();
+				last_checkpoint = block_height;
+			}
+		}
+		drop(config_key_map);
+
+		// resuming starts from the last interval checkpoint, not from the batch start
+		let resume_from = Config::get::<_, (BlockHeight, BlockHeight)>(db.get(), chunk).await?;
+		assert_eq!(resume_from.map(|v| v.value), Some((20, 100)));
+
+		// no interval checkpoints when disabled
+		assert!(!is_checkpoint_due(0, 0, 1_000));
+		assert!(!is_checkpoint_due(10, 20, 29));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_process_range_on_shutdown() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let config_key = ConfigKey::IndexerProcessChunk(1, 10);
+		let (pipe_sender, mut pipe_receiver) = mpsc::channel(1);
+		let (receipt_sender, receipt) = mpsc::channel(1);
+		let (abort_sender, _) = broadcast::channel(1);
+		let pipe = Pipe::new(config_key, pipe_sender, receipt, abort_sender.subscribe());
+		let should_keep_going = Arc::new(AtomicBool::new(true));
+
+		// block 3 takes a while
+		let (started_sender, mut started) = mpsc::channel(1);
+		let processing = tokio::spawn({
+			let db = db.get().clone();
+			let should_keep_going = should_keep_going.clone();
+
+			async move {
+				process_range(
+					&db,
+					pipe,
+					NetworkRange::new(1, 0, Some(10), &[]),
+					should_keep_going,
+					0,
+					Duration::ZERO,
+					|block_height| {
+						let started_sender = started_sender.clone();
+						async move {
+							if block_height == 3 {
+								let _ = started_sender.send(()).await;
+								sleep(Duration::from_millis(50)).await;
+							}
+
+							let mut data = WarehouseData::new();
+							data.clusters.insert((1, [block_height.to_string()].into()));
+							Ok::<_, ChainError>(Some(data))
+						}
+					},
+				)
+				.await
+			}
+		});
+
+		// shut down while block 3 is in flight
+		started.recv().await;
+		should_keep_going.store(false, Ordering::SeqCst);
+
+		// the in-flight block is finished and pushed along with its checkpoint
+		let (key, value, data, force_commit) = pipe_receiver.recv().await.unwrap();
+		receipt_sender.send(()).await?;
+		processing.await??;
+
+		assert!(force_commit);
+		assert_eq!(data.clusters.len(), 3);
+		assert!(pipe_receiver.recv().await.is_none());
+
+		save_checkpoints(db.get(), &HashMap::from([(key, value)])).await?;
+		let checkpoint = Config::get::<_, (BlockHeight, BlockHeight)>(db.get(), config_key).await?;
+		assert_eq!(checkpoint.map(|v| v.value), Some((3, 10)));
+
+		Ok(())
+	}
+}
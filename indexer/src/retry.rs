@@ -0,0 +1,198 @@
+use eyre::Result;
+use sea_orm::ConnectionTrait;
+use std::{
+	cmp,
+	future::Future,
+	sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::time::{sleep, Duration};
+use tracing::{error, warn};
+
+use barreleye_common::{
+	chain::ChainError,
+	models::{Config, ConfigKey, PrimaryId},
+	INDEXER_RETRY_MAX_BACKOFF,
+};
+
+/// Keeps retrying `f` (with backoff) until the block it processes or extracts goes through.
+/// Failures are logged and counted per network rather than returned, so one network's bad
+/// rpc doesn't take down indexing for the others. Failures that won't go away on their own
+/// (eg a block that can't be decoded) aren't retried: the network is held at that block
+/// until indexing restarts. Either way `None` is returned once `should_keep_going` goes
+/// off, so shutting down isn't held up by a node that's down
+pub async fn retry_block<C, F, Fut, T>(
+	c: &C,
+	network_id: PrimaryId,
+	should_keep_going: &AtomicBool,
+	mut f: F,
+) -> Option<T>
+where
+	C: ConnectionTrait,
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<T, ChainError>>,
+{
+	let mut attempt = 0u32;
+
+	loop {
+		match f().await {
+			Ok(v) => return Some(v),
+			Err(e) => {
+				match e.is_transient() {
+					true => warn!(network_id, attempt, error = %e, "block failed, retrying"),
+					_ => error!(network_id, error = %e, "block needs attention (not retrying)"),
+				}
+
+				if let Err(e) = increment_errors(c, network_id).await {
+					warn!(network_id, error = %e, "could not update error count");
+				}
+
+				if !e.is_transient() {
+					while should_keep_going.load(Ordering::SeqCst) {
+						sleep(Duration::from_secs(1)).await;
+					}
+
+					return None;
+				}
+
+				// wait a second at a time, to notice shutting down in the meantime
+				for _ in 0..get_backoff(attempt) {
+					if !should_keep_going.load(Ordering::SeqCst) {
+						return None;
+					}
+
+					sleep(Duration::from_secs(1)).await;
+				}
+				attempt += 1;
+			}
+		}
+	}
+}
+
+/// Seconds to wait before trying a transient failure again
+fn get_backoff(attempt: u32) -> u64 {
+	cmp::min(1 << attempt.min(6), INDEXER_RETRY_MAX_BACKOFF)
+}
+
+async fn increment_errors<C>(c: &C, network_id: PrimaryId) -> Result<()>
+where
+	C: ConnectionTrait,
+{
+	let config_key = ConfigKey::IndexerProcessErrors(network_id);
+	let errors = Config::get::<_, u64>(c, config_key).await?.map(|v| v.value).unwrap_or(0);
+
+	Config::set::<_, u64>(c, config_key, errors + 1).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{BlockHeight, Db};
+	use std::sync::{atomic::AtomicUsize, Arc};
+
+	#[tokio::test]
+	async fn test_retry_block() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let should_keep_going = AtomicBool::new(true);
+
+		// network 1 keeps failing in the background
+		let failing = tokio::spawn({
+			let db = db.get().clone();
+			async move {
+				let should_keep_going = AtomicBool::new(true);
+				retry_block(&db, 1, &should_keep_going, || async {
+					Err::<(), _>(ChainError::rpc("rpc is down"))
+				})
+				.await
+			}
+		});
+
+		// ...while network 2 keeps advancing its checkpoint
+		for block_height in 1..=3 {
+			let processed =
+				retry_block(db.get(), 2, &should_keep_going, || async { Ok(block_height) })
+					.await
+					.unwrap();
+			Config::set::<_, BlockHeight>(db.get(), ConfigKey::IndexerProcessTail(2), processed)
+				.await?;
+		}
+		sleep(Duration::from_millis(100)).await;
+
+		let get_value = |config_key| Config::get::<_, u64>(db.get(), config_key);
+		assert_eq!(get_value(ConfigKey::IndexerProcessTail(2)).await?.map(|v| v.value), Some(3));
+		assert_eq!(get_value(ConfigKey::IndexerProcessErrors(1)).await?.map(|v| v.value), Some(1));
+		assert!(get_value(ConfigKey::IndexerProcessErrors(2)).await?.is_none());
+		assert!(!failing.is_finished());
+
+		failing.abort();
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_retry_block_on_decode_error() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let should_keep_going = Arc::new(AtomicBool::new(true));
+		let attempts = Arc::new(AtomicUsize::new(0));
+
+		let failing = tokio::spawn({
+			let db = db.get().clone();
+			let should_keep_going = should_keep_going.clone();
+			let attempts = attempts.clone();
+
+			async move {
+				retry_block(&db, 1, &should_keep_going, || {
+					attempts.fetch_add(1, Ordering::SeqCst);
+					async { Err::<(), _>(ChainError::decode("bad block")) }
+				})
+				.await
+			}
+		});
+
+		// held at the bad block without retrying it...
+		sleep(Duration::from_millis(100)).await;
+		assert!(!failing.is_finished());
+		assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+		let errors = Config::get::<_, u64>(db.get(), ConfigKey::IndexerProcessErrors(1)).await?;
+		assert_eq!(errors.map(|v| v.value), Some(1));
+
+		// ...until shutting down
+		should_keep_going.store(false, Ordering::SeqCst);
+		assert_eq!(failing.await?, None);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_retry_block_on_shutdown() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let should_keep_going = Arc::new(AtomicBool::new(true));
+
+		let failing = tokio::spawn({
+			let db = db.get().clone();
+			let should_keep_going = should_keep_going.clone();
+
+			async move {
+				retry_block(&db, 1, &should_keep_going, || async {
+					Err::<(), _>(ChainError::rpc("rpc is down"))
+				})
+				.await
+			}
+		});
+
+		// shutting down gives up on the block mid-backoff
+		sleep(Duration::from_millis(100)).await;
+		should_keep_going.store(false, Ordering::SeqCst);
+
+		let given_up = tokio::time::timeout(Duration::from_secs(3), failing).await??;
+		assert_eq!(given_up, None);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_get_backoff() {
+		assert_eq!((get_backoff(0), get_backoff(3)), (1, 8));
+		assert_eq!(get_backoff(10), INDEXER_RETRY_MAX_BACKOFF);
+	}
+}
@@ -0,0 +1,126 @@
+use eyre::Result;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use tokio::time::{sleep, Duration};
+use tracing::debug;
+
+use crate::Indexer;
+use barreleye_common::{
+	models::{Address, BasicModel, Config, ConfigKey, Network, PrimaryId, Rule},
+	IdPrefix, INDEXER_RULES_BATCH_SIZE, INDEXER_RULES_INTERVAL,
+};
+
+impl Indexer {
+	#[tracing::instrument(name = "indexer::rules", skip_all)]
+	pub async fn rules(&self) -> Result<()> {
+		loop {
+			if self.app.is_leading() {
+				let assigned = self.assign_by_rules().await?;
+				if assigned > 0 {
+					debug!(assigned, "assigned addresses by rules");
+				}
+
+				// a full batch means there's likely more to go
+				if assigned as u64 == INDEXER_RULES_BATCH_SIZE {
+					continue;
+				}
+			}
+
+			sleep(Duration::from_secs(INDEXER_RULES_INTERVAL)).await;
+		}
+	}
+
+	/// Assigns a batch of addresses proposed by entity rules, tagging each with the rule
+	/// that matched it. Returns how many were assigned
+	async fn assign_by_rules(&self) -> Result<usize> {
+		let proposals = Rule::get_proposals(self.app.db(), INDEXER_RULES_BATCH_SIZE).await?;
+		if proposals.is_empty() {
+			return Ok(0);
+		}
+
+		let network_ids = proposals.iter().map(|p| p.network_id).collect::<HashSet<_>>();
+		let networks = Network::get_all_by_network_ids(
+			self.app.db(),
+			network_ids.into_iter().collect::<Vec<_>>().into(),
+			Some(false),
+		)
+		.await?
+		.into_iter()
+		.map(|n| (n.network_id, n.id))
+		.collect::<HashMap<_, _>>();
+
+		let proposals = proposals
+			.into_iter()
+			.filter_map(|p| networks.get(&p.network_id).map(|network| (p, network.clone())))
+			.collect::<Vec<_>>();
+		if proposals.is_empty() {
+			return Ok(0);
+		}
+
+		// create new & tell the link step about them, all or nothing
+		let ret = proposals.len();
+		let id_length = self.app.settings.id_length;
+		let assigned = proposals
+			.iter()
+			.map(|(p, _)| ((p.network_id, p.address.clone()), p.entity_id))
+			.collect::<HashMap<_, _>>();
+		let new_addresses = move |ids: Vec<String>| {
+			proposals
+				.iter()
+				.zip(ids)
+				.map(|((p, network), id)| {
+					Address::new_model(
+						Some(id),
+						p.entity_id,
+						p.network_id,
+						network,
+						&p.address,
+						"",
+						Some(json!({ "rule": p.rule })),
+					)
+				})
+				.collect::<Vec<_>>()
+		};
+		self.app
+			.db_tx_with_retry(|tx| {
+				let new_addresses = new_addresses.clone();
+				let assigned = assigned.clone();
+
+				Box::pin(async move {
+					Address::create_many_with_ids(
+						tx,
+						ret,
+						IdPrefix::Address,
+						id_length,
+						new_addresses,
+					)
+					.await?;
+
+					Config::set_many::<_, PrimaryId>(
+						tx,
+						Address::get_all_by_addresses(
+							tx,
+							assigned.keys().map(|(_, address)| address.clone()).collect(),
+							Some(false),
+						)
+						.await?
+						.into_iter()
+						.filter(|a| {
+							assigned.get(&(a.network_id, a.address.clone())) == Some(&a.entity_id)
+						})
+						.map(|a| {
+							(ConfigKey::NewlyAddedAddress(a.network_id, a.address_id), a.address_id)
+						})
+						.collect::<HashMap<ConfigKey, PrimaryId>>(),
+					)
+					.await?;
+
+					// newly assigned addresses show up under their entity in info responses
+					Config::bump_info_version(tx).await
+				})
+			})
+			.await?;
+
+		Ok(ret)
+	}
+}
@@ -1,16 +1,26 @@
 use eyre::{Report, Result};
-use futures::future;
-use std::{collections::HashMap, error::Error, time::SystemTime};
+use sea_orm::ConnectionTrait;
+use std::{
+	collections::HashMap,
+	error::Error,
+	future::Future,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::SystemTime,
+};
 use tokio::{
 	sync::watch,
-	task,
+	task::JoinSet,
 	time::{sleep, Duration},
 };
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::Indexer;
+use crate::{retry::retry_block, Indexer};
 use barreleye_common::{
-	models::{Config, ConfigKey, PrimaryId},
+	chain::ChainError,
+	models::{Config, ConfigKey, Network, PrimaryId},
 	BlockHeight,
 };
 
@@ -26,9 +36,47 @@ impl NetworkRange {
 	}
 }
 
+/// Extracts blocks from `start` up to the tip, staying `confirmations` behind it to avoid
+/// reorg churn, and moves the sync tail along. Returns where the next pass starts from. A block
+/// that doesn't go through (see `retry_block`) holds the tail in place, and is where the next
+/// pass starts from too
+async fn sync_tail<C, F, Fut>(
+	c: &C,
+	network: &Network,
+	should_keep_going: &AtomicBool,
+	start: BlockHeight,
+	tip: BlockHeight,
+	mut extract_block: F,
+) -> Result<BlockHeight>
+where
+	C: ConnectionTrait,
+	F: FnMut(BlockHeight) -> Fut,
+	Fut: Future<Output = Result<bool, ChainError>>,
+{
+	let latest_block_height = network.get_confirmed_block_height(tip);
+
+	for block_height in start..=latest_block_height {
+		if retry_block(c, network.network_id, should_keep_going, || extract_block(block_height))
+			.await
+			.is_none()
+		{
+			return Ok(block_height);
+		}
+
+		let config_key = ConfigKey::IndexerSyncTail(network.network_id);
+		Config::set::<_, BlockHeight>(c, config_key, block_height).await?;
+	}
+
+	Ok(start.max(latest_block_height + 1))
+}
+
 impl Indexer {
 	#[tracing::instrument(name = "indexer::sync", skip_all)]
-	pub async fn sync(&self, mut networks_updated: watch::Receiver<SystemTime>) -> Result<()> {
+	pub async fn sync(
+		&self,
+		mut networks_updated: watch::Receiver<SystemTime>,
+		mut shutdown: watch::Receiver<bool>,
+	) -> Result<()> {
 		tokio::spawn({
 			let s = self.clone();
 			async move { s.show_sync_progress(10).await }
@@ -41,79 +89,123 @@ impl Indexer {
 			}
 
 			if self.app.should_reconnect().await? {
-				self.app.connect_networks(true).await?;
-			}
-
-			tokio::select! {
-				_ = networks_updated.changed() => {
-					continue;
+				if let Err(e) = self.app.connect_networks(true).await {
+					warn!("{e}");
 				}
+			}
 
-				_ = sleep(Duration::from_secs(0)) => {
-					let network_range_map = self.get_network_ranges().await?;
+			let network_range_map = self.get_network_ranges().await?;
 
-					if network_range_map.is_empty() {
-						sleep(Duration::from_secs(3)).await;
-						continue;
-					}
-
-					let mut tasks = vec![];
-					for (_config_key, network_range) in network_range_map.clone().into_iter() {
-						let task = task::spawn({
-							let networks = self.app.networks.read().await;
-							let chain = networks[&network_range.network_id].clone();
-							let db = self.app.db().clone();
-							let storage = self.app.storage.clone();
-
-							async move {
-								match network_range.range {
-									(start, Some(end)) => {
-										let config_key = ConfigKey::IndexerSyncChunk(network_range.network_id, end);
-
-										for block_height in start..end {
-											chain.extract_block(storage.clone(), block_height).await?;
-
-											Config::set::<_, (BlockHeight, BlockHeight)>(
-												&db,
-												config_key,
-												(block_height, end),
-											)
-											.await?;
-										}
+			if network_range_map.is_empty() {
+				sleep(Duration::from_secs(3)).await;
+				continue;
+			}
 
-										// chunk is done, can delete
-										Config::delete(&db, config_key).await?;
+			let should_keep_going = Arc::new(AtomicBool::new(true));
+
+			let mut tasks = JoinSet::new();
+			for (_config_key, network_range) in network_range_map.clone().into_iter() {
+				tasks.spawn({
+					let networks = self.app.networks.read().await;
+					let chain = networks[&network_range.network_id].clone();
+					let db = self.app.db().clone();
+					let storage = self.app.storage.clone();
+					let should_keep_going = should_keep_going.clone();
+
+					async move {
+						match network_range.range {
+							(start, Some(end)) => {
+								let config_key =
+									ConfigKey::IndexerSyncChunk(network_range.network_id, end);
+
+								for block_height in start..end {
+									if retry_block(
+										&db,
+										network_range.network_id,
+										&should_keep_going,
+										|| chain.extract_block(storage.clone(), block_height),
+									)
+									.await
+									.is_none()
+									{
+										return Ok(());
 									}
-									(start, None) => {
-										loop {
-											let latest_block_height = chain.get_block_height().await?;
 
-											for block_height in start..=latest_block_height {
-												chain.extract_block(storage.clone(), block_height).await?;
+									Config::set::<_, (BlockHeight, BlockHeight)>(
+										&db,
+										config_key,
+										(block_height, end),
+									)
+									.await?;
+								}
 
-												let config_key = ConfigKey::IndexerSyncTail(network_range.network_id);
-												Config::set::<_, BlockHeight>(&db, config_key, block_height).await?;
-											}
+								// chunk is done, can delete
+								Config::delete(&db, config_key).await?;
+							}
+							(mut start, None) => {
+								while should_keep_going.load(Ordering::SeqCst) {
+									// hold the tail in place while paused
+									if Network::is_paused(&db, network_range.network_id).await? {
+										sleep(Duration::from_secs(1)).await;
+										continue;
+									}
 
-											sleep(Duration::from_millis(
-												chain.get_network().block_time as u64,
-											))
-											.await;
+									// ...and while the node is down
+									let tip = match chain.get_block_height().await {
+										Ok(tip) => tip,
+										Err(e) => {
+											warn!(network_id = network_range.network_id, error = %e, "could not get block height");
+											sleep(Duration::from_secs(1)).await;
+											continue;
 										}
-									}
+									};
+
+									start = sync_tail(
+										&db,
+										&chain.get_network(),
+										&should_keep_going,
+										start,
+										tip,
+										|block_height| {
+											chain.extract_block(storage.clone(), block_height)
+										},
+									)
+									.await?;
+
+									sleep(Duration::from_millis(
+										chain.get_network().block_time as u64,
+									))
+									.await;
 								}
-
-								Ok::<(), Box<dyn Error + Send + Sync>>(())
 							}
+						}
 
-						});
-
-						tasks.push(task);
+						Ok::<(), Box<dyn Error + Send + Sync>>(())
 					}
+				});
+			}
 
-					let results = future::join_all(tasks).await;
-					if let Some(err) = results.into_iter().find(|result| result.is_err()) {
-						return Err(Report::msg(format!("A task failed: {:?}", err.as_ref().unwrap_err())));
+			loop {
+				tokio::select! {
+					_ = shutdown.changed() => {
+						// nothing's half-saved in between blocks, so syncing is dropped
+						// rather than drained
+						should_keep_going.store(false, Ordering::SeqCst);
+						return Ok(());
+					}
+					_ = networks_updated.changed() => {
+						// start over with the updated networks (the tasks, incl ones held at
+						// a block, are dropped along with the set)
+						should_keep_going.store(false, Ordering::SeqCst);
+						break;
+					}
+					result = tasks.join_next() => {
+						match result {
+							Some(Ok(Ok(()))) => {}
+							Some(Ok(Err(e))) => return Err(Report::msg(format!("A task failed: {e:?}"))),
+							Some(Err(e)) => return Err(e.into()),
+							None => break,
+						}
 					}
 				}
 			}
@@ -123,16 +215,28 @@ impl Indexer {
 	async fn get_network_ranges(&self) -> Result<HashMap<ConfigKey, NetworkRange>> {
 		let mut ret = HashMap::new();
 
-		for (network_id, _) in self.app.networks.read().await.iter() {
+		for (network_id, chain) in self.app.networks.read().await.iter() {
 			let nid = *network_id;
 
+			// skip paused networks
+			if Network::is_paused(self.app.db(), nid).await? {
+				continue;
+			}
+
 			let mut last_copied_block =
 				Config::get::<_, BlockHeight>(self.app.db(), ConfigKey::IndexerSyncTail(nid))
 					.await?
 					.map(|h| h.value)
 					.unwrap_or(0);
 
-			let block_height = self.get_updated_block_height(nid, Some(last_copied_block)).await?;
+			let block_height = chain.get_network().get_confirmed_block_height(
+				self.get_updated_block_height(nid, Some(last_copied_block)).await?,
+			);
+
+			// nothing deep enough to index yet
+			if block_height == 0 {
+				continue;
+			}
 
 			// if first time, split up network into chunks for faster
 			// initial syncing
@@ -241,3 +345,178 @@ impl Indexer {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		models::{BasicModel, NetworkActiveModel},
+		testing::{self, MockRpc},
+		Db,
+	};
+	use sea_orm::ActiveValue::Set;
+	use serde_json::json;
+	use std::sync::Mutex;
+
+	#[tokio::test]
+	async fn test_sync_tail() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let network = testing::create_network(db.get(), "net_a", 6).await?;
+		let config_key = ConfigKey::IndexerSyncTail(network.network_id);
+		let should_keep_going = AtomicBool::new(true);
+
+		let mut extracted = vec![];
+		let mut extract_block = |block_height| {
+			extracted.push(block_height);
+			async { Ok(true) }
+		};
+
+		// stops 6 blocks short of the tip
+		let start =
+			sync_tail(db.get(), &network, &should_keep_going, 90, 100, &mut extract_block).await?;
+		assert_eq!(start, 95);
+		assert_eq!(
+			Config::get::<_, BlockHeight>(db.get(), config_key).await?.map(|v| v.value),
+			Some(94)
+		);
+
+		// picks up where it left off once the tip moves
+		for (tip, next_start) in [(100, 95), (102, 97)] {
+			assert_eq!(
+				sync_tail(db.get(), &network, &should_keep_going, start, tip, &mut extract_block)
+					.await?,
+				next_start
+			);
+		}
+
+		assert_eq!(extracted, (90..=96).collect::<Vec<_>>());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_sync_tail_on_rpc_error() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let should_keep_going = AtomicBool::new(true);
+
+		// the node is down for the first try at block 1
+		let mut attempts = vec![];
+		let extract_block = |block_height| {
+			attempts.push(block_height);
+			let is_down = attempts.len() == 2;
+			async move {
+				match is_down {
+					true => Err(ChainError::rpc("rpc is down")),
+					_ => Ok(true),
+				}
+			}
+		};
+
+		// the block is retried rather than failing the tail
+		assert_eq!(
+			sync_tail(db.get(), &network, &should_keep_going, 0, 2, extract_block).await?,
+			3
+		);
+		assert_eq!(attempts, vec![0, 1, 1, 2]);
+
+		let errors = ConfigKey::IndexerProcessErrors(network.network_id);
+		assert_eq!(Config::get::<_, u64>(db.get(), errors).await?.map(|v| v.value), Some(1));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_sync_tail_on_decode_error() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let config_key = ConfigKey::IndexerSyncTail(network.network_id);
+		let should_keep_going = Arc::new(AtomicBool::new(true));
+		let extracted = Arc::new(Mutex::new(vec![]));
+
+		// block 1 can't be decoded, so it won't go through no matter how often it's tried
+		let syncing = tokio::spawn({
+			let db = db.get().clone();
+			let should_keep_going = should_keep_going.clone();
+			let extracted = extracted.clone();
+
+			async move {
+				sync_tail(&db, &network, &should_keep_going, 0, 2, |block_height| {
+					extracted.lock().unwrap().push(block_height);
+					async move {
+						match block_height {
+							1 => Err(ChainError::decode("bad block")),
+							_ => Ok(true),
+						}
+					}
+				})
+				.await
+			}
+		});
+
+		// the tail stays behind the bad block...
+		sleep(Duration::from_millis(100)).await;
+		assert!(!syncing.is_finished());
+		assert_eq!(
+			Config::get::<_, BlockHeight>(db.get(), config_key).await?.map(|v| v.value),
+			Some(0)
+		);
+
+		// ...which is where syncing picks up from once it's called off
+		should_keep_going.store(false, Ordering::SeqCst);
+		assert_eq!(syncing.await??, 1);
+		assert_eq!(
+			Config::get::<_, BlockHeight>(db.get(), config_key).await?.map(|v| v.value),
+			Some(0)
+		);
+		assert_eq!(*extracted.lock().unwrap(), vec![0, 1]);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_get_network_ranges_of_new_network() -> Result<()> {
+		let mut app = testing::new_app(Arc::new(Db::new_in_memory().await?)).await?;
+		app.cpu_count = 0;
+		let app = Arc::new(app);
+
+		// the node is down at first
+		let is_up = Arc::new(AtomicBool::new(false));
+		let rpc = MockRpc::start({
+			let is_up = is_up.clone();
+			move |method, _params| match (is_up.load(Ordering::SeqCst), method) {
+				(false, _) => Err(json!({ "code": -28, "message": "loading block index" })),
+				(_, "getblockchaininfo") => Ok(testing::bitcoind_blockchain_info("main", 10)),
+				_ => Ok(json!(10)),
+			}
+		})
+		.await?;
+
+		let network_id = Network::create(
+			app.db(),
+			NetworkActiveModel {
+				rpc_endpoint: Set(rpc.url.clone()),
+				..testing::new_network_model("net_a", 0)
+			},
+		)
+		.await?;
+		let network = Network::get(app.db(), network_id).await?.unwrap();
+
+		assert!(!app.add_network(network).await?);
+		assert!(!app.connect_missing_networks().await?);
+		assert!(app.networks.read().await.is_empty());
+
+		// it's picked up once it's back...
+		is_up.store(true, Ordering::SeqCst);
+		assert!(app.connect_missing_networks().await?);
+		assert!(app.networks.read().await.contains_key(&network_id));
+
+		// ...and synced from the start
+		let indexer = Indexer::new(app.clone());
+		let network_ranges = indexer.get_network_ranges().await?;
+		assert_eq!(network_ranges.len(), 1);
+		assert_eq!(network_ranges[&ConfigKey::IndexerSyncTail(network_id)].range, (0, None));
+
+		Ok(())
+	}
+}
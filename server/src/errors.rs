@@ -1,13 +1,32 @@
 use axum::{
+	extract::rejection::JsonRejection,
 	http::StatusCode,
 	response::{IntoResponse, Response},
 	Json,
 };
+use axum_extra::extract::QueryRejection;
 use derive_more::{Display, Error};
 use eyre::{ErrReport, Report};
 use sea_orm::DbErr;
 use serde_json::json;
 use std::borrow::Cow;
+use tracing::error;
+
+/// Underlying cause of an internal error, attached to its response but left out of the body
+/// (see `Server::debug_errors`)
+#[derive(Clone, Debug)]
+pub struct ErrorCause(pub String);
+
+impl IntoResponse for ErrorCause {
+	fn into_response(self) -> Response {
+		let error = json!({
+			"code": "internal",
+			"message": self.0,
+		});
+
+		(StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": error }))).into_response()
+	}
+}
 
 #[derive(Debug, Display, Error)]
 pub enum ServerError<'a> {
@@ -35,6 +54,9 @@ pub enum ServerError<'a> {
 	#[display("bad request: {reason}")]
 	BadRequest { reason: Cow<'a, str> },
 
+	#[display("payload too large")]
+	PayloadTooLarge,
+
 	#[display("too early: {reason}")]
 	TooEarly { reason: Cow<'a, str> },
 
@@ -45,21 +67,69 @@ pub enum ServerError<'a> {
 	Internal { error: Report },
 }
 
+impl ServerError<'_> {
+	/// Stable identifier for clients to branch on, unlike the message it never changes
+	pub fn code(&self) -> &'static str {
+		match self {
+			ServerError::Unauthorized => "unauthorized",
+			ServerError::InvalidParam { .. } => "invalid_param",
+			ServerError::InvalidValues { .. } => "invalid_values",
+			ServerError::MissingInputParams => "missing_input_params",
+			ServerError::InvalidService { .. } => "invalid_service",
+			ServerError::Duplicate { .. } => "duplicate",
+			ServerError::Duplicates { .. } => "duplicates",
+			ServerError::BadRequest { .. } => "bad_request",
+			ServerError::PayloadTooLarge => "payload_too_large",
+			ServerError::TooEarly { .. } => "too_early",
+			ServerError::NotFound => "not_found",
+			ServerError::Internal { .. } => "internal",
+		}
+	}
+
+	pub fn field(&self) -> Option<&str> {
+		match self {
+			ServerError::InvalidParam { field, .. } |
+			ServerError::InvalidValues { field, .. } |
+			ServerError::Duplicate { field, .. } |
+			ServerError::Duplicates { field, .. } => Some(field),
+			_ => None,
+		}
+	}
+}
+
 impl IntoResponse for ServerError<'static> {
 	fn into_response(self) -> Response {
 		let http_code = match self {
 			ServerError::NotFound => StatusCode::NOT_FOUND,
 			ServerError::Unauthorized => StatusCode::UNAUTHORIZED,
+			ServerError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
 			ServerError::TooEarly { .. } => StatusCode::from_u16(425).unwrap(),
 			ServerError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
 			_ => StatusCode::BAD_REQUEST,
 		};
 
-		let body = Json(json!({
-			"error": self.to_string(),
-		}));
+		let cause = match &self {
+			ServerError::Internal { error } => {
+				error!("internal error: {error:?}");
+				Some(ErrorCause(format!("{error:#}")))
+			}
+			_ => None,
+		};
+
+		let mut error = json!({
+			"code": self.code(),
+			"message": self.to_string(),
+		});
+		if let Some(field) = self.field() {
+			error["field"] = field.into();
+		}
 
-		(http_code, body).into_response()
+		let mut res = (http_code, Json(json!({ "error": error }))).into_response();
+		if let Some(cause) = cause {
+			res.extensions_mut().insert(cause);
+		}
+
+		res
 	}
 }
 
@@ -80,3 +150,83 @@ impl From<serde_json::Error> for ServerError<'static> {
 		ServerError::Internal { error: Report::new(e) }
 	}
 }
+
+impl From<JsonRejection> for ServerError<'static> {
+	fn from(rejection: JsonRejection) -> ServerError<'static> {
+		match rejection.status() {
+			StatusCode::PAYLOAD_TOO_LARGE => ServerError::PayloadTooLarge,
+			_ => ServerError::BadRequest { reason: rejection.body_text().into() },
+		}
+	}
+}
+
+impl From<QueryRejection> for ServerError<'static> {
+	fn from(rejection: QueryRejection) -> ServerError<'static> {
+		ServerError::BadRequest { reason: rejection.body_text().into() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::body;
+	use serde_json::Value as JsonValue;
+
+	async fn get_body(error: ServerError<'static>) -> eyre::Result<(StatusCode, JsonValue)> {
+		let res = error.into_response();
+		let status = res.status();
+		let body = body::to_bytes(res.into_body(), usize::MAX).await?;
+
+		Ok((status, serde_json::from_slice(&body)?))
+	}
+
+	#[tokio::test]
+	async fn test_error_body() -> eyre::Result<()> {
+		let error = ServerError::Duplicate { field: "name".into(), value: "Exchange".into() };
+		assert_eq!(
+			get_body(error).await?,
+			(
+				StatusCode::BAD_REQUEST,
+				json!({
+					"error": {
+						"code": "duplicate",
+						"message": "duplicate found at `name`: Exchange",
+						"field": "name",
+					},
+				})
+			)
+		);
+
+		let error = ServerError::InvalidValues { field: "tags".into(), values: "a, b".into() };
+		assert_eq!(
+			get_body(error).await?,
+			(
+				StatusCode::BAD_REQUEST,
+				json!({
+					"error": {
+						"code": "invalid_values",
+						"message": "invalid value(s) for `tags`: a, b",
+						"field": "tags",
+					},
+				})
+			)
+		);
+
+		// the cause stays in the logs
+		let error = ServerError::Internal { error: Report::msg("password=hunter2") };
+		assert_eq!(
+			get_body(error).await?,
+			(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				json!({
+					"error": {
+						"code": "internal",
+						"message": "rekt",
+					},
+				})
+			)
+		);
+
+		Ok(())
+	}
+}
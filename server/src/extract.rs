@@ -0,0 +1,107 @@
+use axum::{
+	extract::{FromRequest, FromRequestParts},
+	response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::errors::ServerError;
+
+/// Drop-in for `axum::Json`, except malformed bodies are rejected as a `ServerError`
+/// (with the offending field) instead of axum's plain-text 422
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ServerError<'static>))]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T> {
+	fn into_response(self) -> Response {
+		axum::Json(self.0).into_response()
+	}
+}
+
+impl<T> From<T> for Json<T> {
+	fn from(inner: T) -> Self {
+		Self(inner)
+	}
+}
+
+/// Drop-in for `axum_extra::extract::Query`, with the same rejection handling as `Json`
+#[derive(FromRequestParts)]
+#[from_request(via(axum_extra::extract::Query), rejection(ServerError<'static>))]
+pub struct Query<T>(pub T);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{
+		body::{self, Body},
+		extract::Request,
+		http::{header, StatusCode},
+		routing::{get, post},
+		Router,
+	};
+	use serde::Deserialize;
+	use serde_json::Value as JsonValue;
+	use tower::ServiceExt;
+
+	#[derive(Deserialize)]
+	struct Payload {
+		#[allow(dead_code)]
+		name: String,
+		#[allow(dead_code)]
+		limit: u64,
+	}
+
+	async fn get_error(router: Router, req: Request) -> eyre::Result<(StatusCode, String)> {
+		let res = router.oneshot(req).await?;
+		let status = res.status();
+		let body: JsonValue =
+			serde_json::from_slice(&body::to_bytes(res.into_body(), usize::MAX).await?)?;
+
+		Ok((status, body["error"]["message"].as_str().unwrap_or_default().to_string()))
+	}
+
+	#[tokio::test]
+	async fn test_json_rejection() -> eyre::Result<()> {
+		let router = Router::new().route("/", post(|Json(_): Json<Payload>| async { "ok" }));
+		let new_request = |body: &'static str| {
+			Request::builder()
+				.method("POST")
+				.uri("/")
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(Body::from(body))
+		};
+
+		let (status, error) = get_error(router.clone(), new_request(r#"{"name": "a"}"#)?).await?;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert!(error.contains("missing field `limit`"));
+
+		let (status, error) =
+			get_error(router.clone(), new_request(r#"{"name": "a", "limit": "ten"}"#)?).await?;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert!(error.contains("limit: invalid type"));
+
+		let (status, _) = get_error(router, new_request("{")?).await?;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_query_rejection() -> eyre::Result<()> {
+		let router = Router::new().route("/", get(|Query(_): Query<Payload>| async { "ok" }));
+		let new_request = |uri: &'static str| Request::builder().uri(uri).body(Body::empty());
+
+		let (status, error) = get_error(router.clone(), new_request("/?name=a")?).await?;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert!(error.contains("missing field `limit`"));
+
+		let (status, error) = get_error(router.clone(), new_request("/?name=a&limit=ten")?).await?;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert!(error.contains("limit: "));
+
+		let res = router.oneshot(new_request("/?name=a&limit=10")?).await?;
+		assert_eq!(res.status(), StatusCode::OK);
+
+		Ok(())
+	}
+}
@@ -5,6 +5,12 @@ use barreleye_common::App;
 
 mod v1;
 
+/// Request/response routes, which are subject to the request timeout
 pub fn get_routes() -> Router<Arc<App>> {
 	Router::new().nest("/v1", v1::get_routes())
 }
+
+/// Long-lived streaming routes (eg: SSE), which are not
+pub fn get_streaming_routes() -> Router<Arc<App>> {
+	Router::new().nest("/v1", v1::get_streaming_routes())
+}
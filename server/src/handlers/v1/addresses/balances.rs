@@ -0,0 +1,81 @@
+use axum::extract::{Path, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	ServerResult,
+};
+use barreleye_common::{
+	chain::JsonAmount,
+	models::{Amount, Network, SoftDeleteModel},
+	App, BlockHeight,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	network: Option<String>,
+	at_block: Option<BlockHeight>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseBalance {
+	network: String,
+	asset: Option<String>,
+	balance: JsonAmount,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	balances: Vec<ResponseBalance>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(address): Path<String>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let address = address.trim();
+
+	// block heights only mean something within a single network
+	let network = match payload.network {
+		Some(id) => {
+			let network = Network::get_existing_by_id(app.db(), &id)
+				.await?
+				.ok_or(ServerError::InvalidParam { field: "network".into(), value: id.into() })?;
+
+			Some(network)
+		}
+		None => None,
+	};
+
+	let balances = match (network.as_ref(), payload.at_block) {
+		(Some(network), Some(block_height)) => {
+			Amount::get_balance_at_block(&app.warehouse, address, network.network_id, block_height)
+				.await?
+		}
+		(None, Some(_)) => {
+			return Err(ServerError::BadRequest { reason: "`atBlock` requires a `network`".into() })
+		}
+		(_, None) => Amount::get_balances_by_address(&app.warehouse, address).await?,
+	};
+
+	let networks = app.networks.read().await;
+	let balances = balances
+		.into_iter()
+		.filter(|b| network.as_ref().is_none_or(|n| n.network_id == b.network_id))
+		.filter_map(|b| {
+			networks.get(&b.network_id).map(|chain| ResponseBalance {
+				network: chain.get_network().id,
+				asset: (!b.asset_address.is_empty()).then_some(b.asset_address),
+				balance: JsonAmount::new(b.balance, app.settings.numeric_amounts),
+			})
+		})
+		.collect();
+
+	Ok(Response { balances }.into())
+}
@@ -0,0 +1,52 @@
+use axum::extract::{Path, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	ServerResult,
+};
+use barreleye_common::{
+	models::{Address, BasicModel, Cluster, Network, SoftDeleteModel},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	offset: Option<u64>,
+	limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	network: String,
+	addresses: Vec<String>,
+}
+
+/// Addresses sharing a cluster with the address (on its network), sorted and paginated
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(address_id): Path<String>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let address =
+		Address::get_existing_by_id(app.db(), &address_id).await?.ok_or(ServerError::NotFound)?;
+	let network = Network::get(app.db(), address.network_id).await?.ok_or(ServerError::NotFound)?;
+
+	let addresses = Cluster::get_all_paginated_by_address(
+		app.db(),
+		address.network_id,
+		&address.address,
+		payload.offset,
+		payload.limit,
+	)
+	.await?
+	.into_iter()
+	.map(|m| m.address)
+	.collect();
+
+	Ok(Response { network: network.id, addresses }.into())
+}
@@ -1,4 +1,4 @@
-use axum::{extract::State, Json};
+use axum::extract::State;
 use sea_orm::prelude::Json as JsonData;
 use serde::Deserialize;
 use std::{
@@ -6,10 +6,12 @@ use std::{
 	sync::Arc,
 };
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
-	models::{Address, BasicModel, Config, ConfigKey, Entity, Network, PrimaryId, SoftDeleteModel},
-	App,
+	models::{
+		set, Address, BasicModel, Config, ConfigKey, Entity, Network, PrimaryId, SoftDeleteModel,
+	},
+	App, IdPrefix,
 };
 
 #[derive(Clone, Deserialize)]
@@ -18,6 +20,7 @@ pub struct PayloadAddress {
 	address: String,
 	description: String,
 	data: Option<JsonData>,
+	locked: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -93,43 +96,54 @@ pub async fn handler(
 		});
 	}
 
-	// create new
-	Address::create_many(
-		app.db(),
+	// create new & tell upstream indexer about them, all or nothing
+	let (n, id_length) = (payload.addresses.len(), app.settings.id_length);
+	let new_addresses = move |ids: Vec<String>| {
 		payload
 			.addresses
-			.clone()
 			.iter()
-			.map(|address| {
-				Address::new_model(
-					None,
+			.zip(ids)
+			.map(|(address, id)| {
+				let mut model = Address::new_model(
+					Some(id),
 					entity.entity_id,
 					network.network_id,
 					&network.id,
 					&address.address,
 					&address.description,
 					address.data.clone(),
-				)
+				);
+				model.is_locked = set(address.locked.unwrap_or(false));
+				model
 			})
-			.collect(),
-	)
-	.await?;
+			.collect::<Vec<_>>()
+	};
+	app.db_tx_with_retry(|tx| {
+		let new_addresses = new_addresses.clone();
+		let unique_addresses = unique_addresses.clone().into_iter().collect::<Vec<String>>();
+		let (entity_id, network_id) = (entity.entity_id, network.network_id);
 
-	// tell upstream indexer about newly created addresses
-	Config::set_many::<_, PrimaryId>(
-		app.db(),
-		Address::get_all_by_entity_id_network_id_and_addresses(
-			app.db(),
-			entity.entity_id,
-			network.network_id,
-			unique_addresses.clone().into_iter().collect::<Vec<String>>(),
-			Some(false),
-		)
-		.await?
-		.into_iter()
-		.map(|a| (ConfigKey::NewlyAddedAddress(a.network_id, a.address_id), a.address_id))
-		.collect::<HashMap<ConfigKey, PrimaryId>>(),
-	)
+		Box::pin(async move {
+			Address::create_many_with_ids(tx, n, IdPrefix::Address, id_length, new_addresses)
+				.await?;
+
+			Config::set_many::<_, PrimaryId>(
+				tx,
+				Address::get_all_by_entity_id_network_id_and_addresses(
+					tx,
+					entity_id,
+					network_id,
+					unique_addresses,
+					Some(false),
+				)
+				.await?
+				.into_iter()
+				.map(|a| (ConfigKey::NewlyAddedAddress(a.network_id, a.address_id), a.address_id))
+				.collect::<HashMap<ConfigKey, PrimaryId>>(),
+			)
+			.await
+		})
+	})
 	.await?;
 
 	// return newly created
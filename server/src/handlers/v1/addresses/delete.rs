@@ -1,46 +1,206 @@
-use axum::{extract::State, http::StatusCode, Json};
-use sea_orm::ColumnTrait;
-use serde::Deserialize;
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response as AxumResponse},
+};
+use sea_orm::{ColumnTrait, ConnectionTrait};
+use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, sync::Arc};
 
-use crate::ServerResult;
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	utils, ServerResult,
+};
 use barreleye_common::{
-	models::{set, Address, AddressActiveModel, AddressColumn, BasicModel, PrimaryId},
+	models::{Address, AddressColumn, PrimaryId, RuleRejection, SoftDeleteModel},
 	App,
 };
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Params {
+	force_partial: Option<bool>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Payload {
 	addresses: HashSet<String>,
 }
 
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	deleted: u64,
+	skipped_locked: Vec<String>,
+}
+
+/// By default deletes are all-or-nothing: if any address is locked, nothing is deleted
+/// and the locked ids are returned as invalid values. With `?forcePartial=true` the
+/// unlocked ones are deleted and the locked ones are reported in `skippedLocked`
 pub async fn handler(
 	State(app): State<Arc<App>>,
+	Query(params): Query<Params>,
 	Json(payload): Json<Payload>,
-) -> ServerResult<'static, StatusCode> {
+) -> ServerResult<'static, AxumResponse> {
 	// exit if no input
 	if payload.addresses.is_empty() {
-		return Ok(StatusCode::NO_CONTENT);
+		return Ok(StatusCode::NO_CONTENT.into_response());
 	}
 
+	utils::check_bulk_size("addresses", payload.addresses.len(), app.settings.max_bulk_size)?;
+
+	let force_partial = params.force_partial.unwrap_or(false);
+	Ok(Json(soft_delete(app.db(), payload.addresses, force_partial).await?).into_response())
+}
+
+/// Soft-deletes addresses by id. Ids that don't exist (or were deleted already) only go
+/// uncounted
+async fn soft_delete<C>(
+	c: &C,
+	ids: HashSet<String>,
+	force_partial: bool,
+) -> ServerResult<'static, Response>
+where
+	C: ConnectionTrait,
+{
 	// get all addresses
-	let all_addresses =
-		Address::get_all_where(app.db(), AddressColumn::Id.is_in(payload.addresses)).await?;
+	let (locked, all_addresses): (Vec<_>, Vec<_>) =
+		Address::get_all_existing_where(c, AddressColumn::Id.is_in(ids))
+			.await?
+			.into_iter()
+			.partition(|a| a.is_locked);
+
+	let mut skipped_locked = locked.into_iter().map(|a| a.id).collect::<Vec<_>>();
+	skipped_locked.sort();
+
+	// all or nothing, unless asked otherwise
+	if !skipped_locked.is_empty() && !force_partial {
+		return Err(ServerError::InvalidValues {
+			field: "addresses".into(),
+			values: skipped_locked.join(", ").into(),
+		});
+	}
 
 	// proceed only when there's something to delete
 	if all_addresses.is_empty() {
-		return Ok(StatusCode::NO_CONTENT);
+		return Ok(Response { deleted: 0, skipped_locked });
 	}
 
+	// remember the ones rules assigned, so they don't get proposed again
+	RuleRejection::create_many_by_addresses(c, &all_addresses).await?;
+
 	// soft-delete all associated addresses
-	Address::update_all_where(
-		app.db(),
+	let deleted = Address::soft_delete_all_where(
+		c,
 		AddressColumn::AddressId
 			.is_in(all_addresses.iter().map(|a| a.address_id).collect::<Vec<PrimaryId>>()),
-		AddressActiveModel { is_deleted: set(true), ..Default::default() },
 	)
 	.await?;
 
-	Ok(StatusCode::NO_CONTENT)
+	Ok(Response { deleted, skipped_locked })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		models::{set, BasicModel, Entity},
+		testing, Db,
+	};
+	use tokio::time::{sleep, Duration};
+
+	#[tokio::test]
+	async fn test_soft_delete() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let network_id = network.network_id;
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+
+		for (id, address, is_locked) in
+			[("adr_a", "a", false), ("adr_b", "b", false), ("adr_c", "c", true)]
+		{
+			let mut model = Address::new_model(
+				Some(id.to_string()),
+				entity_id,
+				network_id,
+				&network.id,
+				address,
+				"",
+				None,
+			);
+			model.is_locked = set(is_locked);
+			Address::create(db.get(), model).await?;
+		}
+
+		let ids =
+			HashSet::from(["adr_a", "adr_b", "adr_c", "adr_missing"].map(|id| id.to_string()));
+
+		// strict by default: a locked address fails the whole request
+		assert!(matches!(
+			soft_delete(db.get(), ids.clone(), false).await,
+			Err(ServerError::InvalidValues { values, .. }) if values == "adr_c"
+		));
+		assert!(Address::get_all_where(db.get(), AddressColumn::IsDeleted.eq(true))
+			.await?
+			.is_empty());
+
+		// partial mode deletes the rest
+		assert_eq!(
+			soft_delete(db.get(), ids.clone(), true).await?,
+			Response { deleted: 2, skipped_locked: vec!["adr_c".to_string()] }
+		);
+
+		// already deleted ones don't count twice
+		assert_eq!(
+			soft_delete(db.get(), ids, true).await?,
+			Response { deleted: 0, skipped_locked: vec!["adr_c".to_string()] }
+		);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_soft_delete_already_deleted() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+		Address::create(
+			db.get(),
+			Address::new_model(
+				Some("adr_a".to_string()),
+				entity_id,
+				network.network_id,
+				&network.id,
+				"a",
+				"",
+				None,
+			),
+		)
+		.await?;
+
+		let ids = HashSet::from(["adr_a".to_string()]);
+		assert_eq!(soft_delete(db.get(), ids.clone(), false).await?.deleted, 1);
+
+		let updated_at = Address::get_by_id(db.get(), "adr_a").await?.unwrap().updated_at;
+		assert!(updated_at.is_some());
+
+		// so that touching it again would show
+		sleep(Duration::from_millis(10)).await;
+
+		// deleting it again is a no-op
+		assert_eq!(
+			soft_delete(db.get(), ids, false).await?,
+			Response { deleted: 0, skipped_locked: vec![] }
+		);
+
+		let address = Address::get_by_id(db.get(), "adr_a").await?.unwrap();
+		assert!(address.is_deleted);
+		assert_eq!(address.updated_at, updated_at);
+
+		Ok(())
+	}
 }
@@ -1,11 +1,8 @@
-use axum::{
-	extract::{Path, State},
-	Json,
-};
+use axum::extract::{Path, State};
 use serde::Serialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
 	models::{Address, Network, SoftDeleteModel},
 	utils, App,
@@ -1,20 +1,44 @@
-use axum::{extract::State, Json};
-use axum_extra::extract::Query;
-use sea_orm::ColumnTrait;
+use axum::extract::State;
+use sea_orm::{ColumnTrait, Condition, Order};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::ServerResult;
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	ServerResult,
+};
 use barreleye_common::{
-	models::{Address, AddressColumn, BasicModel, Network, PrimaryId},
+	models::{Address, AddressColumn, BasicModel, Entity, Network, PrimaryId},
 	utils, App,
 };
 
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadSort {
+	#[default]
+	CreatedAt,
+	Address,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadOrder {
+	#[default]
+	Asc,
+	Desc,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Payload {
 	offset: Option<u64>,
 	limit: Option<u64>,
+	entity: Option<String>,
+	network: Option<String>,
+	locked: Option<bool>,
+	sort: Option<PayloadSort>,
+	order: Option<PayloadOrder>,
 }
 
 #[derive(Serialize)]
@@ -28,9 +52,45 @@ pub async fn handler(
 	State(app): State<Arc<App>>,
 	Query(payload): Query<Payload>,
 ) -> ServerResult<'static, Json<Response>> {
-	let addresses = Address::get_all_paginated_where(
+	let mut condition = Condition::all().add(AddressColumn::IsDeleted.eq(false));
+
+	// filter by entity
+	if let Some(entity) = payload.entity {
+		let entity = Entity::get_by_id(app.db(), &entity)
+			.await?
+			.ok_or(ServerError::InvalidParam { field: "entity".into(), value: entity.into() })?;
+
+		condition = condition.add(AddressColumn::EntityId.eq(entity.entity_id));
+	}
+
+	// filter by network
+	if let Some(network) = payload.network {
+		let network = Network::get_by_id(app.db(), &network)
+			.await?
+			.ok_or(ServerError::InvalidParam { field: "network".into(), value: network.into() })?;
+
+		condition = condition.add(AddressColumn::NetworkId.eq(network.network_id));
+	}
+
+	// filter by locked status
+	if let Some(locked) = payload.locked {
+		condition = condition.add(AddressColumn::IsLocked.eq(locked));
+	}
+
+	let order_by = match payload.sort.unwrap_or_default() {
+		PayloadSort::CreatedAt => AddressColumn::CreatedAt,
+		PayloadSort::Address => AddressColumn::Address,
+	};
+	let order = match payload.order.unwrap_or_default() {
+		PayloadOrder::Asc => Order::Asc,
+		PayloadOrder::Desc => Order::Desc,
+	};
+
+	let addresses = Address::get_all_sorted_paginated_where(
 		app.db(),
-		AddressColumn::IsDeleted.eq(false),
+		condition,
+		order_by,
+		order,
 		payload.offset,
 		payload.limit,
 	)
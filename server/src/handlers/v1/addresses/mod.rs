@@ -1,20 +1,26 @@
 use axum::{
-	routing::{delete, get, post},
+	routing::{delete, get, post, put},
 	Router,
 };
 use std::sync::Arc;
 
 use barreleye_common::App;
 
+mod balances;
+mod cluster;
 mod create;
 mod delete;
 mod get;
 mod list;
+mod update;
 
 pub fn get_routes() -> Router<Arc<App>> {
 	Router::new()
 		.route("/", post(create::handler))
 		.route("/", get(list::handler))
 		.route("/{id}", get(get::handler))
+		.route("/{id}", put(update::handler))
+		.route("/{id}/balances", get(balances::handler))
+		.route("/{id}/cluster", get(cluster::handler))
 		.route("/", delete(delete::handler))
 }
@@ -0,0 +1,44 @@
+use axum::{
+	extract::{Path, State},
+	http::StatusCode,
+};
+use sea_orm::{prelude::Json as JsonData, ActiveModelTrait};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{errors::ServerError, extract::Json, ServerResult};
+use barreleye_common::{
+	models::{optional_set, Address, AddressActiveModel, BasicModel, SoftDeleteModel},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	description: Option<String>,
+	data: Option<JsonData>,
+	locked: Option<bool>,
+}
+
+/// Unlocking (`"locked": false`) is what lets a locked address be deleted again
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(address_id): Path<String>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, StatusCode> {
+	if Address::get_existing_by_id(app.db(), &address_id).await?.is_none() {
+		return Err(ServerError::NotFound);
+	}
+
+	let update_data = AddressActiveModel {
+		description: optional_set(payload.description),
+		data: optional_set(payload.data),
+		is_locked: optional_set(payload.locked),
+		..Default::default()
+	};
+	if update_data.is_changed() {
+		Address::update_by_id(app.db(), &address_id, update_data).await?;
+	}
+
+	Ok(StatusCode::NO_CONTENT)
+}
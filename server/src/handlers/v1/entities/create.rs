@@ -1,9 +1,12 @@
-use axum::{extract::State, Json};
+use axum::extract::State;
 use sea_orm::{prelude::Json as JsonData, ColumnTrait};
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, utils::extract_primary_ids, ServerResult};
+use crate::{
+	errors::ServerError, extract::Json, handlers::v1::entities::check_name,
+	utils::extract_primary_ids, ServerResult,
+};
 use barreleye_common::{
 	models::{is_valid_id, BasicModel, Entity, EntityTag, Tag, TagColumn},
 	App, IdPrefix,
@@ -32,18 +35,8 @@ pub async fn handler(
 	}
 
 	// check name
-	if let Some(name) = payload.name.clone() {
-		// check for soft-deleted matches
-		if Entity::get_by_name(app.db(), &name, Some(true)).await?.is_some() {
-			return Err(ServerError::TooEarly {
-				reason: format!("entity hasn't been deleted yet: {name}").into(),
-			});
-		}
-
-		// check for any duplicate
-		if Entity::get_by_name(app.db(), &name, None).await?.is_some() {
-			return Err(ServerError::Duplicate { field: "name".into(), value: name.into() });
-		}
+	if let Some(name) = payload.name.as_deref() {
+		check_name(app.db(), name, None).await?;
 	}
 
 	// check for invalid tags
@@ -67,21 +60,35 @@ pub async fn handler(
 		}
 	}
 
-	// create new
-	let entity_id = Entity::create(
-		app.db(),
-		Entity::new_model(payload.id, payload.name, &payload.description, payload.data),
-	)
-	.await?;
+	// create new along with its entity/tag mappings, generating an id unless one was given
+	let (name, description, data) = (payload.name, payload.description, payload.data);
+	let new_entity =
+		move |id| Entity::new_model(Some(id), name.clone(), &description, data.clone());
+	let id_length = app.settings.id_length;
+	let entity_id = app
+		.db_tx_with_retry(|tx| {
+			let (id, new_entity, tag_ids) =
+				(payload.id.clone(), new_entity.clone(), tag_ids.clone());
+
+			Box::pin(async move {
+				let entity_id =
+					Entity::create_with_id(tx, id, IdPrefix::Entity, id_length, new_entity).await?;
 
-	// upsert entity/tag mappings
-	if !tag_ids.is_empty() {
-		EntityTag::create_many(
-			app.db(),
-			tag_ids.into_iter().map(|tag_id| EntityTag::new_model(entity_id, tag_id)).collect(),
-		)
+				if !tag_ids.is_empty() {
+					EntityTag::create_many(
+						tx,
+						tag_ids
+							.into_iter()
+							.map(|tag_id| EntityTag::new_model(entity_id, tag_id))
+							.collect(),
+					)
+					.await?;
+				}
+
+				Ok(entity_id)
+			})
+		})
 		.await?;
-	}
 
 	// return newly created
 	Ok(Entity::get(app.db(), entity_id).await?.unwrap().into())
@@ -1,14 +1,11 @@
-use axum::{extract::State, http::StatusCode, Json};
-use sea_orm::ColumnTrait;
+use axum::{extract::State, http::StatusCode};
+use sea_orm::{ColumnTrait, ConnectionTrait};
 use serde::Deserialize;
 use std::{collections::HashSet, sync::Arc};
 
-use crate::ServerResult;
+use crate::{errors::ServerError, extract::Json, utils, ServerResult};
 use barreleye_common::{
-	models::{
-		set, Address, AddressActiveModel, AddressColumn, BasicModel, Entity, EntityActiveModel,
-		EntityColumn, PrimaryId,
-	},
+	models::{Address, AddressColumn, Entity, EntityColumn, PrimaryId, SoftDeleteModel},
 	App,
 };
 
@@ -27,31 +24,117 @@ pub async fn handler(
 		return Ok(StatusCode::NO_CONTENT);
 	}
 
+	utils::check_bulk_size("entities", payload.entities.len(), app.settings.max_bulk_size)?;
+
+	soft_delete(app.db(), payload.entities).await?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+/// Soft-deletes entities by id, along with their addresses. Deleting an entity would delete
+/// its locked addresses too, so if any of them has some, nothing is deleted and those
+/// entities' ids are returned as invalid values
+async fn soft_delete<C>(c: &C, ids: HashSet<String>) -> ServerResult<'static, ()>
+where
+	C: ConnectionTrait,
+{
 	// get all entities
-	let all_entities =
-		Entity::get_all_where(app.db(), EntityColumn::Id.is_in(payload.entities)).await?;
+	let all_entities = Entity::get_all_existing_where(c, EntityColumn::Id.is_in(ids)).await?;
 
 	// proceed only when there's something to delete
 	if all_entities.is_empty() {
-		return Ok(StatusCode::NO_CONTENT);
+		return Ok(());
 	}
 
-	// soft-delete all associated addresses
+	// refuse to delete locked addresses
 	let all_entity_ids = all_entities.iter().map(|e| e.entity_id).collect::<Vec<PrimaryId>>();
-	Address::update_all_where(
-		app.db(),
-		AddressColumn::EntityId.is_in(all_entity_ids.clone()),
-		AddressActiveModel { is_deleted: set(true), ..Default::default() },
+	let locked_entity_ids = Address::get_all_existing_where(
+		c,
+		AddressColumn::EntityId.is_in(all_entity_ids.clone()).and(AddressColumn::IsLocked.eq(true)),
 	)
-	.await?;
+	.await?
+	.into_iter()
+	.map(|a| a.entity_id)
+	.collect::<HashSet<_>>();
+	if !locked_entity_ids.is_empty() {
+		let mut locked = all_entities
+			.into_iter()
+			.filter(|e| locked_entity_ids.contains(&e.entity_id))
+			.map(|e| e.id)
+			.collect::<Vec<_>>();
+		locked.sort();
+
+		return Err(ServerError::InvalidValues {
+			field: "entities".into(),
+			values: locked.join(", ").into(),
+		});
+	}
+
+	// soft-delete all associated addresses
+	Address::soft_delete_all_where(c, AddressColumn::EntityId.is_in(all_entity_ids.clone()))
+		.await?;
 
 	// soft-delete all entities
-	Entity::update_all_where(
-		app.db(),
-		EntityColumn::EntityId.is_in(all_entity_ids),
-		EntityActiveModel { is_deleted: set(true), ..Default::default() },
-	)
-	.await?;
+	Entity::soft_delete_all_where(c, EntityColumn::EntityId.is_in(all_entity_ids)).await?;
 
-	Ok(StatusCode::NO_CONTENT)
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		models::{set, AddressActiveModel, BasicModel},
+		testing, Db,
+	};
+
+	#[tokio::test]
+	async fn test_soft_delete() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+
+		for (id, address, is_locked) in [("ent_a", "a", false), ("ent_b", "b", true)] {
+			let entity_id =
+				Entity::create(db.get(), Entity::new_model(Some(id.to_string()), None, "", None))
+					.await?;
+
+			let mut model = Address::new_model(
+				None,
+				entity_id,
+				network.network_id,
+				&network.id,
+				address,
+				"",
+				None,
+			);
+			model.is_locked = set(is_locked);
+			Address::create(db.get(), model).await?;
+		}
+
+		let ids = HashSet::from(["ent_a", "ent_b"].map(|id| id.to_string()));
+		let get_deleted = || async {
+			let addresses = Address::get_all_where(db.get(), AddressColumn::IsDeleted.eq(true));
+			let entities = Entity::get_all_where(db.get(), EntityColumn::IsDeleted.eq(true));
+			eyre::Ok((addresses.await?.len(), entities.await?.len()))
+		};
+
+		// an entity with a locked address fails the whole request
+		assert!(matches!(
+			soft_delete(db.get(), ids.clone()).await,
+			Err(ServerError::InvalidValues { values, .. }) if values == "ent_b"
+		));
+		assert_eq!(get_deleted().await?, (0, 0));
+
+		// once unlocked, it goes through
+		Address::update_all_where(
+			db.get(),
+			AddressColumn::IsLocked.eq(true),
+			AddressActiveModel { is_locked: set(false), ..Default::default() },
+		)
+		.await?;
+		soft_delete(db.get(), ids).await?;
+		assert_eq!(get_deleted().await?, (2, 2));
+
+		Ok(())
+	}
 }
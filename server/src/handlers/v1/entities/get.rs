@@ -1,13 +1,11 @@
-use axum::{
-	extract::{Path, State},
-	Json,
-};
-use serde::Serialize;
+use axum::extract::{Path, State};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::{
 	errors::ServerError,
-	handlers::v1::entities::{get_addresses_data, get_tags_data},
+	extract::{Json, Query},
+	handlers::v1::entities::{get_addresses_data, get_risk_score, get_tags_data, ResponseEntity},
 	ServerResult,
 };
 use barreleye_common::{
@@ -15,10 +13,16 @@ use barreleye_common::{
 	App,
 };
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	include_meta: Option<bool>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Response {
-	entity: Entity,
+	entity: ResponseEntity,
 	tags: Vec<Tag>,
 	addresses: Vec<Address>,
 	networks: Vec<Network>,
@@ -27,6 +31,7 @@ pub struct Response {
 pub async fn handler(
 	State(app): State<Arc<App>>,
 	Path(entity_id): Path<String>,
+	Query(payload): Query<Payload>,
 ) -> ServerResult<'static, Json<Response>> {
 	if let Some(mut entity) = Entity::get_existing_by_id(app.db(), &entity_id).await? {
 		let (tags_data, addresses_data) = tokio::join!(
@@ -39,6 +44,9 @@ pub async fn handler(
 
 		entity.tags = tags_map.get(&entity.entity_id).cloned().or(Some(vec![]));
 		entity.addresses = addresses_map.get(&entity.entity_id).cloned().or(Some(vec![]));
+		entity.risk_score = Some(get_risk_score(&app, &entity, &tags));
+
+		let entity = ResponseEntity::new(entity, payload.include_meta.unwrap_or(false));
 
 		Ok(Response { entity, tags, addresses, networks }.into())
 	} else {
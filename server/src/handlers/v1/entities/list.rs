@@ -1,13 +1,11 @@
-use axum::{
-	extract::{Query, State},
-	Json,
-};
+use axum::extract::State;
 use sea_orm::ColumnTrait;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::{
-	handlers::v1::entities::{get_addresses_data, get_tags_data},
+	extract::{Json, Query},
+	handlers::v1::entities::{get_addresses_data, get_risk_score, get_tags_data, ResponseEntity},
 	ServerResult,
 };
 use barreleye_common::{
@@ -20,12 +18,13 @@ use barreleye_common::{
 pub struct Payload {
 	offset: Option<u64>,
 	limit: Option<u64>,
+	include_meta: Option<bool>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Response {
-	entities: Vec<Entity>,
+	entities: Vec<ResponseEntity>,
 	tags: Vec<Tag>,
 	addresses: Vec<Address>,
 	networks: Vec<Network>,
@@ -54,7 +53,11 @@ pub async fn handler(
 	for entity in entities.iter_mut() {
 		entity.tags = tags_map.get(&entity.entity_id).cloned().or(Some(vec![]));
 		entity.addresses = addresses_map.get(&entity.entity_id).cloned().or(Some(vec![]));
+		entity.risk_score = Some(get_risk_score(&app, entity, &tags));
 	}
 
+	let include_meta = payload.include_meta.unwrap_or(false);
+	let entities = entities.into_iter().map(|e| ResponseEntity::new(e, include_meta)).collect();
+
 	Ok(Response { entities, tags, addresses, networks }.into())
 }
@@ -3,10 +3,13 @@ use axum::{
 	Router,
 };
 use eyre::Result;
+use sea_orm::{prelude::DateTime, ConnectionTrait};
+use serde::Serialize;
 use std::{collections::HashMap, sync::Arc};
 
+use crate::{errors::ServerError, ServerResult};
 use barreleye_common::{
-	models::{Address, Network, PrimaryId, PrimaryIds, Tag},
+	models::{Address, Entity, Network, PrimaryId, PrimaryIds, Tag},
 	utils, App,
 };
 
@@ -25,6 +28,62 @@ pub fn get_routes() -> Router<Arc<App>> {
 		.route("/", delete(delete::handler))
 }
 
+/// Entity as returned by the api. `updatedAt` & `isDeleted` are left out unless
+/// `?includeMeta=true` is passed
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseEntity {
+	#[serde(flatten)]
+	entity: Entity,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	updated_at: Option<Option<DateTime>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	is_deleted: Option<bool>,
+}
+
+impl ResponseEntity {
+	pub fn new(entity: Entity, include_meta: bool) -> Self {
+		let (updated_at, is_deleted) = match include_meta {
+			true => (Some(entity.updated_at), Some(entity.is_deleted)),
+			false => (None, None),
+		};
+
+		Self { entity, updated_at, is_deleted }
+	}
+}
+
+/// Rejects `name` if another active entity holds it. Otherwise soft-deleted entities that
+/// still have it give it up, so it's free to be (re)used
+pub async fn check_name<C>(
+	c: &C,
+	name: &str,
+	except_entity_id: Option<PrimaryId>,
+) -> ServerResult<'static, ()>
+where
+	C: ConnectionTrait,
+{
+	if Entity::get_name_conflict(c, name, except_entity_id).await?.is_some() {
+		return Err(ServerError::Duplicate {
+			field: "name".into(),
+			value: name.to_string().into(),
+		});
+	}
+
+	Entity::release_deleted_name(c, name).await?;
+
+	Ok(())
+}
+
+/// Scores `entity` by the tags already set on it (out of `tags`), see `Entity::risk_score()`
+pub fn get_risk_score(app: &App, entity: &Entity, tags: &[Tag]) -> u8 {
+	let tag_ids = entity.tags.as_deref().unwrap_or_default();
+
+	Entity::risk_score(
+		tags.iter().filter(|t| tag_ids.contains(&t.id)).map(|t| t.risk_level),
+		&app.settings.risk_score_weights,
+	)
+}
+
 pub async fn get_tags_data(
 	app: Arc<App>,
 	entity_ids: PrimaryIds,
@@ -75,3 +134,58 @@ pub async fn get_addresses_data(
 
 	Ok((addresses, map, networks))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		models::{BasicModel, EntityColumn, SoftDeleteModel},
+		Db,
+	};
+	use sea_orm::ColumnTrait;
+
+	#[tokio::test]
+	async fn test_check_name() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let entity_a =
+			Entity::create(db.get(), Entity::new_model(None, Some("A".into()), "", None)).await?;
+		Entity::create(db.get(), Entity::new_model(None, Some("B".into()), "", None)).await?;
+
+		// renaming A to B's name is a duplicate
+		assert!(matches!(
+			check_name(db.get(), "B", Some(entity_a)).await,
+			Err(ServerError::Duplicate { .. })
+		));
+
+		// keeping its own name or taking a free one is fine
+		assert!(check_name(db.get(), "A", Some(entity_a)).await.is_ok());
+		assert!(check_name(db.get(), "C", Some(entity_a)).await.is_ok());
+
+		// so is a soft-deleted entity's
+		Entity::soft_delete_all_where(db.get(), EntityColumn::EntityId.ne(entity_a)).await?;
+		assert!(check_name(db.get(), "B", Some(entity_a)).await.is_ok());
+		Entity::create(db.get(), Entity::new_model(None, Some("B".into()), "", None)).await?;
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_response_entity() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+		let entity = Entity::get(db.get(), entity_id).await?.unwrap();
+
+		let without_meta = serde_json::to_value(ResponseEntity::new(entity.clone(), false))?;
+		assert!(without_meta.get("createdAt").is_some());
+		assert!(without_meta.get("updatedAt").is_none());
+		assert!(without_meta.get("isDeleted").is_none());
+
+		let with_meta = serde_json::to_value(ResponseEntity::new(entity, true))?;
+		assert_eq!(with_meta.get("updatedAt"), Some(&serde_json::Value::Null));
+		assert_eq!(with_meta.get("isDeleted"), Some(&serde_json::Value::Bool(false)));
+
+		Ok(())
+	}
+}
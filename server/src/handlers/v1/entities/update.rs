@@ -1,13 +1,15 @@
 use axum::{
 	extract::{Path, State},
 	http::StatusCode,
-	Json,
 };
 use sea_orm::{prelude::Json as JsonData, ActiveModelTrait, ColumnTrait};
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, utils::extract_primary_ids, ServerResult};
+use crate::{
+	errors::ServerError, extract::Json, handlers::v1::entities::check_name,
+	utils::extract_primary_ids, ServerResult,
+};
 use barreleye_common::{
 	models::{
 		optional_set, BasicModel, Entity, EntityActiveModel, EntityTag, SoftDeleteModel, Tag,
@@ -32,23 +34,8 @@ pub async fn handler(
 ) -> ServerResult<'static, StatusCode> {
 	if let Some(entity) = Entity::get_existing_by_id(app.db(), &entity_id).await? {
 		// check name
-		if let Some(Some(name)) = payload.name.clone() {
-			// check for soft-deleted matches
-			if Entity::get_by_name(app.db(), &name, Some(true)).await?.is_some() {
-				return Err(ServerError::TooEarly {
-					reason: format!("entity hasn't been deleted yet: {name}").into(),
-				});
-			}
-
-			// check for any duplicate
-			if let Some(other_entity) = Entity::get_by_name(app.db(), &name, None).await? {
-				if other_entity.id != entity.id {
-					return Err(ServerError::Duplicate {
-						field: "name".into(),
-						value: name.into(),
-					});
-				}
-			}
+		if let Some(Some(name)) = payload.name.as_ref() {
+			check_name(app.db(), name, Some(entity.entity_id)).await?;
 		}
 
 		// check for invalid tags
@@ -1,5 +1,24 @@
-use axum::http::StatusCode;
+use axum::{extract::State, http::StatusCode};
+use std::sync::Arc;
 
-pub async fn handler() -> StatusCode {
-	StatusCode::NO_CONTENT
+use crate::ServerResult;
+use barreleye_common::{
+	progress::{Health, ProgressTracker},
+	App,
+};
+
+/// Fails with a 503 while any network is degraded (see `--max-blocks-behind`)
+pub async fn handler(State(app): State<Arc<App>>) -> ServerResult<'static, StatusCode> {
+	let max_blocks_behind = app.settings.max_blocks_behind;
+
+	if max_blocks_behind > 0 {
+		let progress = ProgressTracker::new().get_all(app.db()).await?;
+		let mut health = progress.iter().map(|p| Health::new(p.blocks_behind, max_blocks_behind));
+
+		if health.any(|h| h == Health::Degraded) {
+			return Ok(StatusCode::SERVICE_UNAVAILABLE);
+		}
+	}
+
+	Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,23 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::get::get_response;
+use crate::{extract::Query, ServerResult};
+use barreleye_common::App;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	q: String,
+}
+
+/// Same lookup as `/v1/info`, rendered as a Graphviz graph
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, impl IntoResponse> {
+	let response = get_response(app, &payload.q).await?;
+
+	Ok(([(header::CONTENT_TYPE, "text/vnd.graphviz")], response.to_dot()))
+}
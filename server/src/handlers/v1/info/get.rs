@@ -1,44 +1,64 @@
-use axum::{extract::State, Json};
-use axum_extra::extract::Query;
+use axum::{
+	extract::State,
+	http::{header, HeaderMap, StatusCode},
+	response::{IntoResponse, Response as HttpResponse},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use eyre::Result;
-use sea_orm::ColumnTrait;
+use schemars::JsonSchema;
+use sea_orm::{ColumnTrait, ConnectionTrait};
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	sync::Arc,
 };
 
-use crate::{errors::ServerError, ServerResult};
+use super::get_cached_response;
+use crate::{errors::ServerError, extract::Query, utils::format_address, ServerResult};
 use barreleye_common::{
+	cache::CacheKey,
+	chain::{JsonAmount, U256},
 	models::{
-		Address, Amount, Balance, BasicModel, Entity, Link, Network, PrimaryId, SanitizedEntity,
-		SanitizedNetwork, SanitizedTag, Tag, Token, TokenColumn,
+		Address, Amount, Balance, BasicModel, Config, Entity, Link, Network, NetworkColumn,
+		PrimaryId, SanitizedEntity, SanitizedNetwork, SanitizedTag, Tag, Token, TokenColumn,
+		Transfer,
 	},
-	App, RiskLevel, RiskReason,
+	utils, App, RiskLevel, RiskReason, INFO_CACHE_TTL, INFO_ENTITIES_MAX,
+	INFO_SOURCES_DEFAULT_LIMIT, INFO_SOURCES_MAX_LIMIT, TRANSFERS_BY_UUIDS_CHUNK_SIZE,
 };
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadGroupBy {
+	Entity,
+}
+
+#[derive(Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
+#[schemars(rename = "InfoPayload")]
 pub struct Payload {
 	q: String,
+	group_by: Option<PayloadGroupBy>,
+	cursor: Option<String>,
+	limit: Option<u64>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponseRisk {
 	level: RiskLevel,
-	reasons: HashSet<RiskReason>,
+	reasons: BTreeSet<RiskReason>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponseAsset {
 	network: String,
 	token: Option<String>,
-	balance: String,
+	balance: JsonAmount,
 }
 
-#[derive(Serialize, Eq, PartialEq, Hash)]
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponseToken {
 	id: String,
@@ -48,7 +68,29 @@ pub struct ResponseToken {
 	decimals: u16,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseTransaction {
+	hash: String,
+	from: String,
+	to: String,
+	asset: Option<String>,
+	amount: JsonAmount,
+}
+
+impl ResponseTransaction {
+	fn new(t: &Transfer, is_numeric: bool) -> Self {
+		Self {
+			hash: t.tx_hash.clone(),
+			from: t.from_address.clone(),
+			to: t.to_address.clone(),
+			asset: (!t.asset_address.is_empty()).then(|| t.asset_address.clone()),
+			amount: JsonAmount::new(t.relative_amount, is_numeric),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponseSource {
 	network: String,
@@ -56,34 +98,361 @@ pub struct ResponseSource {
 	from: String,
 	to: String,
 	hops: u64,
+	transactions: Vec<ResponseTransaction>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseSourceGroup {
+	entity: String,
+	networks: Vec<String>,
+	addresses: Vec<String>,
+	hops: u64,
+	transactions: Vec<ResponseTransaction>,
+	amounts: Vec<ResponseSourceGroupAmount>,
+}
+
+/// What a group's transactions add up to, for a single asset (`None` being the native one)
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseSourceGroupAmount {
+	asset: Option<String>,
+	amount: JsonAmount,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
+pub struct ResponseWarnings {
+	dropped_network_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "InfoResponse")]
 pub struct Response {
 	addresses: Vec<String>,
 	risk: ResponseRisk,
 	assets: Vec<ResponseAsset>,
 	tokens: Vec<ResponseToken>,
 	sources: Vec<ResponseSource>,
+	sources_next_cursor: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	sources_by_entity: Option<Vec<ResponseSourceGroup>>,
 	networks: Vec<SanitizedNetwork>,
 	entities: Vec<SanitizedEntity>,
+	entities_truncated: bool,
 	tags: Vec<SanitizedTag>,
+	warnings: ResponseWarnings,
+}
+
+impl Response {
+	/// Graphviz DOT rendering: queried addresses, sources and entities are nodes; transfers
+	/// (or whole links, when their transfers couldn't be found) are edges
+	pub fn to_dot(&self) -> String {
+		fn escape(s: &str) -> String {
+			s.replace('\\', "\\\\").replace('"', "\\\"")
+		}
+
+		let mut nodes = BTreeMap::new();
+		let mut edges = BTreeSet::new();
+
+		for address in self.addresses.iter() {
+			nodes.insert(address.as_str(), "doublecircle");
+		}
+
+		for source in self.sources.iter() {
+			nodes.entry(source.entity.as_str()).or_insert("box");
+			nodes.entry(source.from.as_str()).or_insert("ellipse");
+			edges.insert(format!(
+				"\"{}\" -> \"{}\" [style=dashed]",
+				escape(&source.entity),
+				escape(&source.from)
+			));
+
+			if source.transactions.is_empty() {
+				nodes.entry(source.to.as_str()).or_insert("ellipse");
+				edges.insert(format!(
+					"\"{}\" -> \"{}\" [label=\"{} hops\"]",
+					escape(&source.from),
+					escape(&source.to),
+					source.hops
+				));
+			}
+
+			for t in source.transactions.iter() {
+				nodes.entry(t.from.as_str()).or_insert("ellipse");
+				nodes.entry(t.to.as_str()).or_insert("ellipse");
+				edges.insert(format!(
+					"\"{}\" -> \"{}\" [label=\"{}\\n{}\"]",
+					escape(&t.from),
+					escape(&t.to),
+					escape(&t.amount.to_string()),
+					escape(&t.hash)
+				));
+			}
+		}
+
+		let mut ret = vec!["digraph info {".to_string()];
+		ret.extend(
+			nodes.into_iter().map(|(id, shape)| format!("\t\"{}\" [shape={shape}];", escape(id))),
+		);
+		ret.extend(edges.into_iter().map(|edge| format!("\t{edge};")));
+		ret.push("}".to_string());
+
+		ret.join("\n") + "\n"
+	}
+}
+
+/// Distinct link sources plus the queried addresses themselves, in a single pass
+/// over `links`
+fn get_entity_addresses(links: &[Link], addresses: &[String]) -> Vec<String> {
+	let mut ret = HashSet::with_capacity(links.len() + addresses.len());
+
+	for link in links.iter() {
+		if !ret.contains(&link.from_address) {
+			ret.insert(link.from_address.clone());
+		}
+	}
+	for address in addresses.iter() {
+		if !ret.contains(address) {
+			ret.insert(address.clone());
+		}
+	}
+
+	ret.into_iter().collect()
+}
+
+/// Splits off links on networks that aren't `configured`, returning their network ids
+fn split_by_network(
+	links: Vec<Link>,
+	configured: impl Fn(PrimaryId) -> bool,
+) -> (Vec<Link>, Vec<PrimaryId>) {
+	let mut dropped_network_ids = BTreeSet::new();
+
+	let links = links
+		.into_iter()
+		.filter(|l| {
+			let network_id = l.network_id as PrimaryId;
+			if !configured(network_id) {
+				dropped_network_ids.insert(network_id);
+				return false;
+			}
+
+			true
+		})
+		.collect();
+
+	(links, dropped_network_ids.into_iter().collect())
+}
+
+/// Sorted by id so responses are deterministic, and capped at `max`. Also returns
+/// whether any were left out
+fn sort_and_cap_entities(
+	mut entities: Vec<SanitizedEntity>,
+	max: usize,
+) -> (Vec<SanitizedEntity>, bool) {
+	entities.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+
+	let truncated = entities.len() > max;
+	entities.truncate(max);
+
+	(entities, truncated)
+}
+
+/// Collapses sources into one group per entity (all of an exchange's addresses become a
+/// single node), keeping the fewest hops and collecting the transactions of all of them
+/// (with their totals per asset). Sources always have an entity, so there's no bucket for
+/// unknown ones
+fn group_sources_by_entity(sources: &[ResponseSource]) -> Vec<ResponseSourceGroup> {
+	#[derive(Default)]
+	struct Group<'a> {
+		networks: BTreeSet<&'a str>,
+		addresses: BTreeSet<&'a str>,
+		hops: u64,
+		transactions: Vec<&'a ResponseTransaction>,
+		amounts: BTreeMap<Option<&'a str>, JsonAmount>,
+	}
+
+	let mut groups = BTreeMap::<&str, Group>::new();
+
+	for source in sources.iter() {
+		let group = groups
+			.entry(source.entity.as_str())
+			.or_insert_with(|| Group { hops: source.hops, ..Default::default() });
+
+		group.networks.insert(&source.network);
+		group.addresses.insert(&source.from);
+		group.hops = group.hops.min(source.hops);
+
+		for t in source.transactions.iter() {
+			let total = group
+				.amounts
+				.entry(t.asset.as_deref())
+				.or_insert(JsonAmount::new(U256::zero(), t.amount.is_numeric));
+			total.amount = total.amount.saturating_add(t.amount.amount);
+
+			group.transactions.push(t);
+		}
+	}
+
+	groups
+		.into_iter()
+		.map(|(entity, group)| ResponseSourceGroup {
+			entity: entity.to_string(),
+			networks: group.networks.into_iter().map(|n| n.to_string()).collect(),
+			addresses: group.addresses.into_iter().map(|a| a.to_string()).collect(),
+			hops: group.hops,
+			transactions: group.transactions.into_iter().cloned().collect(),
+			amounts: group
+				.amounts
+				.into_iter()
+				.map(|(asset, amount)| ResponseSourceGroupAmount {
+					asset: asset.map(|a| a.to_string()),
+					amount,
+				})
+				.collect(),
+		})
+		.collect()
+}
+
+/// Where a page of sources left off: the (network, from) pair of its last source, which is
+/// also the order sources are sorted in
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct SourceCursor(String, String);
+
+impl SourceCursor {
+	fn decode(cursor: &str) -> Option<Self> {
+		serde_json::from_slice(&URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()
+	}
+
+	fn encode(&self) -> String {
+		URL_SAFE_NO_PAD.encode(serde_json::to_string(self).unwrap_or_default())
+	}
+}
+
+impl From<&ResponseSource> for SourceCursor {
+	fn from(source: &ResponseSource) -> Self {
+		Self(source.network.clone(), source.from.clone())
+	}
+}
+
+/// Keeps a page of at most `limit` sources after `cursor`, setting the next cursor while
+/// there are more. Risk and grouping are still based on all of them
+fn with_sources_page(mut response: Response, cursor: Option<SourceCursor>, limit: u64) -> Response {
+	if let Some(cursor) = cursor {
+		response.sources.retain(|s| SourceCursor::from(s) > cursor);
+	}
+
+	response.sources_next_cursor = match response.sources.len() as u64 > limit {
+		true => {
+			response.sources.truncate(limit as usize);
+			response.sources.last().map(|s| SourceCursor::from(s).encode())
+		}
+		_ => None,
+	};
+
+	response
+}
+
+fn with_grouping(mut response: Response, group_by: Option<PayloadGroupBy>) -> Response {
+	response.sources_by_entity = match group_by {
+		Some(PayloadGroupBy::Entity) => Some(group_sources_by_entity(&response.sources)),
+		None => None,
+	};
+
+	response
 }
 
 pub async fn handler(
 	State(app): State<Arc<App>>,
+	headers: HeaderMap,
 	Query(payload): Query<Payload>,
-) -> ServerResult<'static, Json<Response>> {
+) -> ServerResult<'static, HttpResponse> {
+	let cursor = match payload.cursor {
+		Some(cursor) => {
+			Some(SourceCursor::decode(&cursor).ok_or(ServerError::InvalidParam {
+				field: "cursor".into(),
+				value: cursor.into(),
+			})?)
+		}
+		_ => None,
+	};
+
+	let limit = payload.limit.unwrap_or(INFO_SOURCES_DEFAULT_LIMIT);
+	if !(1..=INFO_SOURCES_MAX_LIMIT).contains(&limit) {
+		return Err(ServerError::InvalidParam {
+			field: "limit".into(),
+			value: limit.to_string().into(),
+		});
+	}
+
+	let response = with_grouping(get_response(app, &payload.q).await?, payload.group_by);
+	with_etag(&headers, &with_sources_page(response, cursor, limit))
+}
+
+/// Tags the serialized response with a strong ETag (its sha256), and answers with an empty
+/// `304 Not Modified` when the client already has that exact body
+fn with_etag(headers: &HeaderMap, response: &Response) -> ServerResult<'static, HttpResponse> {
+	let body = serde_json::to_string(response)?;
+	let etag = format!(
+		"\"{}\"",
+		utils::sha256(&body).iter().map(|b| format!("{b:02x}")).collect::<String>()
+	);
+
+	// `If-None-Match` may list several tags, or `*` for any
+	let is_not_modified = headers
+		.get_all(header::IF_NONE_MATCH)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.map(|t| t.trim())
+		.any(|t| t == "*" || t.trim_start_matches("W/") == etag);
+
+	Ok(match is_not_modified {
+		true => (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response(),
+		_ => ([(header::CONTENT_TYPE, "application/json".to_string()), (header::ETAG, etag)], body)
+			.into_response(),
+	})
+}
+
+async fn get_cache_key<C>(c: &C, address: &str) -> Result<CacheKey>
+where
+	C: ConnectionTrait,
+{
+	Ok(CacheKey::Info(Config::get_info_version(c).await?, address.to_string()))
+}
+
+/// Looks up `q` (an entity id or an address), without any grouping applied
+pub async fn get_response(app: Arc<App>, q: &str) -> ServerResult<'static, Response> {
+	let q = q.trim();
+	if q.is_empty() {
+		return Err(ServerError::MissingInputParams);
+	}
+
+	let entity = Entity::get_by_id(app.db(), q).await?;
+
+	// anything but an entity id has to be an address valid on some configured network
+	let q = match entity.is_some() {
+		true => q.to_string(),
+		_ => format_address(&app, "q", q).await?,
+	};
+
+	// address lookups are cached briefly; indexing (or any change through the api) bumps
+	// the info version they're keyed by, which drops them
+	let cache_key = match entity.is_none() {
+		true => Some(get_cache_key(app.db(), &q).await?),
+		_ => None,
+	};
+	if let Some(response) =
+		cache_key.as_ref().and_then(|k| get_cached_response::<Response>(&app.cache, k))
+	{
+		return Ok(response);
+	}
+
 	let addresses = {
 		let mut ret = HashSet::new();
 
-		let q = payload.q.trim();
-		if q.is_empty() {
-			return Err(ServerError::MissingInputParams);
-		}
-
-		if let Some(entity) = Entity::get_by_id(app.db(), q).await? {
+		if let Some(entity) = entity {
 			for address in
 				Address::get_all_by_entity_ids(app.db(), vec![entity.entity_id].into(), Some(false))
 					.await?
@@ -98,7 +467,7 @@ pub async fn handler(
 	};
 
 	// find links
-	let links = Link::get_all_disinct_by_addresses(&app.warehouse, addresses.clone()).await?;
+	let links = Link::get_all_distinct_by_addresses(&app.warehouse, addresses.clone()).await?;
 
 	async fn get_assets(
 		app: Arc<App>,
@@ -128,7 +497,10 @@ pub async fn handler(
 						ResponseAsset {
 							network: network.id,
 							token: None,
-							balance: balance_data.balance.to_string(),
+							balance: JsonAmount::new(
+								balance_data.balance,
+								app.settings.numeric_amounts,
+							),
 						},
 					);
 
@@ -160,7 +532,14 @@ pub async fn handler(
 			}
 		}
 
-		Ok((assets_map.into_values().collect(), tokens.into_iter().collect()))
+		// sorted, so identical lookups serialize identically (and get the same etag)
+		let mut assets = assets_map.into_values().collect::<Vec<_>>();
+		assets.sort_unstable_by(|a, b| (&a.network, &a.token).cmp(&(&b.network, &b.token)));
+
+		let mut tokens = tokens.into_iter().collect::<Vec<_>>();
+		tokens.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+
+		Ok((assets, tokens))
 	}
 
 	async fn get_entities_data(
@@ -215,6 +594,13 @@ pub async fn handler(
 
 				for (entity_id, entity) in entities.iter_mut() {
 					entity.tags = map.get(entity_id).cloned().or(Some(vec![]));
+					entity.risk_score = Some(Entity::risk_score(
+						joined_tags
+							.iter()
+							.filter(|t| t.entity_id == *entity_id)
+							.map(|t| t.risk_level),
+						&app.settings.risk_score_weights,
+					));
 				}
 
 				tags = joined_tags.into_iter().map(|jt| jt.into()).collect();
@@ -235,28 +621,57 @@ pub async fn handler(
 				ret.push(chain.get_network());
 			}
 		}
+		ret.sort_unstable_by(|a, b| a.id.cmp(&b.id));
 
 		Ok(ret)
 	}
 
+	// addresses to look up entities for, collected before the queries are spawned
+	let entity_addresses = get_entity_addresses(&links, &addresses);
+
 	let (assets_data, networks, entities_data) = tokio::join!(
 		get_assets(app.clone(), addresses.clone()),
 		get_networks(app.clone(), addresses.clone()),
-		get_entities_data(app.clone(), {
-			let mut entity_addresses =
-				links.iter().map(|l| l.from_address.clone()).collect::<HashSet<String>>();
-
-			for address in addresses.clone() {
-				entity_addresses.insert(address);
-			}
-
-			entity_addresses.into_iter().collect::<Vec<_>>()
-		}),
+		get_entities_data(app.clone(), entity_addresses),
 	);
 
 	let (assets, tokens) = assets_data?;
 	let (address_map, entities_map, tags, risk_level) = entities_data?;
 
+	// links on networks that are no longer configured can't be resolved, so they're
+	// reported as warnings rather than silently left out
+	let (links, dropped_network_ids) = {
+		let n = app.networks.read().await;
+		split_by_network(links, |network_id| n.contains_key(&network_id))
+	};
+	let dropped_network_ids = if dropped_network_ids.is_empty() {
+		vec![]
+	} else {
+		Network::get_all_where(app.db(), NetworkColumn::NetworkId.is_in(dropped_network_ids))
+			.await?
+			.into_iter()
+			.map(|n| n.id)
+			.collect()
+	};
+
+	// transfers behind the sources (of every link collapsed into them), so they can be
+	// weighed by value
+	let transfers = Transfer::get_all_by_uuids(
+		&app.warehouse,
+		links
+			.iter()
+			.filter(|l| {
+				address_map.contains_key(&(l.network_id as PrimaryId, l.from_address.clone()))
+			})
+			.flat_map(|l| l.merged_transfer_uuids.iter().map(|u| u.0))
+			.collect(),
+		TRANSFERS_BY_UUIDS_CHUNK_SIZE,
+	)
+	.await?
+	.into_iter()
+	.map(|t| (t.uuid, t))
+	.collect::<HashMap<_, _>>();
+
 	// assemble sources
 	let mut sources = vec![];
 	let n = app.networks.read().await;
@@ -267,19 +682,31 @@ pub async fn handler(
 
 			if let Some(&entity_id) = address_map.get(&(network_id, link.from_address.clone())) {
 				if let Some(entity) = entities_map.get(&entity_id) {
+					// in chain order, so identical lookups serialize (and hash) the same
+					let mut transactions = link
+						.merged_transfer_uuids
+						.iter()
+						.filter_map(|u| transfers.get(&u.0))
+						.collect::<Vec<_>>();
+					transactions.sort_unstable_by_key(|t| (t.block_height, t.uuid));
+
 					sources.push(ResponseSource {
 						network: network.id,
 						from: link.from_address,
 						to: link.to_address,
 						entity: entity.id.clone(),
 						hops: link.transfer_uuids.len() as u64,
+						transactions: transactions
+							.into_iter()
+							.map(|t| ResponseTransaction::new(t, app.settings.numeric_amounts))
+							.collect(),
 					});
 				}
 			}
 		}
 	}
 
-	let mut risk_reasons = HashSet::new();
+	let mut risk_reasons = BTreeSet::new();
 	for (_, network_address) in address_map.keys() {
 		if addresses.contains(network_address) {
 			risk_reasons.insert(RiskReason::Entity);
@@ -290,15 +717,414 @@ pub async fn handler(
 		risk_reasons.insert(RiskReason::Source);
 	}
 
-	Ok(Response {
+	// in cursor order, so they can be paged through
+	sources.sort_unstable_by(|a, b| (&a.network, &a.from).cmp(&(&b.network, &b.from)));
+
+	let (entities, entities_truncated) = sort_and_cap_entities(
+		entities_map.into_values().map(|e| e.into()).collect(),
+		INFO_ENTITIES_MAX,
+	);
+
+	let mut tags = tags.into_iter().map(SanitizedTag::from).collect::<Vec<_>>();
+	tags.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+
+	let response = Response {
 		addresses,
 		risk: ResponseRisk { level: risk_level, reasons: risk_reasons },
 		assets,
 		tokens,
 		sources,
+		sources_next_cursor: None,
+		sources_by_entity: None,
 		networks: networks?.into_iter().map(|n| n.into()).collect(),
-		entities: entities_map.into_values().map(|e| e.into()).collect(),
-		tags: tags.into_iter().map(|t| t.into()).collect(),
+		entities,
+		entities_truncated,
+		tags,
+		warnings: ResponseWarnings { dropped_network_ids },
+	};
+
+	if let Some(cache_key) = cache_key {
+		app.cache.set(&cache_key, (utils::now().and_utc().timestamp() + INFO_CACHE_TTL, &response));
+	}
+
+	Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{chain::ModuleId, Cache, Db};
+
+	#[test]
+	fn test_get_entity_addresses() {
+		let addresses = vec!["to".to_string(), "from_0".to_string()];
+		let links = (0..100_000)
+			.map(|i| Link::new(1 + i % 2, 1, &format!("from_{}", i % 5_000), "to", vec![], 0))
+			.collect::<Vec<_>>();
+
+		let mut ret = get_entity_addresses(&links, &addresses);
+		ret.sort_unstable();
+
+		// same as collecting sources & addresses separately
+		let mut expected = links
+			.iter()
+			.map(|l| l.from_address.clone())
+			.chain(addresses.clone())
+			.collect::<HashSet<_>>()
+			.into_iter()
+			.collect::<Vec<_>>();
+		expected.sort_unstable();
+
+		assert_eq!(ret.len(), 5_001);
+		assert_eq!(ret, expected);
+	}
+
+	#[test]
+	fn test_split_by_network() {
+		let links = vec![
+			Link::new(1, 1, "a", "to", vec![], 0),
+			Link::new(2, 1, "b", "to", vec![], 0),
+			Link::new(2, 1, "c", "to", vec![], 0),
+		];
+
+		// network 2 is no longer configured
+		let (links, dropped_network_ids) = split_by_network(links, |network_id| network_id == 1);
+		assert_eq!(links, vec![Link::new(1, 1, "a", "to", vec![], 0)]);
+		assert_eq!(dropped_network_ids, vec![2]);
+	}
+
+	#[test]
+	fn test_sort_and_cap_entities() {
+		let entities = |ids: &[&str]| {
+			ids.iter()
+				.map(|id| SanitizedEntity {
+					id: id.to_string(),
+					name: None,
+					description: String::new(),
+					data: serde_json::Value::Null,
+					tags: None,
+					risk_score: None,
+				})
+				.collect::<Vec<_>>()
+		};
+		let ids =
+			|entities: Vec<SanitizedEntity>| entities.into_iter().map(|e| e.id).collect::<Vec<_>>();
+
+		// same order no matter how the entities came in
+		for _ in 0..10 {
+			let shuffled = ["ent_c", "ent_a", "ent_d", "ent_b"]
+				.into_iter()
+				.collect::<HashSet<_>>()
+				.into_iter()
+				.collect::<Vec<_>>();
+
+			let (sorted, truncated) = sort_and_cap_entities(entities(&shuffled), 10);
+			assert_eq!(ids(sorted), vec!["ent_a", "ent_b", "ent_c", "ent_d"]);
+			assert!(!truncated);
+		}
+
+		let (capped, truncated) = sort_and_cap_entities(entities(&["ent_c", "ent_a", "ent_b"]), 2);
+		assert_eq!(ids(capped), vec!["ent_a", "ent_b"]);
+		assert!(truncated);
+	}
+
+	#[test]
+	fn test_group_sources_by_entity() {
+		let transaction = |hash: &str, asset: Option<&str>, amount: u64| ResponseTransaction {
+			hash: hash.to_string(),
+			from: "from".to_string(),
+			to: "to".to_string(),
+			asset: asset.map(|a| a.to_string()),
+			amount: U256::from(amount).into(),
+		};
+		let source = |network: &str, entity: &str, from: &str, hops, transactions| ResponseSource {
+			network: network.to_string(),
+			entity: entity.to_string(),
+			from: from.to_string(),
+			to: "to".to_string(),
+			hops,
+			transactions,
+		};
+
+		// two addresses of the same entity collapse into one group
+		assert_eq!(
+			group_sources_by_entity(&[
+				source("net_a", "ent_b", "b", 1, vec![]),
+				source("net_a", "ent_a", "a1", 3, vec![transaction("tx_a", None, 10)]),
+				source(
+					"net_b",
+					"ent_a",
+					"a2",
+					2,
+					vec![transaction("tx_b", None, 5), transaction("tx_c", Some("token"), 1)]
+				),
+			]),
+			vec![
+				ResponseSourceGroup {
+					entity: "ent_a".to_string(),
+					networks: vec!["net_a".to_string(), "net_b".to_string()],
+					addresses: vec!["a1".to_string(), "a2".to_string()],
+					hops: 2,
+					transactions: vec![
+						transaction("tx_a", None, 10),
+						transaction("tx_b", None, 5),
+						transaction("tx_c", Some("token"), 1),
+					],
+					amounts: vec![
+						ResponseSourceGroupAmount { asset: None, amount: U256::from(15).into() },
+						ResponseSourceGroupAmount {
+							asset: Some("token".to_string()),
+							amount: U256::from(1).into(),
+						},
+					],
+				},
+				ResponseSourceGroup {
+					entity: "ent_b".to_string(),
+					networks: vec!["net_a".to_string()],
+					addresses: vec!["b".to_string()],
+					hops: 1,
+					transactions: vec![],
+					amounts: vec![],
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn test_with_sources_page() {
+		let response = |froms: &[&str]| Response {
+			addresses: vec!["to".to_string()],
+			risk: ResponseRisk { level: RiskLevel::High, reasons: BTreeSet::new() },
+			assets: vec![],
+			tokens: vec![],
+			sources: froms
+				.iter()
+				.map(|from| ResponseSource {
+					network: "net_a".to_string(),
+					entity: "ent_a".to_string(),
+					from: from.to_string(),
+					to: "to".to_string(),
+					hops: 1,
+					transactions: vec![],
+				})
+				.collect(),
+			sources_next_cursor: None,
+			sources_by_entity: None,
+			networks: vec![],
+			entities: vec![],
+			entities_truncated: false,
+			tags: vec![],
+			warnings: ResponseWarnings { dropped_network_ids: vec![] },
+		};
+		let froms = |response: &Response| {
+			response.sources.iter().map(|s| s.from.clone()).collect::<Vec<_>>()
+		};
+
+		let (mut seen, mut cursor) = (vec![], None);
+		loop {
+			let page = with_sources_page(response(&["a", "b", "c", "d", "e"]), cursor, 2);
+			seen.extend(froms(&page));
+
+			// clients only ever get to see the encoded cursor
+			match page.sources_next_cursor {
+				Some(next_cursor) => cursor = SourceCursor::decode(&next_cursor),
+				None => break,
+			}
+		}
+		assert_eq!(seen, vec!["a", "b", "c", "d", "e"]);
+
+		// a source showing up before the cursor doesn't shift the next page
+		let cursor = Some(SourceCursor("net_a".to_string(), "b".to_string()));
+		let page = with_sources_page(response(&["a", "aa", "b", "c"]), cursor, 2);
+		assert_eq!(froms(&page), vec!["c"]);
+		assert_eq!(page.sources_next_cursor, None);
+
+		assert_eq!(SourceCursor::decode("not a cursor"), None);
+	}
+
+	#[test]
+	fn test_response_transaction() {
+		let new_transfer = |asset_address: Option<&str>, amount: U256| {
+			Transfer::new(
+				ModuleId::BitcoinTransfer,
+				1,
+				1,
+				"tx",
+				"from",
+				"to",
+				asset_address.map(|a| a.to_string()),
+				amount,
+				amount,
+				0,
+			)
+		};
+
+		assert_eq!(
+			ResponseTransaction::new(&new_transfer(None, U256::from(1_000)), false),
+			ResponseTransaction {
+				hash: "tx".to_string(),
+				from: "from".to_string(),
+				to: "to".to_string(),
+				asset: None,
+				amount: U256::from(1_000).into(),
+			}
+		);
+
+		// large values keep full precision, even as numeric amounts
+		let transaction = ResponseTransaction::new(&new_transfer(Some("token"), U256::MAX), true);
+		assert_eq!(transaction.asset, Some("token".to_string()));
+		assert_eq!(
+			serde_json::to_value(&transaction).unwrap()["amount"],
+			"115792089237316195423570985008687907853269984665640564039457584007913129639935"
+		);
+	}
+
+	#[test]
+	fn test_to_dot() {
+		let transaction = |from: &str, to: &str| ResponseTransaction {
+			hash: format!("tx_{from}"),
+			from: from.to_string(),
+			to: to.to_string(),
+			asset: None,
+			amount: U256::from(1_000).into(),
+		};
+
+		let response = Response {
+			addresses: vec!["to".to_string()],
+			risk: ResponseRisk { level: RiskLevel::Low, reasons: BTreeSet::new() },
+			assets: vec![],
+			tokens: vec![],
+			sources: vec![ResponseSource {
+				network: "net_a".to_string(),
+				entity: "ent_a".to_string(),
+				from: "from".to_string(),
+				to: "to".to_string(),
+				hops: 2,
+				transactions: vec![transaction("from", "mid"), transaction("mid", "to")],
+			}],
+			sources_next_cursor: None,
+			sources_by_entity: None,
+			networks: vec![],
+			entities: vec![],
+			entities_truncated: false,
+			tags: vec![],
+			warnings: ResponseWarnings { dropped_network_ids: vec![] },
+		};
+
+		assert_eq!(
+			response.to_dot(),
+			[
+				"digraph info {",
+				"\t\"ent_a\" [shape=box];",
+				"\t\"from\" [shape=ellipse];",
+				"\t\"mid\" [shape=ellipse];",
+				"\t\"to\" [shape=doublecircle];",
+				"\t\"ent_a\" -> \"from\" [style=dashed];",
+				"\t\"from\" -> \"mid\" [label=\"1000\\ntx_from\"];",
+				"\t\"mid\" -> \"to\" [label=\"1000\\ntx_mid\"];",
+				"}",
+				"",
+			]
+			.join("\n")
+		);
+	}
+
+	#[tokio::test]
+	async fn test_cached_response() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+		let cache = Cache::new(10);
+		let cache_key = get_cache_key(db.get(), "to").await?;
+		let response = Response {
+			addresses: vec!["to".to_string()],
+			risk: ResponseRisk { level: RiskLevel::Low, reasons: BTreeSet::new() },
+			assets: vec![],
+			tokens: vec![],
+			sources: vec![],
+			sources_next_cursor: None,
+			sources_by_entity: None,
+			networks: vec![],
+			entities: vec![],
+			entities_truncated: false,
+			tags: vec![],
+			warnings: ResponseWarnings { dropped_network_ids: vec![] },
+		};
+
+		assert!(get_cached_response::<Response>(&cache, &cache_key).is_none());
+
+		// second identical request is served from the cache
+		cache.set(&cache_key, (utils::now().and_utc().timestamp() + INFO_CACHE_TTL, &response));
+		let cached = get_cached_response::<Response>(&cache, &cache_key).unwrap();
+		assert_eq!(cached.addresses, response.addresses);
+		assert_eq!(cache.stats().hits, 1);
+
+		// indexing a new transfer (from whichever process) busts it
+		Config::bump_info_version(db.get()).await?;
+		let cache_key = get_cache_key(db.get(), "to").await?;
+		assert!(get_cached_response::<Response>(&cache, &cache_key).is_none());
+
+		// so do expired entries
+		cache.set(&cache_key, (utils::now().and_utc().timestamp() - 1, &response));
+		assert!(get_cached_response::<Response>(&cache, &cache_key).is_none());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_with_etag() -> eyre::Result<()> {
+		let mut response = Response {
+			addresses: vec!["to".to_string()],
+			risk: ResponseRisk { level: RiskLevel::Low, reasons: BTreeSet::new() },
+			assets: vec![],
+			tokens: vec![],
+			sources: vec![],
+			sources_next_cursor: None,
+			sources_by_entity: None,
+			networks: vec![],
+			entities: vec![],
+			entities_truncated: false,
+			tags: vec![],
+			warnings: ResponseWarnings { dropped_network_ids: vec![] },
+		};
+
+		let res = with_etag(&HeaderMap::new(), &response)?;
+		assert_eq!(res.status(), StatusCode::OK);
+		let etag = res.headers()[header::ETAG].clone();
+
+		// second request with the returned etag gets an empty 304
+		let mut headers = HeaderMap::new();
+		headers.insert(header::IF_NONE_MATCH, etag.clone());
+		let res = with_etag(&headers, &response)?;
+		assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+		assert_eq!(res.headers()[header::ETAG], etag);
+		assert!(axum::body::to_bytes(res.into_body(), usize::MAX).await?.is_empty());
+
+		// a new transfer changes the response, and so its etag
+		response.sources.push(ResponseSource {
+			network: "net_a".to_string(),
+			entity: "ent_a".to_string(),
+			from: "from".to_string(),
+			to: "to".to_string(),
+			hops: 1,
+			transactions: vec![ResponseTransaction::new(
+				&Transfer::new(
+					ModuleId::BitcoinTransfer,
+					1,
+					1,
+					"tx",
+					"from",
+					"to",
+					None,
+					U256::from(1),
+					U256::from(1),
+					0,
+				),
+				false,
+			)],
+		});
+		let res = with_etag(&headers, &response)?;
+		assert_eq!(res.status(), StatusCode::OK);
+		assert_ne!(res.headers()[header::ETAG], etag);
+
+		Ok(())
 	}
-	.into())
 }
@@ -1,10 +1,28 @@
 use axum::{routing::get, Router};
+use serde::Deserialize;
 use std::sync::Arc;
 
-use barreleye_common::App;
+use barreleye_common::{cache::CacheKey, utils, App, Cache};
 
+mod dot;
 mod get;
+mod totals;
+
+pub use dot::handler as dot_handler;
+pub use get::{Payload as InfoPayload, Response as InfoResponse};
+pub use totals::Response as InfoTotalsResponse;
 
 pub fn get_routes() -> Router<Arc<App>> {
-	Router::new().route("/", get(get::handler))
+	Router::new().route("/", get(get::handler)).route("/totals", get(totals::handler))
+}
+
+/// Responses are cached along with their expiry (unix timestamp)
+fn get_cached_response<T>(cache: &Cache, cache_key: &CacheKey) -> Option<T>
+where
+	T: for<'a> Deserialize<'a>,
+{
+	let now = utils::now().and_utc().timestamp();
+	cache
+		.get::<(i64, T)>(cache_key)
+		.and_then(|(expires_at, response)| (expires_at > now).then_some(response))
 }
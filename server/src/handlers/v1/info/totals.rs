@@ -0,0 +1,135 @@
+use axum::extract::State;
+use eyre::Result;
+use schemars::JsonSchema;
+use sea_orm::ConnectionTrait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::get_cached_response;
+use crate::{extract::Json, ServerResult};
+use barreleye_common::{
+	cache::CacheKey,
+	models::{Address, Config, ConfigKey, Entity, Network, SoftDeleteModel, Transfer},
+	utils, App, BlockHeight, INFO_CACHE_TTL,
+};
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseTip {
+	network: String,
+	block_height: BlockHeight,
+	confirmations: u64,
+	/// Balances as of this block or below won't change anymore
+	confirmed_block_height: BlockHeight,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "InfoTotalsResponse")]
+pub struct Response {
+	networks: u64,
+	transfers: u64,
+	addresses: u64,
+	entities: u64,
+	tips: Vec<ResponseTip>,
+}
+
+pub async fn handler(State(app): State<Arc<App>>) -> ServerResult<'static, Json<Response>> {
+	// counting is expensive on large tables, so totals are only refreshed once in a while
+	let cache_key = CacheKey::InfoTotals;
+	if let Some(response) = get_cached_response::<Response>(&app.cache, &cache_key) {
+		return Ok(response.into());
+	}
+
+	let transfers = Transfer::count(&app.warehouse).await?;
+	let response = get_totals(app.db(), transfers).await?;
+
+	app.cache.set(&cache_key, (utils::now().and_utc().timestamp() + INFO_CACHE_TTL, &response));
+
+	Ok(response.into())
+}
+
+async fn get_totals<C>(c: &C, transfers: u64) -> Result<Response>
+where
+	C: ConnectionTrait,
+{
+	let mut tips = vec![];
+	for network in Network::get_all_existing(c, Some(false)).await?.into_iter() {
+		let block_height =
+			Config::get::<_, BlockHeight>(c, ConfigKey::BlockHeight(network.network_id))
+				.await?
+				.map(|v| v.value)
+				.unwrap_or(0);
+
+		tips.push(ResponseTip {
+			confirmations: network.confirmations.max(0) as u64,
+			confirmed_block_height: network.get_confirmed_block_height(block_height),
+			network: network.id,
+			block_height,
+		});
+	}
+
+	Ok(Response {
+		networks: tips.len() as u64,
+		transfers,
+		addresses: Address::count_existing(c, Some(false)).await?,
+		entities: Entity::count_existing(c, Some(false)).await?,
+		tips,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		models::{BasicModel, EntityColumn, NetworkColumn},
+		testing, Db,
+	};
+	use sea_orm::ColumnTrait;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn test_get_totals() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		let network_id = Network::create(db.get(), testing::new_network_model("net_a", 6)).await?;
+		let deleted_network_id =
+			Network::create(db.get(), testing::new_network_model("net_b", 6)).await?;
+		Config::set::<_, BlockHeight>(db.get(), ConfigKey::BlockHeight(network_id), 100).await?;
+
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+		let deleted_entity_id =
+			Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+		for address in ["a", "b"] {
+			Address::create(
+				db.get(),
+				Address::new_model(None, entity_id, network_id, "net_a", address, "", None),
+			)
+			.await?;
+		}
+
+		// deleted rows are not counted
+		Network::soft_delete_all_where(db.get(), NetworkColumn::NetworkId.eq(deleted_network_id))
+			.await?;
+		Entity::soft_delete_all_where(db.get(), EntityColumn::EntityId.eq(deleted_entity_id))
+			.await?;
+
+		assert_eq!(
+			serde_json::to_value(get_totals(db.get(), 5).await?)?,
+			json!({
+				"networks": 1,
+				"transfers": 5,
+				"addresses": 2,
+				"entities": 1,
+				"tips": [{
+					"network": "net_a",
+					"blockHeight": 100,
+					"confirmations": 6,
+					"confirmedBlockHeight": 94,
+				}],
+			})
+		);
+
+		Ok(())
+	}
+}
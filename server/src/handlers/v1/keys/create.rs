@@ -1,8 +1,8 @@
-use axum::{extract::State, Json};
+use axum::extract::State;
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
 	models::{is_valid_id, ApiKey, BasicModel},
 	App, IdPrefix,
@@ -26,8 +26,15 @@ pub async fn handler(
 		}
 	}
 
-	// create new
-	let api_key_id = ApiKey::create(app.db(), ApiKey::new_model(payload.id)).await?;
+	// create new, generating an id unless one was given
+	let api_key_id = ApiKey::create_with_id(
+		app.db(),
+		payload.id,
+		IdPrefix::ApiKey,
+		app.settings.id_length,
+		|id| ApiKey::new_model(Some(id)),
+	)
+	.await?;
 
 	// return newly created
 	Ok(ApiKey::get(app.db(), api_key_id).await?.unwrap().format().into())
@@ -1,9 +1,9 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{extract::State, http::StatusCode};
 use sea_orm::ColumnTrait;
 use serde::Deserialize;
 use std::{collections::HashSet, sync::Arc};
 
-use crate::ServerResult;
+use crate::{extract::Json, utils, ServerResult};
 use barreleye_common::{
 	models::{ApiKey, ApiKeyColumn, BasicModel},
 	App,
@@ -24,6 +24,8 @@ pub async fn handler(
 		return Ok(StatusCode::NO_CONTENT);
 	}
 
+	utils::check_bulk_size("keys", payload.keys.len(), app.settings.max_bulk_size)?;
+
 	// delete all keys
 	ApiKey::delete_all_where(
 		app.db(),
@@ -1,11 +1,10 @@
-use axum::{
-	extract::{Path, State},
-	Json,
-};
+use axum::extract::{Path, State};
+use eyre::Result;
+use sea_orm::ConnectionTrait;
 use serde::Serialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
 	models::{ApiKey, BasicModel},
 	App,
@@ -21,8 +20,43 @@ pub async fn handler(
 	State(app): State<Arc<App>>,
 	Path(api_key_id): Path<String>,
 ) -> ServerResult<'static, Json<Response>> {
-	ApiKey::get_by_id(app.db(), &api_key_id)
+	get_key(app.db(), &api_key_id)
 		.await?
-		.map(|key| Response { key: key.format() }.into())
+		.map(|key| Response { key }.into())
 		.ok_or(ServerError::NotFound)
 }
+
+/// Key without its secret, even when it hasn't been used yet
+async fn get_key<C>(c: &C, id: &str) -> Result<Option<ApiKey>>
+where
+	C: ConnectionTrait,
+{
+	Ok(ApiKey::get_by_id(c, id).await?.map(|k| ApiKey { key: None, ..k }))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::Db;
+
+	#[tokio::test]
+	async fn test_get_key() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		// a fresh key still has its secret around
+		let api_key_id = ApiKey::create(db.get(), ApiKey::new_model(None)).await?;
+		let api_key = ApiKey::get(db.get(), api_key_id).await?.unwrap();
+		let secret_key = api_key.secret_key.clone().unwrap();
+
+		let key = get_key(db.get(), &api_key.id).await?.unwrap();
+		assert_eq!(key.id, api_key.id);
+
+		let json = serde_json::to_value(Response { key })?;
+		assert!(json["key"]["key"].is_null());
+		assert!(!json.to_string().contains(&secret_key));
+
+		assert!(get_key(db.get(), "key_missing").await?.is_none());
+
+		Ok(())
+	}
+}
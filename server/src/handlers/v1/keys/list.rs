@@ -1,9 +1,13 @@
-use axum::{extract::State, Json};
-use axum_extra::extract::Query;
+use axum::extract::State;
+use eyre::Result;
+use sea_orm::ConnectionTrait;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::ServerResult;
+use crate::{
+	extract::{Json, Query},
+	ServerResult,
+};
 use barreleye_common::{
 	models::{ApiKey, BasicModel},
 	App,
@@ -26,11 +30,45 @@ pub async fn handler(
 	State(app): State<Arc<App>>,
 	Query(payload): Query<Payload>,
 ) -> ServerResult<'static, Json<Response>> {
-	let keys = ApiKey::get_all_paginated(app.db(), payload.offset, payload.limit)
-		.await?
-		.iter()
-		.map(|k| k.format())
-		.collect::<Vec<ApiKey>>();
+	let keys = get_keys(app.db(), payload.offset, payload.limit).await?;
 
 	Ok(Response { keys }.into())
 }
+
+/// Keys without their secrets, not even for the ones that haven't been used yet
+async fn get_keys<C>(c: &C, offset: Option<u64>, limit: Option<u64>) -> Result<Vec<ApiKey>>
+where
+	C: ConnectionTrait,
+{
+	Ok(ApiKey::get_all_paginated(c, offset, limit)
+		.await?
+		.into_iter()
+		.map(|k| ApiKey { key: None, ..k })
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::Db;
+
+	#[tokio::test]
+	async fn test_get_keys() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		// a fresh key still has its secret around
+		let api_key_id = ApiKey::create(db.get(), ApiKey::new_model(None)).await?;
+		let api_key = ApiKey::get(db.get(), api_key_id).await?.unwrap();
+		let secret_key = api_key.secret_key.clone().unwrap();
+
+		let keys = get_keys(db.get(), None, None).await?;
+		assert_eq!(keys.iter().map(|k| k.id.clone()).collect::<Vec<_>>(), vec![api_key.id]);
+
+		let json = serde_json::to_value(Response { keys })?;
+		assert!(json["keys"][0]["key"].is_null());
+		assert!(json["keys"][0]["isActive"].as_bool().unwrap());
+		assert!(!json.to_string().contains(&secret_key));
+
+		Ok(())
+	}
+}
@@ -10,13 +10,17 @@ mod create;
 mod delete;
 mod get;
 mod list;
+mod revoke;
+mod rotate;
 mod update;
 
 pub fn get_routes() -> Router<Arc<App>> {
 	Router::new()
 		.route("/", post(create::handler))
 		.route("/", get(list::handler))
+		.route("/revoke", post(revoke::handler))
 		.route("/{id}", get(get::handler))
 		.route("/{id}", put(update::handler))
+		.route("/{id}/rotate", post(rotate::handler))
 		.route("/", delete(delete::handler))
 }
@@ -0,0 +1,99 @@
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response as AxumResponse},
+};
+use eyre::Result;
+use sea_orm::{ActiveValue::Set, ColumnTrait, ConnectionTrait};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{extract::Json, utils, ServerResult};
+use barreleye_common::{
+	models::{ApiKey, ApiKeyActiveModel, ApiKeyColumn, BasicModel},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	keys: HashSet<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	revoked: u64,
+	skipped: Vec<String>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, AxumResponse> {
+	// exit if no input
+	if payload.keys.is_empty() {
+		return Ok(StatusCode::NO_CONTENT.into_response());
+	}
+
+	utils::check_bulk_size("keys", payload.keys.len(), app.settings.max_bulk_size)?;
+
+	Ok(Json(revoke(app.db(), payload.keys).await?).into_response())
+}
+
+/// Deactivates keys by id, reporting the ids that don't exist as skipped. Revoked keys stay
+/// around (unlike deleted ones), so they can still be looked at or reactivated
+async fn revoke<C>(c: &C, ids: HashSet<String>) -> Result<Response>
+where
+	C: ConnectionTrait,
+{
+	let found = ApiKey::get_all_where(c, ApiKeyColumn::Id.is_in(ids.clone()))
+		.await?
+		.into_iter()
+		.map(|k| k.id)
+		.collect::<HashSet<_>>();
+
+	let mut skipped = ids.into_iter().filter(|id| !found.contains(id)).collect::<Vec<_>>();
+	skipped.sort();
+
+	// proceed only when there's something to revoke
+	if found.is_empty() {
+		return Ok(Response { revoked: 0, skipped });
+	}
+
+	let revoked = ApiKey::update_all_where(
+		c,
+		ApiKeyColumn::Id.is_in(found),
+		ApiKeyActiveModel { is_active: Set(false), ..Default::default() },
+	)
+	.await?;
+
+	Ok(Response { revoked, skipped })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::Db;
+
+	#[tokio::test]
+	async fn test_revoke() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		for id in ["key_a", "key_b", "key_c"] {
+			ApiKey::create(db.get(), ApiKey::new_model(Some(id.to_string()))).await?;
+		}
+
+		let ids = HashSet::from(["key_a".to_string(), "key_b".to_string(), "key_x".to_string()]);
+		assert_eq!(
+			revoke(db.get(), ids).await?,
+			Response { revoked: 2, skipped: vec!["key_x".to_string()] }
+		);
+
+		for (id, is_active) in [("key_a", false), ("key_b", false), ("key_c", true)] {
+			assert_eq!(ApiKey::get_by_id(db.get(), id).await?.unwrap().is_active, is_active);
+		}
+
+		Ok(())
+	}
+}
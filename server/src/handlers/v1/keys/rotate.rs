@@ -0,0 +1,21 @@
+use axum::extract::{Path, State};
+use std::sync::Arc;
+
+use crate::{errors::ServerError, extract::Json, ServerResult};
+use barreleye_common::{
+	models::{ApiKey, BasicModel},
+	App,
+};
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(api_key_id): Path<String>,
+) -> ServerResult<'static, Json<ApiKey>> {
+	let api_key = ApiKey::get_by_id(app.db(), &api_key_id).await?.ok_or(ServerError::NotFound)?;
+
+	// swap secret
+	ApiKey::rotate_key(app.db(), api_key.api_key_id).await?;
+
+	// return with the new secret, same as on creation
+	Ok(ApiKey::get(app.db(), api_key.api_key_id).await?.unwrap().format().into())
+}
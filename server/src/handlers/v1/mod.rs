@@ -1,4 +1,4 @@
-use axum::Router;
+use axum::{routing::get, Router};
 use std::sync::Arc;
 
 use barreleye_common::App;
@@ -9,9 +9,17 @@ mod heartbeat;
 mod info;
 mod keys;
 mod networks;
+mod openapi;
+mod path;
+mod progress;
+mod rules;
 mod stats;
+mod stream;
 mod tags;
 mod tokens;
+mod transactions;
+mod transfers;
+mod webhooks;
 
 pub fn get_routes() -> Router<Arc<App>> {
 	Router::new()
@@ -23,5 +31,16 @@ pub fn get_routes() -> Router<Arc<App>> {
 		.nest("/addresses", addresses::get_routes())
 		.nest("/tokens", tokens::get_routes())
 		.nest("/tags", tags::get_routes())
+		.nest("/transfers", transfers::get_routes())
+		.nest("/transactions", transactions::get_routes())
 		.nest("/info", info::get_routes())
+		.route("/info.dot", get(info::dot_handler))
+		.route("/openapi.json", get(openapi::handler))
+		.nest("/path", path::get_routes())
+		.nest("/webhooks", webhooks::get_routes())
+		.nest("/rules", rules::get_routes())
+}
+
+pub fn get_streaming_routes() -> Router<Arc<App>> {
+	Router::new().nest("/progress", progress::get_routes()).nest("/stream", stream::get_routes())
 }
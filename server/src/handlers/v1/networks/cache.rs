@@ -0,0 +1,24 @@
+use axum::{
+	extract::{Path, State},
+	http::StatusCode,
+};
+use std::sync::Arc;
+
+use crate::{errors::ServerError, ServerResult};
+use barreleye_common::{
+	cache::CacheKey,
+	models::{Network, SoftDeleteModel},
+	App,
+};
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(network_id): Path<String>,
+) -> ServerResult<'static, StatusCode> {
+	let network =
+		Network::get_existing_by_id(app.db(), &network_id).await?.ok_or(ServerError::NotFound)?;
+
+	app.cache.delete_by_prefix(&CacheKey::network_prefix(network.network_id));
+
+	Ok(StatusCode::NO_CONTENT)
+}
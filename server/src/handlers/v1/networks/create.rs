@@ -1,12 +1,13 @@
-use axum::{extract::State, Json};
+use axum::extract::State;
+use sea_orm::ActiveValue::Set;
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, utils, ServerResult};
 use barreleye_common::{
-	chain::{Bitcoin, ChainTrait, Evm},
-	models::{is_valid_id, BasicModel, Config, ConfigKey, Network},
-	App, Architecture, IdPrefix,
+	chain::{Bitcoin, ChainError, ChainTrait, Evm},
+	models::{is_valid_id, BasicModel, Config, ConfigKey, Network, NetworkActiveModel},
+	App, Architecture, Attribution, IdPrefix,
 };
 
 #[derive(Deserialize)]
@@ -19,14 +20,21 @@ pub struct Payload {
 	rpc_endpoint: String,
 	chain_id: Option<u64>,
 	rps: Option<u32>,
+	confirmations: Option<u32>,
+	skip_change_outputs: Option<bool>,
+	attribution: Option<Attribution>,
+	script_addresses: Option<bool>,
 }
 
 pub async fn handler(
 	State(app): State<Arc<App>>,
 	Json(payload): Json<Payload>,
 ) -> ServerResult<'static, Json<Network>> {
-	let chain_id = payload.chain_id.unwrap_or_default();
-	let rps = payload.rps.unwrap_or(100);
+	let chain_id: i64 = utils::convert("chainId", payload.chain_id.unwrap_or_default())?;
+	let block_time: i64 = utils::convert("blockTime", payload.block_time)?;
+	let rps: i32 = utils::convert("rps", payload.rps.unwrap_or(100))?;
+	let confirmations: i32 =
+		utils::convert("confirmations", payload.confirmations.unwrap_or_default())?;
 
 	// check that id is valid
 	if let Some(id) = payload.id.clone() {
@@ -50,14 +58,9 @@ pub async fn handler(
 	}
 
 	// check for duplicate chain id
-	if Network::get_by_architecture_and_chain_id(
-		app.db(),
-		payload.architecture,
-		chain_id as i64,
-		None,
-	)
-	.await?
-	.is_some()
+	if Network::get_by_architecture_and_chain_id(app.db(), payload.architecture, chain_id, None)
+		.await?
+		.is_some()
 	{
 		return Err(ServerError::Duplicate {
 			field: "chainId".into(),
@@ -65,38 +68,131 @@ pub async fn handler(
 		});
 	}
 
-	// check rpc connection
-	let n = Network { rpc_endpoint: payload.rpc_endpoint.clone(), ..Default::default() };
+	// check rpc connection (the name goes along, since credentials can be looked up by it)
+	let n = Network {
+		name: payload.name.clone(),
+		chain_id,
+		rpc_endpoint: payload.rpc_endpoint.clone(),
+		..Default::default()
+	};
 	let mut boxed_chain: Box<dyn ChainTrait> = match payload.architecture {
-		Architecture::Bitcoin => Box::new(Bitcoin::new(n)),
+		Architecture::Bitcoin => {
+			let bitcoin = Bitcoin::new(n).map_err(|_| ServerError::InvalidParam {
+				field: "chainId".into(),
+				value: chain_id.to_string().into(),
+			})?;
+			Box::new(bitcoin.with_rpc_timeout(app.settings.rpc_timeout))
+		}
 		Architecture::Evm => Box::new(Evm::new(n)),
 	};
-	if !boxed_chain.connect().await? {
+	let connected = match boxed_chain.connect().await {
+		Err(e) if matches!(e.downcast_ref(), Some(ChainError::Mismatch { .. })) => {
+			return Err(ServerError::InvalidParam {
+				field: "chainId".into(),
+				value: chain_id.to_string().into(),
+			});
+		}
+		connected => connected?,
+	};
+	if !connected {
 		return Err(ServerError::InvalidService { name: boxed_chain.get_network().name.into() });
 	}
 
-	// create new
-	let network_id = Network::create(
+	// create new, generating an id unless one was given
+	let network_id = Network::create_with_id(
 		app.db(),
-		Network::new_model(
-			payload.id,
-			&payload.name,
-			payload.architecture,
-			chain_id as i64,
-			payload.block_time as i64,
-			payload.rpc_endpoint,
-			rps as i32,
-		),
+		payload.id,
+		IdPrefix::Network,
+		app.settings.id_length,
+		|id| NetworkActiveModel {
+			attribution: Set(payload.attribution.unwrap_or_default()),
+			script_addresses: Set(payload.script_addresses.unwrap_or_default()),
+			..Network::new_model(
+				Some(id),
+				&payload.name,
+				payload.architecture,
+				chain_id,
+				block_time,
+				payload.rpc_endpoint.clone(),
+				rps,
+				confirmations,
+				payload.skip_change_outputs.unwrap_or_default(),
+			)
+		},
 	)
 	.await?;
 
+	// register with app's networks (and take it back if the rpc went down in the meantime,
+	// rather than report a network that isn't live)
+	let network = Network::get(app.db(), network_id).await?.unwrap();
+	if !app.add_network(network.clone()).await? {
+		Network::delete_by_id(app.db(), &network.id).await?;
+		return Err(ServerError::InvalidService { name: network.name.into() });
+	}
+
 	// update config
 	Config::set::<_, u8>(app.db(), ConfigKey::NetworksUpdated, 1).await?;
 
-	// update app's networks
-	let mut networks = app.networks.write().await;
-	*networks = app.get_networks().await?;
-
 	// return newly created
-	Ok(Network::get(app.db(), network_id).await?.unwrap().into())
+	Ok(network.into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		testing::{self, MockRpc},
+		Db,
+	};
+	use serde_json::json;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	fn payload(name: &str, rpc_endpoint: &str) -> Payload {
+		Payload {
+			id: None,
+			name: name.to_string(),
+			architecture: Architecture::Bitcoin,
+			block_time: 600,
+			rpc_endpoint: rpc_endpoint.to_string(),
+			chain_id: Some(2),
+			rps: None,
+			confirmations: None,
+			skip_change_outputs: None,
+			attribution: None,
+			script_addresses: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_create() -> eyre::Result<()> {
+		let app = Arc::new(testing::new_app(Arc::new(Db::new_in_memory().await?)).await?);
+
+		// the node answers the connection check, then goes down
+		let calls = Arc::new(AtomicUsize::new(0));
+		let rpc = MockRpc::start({
+			let calls = calls.clone();
+			move |_method, _params| match calls.fetch_add(1, Ordering::SeqCst) {
+				0 => Ok(testing::bitcoind_blockchain_info("regtest", 0)),
+				_ => Err(json!({ "code": -28, "message": "shutting down" })),
+			}
+		})
+		.await?;
+
+		// so the network isn't kept around as if it were live
+		assert!(matches!(
+			handler(State(app.clone()), Json(payload("down", &rpc.url))).await,
+			Err(ServerError::InvalidService { .. })
+		));
+		assert!(Network::get_by_name(app.db(), "down", None).await?.is_none());
+		assert!(app.networks.read().await.is_empty());
+
+		// ...while one that's up is registered right away
+		let rpc =
+			MockRpc::start(|_, _| Ok(testing::bitcoind_blockchain_info("regtest", 0))).await?;
+		let Json(network) = handler(State(app.clone()), Json(payload("up", &rpc.url))).await?;
+		assert!(app.networks.read().await.contains_key(&network.network_id));
+		assert!(Config::get::<_, u8>(app.db(), ConfigKey::NetworksUpdated).await?.is_some());
+
+		Ok(())
+	}
 }
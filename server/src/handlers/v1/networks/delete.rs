@@ -1,13 +1,13 @@
-use axum::{extract::State, http::StatusCode, Json};
-use sea_orm::ColumnTrait;
+use axum::{extract::State, http::StatusCode};
+use sea_orm::{ColumnTrait, ConnectionTrait};
 use serde::Deserialize;
 use std::{collections::HashSet, sync::Arc};
 
-use crate::ServerResult;
+use crate::{extract::Json, utils, ServerResult};
 use barreleye_common::{
 	models::{
-		set, Address, AddressActiveModel, BasicModel, Config, ConfigKey, Network,
-		NetworkActiveModel, NetworkColumn, PrimaryId,
+		Address, AddressColumn, Config, ConfigKey, Network, NetworkColumn, PrimaryId,
+		SoftDeleteModel,
 	},
 	App,
 };
@@ -27,32 +27,13 @@ pub async fn handler(
 		return Ok(StatusCode::NO_CONTENT);
 	}
 
-	// get all networks
-	let all_networks =
-		Network::get_all_where(app.db(), NetworkColumn::Id.is_in(payload.networks)).await?;
+	utils::check_bulk_size("networks", payload.networks.len(), app.settings.max_bulk_size)?;
 
 	// proceed only when there's something to delete
-	if all_networks.is_empty() {
+	if !soft_delete(app.db(), payload.networks).await? {
 		return Ok(StatusCode::NO_CONTENT);
 	}
 
-	// soft-delete all associated addresses
-	let all_network_ids = all_networks.iter().map(|n| n.network_id).collect::<Vec<PrimaryId>>();
-	Address::update_all_where(
-		app.db(),
-		NetworkColumn::NetworkId.is_in(all_network_ids.clone()),
-		AddressActiveModel { is_deleted: set(true), ..Default::default() },
-	)
-	.await?;
-
-	// soft-delete networks
-	Network::update_all_where(
-		app.db(),
-		NetworkColumn::NetworkId.is_in(all_network_ids),
-		NetworkActiveModel { is_deleted: set(true), ..Default::default() },
-	)
-	.await?;
-
 	// update config
 	Config::set::<_, u8>(app.db(), ConfigKey::NetworksUpdated, 1).await?;
 
@@ -62,3 +43,68 @@ pub async fn handler(
 
 	Ok(StatusCode::NO_CONTENT)
 }
+
+/// Soft-deletes networks by id along with all of their addresses. Returns whether
+/// any network was deleted
+async fn soft_delete<C>(c: &C, ids: HashSet<String>) -> ServerResult<'static, bool>
+where
+	C: ConnectionTrait,
+{
+	// get all networks
+	let all_networks = Network::get_all_existing_where(c, NetworkColumn::Id.is_in(ids)).await?;
+	if all_networks.is_empty() {
+		return Ok(false);
+	}
+
+	// soft-delete all associated addresses
+	let all_network_ids = all_networks.iter().map(|n| n.network_id).collect::<Vec<PrimaryId>>();
+	Address::soft_delete_all_where(c, AddressColumn::NetworkId.is_in(all_network_ids.clone()))
+		.await?;
+
+	// soft-delete networks
+	Network::soft_delete_all_where(c, NetworkColumn::NetworkId.is_in(all_network_ids)).await?;
+
+	Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		models::{BasicModel, Entity},
+		testing, Db,
+	};
+
+	#[tokio::test]
+	async fn test_soft_delete() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+		let entity_id = Entity::create(db.get(), Entity::new_model(None, None, "", None)).await?;
+
+		let mut networks = vec![];
+		for id in ["net_a", "net_b"] {
+			let network_id = testing::create_network(db.get(), id, 0).await?.network_id;
+			Address::create(
+				db.get(),
+				Address::new_model(None, entity_id, network_id, id, "address", "", None),
+			)
+			.await?;
+
+			networks.push(network_id);
+		}
+
+		assert!(soft_delete(db.get(), HashSet::from(["net_a".to_string()])).await?);
+
+		// only addresses on the deleted network go with it
+		let addresses = Address::get_all_existing_where(
+			db.get(),
+			AddressColumn::NetworkId.is_in(networks.clone()),
+		)
+		.await?;
+		assert_eq!(addresses.into_iter().map(|a| a.network_id).collect::<Vec<_>>(), [networks[1]]);
+
+		// nothing left to delete
+		assert!(!soft_delete(db.get(), HashSet::from(["net_a".to_string()])).await?);
+
+		Ok(())
+	}
+}
@@ -1,10 +1,12 @@
-use axum::{extract::State, Json};
-use axum_extra::extract::Query;
+use axum::extract::State;
 use sea_orm::ColumnTrait;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::ServerResult;
+use crate::{
+	extract::{Json, Query},
+	ServerResult,
+};
 use barreleye_common::{
 	models::{BasicModel, Network, NetworkColumn},
 	utils, App,
@@ -6,17 +6,29 @@ use std::sync::Arc;
 
 use barreleye_common::App;
 
+mod cache;
 mod create;
 mod delete;
 mod get;
 mod list;
+mod pause;
+mod reindex;
+mod resume;
 mod update;
+mod validate;
+mod volume;
 
 pub fn get_routes() -> Router<Arc<App>> {
 	Router::new()
 		.route("/", post(create::handler))
 		.route("/", get(list::handler))
+		.route("/validate", post(validate::handler))
 		.route("/{id}", get(get::handler))
 		.route("/{id}", put(update::handler))
+		.route("/{id}/cache", delete(cache::handler))
+		.route("/{id}/pause", post(pause::handler))
+		.route("/{id}/reindex", post(reindex::handler))
+		.route("/{id}/resume", post(resume::handler))
+		.route("/{id}/volume", get(volume::handler))
 		.route("/", delete(delete::handler))
 }
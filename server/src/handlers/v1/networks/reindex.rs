@@ -0,0 +1,303 @@
+use axum::{
+	extract::{Path, State},
+	http::StatusCode,
+};
+use sea_orm::ConnectionTrait;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{errors::ServerError, extract::Json, ServerResult};
+use barreleye_common::{
+	chain::ModuleId,
+	models::{Amount, Config, ConfigKey, Link, Network, SoftDeleteModel, Transfer},
+	App, BlockHeight, Warehouse,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	from_block: BlockHeight,
+	to_block: BlockHeight,
+}
+
+/// Reprocesses an already processed block range (both ends inclusive), eg: after a decoding fix
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(network_id): Path<String>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, StatusCode> {
+	let network =
+		Network::get_existing_by_id(app.db(), &network_id).await?.ok_or(ServerError::NotFound)?;
+
+	reindex(app.db(), &app.warehouse, &network, (payload.from_block, payload.to_block)).await?;
+
+	// update config so the indexer restarts its loops
+	Config::set::<_, u8>(app.db(), ConfigKey::NetworksUpdated, 1).await?;
+
+	Ok(StatusCode::ACCEPTED)
+}
+
+/// Drops the range's transfers and amounts and queues the range up again for the network's
+/// transfer modules, the same way a newly added module catches up. The tail (and the other
+/// modules) keep going undisturbed. Reprocessed transfers don't have to match the dropped ones,
+/// so links going through the range are dropped too and rebuilt from its start
+async fn reindex<C>(
+	c: &C,
+	warehouse: &Warehouse,
+	network: &Network,
+	(from_block, to_block): (BlockHeight, BlockHeight),
+) -> ServerResult<'static, ()>
+where
+	C: ConnectionTrait,
+{
+	let nid = network.network_id;
+
+	if from_block > to_block {
+		return Err(ServerError::InvalidParam {
+			field: "fromBlock".into(),
+			value: from_block.to_string().into(),
+		});
+	}
+
+	// blocks above the tail will be processed anyway
+	let tail = Config::get::<_, BlockHeight>(c, ConfigKey::IndexerProcessTail(nid))
+		.await?
+		.map(|v| v.value)
+		.unwrap_or(0);
+	if to_block > tail {
+		return Err(ServerError::InvalidParam {
+			field: "toBlock".into(),
+			value: to_block.to_string().into(),
+		});
+	}
+
+	// ranges are processed starting right after their lower bound
+	let mut block_ranges = vec![];
+	for module_id in ModuleId::get_transfer_module_ids(network.architecture).into_iter() {
+		let mid = module_id as u16;
+		let mut block_range = (from_block.saturating_sub(1), to_block);
+
+		if Config::get::<_, u8>(c, ConfigKey::IndexerProcessModuleDone(nid, mid)).await?.is_none() {
+			match Config::get::<_, (BlockHeight, BlockHeight)>(
+				c,
+				ConfigKey::IndexerProcessModule(nid, mid),
+			)
+			.await?
+			{
+				// still reindexing, so extend the pending range to cover both
+				Some(hit) => {
+					block_range = (block_range.0.min(hit.value.0), block_range.1.max(hit.value.1))
+				}
+				None => {
+					return Err(ServerError::TooEarly {
+						reason: format!("network hasn't been processed yet: {}", network.id).into(),
+					})
+				}
+			}
+		}
+
+		block_ranges.push((mid, block_range));
+	}
+
+	Transfer::delete_all_by_block_range(warehouse, nid, (from_block, to_block)).await?;
+	Amount::delete_all_by_block_range(warehouse, nid, (from_block, to_block)).await?;
+
+	// pending module ranges also hold off linking until they're processed
+	for (mid, block_range) in block_ranges.into_iter() {
+		Config::set::<_, (BlockHeight, BlockHeight)>(
+			c,
+			ConfigKey::IndexerProcessModule(nid, mid),
+			block_range,
+		)
+		.await?;
+		Config::delete(c, ConfigKey::IndexerProcessModuleDone(nid, mid)).await?;
+	}
+
+	// rewind addresses that linked past the start of the range
+	Link::delete_all_from_block_height(warehouse, nid, from_block).await?;
+	let link_block = from_block.saturating_sub(1);
+	let rewound = Config::get_many::<_, BlockHeight>(c, vec![ConfigKey::IndexerLink(nid, 0)])
+		.await?
+		.into_iter()
+		.filter_map(|(key, hit)| (hit.value > link_block).then_some((key, link_block)))
+		.collect::<HashMap<_, _>>();
+	if !rewound.is_empty() {
+		Config::set_many::<_, BlockHeight>(c, rewound).await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{
+		chain::U256,
+		models::LinkUuid,
+		testing::{self, MockDriver},
+		Architecture, Db,
+	};
+
+	/// Lookups and deletes all match on the block range (open-ended without an upper bound)
+	fn new_driver() -> MockDriver {
+		fn is_in_range(query: &str, block_height: u64) -> bool {
+			let bound = |op: &str| -> Option<u64> {
+				let (_, rest) = query.split_once(&format!("block_height {op} "))?;
+				rest.split_whitespace().next()?.parse().ok()
+			};
+
+			block_height >= bound(">=").unwrap_or(0) &&
+				block_height <= bound("<=").unwrap_or(u64::MAX)
+		}
+
+		MockDriver::default()
+			.with_select("transfers", |query, transfers: Vec<Transfer>| {
+				transfers.into_iter().filter(|t| is_in_range(query, t.block_height)).collect()
+			})
+			.with_delete("transfers", |query, t: &Transfer| is_in_range(query, t.block_height))
+			.with_delete("amounts", |query, a: &Amount| is_in_range(query, a.block_height))
+			.with_delete("links", |query, l: &Link| is_in_range(query, l.block_height))
+	}
+
+	#[tokio::test]
+	async fn test_reindex() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+		let driver = new_driver();
+		let warehouse = driver.warehouse(10);
+
+		let network = testing::create_network(db.get(), "net_a", 6).await?;
+		let network_id = network.network_id;
+
+		// what processing blocks 1 to 5 outputs
+		let process_blocks = || {
+			(1..=5)
+				.map(|block_height| {
+					Transfer::new(
+						ModuleId::BitcoinTransfer,
+						network_id,
+						block_height,
+						"tx",
+						"from",
+						"to",
+						None,
+						U256::from(1),
+						U256::from(1),
+						0,
+					)
+				})
+				.collect::<Vec<_>>()
+		};
+
+		Config::set::<_, BlockHeight>(db.get(), ConfigKey::IndexerProcessTail(network_id), 100)
+			.await?;
+		Transfer::create_many(&warehouse, process_blocks()).await?;
+
+		// their amounts, and links of two addresses: one linked up to block 5, one up to 1
+		let amounts = process_blocks()
+			.iter()
+			.map(|t| {
+				Amount::new(
+					ModuleId::BitcoinTransfer,
+					network_id,
+					t.block_height,
+					&t.tx_hash,
+					&t.to_address,
+					None,
+					t.relative_amount,
+					U256::zero(),
+					0,
+				)
+			})
+			.collect::<Vec<_>>();
+		warehouse.insert("amounts", &amounts).await?;
+
+		let links = process_blocks()
+			.iter()
+			.map(|t| Link::new(network_id, t.block_height, "from", "to", vec![LinkUuid(t.uuid)], 0))
+			.collect::<Vec<_>>();
+		warehouse.insert("links", &links).await?;
+
+		let link_config_keys =
+			[ConfigKey::IndexerLink(network_id, 1), ConfigKey::IndexerLink(network_id, 2)];
+		Config::set::<_, BlockHeight>(db.get(), link_config_keys[0], 5).await?;
+		Config::set::<_, BlockHeight>(db.get(), link_config_keys[1], 1).await?;
+
+		// modules still catching up can't be reindexed yet
+		assert!(matches!(
+			reindex(db.get(), &warehouse, &network, (2, 4)).await,
+			Err(ServerError::TooEarly { .. })
+		));
+
+		for module_id in ModuleId::get_transfer_module_ids(Architecture::Bitcoin) {
+			let config_key = ConfigKey::IndexerProcessModuleDone(network_id, module_id as u16);
+			Config::set::<_, u8>(db.get(), config_key, 1).await?;
+		}
+
+		// nothing above the tail to reindex
+		assert!(matches!(
+			reindex(db.get(), &warehouse, &network, (2, 101)).await,
+			Err(ServerError::InvalidParam { .. })
+		));
+
+		reindex(db.get(), &warehouse, &network, (2, 4)).await?;
+
+		let block_heights =
+			driver.rows::<Transfer>("transfers").iter().map(|t| t.block_height).collect::<Vec<_>>();
+		assert_eq!(block_heights, vec![1, 5]);
+
+		let block_heights =
+			driver.rows::<Amount>("amounts").iter().map(|a| a.block_height).collect::<Vec<_>>();
+		assert_eq!(block_heights, vec![1, 5]);
+
+		// links past the start of the range are dropped (even the ones ending after it) and
+		// get rebuilt from there
+		let block_heights =
+			driver.rows::<Link>("links").iter().map(|l| l.block_height).collect::<Vec<_>>();
+		assert_eq!(block_heights, vec![1]);
+
+		let link_blocks = Config::get_many::<_, BlockHeight>(db.get(), link_config_keys.to_vec())
+			.await?
+			.into_iter()
+			.map(|(key, hit)| (key, hit.value))
+			.collect::<HashMap<_, _>>();
+		assert_eq!(
+			link_blocks,
+			HashMap::from([(link_config_keys[0], 1), (link_config_keys[1], 1)])
+		);
+
+		// the live checkpoint stays put, the range is queued up for the transfer modules
+		let config_key = ConfigKey::IndexerProcessTail(network_id);
+		assert_eq!(
+			Config::get::<_, BlockHeight>(db.get(), config_key).await?.map(|v| v.value),
+			Some(100)
+		);
+
+		let config_key =
+			ConfigKey::IndexerProcessModule(network_id, ModuleId::BitcoinTransfer as u16);
+		assert_eq!(
+			Config::get::<_, (BlockHeight, BlockHeight)>(db.get(), config_key)
+				.await?
+				.map(|v| v.value),
+			Some((1, 4))
+		);
+
+		// reindexing again before it's done just widens the pending range
+		reindex(db.get(), &warehouse, &network, (3, 6)).await?;
+		assert_eq!(
+			Config::get::<_, (BlockHeight, BlockHeight)>(db.get(), config_key)
+				.await?
+				.map(|v| v.value),
+			Some((1, 6))
+		);
+
+		// transfers come back once, even where the reprocessed range overlaps stored ones
+		Transfer::create_many(&warehouse, process_blocks()).await?;
+		Transfer::create_many(&warehouse, process_blocks()).await?;
+
+		let mut stored = driver.rows::<Transfer>("transfers");
+		stored.sort_by_key(|t| t.block_height);
+		assert_eq!(stored, process_blocks());
+
+		Ok(())
+	}
+}
@@ -0,0 +1,26 @@
+use axum::{
+	extract::{Path, State},
+	http::StatusCode,
+};
+use std::sync::Arc;
+
+use crate::{errors::ServerError, ServerResult};
+use barreleye_common::{
+	models::{Config, ConfigKey, Network, SoftDeleteModel},
+	App,
+};
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(network_id): Path<String>,
+) -> ServerResult<'static, StatusCode> {
+	let network =
+		Network::get_existing_by_id(app.db(), &network_id).await?.ok_or(ServerError::NotFound)?;
+
+	Network::set_paused(app.db(), network.network_id, false).await?;
+
+	// update config so the indexer restarts its loops
+	Config::set::<_, u8>(app.db(), ConfigKey::NetworksUpdated, 1).await?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
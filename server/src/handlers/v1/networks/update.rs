@@ -1,18 +1,17 @@
 use axum::{
 	extract::{Path, State},
 	http::StatusCode,
-	Json,
 };
-use sea_orm::ActiveModelTrait;
+use sea_orm::{ActiveModelTrait, ConnectionTrait};
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, utils, ServerResult};
 use barreleye_common::{
 	models::{
 		optional_set, BasicModel, Config, ConfigKey, Network, NetworkActiveModel, SoftDeleteModel,
 	},
-	App, Architecture,
+	App, Architecture, Attribution, BlockHeight,
 };
 
 #[derive(Deserialize)]
@@ -24,6 +23,10 @@ pub struct Payload {
 	block_time: Option<u64>,
 	rpc_endpoint: Option<String>,
 	rps: Option<u32>,
+	confirmations: Option<u32>,
+	skip_change_outputs: Option<bool>,
+	attribution: Option<Attribution>,
+	script_addresses: Option<bool>,
 }
 
 pub async fn handler(
@@ -43,20 +46,29 @@ pub async fn handler(
 			});
 		}
 
-		// check for any duplicate
-		if network_id != network.id &&
-			network.name.trim().to_lowercase() == name.trim().to_lowercase()
+		// check for any duplicate (other than the network itself)
+		if Network::get_by_name(app.db(), &name, Some(false))
+			.await?
+			.is_some_and(|other_network| other_network.network_id != network.network_id)
 		{
 			return Err(ServerError::Duplicate { field: "name".into(), value: name.into() });
 		}
 	}
 
+	check_script_addresses(app.db(), &network, payload.script_addresses).await?;
+
+	let chain_id = payload.chain_id.map(|v| utils::convert("chainId", v)).transpose()?;
+	let block_time = payload.block_time.map(|v| utils::convert("blockTime", v)).transpose()?;
+	let rps = payload.rps.map(|v| utils::convert("rps", v)).transpose()?;
+	let confirmations =
+		payload.confirmations.map(|v| utils::convert("confirmations", v)).transpose()?;
+
 	// check for duplicate chain id
-	if let Some(chain_id) = payload.chain_id {
+	if let Some(chain_id) = chain_id {
 		if Network::get_by_architecture_and_chain_id(
 			app.db(),
 			payload.architecture.unwrap_or(network.architecture),
-			chain_id as i64,
+			chain_id,
 			None,
 		)
 		.await?
@@ -72,10 +84,14 @@ pub async fn handler(
 	let update_data = NetworkActiveModel {
 		name: optional_set(payload.name.clone()),
 		architecture: optional_set(payload.architecture),
-		chain_id: optional_set(payload.chain_id.map(|v| v as i64)),
-		block_time: optional_set(payload.block_time.map(|v| v as i64)),
+		chain_id: optional_set(chain_id),
+		block_time: optional_set(block_time),
 		rpc_endpoint: optional_set(payload.rpc_endpoint.clone()),
-		rps: optional_set(payload.rps.map(|v| v as i32)),
+		rps: optional_set(rps),
+		confirmations: optional_set(confirmations),
+		skip_change_outputs: optional_set(payload.skip_change_outputs),
+		attribution: optional_set(payload.attribution),
+		script_addresses: optional_set(payload.script_addresses),
 		..Default::default()
 	};
 
@@ -93,3 +109,54 @@ pub async fn handler(
 
 	Ok(StatusCode::NO_CONTENT)
 }
+
+/// Addresses are keyed one way or the other across the whole history, so `scriptAddresses` is
+/// fixed once indexing has started
+async fn check_script_addresses<C>(
+	c: &C,
+	network: &Network,
+	script_addresses: Option<bool>,
+) -> ServerResult<'static, ()>
+where
+	C: ConnectionTrait,
+{
+	if let Some(script_addresses) = script_addresses {
+		let process_tail = ConfigKey::IndexerProcessTail(network.network_id);
+		if script_addresses != network.script_addresses &&
+			Config::get::<_, BlockHeight>(c, process_tail).await?.is_some()
+		{
+			return Err(ServerError::InvalidParam {
+				field: "scriptAddresses".into(),
+				value: script_addresses.to_string().into(),
+			});
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{testing, Db};
+
+	#[tokio::test]
+	async fn test_check_script_addresses() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+		let network = testing::create_network(db.get(), "net_a", 0).await?;
+
+		// free to change until indexing starts
+		assert!(check_script_addresses(db.get(), &network, Some(true)).await.is_ok());
+
+		let process_tail = ConfigKey::IndexerProcessTail(network.network_id);
+		Config::set::<_, BlockHeight>(db.get(), process_tail, 0).await?;
+		assert!(matches!(
+			check_script_addresses(db.get(), &network, Some(true)).await,
+			Err(ServerError::InvalidParam { .. })
+		));
+		assert!(check_script_addresses(db.get(), &network, Some(false)).await.is_ok());
+		assert!(check_script_addresses(db.get(), &network, None).await.is_ok());
+
+		Ok(())
+	}
+}
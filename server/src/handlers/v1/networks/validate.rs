@@ -0,0 +1,148 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{errors::ServerError, extract::Json, utils, ServerResult};
+use barreleye_common::{
+	chain::{Bitcoin, ChainError, ChainTrait, Evm},
+	models::Network,
+	App, Architecture, BlockHeight,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	name: Option<String>,
+	architecture: Architecture,
+	rpc_endpoint: String,
+	chain_id: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	connected: bool,
+	magic: Option<String>,
+	block_height: Option<BlockHeight>,
+}
+
+/// Checks a network config the same way creating it would, without persisting anything
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	Ok(validate(payload, app.settings.rpc_timeout).await?.into())
+}
+
+async fn validate(payload: Payload, rpc_timeout: u64) -> ServerResult<'static, Response> {
+	let chain_id: i64 = utils::convert("chainId", payload.chain_id.unwrap_or_default())?;
+
+	// credentials can be looked up by network name
+	let n = Network {
+		name: payload.name.unwrap_or_default(),
+		chain_id,
+		rpc_endpoint: payload.rpc_endpoint,
+		..Default::default()
+	};
+
+	let (mut boxed_chain, magic): (Box<dyn ChainTrait>, _) = match payload.architecture {
+		Architecture::Bitcoin => {
+			let bitcoin = Bitcoin::new(n).map_err(|_| ServerError::InvalidParam {
+				field: "chainId".into(),
+				value: chain_id.to_string().into(),
+			})?;
+			let magic = format!("{:#010x}", bitcoin.get_magic());
+
+			(Box::new(bitcoin.with_rpc_timeout(rpc_timeout)), Some(magic))
+		}
+		Architecture::Evm => (Box::new(Evm::new(n)), None),
+	};
+
+	let connected = match boxed_chain.connect().await {
+		Err(e) if matches!(e.downcast_ref(), Some(ChainError::Mismatch { .. })) => {
+			return Err(ServerError::InvalidParam {
+				field: "chainId".into(),
+				value: chain_id.to_string().into(),
+			});
+		}
+		connected => connected.unwrap_or(false),
+	};
+	let block_height = match connected {
+		true => boxed_chain.get_block_height().await.ok(),
+		_ => None,
+	};
+
+	Ok(Response { connected, magic, block_height })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::testing::{self, MockRpc};
+	use serde_json::json;
+
+	/// Minimal bitcoind on `chain` that only knows `getblockchaininfo` and `getblockcount`
+	async fn mock_rpc(chain: &'static str, block_height: BlockHeight) -> eyre::Result<MockRpc> {
+		MockRpc::start(move |method, _params| {
+			Ok(match method {
+				"getblockchaininfo" => testing::bitcoind_blockchain_info(chain, block_height),
+				_ => json!(block_height),
+			})
+		})
+		.await
+	}
+
+	fn payload(rpc_endpoint: &str, chain_id: u64) -> Payload {
+		Payload {
+			name: None,
+			architecture: Architecture::Bitcoin,
+			rpc_endpoint: rpc_endpoint.to_string(),
+			chain_id: Some(chain_id),
+		}
+	}
+
+	#[tokio::test]
+	async fn test_validate() -> eyre::Result<()> {
+		let url = mock_rpc("regtest", 123).await?.url;
+
+		assert_eq!(
+			validate(payload(&url, 2), 5).await?,
+			Response {
+				connected: true,
+				magic: Some("0xdab5bffa".to_string()),
+				block_height: Some(123),
+			}
+		);
+
+		// the node has to be on the requested chain
+		let url = mock_rpc("main", 123).await?.url;
+		assert!(matches!(
+			validate(payload(&url, 2), 5).await,
+			Err(ServerError::InvalidParam { field, .. }) if field == "chainId"
+		));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_validate_unreachable() -> eyre::Result<()> {
+		let url = MockRpc::unreachable_url().await?;
+
+		assert_eq!(
+			validate(payload(&url, 2), 5).await?,
+			Response {
+				connected: false,
+				magic: Some("0xdab5bffa".to_string()),
+				block_height: None,
+			}
+		);
+
+		// unknown chain ids are rejected before connecting
+		assert!(matches!(
+			validate(payload(&url, 4), 5).await,
+			Err(ServerError::InvalidParam { .. })
+		));
+
+		Ok(())
+	}
+}
@@ -0,0 +1,66 @@
+use axum::extract::{Path, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	ServerResult,
+};
+use barreleye_common::{
+	chain::JsonAmount,
+	models::{Network, SoftDeleteModel, Transfer},
+	utils, App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	from: Option<u32>,
+	to: Option<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseVolume {
+	date: String,
+	count: u64,
+	total_amount: JsonAmount,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	volume: Vec<ResponseVolume>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(network_id): Path<String>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let network =
+		Network::get_existing_by_id(app.db(), &network_id).await?.ok_or(ServerError::NotFound)?;
+
+	// default to the last 30 days
+	let to = payload.to.unwrap_or(utils::now().and_utc().timestamp() as u32);
+	let from = payload.from.unwrap_or(to.saturating_sub(30 * 86_400));
+	if from >= to {
+		return Err(ServerError::InvalidParam {
+			field: "from".into(),
+			value: from.to_string().into(),
+		});
+	}
+
+	let volume = Transfer::aggregate_by_day(&app.warehouse, network.network_id, (from, to))
+		.await?
+		.into_iter()
+		.map(|v| ResponseVolume {
+			date: v.date.to_string(),
+			count: v.count,
+			total_amount: JsonAmount::new(v.total_amount, app.settings.numeric_amounts),
+		})
+		.collect();
+
+	Ok(Response { volume }.into())
+}
@@ -0,0 +1,177 @@
+use schemars::{
+	gen::{SchemaGenerator, SchemaSettings},
+	schema::Schema,
+	JsonSchema,
+};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+	extract::Json,
+	handlers::v1::info::{InfoPayload, InfoResponse, InfoTotalsResponse},
+};
+
+pub async fn handler() -> Json<JsonValue> {
+	get_openapi().into()
+}
+
+/// OpenAPI 3.0 document for the address info endpoints, derived from the response and payload
+/// types themselves so it can't drift from what's actually served
+fn get_openapi() -> JsonValue {
+	let mut gen = SchemaSettings::openapi3().into_generator();
+
+	let info_parameters = get_query_parameters::<InfoPayload>(&mut gen);
+	let info_response = gen.subschema_for::<InfoResponse>();
+	let totals_response = gen.subschema_for::<InfoTotalsResponse>();
+
+	let ok = |description: &str, schema: Schema| {
+		json!({
+			"200": {
+				"description": description,
+				"content": { "application/json": { "schema": schema } },
+			},
+		})
+	};
+
+	json!({
+		"openapi": "3.0.3",
+		"info": {
+			"title": "Barreleye",
+			"version": env!("CARGO_PKG_VERSION"),
+		},
+		"paths": {
+			"/v1/info": {
+				"get": {
+					"parameters": info_parameters,
+					"responses": ok("Address info", info_response),
+				},
+			},
+			"/v1/info/totals": {
+				"get": {
+					"responses": ok("Overall totals", totals_response),
+				},
+			},
+		},
+		"components": {
+			"schemas": gen.definitions(),
+		},
+	})
+}
+
+/// Query strings are described field by field in OpenAPI, not as a single object
+fn get_query_parameters<T: JsonSchema>(gen: &mut SchemaGenerator) -> Vec<JsonValue> {
+	let object = gen.root_schema_for::<T>().schema.object.unwrap_or_default();
+
+	object
+		.properties
+		.iter()
+		.map(|(name, schema)| {
+			json!({
+				"name": name,
+				"in": "query",
+				"required": object.required.contains(name),
+				"schema": schema,
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// OpenAPI 3.0 marks optional values with `nullable`, which plain JSON Schema doesn't have
+	fn to_json_schema(value: JsonValue) -> JsonValue {
+		match value {
+			JsonValue::Object(mut object) => {
+				let is_nullable = object.remove("nullable") == Some(json!(true));
+				let object = object.into_iter().map(|(k, v)| (k, to_json_schema(v))).collect();
+
+				match is_nullable {
+					true => json!({ "anyOf": [JsonValue::Object(object), { "type": "null" }] }),
+					false => JsonValue::Object(object),
+				}
+			}
+			JsonValue::Array(items) => items.into_iter().map(to_json_schema).collect(),
+			value => value,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_openapi() -> eyre::Result<()> {
+		let openapi = handler().await.0;
+		let info = &openapi["paths"]["/v1/info"]["get"];
+
+		// components go along, since that's where the schema's `$ref`s point to
+		let mut schema = info["responses"]["200"]["content"]["application/json"]["schema"].clone();
+		schema["components"] = openapi["components"].clone();
+		let validator = jsonschema::validator_for(&to_json_schema(schema))?;
+
+		let parameters = info["parameters"].as_array().unwrap();
+		assert!(parameters.contains(&json!({
+			"name": "q",
+			"in": "query",
+			"required": true,
+			"schema": { "type": "string" },
+		})));
+
+		// round-trip through the actual type so the sample is a response the server could send
+		let response = serde_json::to_value(serde_json::from_value::<InfoResponse>(json!({
+			"addresses": ["to"],
+			"risk": { "level": "high", "reasons": ["entity", "source"] },
+			"assets": [{ "network": "net_a", "token": null, "balance": "1000" }],
+			"tokens": [{
+				"id": "tok_a",
+				"name": "Bitcoin",
+				"symbol": "BTC",
+				"address": "",
+				"decimals": 8,
+			}],
+			"sources": [{
+				"network": "net_a",
+				"entity": "ent_a",
+				"from": "from",
+				"to": "to",
+				"hops": 1,
+				"transactions": [{
+					"hash": "tx",
+					"from": "from",
+					"to": "to",
+					"asset": null,
+					"amount": "1000",
+				}],
+			}],
+			"sourcesByEntity": [{
+				"entity": "ent_a",
+				"networks": ["net_a"],
+				"addresses": ["from"],
+				"hops": 1,
+				"transactions": [],
+				"amounts": [{ "asset": null, "amount": "1000" }],
+			}],
+			"networks": [{ "id": "net_a", "name": "Bitcoin", "chainId": 0 }],
+			"entities": [{
+				"id": "ent_a",
+				"name": null,
+				"description": "",
+				"data": { "anything": ["goes"] },
+				"tags": ["tag_a"],
+				"riskScore": 100,
+			}],
+			"entitiesTruncated": false,
+			"tags": [{ "id": "tag_a", "name": "Exchange", "riskLevel": "critical" }],
+			"warnings": { "droppedNetworkIds": [] },
+		}))?)?;
+		assert!(validator.is_valid(&response));
+
+		// and the schema is strict enough to reject what the server wouldn't send
+		let mut invalid = response.clone();
+		invalid["risk"]["level"] = json!("unknown");
+		assert!(!validator.is_valid(&invalid));
+
+		let mut invalid = response.clone();
+		invalid.as_object_mut().unwrap().remove("addresses");
+		assert!(!validator.is_valid(&invalid));
+
+		Ok(())
+	}
+}
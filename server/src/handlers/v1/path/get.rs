@@ -0,0 +1,87 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	utils, ServerResult,
+};
+use barreleye_common::{
+	models::{Link, Network, PrimaryId, SoftDeleteModel},
+	App, BlockHeight, LINK_PATH_MAX_DEPTH,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	from: String,
+	to: String,
+	network: Option<String>,
+	max_depth: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseHop {
+	network: Option<String>,
+	block_height: BlockHeight,
+	from: String,
+	to: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	path: Option<Vec<ResponseHop>>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let (from, to) = (payload.from.trim(), payload.to.trim());
+	if from.is_empty() || to.is_empty() {
+		return Err(ServerError::MissingInputParams);
+	}
+
+	// reject addresses that can't belong to any configured network
+	let from = utils::format_address(&app, "from", from).await?;
+	let to = utils::format_address(&app, "to", to).await?;
+
+	let max_depth = payload.max_depth.unwrap_or(LINK_PATH_MAX_DEPTH);
+	if max_depth == 0 || max_depth > LINK_PATH_MAX_DEPTH {
+		return Err(ServerError::InvalidParam {
+			field: "maxDepth".into(),
+			value: max_depth.to_string().into(),
+		});
+	}
+
+	// resolve optional network filter
+	let network_id = match payload.network {
+		Some(id) => Some(
+			Network::get_existing_by_id(app.db(), &id)
+				.await?
+				.ok_or(ServerError::InvalidParam { field: "network".into(), value: id.into() })?
+				.network_id,
+		),
+		_ => None,
+	};
+
+	let networks = app.networks.read().await;
+	let path = Link::shortest_path(&app.warehouse, network_id, (&from, &to), max_depth)
+		.await?
+		.map(|links| {
+			links
+				.into_iter()
+				.map(|l| ResponseHop {
+					network: networks.get(&(l.network_id as PrimaryId)).map(|c| c.get_network().id),
+					block_height: l.block_height,
+					from: l.from_address,
+					to: l.to_address,
+				})
+				.collect()
+		});
+
+	Ok(Response { path }.into())
+}
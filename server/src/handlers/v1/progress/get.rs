@@ -0,0 +1,41 @@
+use axum::{
+	extract::State,
+	response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream, StreamExt};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::warn;
+
+use barreleye_common::{progress::ProgressTracker, App, PROGRESS_INTERVAL};
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+	let ticker = interval(Duration::from_secs(PROGRESS_INTERVAL));
+
+	// stream is dropped (and polling stops) as soon as the client disconnects
+	let events = stream::unfold(
+		(app, ProgressTracker::new(), ticker),
+		|(app, mut tracker, mut ticker)| async move {
+			ticker.tick().await;
+
+			let events = match tracker.get_all(app.db()).await {
+				Ok(progress) => progress
+					.iter()
+					.filter_map(|p| Event::default().event("progress").json_data(p).ok())
+					.map(Ok)
+					.collect(),
+				Err(e) => {
+					warn!(error = %e, "could not get indexing progress");
+					vec![]
+				}
+			};
+
+			Some((stream::iter(events), (app, tracker, ticker)))
+		},
+	)
+	.flatten();
+
+	Sse::new(events).keep_alive(KeepAlive::default())
+}
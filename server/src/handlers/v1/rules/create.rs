@@ -0,0 +1,49 @@
+use axum::extract::State;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{errors::ServerError, extract::Json, ServerResult};
+use barreleye_common::{
+	models::{is_valid_id, BasicModel, Entity, Rule, SoftDeleteModel},
+	App, IdPrefix, RuleKind,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	id: Option<String>,
+	entity: String,
+	kind: RuleKind,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, Json<Rule>> {
+	// check that id is valid
+	if let Some(id) = payload.id.clone() {
+		if !is_valid_id(&id, IdPrefix::Rule) || Rule::get_by_id(app.db(), &id).await?.is_some() {
+			return Err(ServerError::InvalidParam { field: "id".into(), value: id.into() });
+		}
+	}
+
+	// fetch entity
+	let entity = Entity::get_existing_by_id(app.db(), &payload.entity).await?.ok_or(
+		ServerError::InvalidParam { field: "entity".into(), value: payload.entity.into() },
+	)?;
+
+	// one rule per kind is enough
+	if Rule::get_by_entity_id_and_kind(app.db(), entity.entity_id, payload.kind).await?.is_some() {
+		return Err(ServerError::Duplicate { field: "entity".into(), value: entity.id.into() });
+	}
+
+	// create new, generating an id unless one was given
+	let rule_id =
+		Rule::create_with_id(app.db(), payload.id, IdPrefix::Rule, app.settings.id_length, |id| {
+			Rule::new_model(Some(id), entity.entity_id, &entity.id, payload.kind)
+		})
+		.await?;
+
+	// return newly created
+	Ok(Rule::get(app.db(), rule_id).await?.unwrap().into())
+}
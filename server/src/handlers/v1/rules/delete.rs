@@ -0,0 +1,37 @@
+use axum::{extract::State, http::StatusCode};
+use sea_orm::ColumnTrait;
+use serde::Deserialize;
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{extract::Json, utils, ServerResult};
+use barreleye_common::{
+	models::{BasicModel, Rule, RuleColumn},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	rules: HashSet<String>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, StatusCode> {
+	// exit if no input
+	if payload.rules.is_empty() {
+		return Ok(StatusCode::NO_CONTENT);
+	}
+
+	utils::check_bulk_size("rules", payload.rules.len(), app.settings.max_bulk_size)?;
+
+	// delete all rules (already assigned addresses stay with their entities)
+	Rule::delete_all_where(
+		app.db(),
+		RuleColumn::Id.is_in(payload.rules.into_iter().collect::<Vec<String>>()),
+	)
+	.await?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,34 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	extract::{Json, Query},
+	ServerResult,
+};
+use barreleye_common::{
+	models::{BasicModel, Rule},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	offset: Option<u64>,
+	limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	rules: Vec<Rule>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let rules = Rule::get_all_paginated(app.db(), payload.offset, payload.limit).await?;
+
+	Ok(Response { rules }.into())
+}
@@ -0,0 +1,129 @@
+use axum::{
+	extract::{
+		ws::{Message, WebSocket, WebSocketUpgrade},
+		State,
+	},
+	response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
+
+use barreleye_common::{
+	chain::JsonAmount,
+	feed::FeedEvent,
+	models::{PrimaryId, Transfer},
+	App, BlockHeight,
+};
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectMessage {
+	networks: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseTransfer {
+	network: Option<String>,
+	block_height: BlockHeight,
+	tx_hash: String,
+	from: String,
+	to: String,
+	asset: String,
+	amount: JsonAmount,
+	fee: JsonAmount,
+	created_at: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResponseEvent {
+	Transfer(ResponseTransfer),
+	Lagged(u64),
+	Error(String),
+}
+
+pub async fn handler(State(app): State<Arc<App>>, ws: WebSocketUpgrade) -> Response {
+	ws.on_upgrade(move |socket| stream(app, socket))
+}
+
+async fn stream(app: Arc<App>, mut socket: WebSocket) {
+	// first message is the subscription filter
+	let connect_message = match socket.recv().await {
+		Some(Ok(Message::Text(text))) => serde_json::from_str::<ConnectMessage>(&text),
+		_ => return,
+	};
+
+	let network_ids = match connect_message {
+		Ok(ConnectMessage { networks: Some(ids) }) => {
+			let networks = app.networks.read().await;
+			let mut network_ids = HashSet::new();
+
+			for id in ids.into_iter() {
+				match networks.values().find(|c| c.get_network().id == id) {
+					Some(c) => network_ids.insert(c.get_network().network_id),
+					None => {
+						let event = ResponseEvent::Error(format!("unknown network `{id}`"));
+						send(&mut socket, &event).await;
+						return;
+					}
+				};
+			}
+
+			Some(network_ids)
+		}
+		Ok(_) => None,
+		Err(e) => {
+			send(&mut socket, &ResponseEvent::Error(e.to_string())).await;
+			return;
+		}
+	};
+
+	let mut subscription = app.feed.subscribe(network_ids);
+	loop {
+		tokio::select! {
+			event = subscription.next() => {
+				let event = match event {
+					Some(FeedEvent::Transfer(t)) => {
+						ResponseEvent::Transfer(to_response_transfer(&app, *t).await)
+					}
+					Some(FeedEvent::Lagged(n)) => ResponseEvent::Lagged(n),
+					None => break,
+				};
+
+				if !send(&mut socket, &event).await {
+					break;
+				}
+			}
+			message = socket.recv() => {
+				match message {
+					Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+					_ => {}
+				}
+			}
+		}
+	}
+}
+
+async fn to_response_transfer(app: &App, t: Transfer) -> ResponseTransfer {
+	let networks = app.networks.read().await;
+
+	ResponseTransfer {
+		network: networks.get(&(t.network_id as PrimaryId)).map(|c| c.get_network().id),
+		block_height: t.block_height,
+		tx_hash: t.tx_hash,
+		from: t.from_address,
+		to: t.to_address,
+		asset: t.asset_address,
+		amount: JsonAmount::new(t.relative_amount, app.settings.numeric_amounts),
+		fee: JsonAmount::new(t.fee, app.settings.numeric_amounts),
+		created_at: t.created_at,
+	}
+}
+
+async fn send(socket: &mut WebSocket, event: &ResponseEvent) -> bool {
+	match serde_json::to_string(event) {
+		Ok(json) => socket.send(Message::Text(json.into())).await.is_ok(),
+		_ => false,
+	}
+}
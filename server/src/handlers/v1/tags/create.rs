@@ -1,8 +1,8 @@
-use axum::{extract::State, Json};
+use axum::extract::State;
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
 	models::{is_valid_id, BasicModel, Tag},
 	App, IdPrefix, RiskLevel,
@@ -32,10 +32,12 @@ pub async fn handler(
 		return Err(ServerError::Duplicate { field: "name".into(), value: payload.name.into() });
 	}
 
-	// create new
+	// create new, generating an id unless one was given
 	let tag_id =
-		Tag::create(app.db(), Tag::new_model(payload.id, &payload.name, payload.risk_level))
-			.await?;
+		Tag::create_with_id(app.db(), payload.id, IdPrefix::Tag, app.settings.id_length, |id| {
+			Tag::new_model(Some(id), &payload.name, payload.risk_level)
+		})
+		.await?;
 
 	// return newly created
 	Ok(Tag::get(app.db(), tag_id).await?.unwrap().into())
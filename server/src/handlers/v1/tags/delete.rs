@@ -1,9 +1,14 @@
-use axum::{extract::State, http::StatusCode, Json};
-use sea_orm::ColumnTrait;
-use serde::Deserialize;
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response as AxumResponse},
+};
+use eyre::Result;
+use sea_orm::{ColumnTrait, ConnectionTrait};
+use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, sync::Arc};
 
-use crate::ServerResult;
+use crate::{extract::Json, utils, ServerResult};
 use barreleye_common::{
 	models::{BasicModel, PrimaryId, Tag, TagColumn},
 	App,
@@ -15,29 +20,66 @@ pub struct Payload {
 	tags: HashSet<String>,
 }
 
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	deleted: u64,
+	skipped_locked: Vec<String>,
+}
+
 pub async fn handler(
 	State(app): State<Arc<App>>,
 	Json(payload): Json<Payload>,
-) -> ServerResult<'static, StatusCode> {
+) -> ServerResult<'static, AxumResponse> {
 	// exit if no input
 	if payload.tags.is_empty() {
-		return Ok(StatusCode::NO_CONTENT);
+		return Ok(StatusCode::NO_CONTENT.into_response());
 	}
 
+	utils::check_bulk_size("tags", payload.tags.len(), app.settings.max_bulk_size)?;
+
+	Ok(Json(delete(app.db(), payload.tags).await?).into_response())
+}
+
+/// Deletes tags by id; ids that don't exist only go uncounted. Tags can't be locked, so
+/// `skippedLocked` is always empty (it's there so bulk deletes all answer the same way)
+async fn delete<C>(c: &C, ids: HashSet<String>) -> Result<Response>
+where
+	C: ConnectionTrait,
+{
 	// get all tags
-	let all_tags = Tag::get_all_where(app.db(), TagColumn::Id.is_in(payload.tags)).await?;
+	let all_tags = Tag::get_all_where(c, TagColumn::Id.is_in(ids)).await?;
 
 	// proceed only when there's something to delete
 	if all_tags.is_empty() {
-		return Ok(StatusCode::NO_CONTENT);
+		return Ok(Response { deleted: 0, skipped_locked: vec![] });
 	}
 
 	// delete all associated tags
-	Tag::delete_all_where(
-		app.db(),
+	let deleted = Tag::delete_all_where(
+		c,
 		TagColumn::TagId.is_in(all_tags.iter().map(|t| t.tag_id).collect::<Vec<PrimaryId>>()),
 	)
 	.await?;
 
-	Ok(StatusCode::NO_CONTENT)
+	Ok(Response { deleted, skipped_locked: vec![] })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use barreleye_common::{Db, RiskLevel};
+
+	#[tokio::test]
+	async fn test_delete() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+
+		Tag::create(db.get(), Tag::new_model(Some("tag_a".to_string()), "A", RiskLevel::Low))
+			.await?;
+
+		let ids = HashSet::from(["tag_a".to_string(), "tag_missing".to_string()]);
+		assert_eq!(delete(db.get(), ids).await?, Response { deleted: 1, skipped_locked: vec![] });
+
+		Ok(())
+	}
 }
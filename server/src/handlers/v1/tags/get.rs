@@ -1,11 +1,10 @@
-use axum::{
-	extract::{Path, State},
-	Json,
-};
+use axum::extract::{Path, State};
 use serde::Serialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, handlers::v1::tags::get_data_by_tag_ids, ServerResult};
+use crate::{
+	errors::ServerError, extract::Json, handlers::v1::tags::get_data_by_tag_ids, ServerResult,
+};
 use barreleye_common::{
 	models::{Address, BasicModel, Entity, Network, Tag},
 	App,
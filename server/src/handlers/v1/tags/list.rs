@@ -1,9 +1,12 @@
-use axum::{extract::State, Json};
-use axum_extra::extract::Query;
+use axum::extract::State;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::{handlers::v1::tags::get_data_by_tag_ids, ServerResult};
+use crate::{
+	extract::{Json, Query},
+	handlers::v1::tags::get_data_by_tag_ids,
+	ServerResult,
+};
 use barreleye_common::{
 	models::{Address, BasicModel, Entity, Network, Tag},
 	App,
@@ -1,13 +1,12 @@
 use axum::{
 	extract::{Path, State},
 	http::StatusCode,
-	Json,
 };
 use sea_orm::ActiveModelTrait;
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
 	models::{optional_set, BasicModel, Tag, TagActiveModel},
 	App, RiskLevel,
@@ -1,9 +1,9 @@
-use axum::{extract::State, Json};
+use axum::extract::State;
 use sea_orm::{ColumnTrait, Condition};
 use serde::Deserialize;
 use std::sync::Arc;
 
-use crate::{errors::ServerError, ServerResult};
+use crate::{errors::ServerError, extract::Json, ServerResult};
 use barreleye_common::{
 	models::{is_valid_id, BasicModel, Network, Token, TokenColumn},
 	App, IdPrefix,
@@ -53,17 +53,22 @@ pub async fn handler(
 		return Err(ServerError::Duplicate { field: "address".into(), value: address.into() });
 	}
 
-	// create new
-	let token_id = Token::create(
+	// create new, generating an id unless one was given
+	let token_id = Token::create_with_id(
 		app.db(),
-		Token::new_model(
-			payload.id,
-			network.network_id,
-			&payload.name,
-			&payload.symbol,
-			&address,
-			payload.decimals as i16,
-		),
+		payload.id,
+		IdPrefix::Token,
+		app.settings.id_length,
+		|id| {
+			Token::new_model(
+				Some(id),
+				network.network_id,
+				&payload.name,
+				&payload.symbol,
+				&address,
+				payload.decimals as i16,
+			)
+		},
 	)
 	.await?;
 
@@ -1,9 +1,9 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{extract::State, http::StatusCode};
 use sea_orm::ColumnTrait;
 use serde::Deserialize;
 use std::{collections::HashSet, sync::Arc};
 
-use crate::ServerResult;
+use crate::{extract::Json, utils, ServerResult};
 use barreleye_common::{
 	models::{BasicModel, PrimaryId, Token, TokenColumn},
 	App,
@@ -24,6 +24,8 @@ pub async fn handler(
 		return Ok(StatusCode::NO_CONTENT);
 	}
 
+	utils::check_bulk_size("tokens", payload.tokens.len(), app.settings.max_bulk_size)?;
+
 	// get all tokens
 	let all_tokens = Token::get_all_where(app.db(), TokenColumn::Id.is_in(payload.tokens)).await?;
 
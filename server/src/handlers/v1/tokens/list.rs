@@ -1,9 +1,11 @@
-use axum::{extract::State, Json};
-use axum_extra::extract::Query;
+use axum::extract::State;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::ServerResult;
+use crate::{
+	extract::{Json, Query},
+	ServerResult,
+};
 use barreleye_common::{
 	models::{BasicModel, Network, PrimaryId, Token},
 	utils, App,
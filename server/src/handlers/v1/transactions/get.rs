@@ -0,0 +1,267 @@
+use axum::extract::{Path, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	ServerResult,
+};
+use barreleye_common::{
+	chain::{BoxedChain, JsonAmount},
+	models::{Network, SoftDeleteModel, Transfer},
+	App, BlockHeight, Cache, Warehouse,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	network: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseTransfer {
+	/// `None` while the transaction is unconfirmed
+	block_height: Option<BlockHeight>,
+	tx_hash: String,
+	from: String,
+	to: String,
+	asset: String,
+	amount: JsonAmount,
+	fee: JsonAmount,
+	created_at: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	indexed: bool,
+	transfers: Vec<ResponseTransfer>,
+}
+
+/// Transfers of a transaction, from the warehouse if its block is indexed or else straight
+/// from the node
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Path(hash): Path<String>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	// the same hash can exist on several networks
+	let network = Network::get_existing_by_id(app.db(), &payload.network).await?.ok_or(
+		ServerError::InvalidParam { field: "network".into(), value: payload.network.into() },
+	)?;
+
+	let chain = app.networks.read().await.get(&network.network_id).cloned();
+
+	let (indexed, transfers) =
+		get_transfers(&app.warehouse, app.cache.clone(), &network, chain, &hash).await?;
+
+	Ok(Response {
+		indexed,
+		transfers: transfers
+			.into_iter()
+			.map(|t| ResponseTransfer {
+				block_height: (indexed || t.block_height > 0).then_some(t.block_height),
+				tx_hash: t.tx_hash,
+				from: t.from_address,
+				to: t.to_address,
+				asset: t.asset_address,
+				amount: JsonAmount::new(t.relative_amount, app.settings.numeric_amounts),
+				fee: JsonAmount::new(t.fee, app.settings.numeric_amounts),
+				created_at: t.created_at,
+			})
+			.collect(),
+	}
+	.into())
+}
+
+/// Recorded transfers when there are any, otherwise whatever the network's node resolves.
+/// The flag is whether they came from the warehouse
+async fn get_transfers(
+	warehouse: &Warehouse,
+	cache: Arc<Cache>,
+	network: &Network,
+	chain: Option<Arc<BoxedChain>>,
+	hash: &str,
+) -> ServerResult<'static, (bool, Vec<Transfer>)> {
+	// hashes are stored without the `0x` prefix, in lowercase
+	let hash = hash.trim().trim_start_matches("0x").to_lowercase();
+
+	let transfers = Transfer::get_all_by_tx_hash(warehouse, network.network_id, &hash).await?;
+	if !transfers.is_empty() {
+		return Ok((true, transfers));
+	}
+
+	match chain {
+		Some(chain) if chain.is_connected() => chain
+			.get_transfers_by_tx_hash(cache, &hash)
+			.await?
+			.map(|transfers| (false, transfers))
+			.ok_or(ServerError::NotFound),
+		_ => Err(ServerError::InvalidService { name: network.name.clone().into() }),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_trait::async_trait;
+	use barreleye_common::{
+		chain::{ChainError, ChainTrait, ModuleId, WarehouseData, U256},
+		testing::{self, MockDriver},
+		Db, RateLimiter, Storage,
+	};
+
+	/// Node that only knows about the transactions it's given
+	struct MockChain {
+		network: Network,
+		transfers: Vec<Transfer>,
+	}
+
+	#[async_trait]
+	impl ChainTrait for MockChain {
+		async fn connect(&mut self) -> eyre::Result<bool> {
+			Ok(true)
+		}
+
+		fn is_connected(&self) -> bool {
+			true
+		}
+
+		fn get_network(&self) -> Network {
+			self.network.clone()
+		}
+
+		fn get_rpc(&self) -> Option<String> {
+			None
+		}
+
+		fn get_module_ids(&self) -> Vec<ModuleId> {
+			vec![ModuleId::BitcoinTransfer]
+		}
+
+		fn format_address(&self, address: &str) -> String {
+			address.to_string()
+		}
+
+		fn is_valid_address(&self, _address: &str) -> bool {
+			true
+		}
+
+		fn get_rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+			None
+		}
+
+		async fn get_block_height(&self) -> eyre::Result<BlockHeight> {
+			Ok(0)
+		}
+
+		async fn process_block(
+			&self,
+			_storage: Arc<Storage>,
+			_cache: Arc<Cache>,
+			_block_height: BlockHeight,
+			_modules: Vec<ModuleId>,
+		) -> Result<Option<WarehouseData>, ChainError> {
+			Ok(None)
+		}
+
+		async fn extract_block(
+			&self,
+			_storage: Arc<Storage>,
+			_block_height: BlockHeight,
+		) -> Result<bool, ChainError> {
+			Ok(false)
+		}
+
+		async fn get_transfers_by_tx_hash(
+			&self,
+			_cache: Arc<Cache>,
+			tx_hash: &str,
+		) -> eyre::Result<Option<Vec<Transfer>>> {
+			if tx_hash == "ee" {
+				eyre::bail!("rpc request timed out");
+			}
+
+			let transfers =
+				self.transfers.iter().filter(|t| t.tx_hash == tx_hash).cloned().collect::<Vec<_>>();
+
+			Ok((!transfers.is_empty()).then_some(transfers))
+		}
+	}
+
+	#[tokio::test]
+	async fn test_get_transfers() -> eyre::Result<()> {
+		let db = Db::new_in_memory().await?;
+		let cache = Arc::new(Cache::new(10));
+		let warehouse = MockDriver::default()
+			.with_select("transfers", |query, transfers: Vec<Transfer>| {
+				// lookups match on the transaction hash
+				transfers
+					.into_iter()
+					.filter(|t| query.contains(&format!("tx_hash = '{}'", t.tx_hash)))
+					.collect()
+			})
+			.warehouse(10);
+
+		let network = testing::create_network(db.get(), "net_a", 6).await?;
+		let network_id = network.network_id;
+
+		let new_transfer = |block_height, tx_hash| {
+			Transfer::new(
+				ModuleId::BitcoinTransfer,
+				network_id,
+				block_height,
+				tx_hash,
+				"from",
+				"to",
+				None,
+				U256::from(1),
+				U256::from(1),
+				0,
+			)
+		};
+
+		let indexed = new_transfer(1, "aa");
+		let unindexed = new_transfer(0, "bb");
+		Transfer::create_many(&warehouse, vec![indexed.clone()]).await?;
+
+		let chain: Arc<BoxedChain> = Arc::new(Box::new(MockChain {
+			network: network.clone(),
+			transfers: vec![indexed.clone(), unindexed.clone()],
+		}));
+
+		// indexed hashes come from the warehouse, whatever the prefix or case
+		let (is_indexed, transfers) =
+			get_transfers(&warehouse, cache.clone(), &network, Some(chain.clone()), "0xAA").await?;
+		assert!(is_indexed);
+		assert_eq!(transfers, vec![indexed]);
+
+		// unindexed ones fall back to the node
+		let (is_indexed, transfers) =
+			get_transfers(&warehouse, cache.clone(), &network, Some(chain.clone()), "bb").await?;
+		assert!(!is_indexed);
+		assert_eq!(transfers, vec![unindexed]);
+
+		// unknown to either
+		assert!(matches!(
+			get_transfers(&warehouse, cache.clone(), &network, Some(chain.clone()), "cc").await,
+			Err(ServerError::NotFound)
+		));
+
+		// a node that fails isn't the same as one that doesn't know the transaction
+		assert!(matches!(
+			get_transfers(&warehouse, cache.clone(), &network, Some(chain), "ee").await,
+			Err(ServerError::Internal { .. })
+		));
+
+		// no node to fall back to
+		assert!(matches!(
+			get_transfers(&warehouse, cache, &network, None, "bb").await,
+			Err(ServerError::InvalidService { .. })
+		));
+
+		Ok(())
+	}
+}
@@ -0,0 +1,131 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	utils, ServerResult,
+};
+use barreleye_common::{
+	chain::JsonAmount,
+	models::{Network, PrimaryId, SoftDeleteModel, Transfer, TransferCursor},
+	App, BlockHeight, TRANSFERS_DEFAULT_LIMIT, TRANSFERS_MAX_LIMIT,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	from: String,
+	to: String,
+	network: Option<String>,
+	block_height_min: Option<BlockHeight>,
+	block_height_max: Option<BlockHeight>,
+	created_at_min: Option<u32>,
+	created_at_max: Option<u32>,
+	cursor: Option<String>,
+	limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseTransfer {
+	network: Option<String>,
+	block_height: BlockHeight,
+	tx_hash: String,
+	from: String,
+	to: String,
+	asset: String,
+	amount: JsonAmount,
+	fee: JsonAmount,
+	created_at: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	transfers: Vec<ResponseTransfer>,
+	next_cursor: Option<String>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let (from, to) = (payload.from.trim(), payload.to.trim());
+	if from.is_empty() || to.is_empty() {
+		return Err(ServerError::MissingInputParams);
+	}
+
+	// reject addresses that can't belong to any configured network
+	let from = utils::format_address(&app, "from", from).await?;
+	let to = utils::format_address(&app, "to", to).await?;
+
+	// resolve optional network filter
+	let network_id = match payload.network {
+		Some(id) => Some(
+			Network::get_existing_by_id(app.db(), &id)
+				.await?
+				.ok_or(ServerError::InvalidParam { field: "network".into(), value: id.into() })?
+				.network_id,
+		),
+		_ => None,
+	};
+
+	let block_range = match (payload.block_height_min, payload.block_height_max) {
+		(None, None) => None,
+		(min, max) => Some((min.unwrap_or(0), max.unwrap_or(BlockHeight::MAX))),
+	};
+
+	let time_range = match (payload.created_at_min, payload.created_at_max) {
+		(None, None) => None,
+		(min, max) => Some((min.unwrap_or(0), max.unwrap_or(u32::MAX))),
+	};
+
+	let cursor = match payload.cursor {
+		Some(cursor) => {
+			Some(TransferCursor::decode(&cursor).ok_or(ServerError::InvalidParam {
+				field: "cursor".into(),
+				value: cursor.into(),
+			})?)
+		}
+		_ => None,
+	};
+
+	let limit = payload.limit.unwrap_or(TRANSFERS_DEFAULT_LIMIT);
+	if !(1..=TRANSFERS_MAX_LIMIT).contains(&limit) {
+		return Err(ServerError::InvalidParam {
+			field: "limit".into(),
+			value: limit.to_string().into(),
+		});
+	}
+
+	let (transfers, next_cursor) = Transfer::get_between_addresses(
+		&app.warehouse,
+		(&from, &to),
+		network_id,
+		block_range,
+		time_range,
+		cursor,
+		limit,
+	)
+	.await?;
+
+	let networks = app.networks.read().await;
+	let transfers = transfers
+		.into_iter()
+		.map(|t| ResponseTransfer {
+			network: networks.get(&(t.network_id as PrimaryId)).map(|c| c.get_network().id),
+			block_height: t.block_height,
+			tx_hash: t.tx_hash,
+			from: t.from_address,
+			to: t.to_address,
+			asset: t.asset_address,
+			amount: JsonAmount::new(t.relative_amount, app.settings.numeric_amounts),
+			fee: JsonAmount::new(t.fee, app.settings.numeric_amounts),
+			created_at: t.created_at,
+		})
+		.collect();
+
+	Ok(Response { transfers, next_cursor: next_cursor.map(|c| c.encode()) }.into())
+}
@@ -0,0 +1,11 @@
+use axum::{routing::get, Router};
+use std::sync::Arc;
+
+use barreleye_common::App;
+
+mod list;
+mod pending;
+
+pub fn get_routes() -> Router<Arc<App>> {
+	Router::new().route("/", get(list::handler)).route("/pending", get(pending::handler))
+}
@@ -0,0 +1,99 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+	errors::ServerError,
+	extract::{Json, Query},
+	utils, ServerResult,
+};
+use barreleye_common::{
+	chain::{JsonAmount, U256},
+	models::{Network, PendingTransfer, SoftDeleteModel},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	address: String,
+	network: Option<String>,
+	offset: Option<u64>,
+	limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseTransfer {
+	network: Option<String>,
+	tx_hash: String,
+	from: String,
+	to: String,
+	asset: String,
+	amount: JsonAmount,
+	fee: JsonAmount,
+	seen_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	transfers: Vec<ResponseTransfer>,
+}
+
+/// Unconfirmed transfers to or from an address, as last seen in the mempool (newest first)
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Query(payload): Query<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	let address = payload.address.trim();
+	if address.is_empty() {
+		return Err(ServerError::MissingInputParams);
+	}
+
+	// reject addresses that can't belong to any configured network
+	let address = utils::format_address(&app, "address", address).await?;
+
+	// resolve optional network filter
+	let network_id = match payload.network {
+		Some(id) => Some(
+			Network::get_existing_by_id(app.db(), &id)
+				.await?
+				.ok_or(ServerError::InvalidParam { field: "network".into(), value: id.into() })?
+				.network_id,
+		),
+		_ => None,
+	};
+
+	let pending_transfers = PendingTransfer::get_all_by_address(
+		app.db(),
+		&address,
+		network_id,
+		payload.offset,
+		payload.limit,
+	)
+	.await?;
+
+	let networks = app.networks.read().await;
+	let transfers = pending_transfers
+		.into_iter()
+		.map(|t| ResponseTransfer {
+			network: networks.get(&t.network_id).map(|c| c.get_network().id),
+			tx_hash: t.tx_hash,
+			from: t.from_address,
+			to: t.to_address,
+			asset: t.asset_address,
+			amount: JsonAmount::new(
+				U256::from_dec_str(&t.amount).unwrap_or_default(),
+				app.settings.numeric_amounts,
+			),
+			fee: JsonAmount::new(
+				U256::from_dec_str(&t.fee).unwrap_or_default(),
+				app.settings.numeric_amounts,
+			),
+			seen_at: t.created_at.and_utc().timestamp(),
+		})
+		.collect();
+
+	Ok(Response { transfers }.into())
+}
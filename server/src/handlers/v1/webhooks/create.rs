@@ -0,0 +1,92 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use url::Url;
+
+use crate::{errors::ServerError, extract::Json, ServerResult};
+use barreleye_common::{
+	models::{is_valid_id, BasicModel, Webhook},
+	notifier::{is_public_url, PublicResolver},
+	App, IdPrefix,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	id: Option<String>,
+	url: String,
+}
+
+/// The secret is only ever returned here, when the webhook is created
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+	#[serde(flatten)]
+	webhook: Webhook,
+	secret: String,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, Json<Response>> {
+	// check that id is valid
+	if let Some(id) = payload.id.clone() {
+		if !is_valid_id(&id, IdPrefix::Webhook) ||
+			Webhook::get_by_id(app.db(), &id).await?.is_some()
+		{
+			return Err(ServerError::InvalidParam { field: "id".into(), value: id.into() });
+		}
+	}
+
+	// check that url is valid
+	if !is_valid_url(&payload.url).await {
+		return Err(ServerError::InvalidParam { field: "url".into(), value: payload.url.into() });
+	}
+
+	// create new, generating an id unless one was given
+	let webhook_id = Webhook::create_with_id(
+		app.db(),
+		payload.id,
+		IdPrefix::Webhook,
+		app.settings.id_length,
+		|id| Webhook::new_model(Some(id), &payload.url),
+	)
+	.await?;
+
+	// return newly created
+	let webhook = Webhook::get(app.db(), webhook_id).await?.unwrap();
+	Ok(Response { secret: webhook.secret.clone(), webhook }.into())
+}
+
+/// Webhooks can only point at public addresses, so they can't be used to reach internal
+/// services
+async fn is_valid_url(url: &str) -> bool {
+	if !is_public_url(url) {
+		return false;
+	}
+
+	match Url::parse(url).ok().and_then(|u| u.domain().map(|d| d.to_string())) {
+		Some(domain) => PublicResolver::lookup(&domain).await.is_ok(),
+		None => true,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_is_valid_url() {
+		for (url, is_valid) in [
+			("https://1.1.1.1/hook", true),
+			("http://127.0.0.1:8080/hook", false),
+			("http://localhost:8080/hook", false),
+			("http://169.254.169.254/latest/meta-data", false),
+			("http://[fd00::1]/hook", false),
+			("ftp://1.1.1.1/hook", false),
+		] {
+			assert_eq!(is_valid_url(url).await, is_valid, "{url}");
+		}
+	}
+}
@@ -0,0 +1,37 @@
+use axum::{extract::State, http::StatusCode};
+use sea_orm::ColumnTrait;
+use serde::Deserialize;
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{extract::Json, utils, ServerResult};
+use barreleye_common::{
+	models::{BasicModel, Webhook, WebhookColumn},
+	App,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+	webhooks: HashSet<String>,
+}
+
+pub async fn handler(
+	State(app): State<Arc<App>>,
+	Json(payload): Json<Payload>,
+) -> ServerResult<'static, StatusCode> {
+	// exit if no input
+	if payload.webhooks.is_empty() {
+		return Ok(StatusCode::NO_CONTENT);
+	}
+
+	utils::check_bulk_size("webhooks", payload.webhooks.len(), app.settings.max_bulk_size)?;
+
+	// delete all webhooks
+	Webhook::delete_all_where(
+		app.db(),
+		WebhookColumn::Id.is_in(payload.webhooks.into_iter().collect::<Vec<String>>()),
+	)
+	.await?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
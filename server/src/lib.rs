@@ -1,28 +1,182 @@
 use axum::{
-	error_handling::HandleErrorLayer,
-	extract::{Request, State},
-	http::{header, Method, StatusCode, Uri},
+	body::{self, Body, HttpBody},
+	extract::{DefaultBodyLimit, Request, State},
+	http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
 	middleware::{self, Next},
-	response::Response,
-	BoxError, Router,
+	response::{IntoResponse, Response},
+	Router,
 };
 use eyre::{Report, Result};
-use signal::unix::SignalKind;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::{net::TcpListener, signal};
-use tower::ServiceBuilder;
-use tower_http::{trace, trace::TraceLayer, LatencyUnit};
-use tracing::{info, warn, Level};
+use futures::{stream, StreamExt};
+use sea_orm::ConnectionTrait;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	future::{pending, Future, IntoFuture},
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
+};
+use tokio::{
+	net::TcpListener,
+	sync::watch,
+	time::{self, sleep},
+};
+use tower::{
+	layer::util::{Identity, Stack},
+	ServiceBuilder,
+};
+use tower_http::{
+	cors::{AllowOrigin, CorsLayer},
+	limit::RequestBodyLimitLayer,
+	request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+	trace::{MakeSpan, TraceLayer},
+};
+use tracing::{info, span, warn, Level, Span};
 
-use crate::errors::ServerError;
-use barreleye_common::{models::ApiKey, quit, App, AppError};
+use crate::errors::{ErrorCause, ServerError};
+use barreleye_common::{
+	cache::CacheKey,
+	models::{ApiKey, Config},
+	quit, shutdown_signal, utils as common_utils, App, AppError, Cache, IDEMPOTENCY_KEY_TTL,
+};
 
 mod errors;
+mod extract;
 mod handlers;
 mod utils;
 
 pub type ServerResult<'a, T> = Result<T, ServerError<'a>>;
 
+type RequestIdLayer = ServiceBuilder<
+	Stack<PropagateRequestIdLayer, Stack<SetRequestIdLayer<MakeRequestUuid>, Identity>>,
+>;
+
+type BodyLimitLayer =
+	ServiceBuilder<Stack<RequestBodyLimitLayer, Stack<DefaultBodyLimit, Identity>>>;
+
+pub static REQUEST_ID_HEADER: &str = "x-request-id";
+pub static IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Seconds a (non-streaming) request has to respond
+const REQUEST_TIMEOUT: u64 = 30;
+
+/// Public id of the api key a request was authenticated with (set on both the request
+/// and the response)
+#[derive(Clone)]
+struct ApiKeyId(String);
+
+/// What's stored for an `Idempotency-Key`: the request it was first used with, and its
+/// response once there is one (replayed on retries)
+#[derive(Serialize, Deserialize)]
+struct IdempotentResponse {
+	fingerprint: String,
+	status: Option<u16>,
+	content_type: Option<String>,
+	body: String,
+	expires_at: i64,
+}
+
+/// Releases a claimed `Idempotency-Key` when dropped, unless its response got stored. So
+/// failed, panicked or abandoned requests can be retried right away
+struct IdempotencyClaim {
+	cache: Arc<Cache>,
+	cache_key: CacheKey,
+	is_done: bool,
+}
+
+impl Drop for IdempotencyClaim {
+	fn drop(&mut self) {
+		if !self.is_done {
+			self.cache.delete(&self.cache_key);
+		}
+	}
+}
+
+/// Opens a span per request at the configured level, with only the allowed headers (so
+/// credentials, whatever header they come in, stay out of the logs)
+#[derive(Clone)]
+struct MakeRequestSpan {
+	level: Level,
+	logged_headers: Arc<Vec<HeaderName>>,
+}
+
+impl MakeRequestSpan {
+	fn new(level: Level, logged_headers: &[String]) -> Self {
+		let logged_headers =
+			logged_headers.iter().filter_map(|h| h.parse::<HeaderName>().ok()).collect();
+
+		Self { level, logged_headers: Arc::new(logged_headers) }
+	}
+}
+
+impl<B> MakeSpan<B> for MakeRequestSpan {
+	fn make_span(&mut self, req: &Request<B>) -> Span {
+		let request_id =
+			req.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()).unwrap_or_default();
+
+		let mut headers = HeaderMap::new();
+		for header_name in self.logged_headers.iter() {
+			for value in req.headers().get_all(header_name).iter() {
+				headers.append(header_name.clone(), value.clone());
+			}
+		}
+
+		// span levels have to be known at compile time
+		macro_rules! request_span {
+			($level:expr) => {
+				span!(
+					$level,
+					"request",
+					method = %req.method(),
+					uri = %req.uri(),
+					version = ?req.version(),
+					request_id,
+					headers = ?headers,
+				)
+			};
+		}
+
+		match self.level {
+			Level::ERROR => request_span!(Level::ERROR),
+			Level::WARN => request_span!(Level::WARN),
+			Level::INFO => request_span!(Level::INFO),
+			Level::DEBUG => request_span!(Level::DEBUG),
+			Level::TRACE => request_span!(Level::TRACE),
+		}
+	}
+}
+
+/// Requests currently being handled, so the ones cut off by a shutdown can be logged
+#[derive(Default)]
+struct InFlight {
+	next_id: AtomicU64,
+	requests: Mutex<HashMap<u64, String>>,
+}
+
+impl InFlight {
+	fn get_all(&self) -> Vec<String> {
+		let mut requests = self.requests.lock().unwrap().values().cloned().collect::<Vec<_>>();
+		requests.sort();
+		requests
+	}
+}
+
+/// Removes its request from `InFlight` when dropped, ie: whether it completes or not
+struct InFlightGuard {
+	in_flight: Arc<InFlight>,
+	id: u64,
+}
+
+impl Drop for InFlightGuard {
+	fn drop(&mut self) {
+		self.in_flight.requests.lock().unwrap().remove(&self.id);
+	}
+}
+
 pub struct Server {
 	app: Arc<App>,
 }
@@ -37,14 +191,20 @@ impl Server {
 		req: Request,
 		next: Next,
 	) -> ServerResult<'static, Response> {
-		if ApiKey::count(app.db()).await? == 0 {
-			return Ok(next.run(req).await);
-		}
+		Self::authenticate(app.db(), app.settings.require_auth, req, next).await
+	}
 
-		for public_endpoint in ["/v1/info"].iter() {
-			if req.uri().to_string().starts_with(public_endpoint) {
-				return Ok(next.run(req).await);
-			}
+	async fn authenticate<C>(
+		c: &C,
+		require_auth: bool,
+		mut req: Request,
+		next: Next,
+	) -> ServerResult<'static, Response>
+	where
+		C: ConnectionTrait,
+	{
+		if !Self::is_auth_required(c, require_auth, req.method(), req.uri()).await? {
+			return Ok(next.run(req).await);
 		}
 
 		let authorization = req
@@ -59,21 +219,368 @@ impl Server {
 			_ => return Err(ServerError::Unauthorized),
 		};
 
-		match ApiKey::get_by_hashing(app.db(), &token)
-			.await
-			.map_err(|_| ServerError::Unauthorized)?
-		{
+		match ApiKey::get_by_hashing(c, &token).await.map_err(|_| ServerError::Unauthorized)? {
 			Some(api_key) if api_key.is_active => {
 				if api_key.secret_key.is_some() {
-					ApiKey::hide_key(app.db(), api_key.api_key_id).await?;
+					ApiKey::hide_key(c, api_key.api_key_id).await?;
+				}
+				// bookkeeping only, so it's not worth failing the request over
+				if let Err(e) = ApiKey::touch(c, &api_key).await {
+					warn!(api_key = api_key.id, error = %e, "could not record api key usage");
 				}
 
-				Ok(next.run(req).await)
+				req.extensions_mut().insert(ApiKeyId(api_key.id.clone()));
+
+				let mut res = next.run(req).await;
+				res.extensions_mut().insert(ApiKeyId(api_key.id));
+
+				Ok(res)
 			}
 			_ => Err(ServerError::Unauthorized),
 		}
 	}
 
+	/// Public endpoints are always open. Everything else is too until the first api key is
+	/// created, unless `require_auth` is set. Even then, creating that first key stays open
+	/// (otherwise there'd be no way to ever authenticate)
+	async fn is_auth_required<C>(
+		c: &C,
+		require_auth: bool,
+		method: &Method,
+		uri: &Uri,
+	) -> Result<bool>
+	where
+		C: ConnectionTrait,
+	{
+		for public_endpoint in ["/v1/info"].iter() {
+			if uri.to_string().starts_with(public_endpoint) {
+				return Ok(false);
+			}
+		}
+
+		if ApiKey::count(c).await? > 0 {
+			return Ok(true);
+		}
+
+		let is_first_key = method == Method::POST && uri.path() == "/v1/keys";
+		Ok(require_auth && !is_first_key)
+	}
+
+	/// Any successful change through the api (entities, tags, addresses, tokens, networks…)
+	/// can show up in info responses, so it makes the cached ones stale
+	async fn info_version(
+		State(app): State<Arc<App>>,
+		req: Request,
+		next: Next,
+	) -> ServerResult<'static, Response> {
+		Self::bump_info_version_on_change(app.db(), req, next).await
+	}
+
+	async fn bump_info_version_on_change<C>(
+		c: &C,
+		req: Request,
+		next: Next,
+	) -> ServerResult<'static, Response>
+	where
+		C: ConnectionTrait,
+	{
+		let is_change = ![Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method());
+
+		let res = next.run(req).await;
+		if is_change && res.status().is_success() {
+			Config::bump_info_version(c).await?;
+		}
+
+		Ok(res)
+	}
+
+	/// Replays the stored response when a POST/DELETE is retried with the same
+	/// `Idempotency-Key` (scoped per api key) within `IDEMPOTENCY_KEY_TTL`. Claiming the key
+	/// is what lets a request through, so concurrent retries can't both run. Keys are tied
+	/// to the method, path and body they were first used with, and only successful
+	/// responses are stored, so failed requests can still be retried
+	async fn idempotency(
+		State(cache): State<Arc<Cache>>,
+		req: Request,
+		next: Next,
+	) -> ServerResult<'static, Response> {
+		if ![Method::POST, Method::DELETE].contains(req.method()) {
+			return Ok(next.run(req).await);
+		}
+
+		let Some(idempotency_key) = req
+			.headers()
+			.get(IDEMPOTENCY_KEY_HEADER)
+			.and_then(|v| v.to_str().ok())
+			.map(|v| v.to_string())
+		else {
+			return Ok(next.run(req).await);
+		};
+
+		let api_key_id = req.extensions().get::<ApiKeyId>().map(|k| k.0.clone());
+		let cache_key = CacheKey::Idempotency(api_key_id.unwrap_or_default(), idempotency_key);
+		let now = common_utils::now().and_utc().timestamp();
+
+		// the body is read upfront, since it's part of the fingerprint
+		let (parts, body) = req.into_parts();
+		let bytes =
+			body::to_bytes(body, usize::MAX).await.map_err(|_| ServerError::PayloadTooLarge)?;
+		let fingerprint = common_utils::sha256(&format!(
+			"{} {}\n{}",
+			parts.method,
+			parts.uri.path(),
+			String::from_utf8_lossy(&bytes)
+		))
+		.iter()
+		.map(|b| format!("{b:02x}"))
+		.collect::<String>();
+
+		// claim the key, unless a live claim already holds it
+		let claim = IdempotentResponse {
+			fingerprint: fingerprint.clone(),
+			status: None,
+			content_type: None,
+			body: String::new(),
+			expires_at: now + IDEMPOTENCY_KEY_TTL,
+		};
+		if let Some(stored) = cache.set_if_absent(&cache_key, claim, |v| v.expires_at > now) {
+			if stored.fingerprint != fingerprint {
+				return Err(ServerError::BadRequest {
+					reason: "idempotency key was already used with a different request".into(),
+				});
+			}
+
+			let Some(status) = stored.status else {
+				return Err(ServerError::TooEarly {
+					reason: "a request with this idempotency key is still in progress".into(),
+				});
+			};
+
+			let mut res = Response::builder().status(status);
+			if let Some(content_type) = stored.content_type {
+				res = res.header(header::CONTENT_TYPE, content_type);
+			}
+
+			return res
+				.body(Body::from(stored.body))
+				.map_err(|e| ServerError::Internal { error: e.into() });
+		}
+
+		let mut claim = IdempotencyClaim { cache: cache.clone(), cache_key, is_done: false };
+
+		let res = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+		if !res.status().is_success() {
+			return Ok(res);
+		}
+
+		let (parts, body) = res.into_parts();
+		let bytes = body::to_bytes(body, usize::MAX)
+			.await
+			.map_err(|e| ServerError::Internal { error: e.into() })?;
+
+		if let Ok(body) = String::from_utf8(bytes.to_vec()) {
+			cache.set(
+				&claim.cache_key,
+				IdempotentResponse {
+					fingerprint,
+					status: Some(parts.status.as_u16()),
+					content_type: parts
+						.headers
+						.get(header::CONTENT_TYPE)
+						.and_then(|v| v.to_str().ok())
+						.map(|v| v.to_string()),
+					body,
+					expires_at: now + IDEMPOTENCY_KEY_TTL,
+				},
+			);
+			claim.is_done = true;
+		}
+
+		Ok(Response::from_parts(parts, Body::from(bytes)))
+	}
+
+	/// Takes `X-Request-Id` from the request (or generates one) and echoes it back
+	/// in the response, so clients can quote it in support tickets
+	fn request_id_layer() -> RequestIdLayer {
+		ServiceBuilder::new()
+			.layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+			.layer(PropagateRequestIdLayer::x_request_id())
+	}
+
+	/// Replaces axum's default (per-extractor) limit with one that covers every request,
+	/// so oversized payloads are rejected with a 413 before they're buffered
+	fn body_limit_layer(max_body_size: usize) -> BodyLimitLayer {
+		ServiceBuilder::new()
+			.layer(DefaultBodyLimit::disable())
+			.layer(RequestBodyLimitLayer::new(max_body_size))
+	}
+
+	/// Handles CORS (preflights included) ahead of `auth`, so preflights that carry no
+	/// credentials aren't rejected. With no allowed origins, no CORS headers are sent
+	fn cors_layer(origins: &[String], methods: &[String], headers: &[String]) -> CorsLayer {
+		let allow_origin = if origins.iter().any(|o| o == "*") {
+			AllowOrigin::any()
+		} else {
+			AllowOrigin::list(origins.iter().filter_map(|o| o.parse::<HeaderValue>().ok()))
+		};
+
+		CorsLayer::new()
+			.allow_origin(allow_origin)
+			.allow_methods(
+				methods.iter().filter_map(|m| m.parse::<Method>().ok()).collect::<Vec<_>>(),
+			)
+			.allow_headers(
+				headers.iter().filter_map(|h| h.parse::<HeaderName>().ok()).collect::<Vec<_>>(),
+			)
+			.expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+	}
+
+	async fn access_log(req: Request, next: Next) -> Response {
+		let method = req.method().clone();
+		let path = req.uri().path().to_string();
+		let request_id = req
+			.headers()
+			.get(REQUEST_ID_HEADER)
+			.and_then(|v| v.to_str().ok())
+			.unwrap_or_default()
+			.to_string();
+		let started_at = Instant::now();
+
+		let res = next.run(req).await;
+
+		let api_key_id =
+			res.extensions().get::<ApiKeyId>().map(|k| k.0.as_str()).unwrap_or_default();
+		info!(
+			method = %method,
+			path = %path,
+			status = res.status().as_u16(),
+			latency_ms = started_at.elapsed().as_millis() as u64,
+			request_id = %request_id,
+			api_key_id,
+			"access"
+		);
+
+		res
+	}
+
+	/// Internal errors show their underlying cause instead of a generic message when
+	/// `is_debug`. Meant for local debugging only, since causes can include queries, paths, etc
+	async fn debug_errors(State(is_debug): State<bool>, req: Request, next: Next) -> Response {
+		let res = next.run(req).await;
+
+		match res.extensions().get::<ErrorCause>() {
+			Some(cause) if is_debug => cause.clone().into_response(),
+			_ => res,
+		}
+	}
+
+	/// Times out `routes` that take longer than `timeout` to respond in full, body included.
+	/// Streaming routes are left out, since their responses are meant to stay open
+	fn with_timeout<S>(
+		routes: Router<S>,
+		streaming_routes: Router<S>,
+		timeout: Duration,
+	) -> Router<S>
+	where
+		S: Clone + Send + Sync + 'static,
+	{
+		routes
+			.layer(middleware::from_fn_with_state(timeout, Self::request_timeout))
+			.merge(streaming_routes)
+	}
+
+	async fn request_timeout(
+		State(timeout): State<Duration>,
+		req: Request,
+		next: Next,
+	) -> Response {
+		let deadline = time::Instant::now() + timeout;
+		let error = format!("`{} {}` timed out", req.method(), req.uri());
+
+		let Ok(res) = time::timeout_at(deadline, next.run(req)).await else {
+			return ServerError::Internal { error: Report::msg(error) }.into_response();
+		};
+
+		// a body that's already in full goes out as is
+		let (parts, body) = res.into_parts();
+		if body.size_hint().exact().is_some() {
+			return Response::from_parts(parts, body);
+		}
+
+		// one still being produced by the deadline errors out, which drops the connection
+		// (rather than passing for a complete response)
+		let state = Some((body.into_data_stream(), Box::pin(time::sleep_until(deadline))));
+		let body = stream::unfold(state, move |state| {
+			let error = error.clone();
+			async move {
+				let (mut body, mut deadline) = state?;
+				tokio::select! {
+					chunk = body.next() => Some((chunk?, Some((body, deadline)))),
+					_ = &mut deadline => Some((Err(axum::Error::new(error)), None)),
+				}
+			}
+		});
+
+		Response::from_parts(parts, Body::from_stream(body))
+	}
+
+	async fn track_in_flight(
+		State(in_flight): State<Arc<InFlight>>,
+		req: Request,
+		next: Next,
+	) -> Response {
+		let id = in_flight.next_id.fetch_add(1, Ordering::Relaxed);
+		in_flight.requests.lock().unwrap().insert(id, format!("{} {}", req.method(), req.uri()));
+		let _guard = InFlightGuard { in_flight, id };
+
+		next.run(req).await
+	}
+
+	/// Serves until `signal` fires, then lets in-flight requests finish for up to
+	/// `grace_period` (`None` waits for them indefinitely) before abandoning them
+	async fn serve<F>(
+		listener: TcpListener,
+		router: Router,
+		signal: F,
+		grace_period: Option<Duration>,
+	) -> Result<()>
+	where
+		F: Future<Output = ()> + Send + 'static,
+	{
+		let in_flight = Arc::new(InFlight::default());
+		let router =
+			router.layer(middleware::from_fn_with_state(in_flight.clone(), Self::track_in_flight));
+
+		let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+		let signal = async move {
+			signal.await;
+			let _ = shutdown_tx.send(true);
+		};
+
+		let deadline = async move {
+			if shutdown_rx.wait_for(|is_shutting_down| *is_shutting_down).await.is_err() {
+				pending::<()>().await;
+			}
+
+			match grace_period {
+				Some(grace_period) => sleep(grace_period).await,
+				None => pending().await,
+			}
+		};
+
+		tokio::select! {
+			res = axum::serve(listener, router).with_graceful_shutdown(signal).into_future() => {
+				res?
+			}
+			_ = deadline => {
+				let requests = in_flight.get_all();
+				warn!(?requests, "shutdown grace period is over, abandoning requests");
+			}
+		}
+
+		Ok(())
+	}
+
 	#[tracing::instrument(name = "server", skip_all)]
 	pub async fn start(&self) -> Result<()> {
 		let settings = self.app.settings.clone();
@@ -82,33 +589,37 @@ impl Server {
 			Err(ServerError::NotFound)
 		}
 
-		async fn handle_timeout_error(
-			method: Method,
-			uri: Uri,
-			_err: BoxError,
-		) -> ServerResult<'static, StatusCode> {
-			Err(ServerError::Internal { error: Report::msg(format!("`{method} {uri}` timed out")) })
-		}
-
 		let app = Router::new()
-			.merge(handlers::get_routes())
+			.merge(Self::with_timeout(
+				handlers::get_routes(),
+				handlers::get_streaming_routes(),
+				Duration::from_secs(REQUEST_TIMEOUT),
+			))
+			.route_layer(middleware::from_fn_with_state(self.app.clone(), Self::info_version))
+			.route_layer(middleware::from_fn_with_state(
+				self.app.idempotency_cache.clone(),
+				Self::idempotency,
+			))
 			.route_layer(middleware::from_fn_with_state(self.app.clone(), Self::auth))
 			.fallback(handle_404)
-			.layer(
-				ServiceBuilder::new()
-					.layer(HandleErrorLayer::new(handle_timeout_error))
-					.timeout(Duration::from_secs(30)),
-			)
+			.layer(Self::body_limit_layer(settings.max_body_size))
+			.layer(middleware::from_fn_with_state(settings.debug_errors, Self::debug_errors))
+			.layer(middleware::from_fn(Self::access_log))
 			.layer(
 				TraceLayer::new_for_http()
-					.make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+					.make_span_with(MakeRequestSpan::new(
+						settings.trace_level,
+						&settings.logged_headers,
+					))
 					.on_request(())
-					.on_response(
-						trace::DefaultOnResponse::new()
-							.include_headers(true)
-							.latency_unit(LatencyUnit::Millis),
-					),
+					.on_response(()),
 			)
+			.layer(Self::request_id_layer())
+			.layer(Self::cors_layer(
+				&settings.allowed_origins,
+				&settings.allowed_methods,
+				&settings.allowed_headers,
+			))
 			.with_state(self.app.clone());
 
 		if let Some(ip_addr) = settings.ip_addr {
@@ -146,36 +657,540 @@ impl Server {
 
 			if let Some(listener) = listener {
 				self.app.set_is_ready();
-				axum::serve(listener, app).with_graceful_shutdown(Self::shutdown_signal()).await?;
+				let grace_period = (settings.shutdown_timeout > 0)
+					.then(|| Duration::from_secs(settings.shutdown_timeout));
+				Self::serve(listener, app, shutdown_signal(), grace_period).await?;
 			}
 		}
 
 		Ok(())
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{
+		response::sse::{Event, Sse},
+		routing::{get, post},
+	};
+	use barreleye_common::{models::BasicModel, testing, Db};
+	use std::{convert::Infallible, sync::atomic::AtomicUsize};
+	use tokio::{
+		io::AsyncWriteExt,
+		net::TcpStream,
+		sync::{oneshot, Notify},
+		time::{interval, timeout},
+	};
+	use tower::ServiceExt;
+	use tracing_subscriber::fmt::MakeWriter;
+
+	/// Collects log output, for tests that check what gets logged
+	#[derive(Clone, Default)]
+	struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+	impl std::io::Write for LogBuffer {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> std::io::Result<()> {
+			Ok(())
+		}
+	}
+
+	impl MakeWriter<'_> for LogBuffer {
+		type Writer = Self;
+
+		fn make_writer(&self) -> Self::Writer {
+			self.clone()
+		}
+	}
+
+	#[tokio::test]
+	async fn test_request_id() -> Result<()> {
+		let router =
+			Router::new().route("/", get(|| async { "ok" })).layer(Server::request_id_layer());
+
+		// provided id is echoed
+		let req =
+			Request::builder().uri("/").header(REQUEST_ID_HEADER, "abc").body(Body::empty())?;
+		let res = router.clone().oneshot(req).await?;
+		assert_eq!(res.headers()[REQUEST_ID_HEADER], "abc");
+
+		// otherwise one is generated
+		let req = Request::builder().uri("/").body(Body::empty())?;
+		let res = router.oneshot(req).await?;
+		assert!(res.headers().get(REQUEST_ID_HEADER).is_some_and(|v| !v.is_empty()));
+
+		Ok(())
+	}
 
-	async fn shutdown_signal() {
-		let ctrl_c = async {
-			if signal::ctrl_c().await.is_err() {
-				quit(AppError::SignalHandler);
+	#[tokio::test]
+	async fn test_access_log() -> Result<()> {
+		let buffer = LogBuffer::default();
+		let subscriber = tracing_subscriber::fmt()
+			.json()
+			.flatten_event(true)
+			.with_writer(buffer.clone())
+			.finish();
+		let _guard = tracing::subscriber::set_default(subscriber);
+
+		let router = Router::new()
+			.route("/", get(|| async { "ok" }))
+			.layer(middleware::from_fn(Server::access_log))
+			.layer(Server::request_id_layer());
+
+		let req =
+			Request::builder().uri("/").header(REQUEST_ID_HEADER, "abc").body(Body::empty())?;
+		router.oneshot(req).await?;
+
+		let line = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+		let log: serde_json::Value = serde_json::from_str(line.trim())?;
+
+		assert_eq!(log["message"], "access");
+		assert_eq!(log["method"], "GET");
+		assert_eq!(log["path"], "/");
+		assert_eq!(log["status"], 200);
+		assert!(log["latency_ms"].is_u64());
+		assert_eq!(log["request_id"], "abc");
+		assert_eq!(log["api_key_id"], "");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_logged_headers() -> Result<()> {
+		let buffer = LogBuffer::default();
+		let subscriber = tracing_subscriber::fmt()
+			.json()
+			.flatten_event(true)
+			.with_writer(buffer.clone())
+			.finish();
+		let _guard = tracing::subscriber::set_default(subscriber);
+
+		let make_span = MakeRequestSpan::new(Level::INFO, &["user-agent".to_string()]);
+		let router = Router::new()
+			.route(
+				"/",
+				get(|| async {
+					info!("handled");
+					"ok"
+				}),
+			)
+			.layer(TraceLayer::new_for_http().make_span_with(make_span));
+
+		let req = Request::builder()
+			.uri("/")
+			.header(header::AUTHORIZATION, "Bearer secret_a")
+			.header(header::COOKIE, "session=secret_b")
+			.header(header::PROXY_AUTHORIZATION, "Basic secret_c")
+			.header("x-api-key", "secret_d")
+			.header(header::USER_AGENT, "curl")
+			.body(Body::empty())?;
+		router.oneshot(req).await?;
+
+		let output = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+		assert!(output.contains("handled"));
+		assert!(output.contains("curl"));
+		assert!(!output.contains("secret"));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_is_auth_required() -> Result<()> {
+		let db = Db::new_in_memory().await?;
+		let protected = Uri::from_static("/v1/networks");
+		let public = Uri::from_static("/v1/info?q=abc");
+		let keys = Uri::from_static("/v1/keys");
+
+		// open until the first key is created, unless told otherwise
+		assert!(!Server::is_auth_required(db.get(), false, &Method::GET, &protected).await?);
+		assert!(Server::is_auth_required(db.get(), true, &Method::GET, &protected).await?);
+		assert!(!Server::is_auth_required(db.get(), true, &Method::GET, &public).await?);
+
+		// except for creating the first key
+		assert!(!Server::is_auth_required(db.get(), true, &Method::POST, &keys).await?);
+		assert!(Server::is_auth_required(db.get(), true, &Method::GET, &keys).await?);
+
+		ApiKey::create(db.get(), ApiKey::new_model(None)).await?;
+		assert!(Server::is_auth_required(db.get(), false, &Method::GET, &protected).await?);
+		assert!(Server::is_auth_required(db.get(), true, &Method::POST, &keys).await?);
+		assert!(!Server::is_auth_required(db.get(), false, &Method::GET, &public).await?);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_auth_bootstrap() -> Result<()> {
+		let db = Arc::new(Db::new_in_memory().await?);
+
+		let router = Router::new()
+			.route(
+				"/v1/keys",
+				post(|State(db): State<Arc<Db>>| async move {
+					let api_key_id = ApiKey::create(db.get(), ApiKey::new_model(None)).await?;
+					let api_key = ApiKey::get(db.get(), api_key_id).await?.unwrap();
+					ServerResult::Ok(api_key.secret_key.unwrap())
+				}),
+			)
+			.route("/v1/networks", get(|| async { "ok" }))
+			.route_layer(middleware::from_fn_with_state(
+				db.clone(),
+				|State(db): State<Arc<Db>>, req: Request, next: Next| async move {
+					Server::authenticate(db.get(), true, req, next).await
+				},
+			))
+			.with_state(db);
+
+		let new_request = |method: Method, uri: &str, token: Option<&str>| {
+			let mut req = Request::builder().method(method).uri(uri);
+			if let Some(token) = token {
+				req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
 			}
+			req.body(Body::empty())
 		};
 
-		#[cfg(unix)]
-		let terminate = async {
-			match signal::unix::signal(SignalKind::terminate()) {
-				Ok(mut signal) => {
-					signal.recv().await;
+		// nothing but creating the first key goes through without one
+		let req = new_request(Method::GET, "/v1/networks", None)?;
+		assert_eq!(router.clone().oneshot(req).await?.status(), StatusCode::UNAUTHORIZED);
+
+		let req = new_request(Method::POST, "/v1/keys", None)?;
+		let res = router.clone().oneshot(req).await?;
+		assert_eq!(res.status(), StatusCode::OK);
+		let secret_key =
+			String::from_utf8(body::to_bytes(res.into_body(), usize::MAX).await?.into())?;
+
+		// after that, it's closed again
+		let req = new_request(Method::POST, "/v1/keys", None)?;
+		assert_eq!(router.clone().oneshot(req).await?.status(), StatusCode::UNAUTHORIZED);
+
+		let req = new_request(Method::GET, "/v1/networks", None)?;
+		assert_eq!(router.clone().oneshot(req).await?.status(), StatusCode::UNAUTHORIZED);
+
+		let req = new_request(Method::GET, "/v1/networks", Some(&secret_key))?;
+		assert_eq!(router.oneshot(req).await?.status(), StatusCode::OK);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_info_version() -> Result<()> {
+		let db = Arc::new(Db::new_in_memory().await?);
+
+		let router = Router::new()
+			.route("/", get(|| async { "ok" }))
+			.route("/", post(|| async { "ok" }))
+			.route("/invalid", post(|| async { ServerResult::<()>::Err(ServerError::NotFound) }))
+			.route_layer(middleware::from_fn_with_state(
+				db.clone(),
+				|State(db): State<Arc<Db>>, req: Request, next: Next| async move {
+					Server::bump_info_version_on_change(db.get(), req, next).await
+				},
+			))
+			.with_state(db.clone());
+
+		let version = Config::get_info_version(db.get()).await?;
+
+		// reads and failed changes leave it be
+		let req = Request::builder().uri("/").body(Body::empty())?;
+		router.clone().oneshot(req).await?;
+		let req = Request::builder().method(Method::POST).uri("/invalid").body(Body::empty())?;
+		router.clone().oneshot(req).await?;
+		assert_eq!(Config::get_info_version(db.get()).await?, version);
+
+		let req = Request::builder().method(Method::POST).uri("/").body(Body::empty())?;
+		router.oneshot(req).await?;
+		assert_ne!(Config::get_info_version(db.get()).await?, version);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_shutdown_grace_period() -> Result<()> {
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+
+		let started = Arc::new(Notify::new());
+		let router = Router::new().route(
+			"/",
+			get({
+				let started = started.clone();
+				move || async move {
+					started.notify_one();
+					sleep(Duration::from_secs(60)).await;
+					"too late"
 				}
-				_ => quit(AppError::SignalHandler),
-			};
+			}),
+		);
+
+		let (signal_tx, signal_rx) = oneshot::channel::<()>();
+		let server = tokio::spawn(Server::serve(
+			listener,
+			router,
+			async move {
+				let _ = signal_rx.await;
+			},
+			Some(Duration::from_millis(100)),
+		));
+
+		let mut stream = TcpStream::connect(addr).await?;
+		stream.write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n").await?;
+		started.notified().await;
+
+		// the stuck request doesn't hold the server up past the grace period
+		let _ = signal_tx.send(());
+		timeout(Duration::from_secs(5), server).await???;
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_with_timeout() -> Result<()> {
+		let routes = Router::new()
+			.route("/fast", get(|| async { "in time" }))
+			.route(
+				"/slow",
+				get(|| async {
+					sleep(Duration::from_secs(1)).await;
+					"too late"
+				}),
+			)
+			.route(
+				"/slow-body",
+				get(|| async {
+					let ticker = interval(Duration::from_millis(50));
+					Body::from_stream(stream::unfold(ticker, |mut ticker| async move {
+						ticker.tick().await;
+						Some((Ok::<_, Infallible>("tick"), ticker))
+					}))
+				}),
+			);
+		let streaming_routes = Router::new().route(
+			"/stream",
+			get(|| async {
+				let ticker = interval(Duration::from_millis(50));
+				Sse::new(stream::unfold(ticker, |mut ticker| async move {
+					ticker.tick().await;
+					Some((Ok::<_, Infallible>(Event::default().data("tick")), ticker))
+				}))
+			}),
+		);
+		let router = Server::with_timeout(routes, streaming_routes, Duration::from_millis(100));
+
+		let req = Request::builder().uri("/slow").body(Body::empty())?;
+		let res = router.clone().oneshot(req).await?;
+		assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+		// a response that starts in time still has to finish in time
+		let req = Request::builder().uri("/slow-body").body(Body::empty())?;
+		let res = router.clone().oneshot(req).await?;
+		assert_eq!(res.status(), StatusCode::OK);
+		let body = timeout(Duration::from_millis(500), body::to_bytes(res.into_body(), usize::MAX))
+			.await?;
+		assert!(body.is_err());
+
+		// ones that are done in time aren't touched
+		let req = Request::builder().uri("/fast").body(Body::empty())?;
+		let res = router.clone().oneshot(req).await?;
+		assert_eq!(body::to_bytes(res.into_body(), usize::MAX).await?, "in time");
+
+		// still streaming well past the timeout
+		let req = Request::builder().uri("/stream").body(Body::empty())?;
+		let res = router.oneshot(req).await?;
+		assert_eq!(res.status(), StatusCode::OK);
+
+		let mut stream = res.into_body().into_data_stream();
+		let mut ticks = 0;
+		let _ = timeout(Duration::from_millis(500), async {
+			while let Some(Ok(_)) = stream.next().await {
+				ticks += 1;
+			}
+		})
+		.await;
+		assert!(ticks >= 5);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_body_limit() -> Result<()> {
+		let router = Router::new()
+			.route("/", post(|body: String| async move { body }))
+			.layer(Server::body_limit_layer(4));
+
+		let req = Request::builder().method(Method::POST).uri("/").body(Body::from("1234"))?;
+		assert_eq!(router.clone().oneshot(req).await?.status(), StatusCode::OK);
+
+		let req = Request::builder().method(Method::POST).uri("/").body(Body::from("12345"))?;
+		assert_eq!(router.oneshot(req).await?.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_debug_errors() -> Result<()> {
+		async fn fail() -> ServerResult<'static, StatusCode> {
+			Err(ServerError::Internal { error: Report::msg("password=hunter2") })
+		}
+
+		let get_message = |is_debug: bool| async move {
+			let router = Router::new()
+				.route("/", get(fail))
+				.layer(middleware::from_fn_with_state(is_debug, Server::debug_errors));
+
+			let res = router.oneshot(Request::get("/").body(Body::empty())?).await?;
+			assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+			let body = body::to_bytes(res.into_body(), usize::MAX).await?;
+			let body: serde_json::Value = serde_json::from_slice(&body)?;
+
+			Ok::<_, Report>(body["error"]["message"].clone())
 		};
 
-		#[cfg(not(unix))]
-		let terminate = future::pending::<()>();
+		assert_eq!(get_message(false).await?, "rekt");
+		assert_eq!(get_message(true).await?, "password=hunter2");
 
-		tokio::select! {
-			_ = ctrl_c => {},
-			_ = terminate => {},
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_idempotency() -> Result<()> {
+		let counter = Arc::new(AtomicUsize::new(0));
+		let router = Router::new()
+			.route(
+				"/",
+				post({
+					let counter = counter.clone();
+					move || async move { counter.fetch_add(1, Ordering::SeqCst).to_string() }
+				}),
+			)
+			.layer(middleware::from_fn_with_state(Arc::new(Cache::new(10)), Server::idempotency));
+
+		let new_request = |idempotency_key: &str| {
+			Request::builder()
+				.method(Method::POST)
+				.uri("/")
+				.header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+				.body(Body::empty())
+		};
+
+		let mut responses = vec![];
+		for idempotency_key in ["a", "a", "b"] {
+			let res = router.clone().oneshot(new_request(idempotency_key)?).await?;
+			assert_eq!(res.status(), StatusCode::OK);
+			responses.push(body::to_bytes(res.into_body(), usize::MAX).await?);
+		}
+
+		// same key is only applied once and replays the same response
+		assert_eq!(responses, vec!["0", "0", "1"]);
+		assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+		// reusing a key for a different request is rejected
+		let req = Request::builder()
+			.method(Method::POST)
+			.uri("/")
+			.header(IDEMPOTENCY_KEY_HEADER, "a")
+			.body(Body::from("other"))?;
+		assert_eq!(router.clone().oneshot(req).await?.status(), StatusCode::BAD_REQUEST);
+		assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+		// concurrent retries don't both go through while the first is in progress
+		let slow_router = Router::new()
+			.route(
+				"/",
+				post({
+					let counter = counter.clone();
+					move || async move {
+						sleep(Duration::from_millis(100)).await;
+						counter.fetch_add(1, Ordering::SeqCst).to_string()
+					}
+				}),
+			)
+			.layer(middleware::from_fn_with_state(Arc::new(Cache::new(10)), Server::idempotency));
+
+		let (a, b) = tokio::join!(
+			slow_router.clone().oneshot(new_request("c")?),
+			slow_router.clone().oneshot(new_request("c")?)
+		);
+		let mut statuses = vec![a?.status().as_u16(), b?.status().as_u16()];
+		statuses.sort_unstable();
+		assert_eq!(statuses, vec![200, 425]);
+		assert_eq!(counter.load(Ordering::SeqCst), 3);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_idempotency_with_full_cache() -> Result<()> {
+		let db = Arc::new(Db::new_in_memory().await?);
+		let app = testing::new_app_with_settings(db, |settings| settings.cache_size = 10).await?;
+
+		let counter = Arc::new(AtomicUsize::new(0));
+		let router = Router::new()
+			.route(
+				"/",
+				post({
+					let counter = counter.clone();
+					move || async move { counter.fetch_add(1, Ordering::SeqCst).to_string() }
+				}),
+			)
+			.layer(middleware::from_fn_with_state(
+				app.idempotency_cache.clone(),
+				Server::idempotency,
+			));
+
+		let new_request = || {
+			Request::builder()
+				.method(Method::POST)
+				.uri("/")
+				.header(IDEMPOTENCY_KEY_HEADER, "a")
+				.body(Body::empty())
+		};
+
+		let res = router.clone().oneshot(new_request()?).await?;
+		assert_eq!(body::to_bytes(res.into_body(), usize::MAX).await?, "0");
+
+		// indexing churns through utxos well past the cache size
+		for i in 0..20 {
+			app.cache.set(&CacheKey::BitcoinTxIndex(1, i.to_string(), 0), i);
 		}
+		assert!(app.cache.get::<u32>(&CacheKey::BitcoinTxIndex(1, "0".to_string(), 0)).is_none());
+
+		// the stored response is still replayed
+		let res = router.oneshot(new_request()?).await?;
+		assert_eq!(res.status(), StatusCode::OK);
+		assert_eq!(body::to_bytes(res.into_body(), usize::MAX).await?, "0");
+		assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_cors() -> Result<()> {
+		let router = Router::new().route("/", get(|| async { "ok" })).layer(Server::cors_layer(
+			&["https://allowed.com".to_string()],
+			&["GET".to_string()],
+			&["authorization".to_string()],
+		));
+
+		let new_preflight = |origin: &str| {
+			Request::builder()
+				.method(Method::OPTIONS)
+				.uri("/")
+				.header(header::ORIGIN, origin)
+				.header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+				.body(Body::empty())
+		};
+
+		let res = router.clone().oneshot(new_preflight("https://allowed.com")?).await?;
+		assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://allowed.com");
+
+		let res = router.oneshot(new_preflight("https://other.com")?).await?;
+		assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+		Ok(())
 	}
 }
@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use crate::{errors::ServerError, ServerResult};
 use barreleye_common::{
 	models::{is_valid_id, PrimaryId},
-	IdPrefix,
+	App, IdPrefix,
 };
 
 pub fn extract_primary_ids(
@@ -11,7 +11,7 @@ pub fn extract_primary_ids(
 	mut ids: Vec<String>,
 	id_prefix: IdPrefix,
 	map: HashMap<String, PrimaryId>,
-) -> ServerResult<Vec<PrimaryId>> {
+) -> ServerResult<'static, Vec<PrimaryId>> {
 	if !ids.is_empty() {
 		ids.sort_unstable();
 		ids.dedup();
@@ -39,3 +39,67 @@ pub fn extract_primary_ids(
 
 	Ok(vec![])
 }
+
+/// Rejects bulk inputs of more than `max` ids (0 disables it), so a single request can't
+/// turn into a giant `IN (...)` query
+pub fn check_bulk_size(field: &str, size: usize, max: usize) -> ServerResult<'static, ()> {
+	if max > 0 && size > max {
+		return Err(ServerError::InvalidParam {
+			field: field.to_string().into(),
+			value: format!("{size} ids, up to {max} are allowed per request").into(),
+		});
+	}
+
+	Ok(())
+}
+
+/// Converts a payload number to the type its column stores, rejecting values that don't
+/// fit instead of letting them wrap around
+pub fn convert<T, V>(field: &str, value: V) -> ServerResult<'static, T>
+where
+	V: TryInto<T> + ToString + Copy,
+{
+	value.try_into().map_err(|_| ServerError::InvalidParam {
+		field: field.to_string().into(),
+		value: value.to_string().into(),
+	})
+}
+
+/// Formats `address`, rejecting it if it isn't valid on any configured network
+pub async fn format_address(
+	app: &App,
+	field: &str,
+	address: &str,
+) -> ServerResult<'static, String> {
+	app.format_valid_address(address).await.ok_or_else(|| ServerError::BadRequest {
+		reason: format!("`{field}` is not a valid address on any configured network: {address}")
+			.into(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_check_bulk_size() {
+		assert!(check_bulk_size("tags", 1_000, 1_000).is_ok());
+		assert!(matches!(
+			check_bulk_size("tags", 1_001, 1_000),
+			Err(ServerError::InvalidParam { field, .. }) if field == "tags"
+		));
+
+		// no cap
+		assert!(check_bulk_size("tags", 1_001, 0).is_ok());
+	}
+
+	#[test]
+	fn test_convert() {
+		assert_eq!(convert::<i32, u32>("rps", 100).ok(), Some(100));
+		assert!(matches!(
+			convert::<i32, u32>("rps", u32::MAX),
+			Err(ServerError::InvalidParam { field, .. }) if field == "rps"
+		));
+		assert!(convert::<i64, u64>("chainId", u64::MAX).is_err());
+	}
+}
@@ -2,7 +2,9 @@ use eyre::Result;
 use std::env;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
-pub fn setup() -> Result<()> {
+use barreleye_common::LogFormat;
+
+pub fn setup(log_format: LogFormat) -> Result<()> {
 	color_eyre::install()?;
 
 	let fmt_layer = fmt::layer()
@@ -11,7 +13,6 @@ pub fn setup() -> Result<()> {
 		.with_line_number(false)
 		.with_file(false)
 		.with_thread_ids(false)
-		.compact()
 		.with_writer(std::io::stdout);
 
 	let rust_log = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
@@ -22,7 +23,11 @@ pub fn setup() -> Result<()> {
 		.add_directive(format!("barreleye_indexer={rust_log}").parse()?)
 		.add_directive(format!("barreleye_server={rust_log}").parse()?);
 
-	tracing_subscriber::registry().with(filter_layer).with(fmt_layer).init();
+	let registry = tracing_subscriber::registry().with(filter_layer);
+	match log_format {
+		LogFormat::Pretty => registry.with(fmt_layer.compact()).init(),
+		LogFormat::Json => registry.with(fmt_layer.json().flatten_event(true)).init(),
+	}
 
 	Ok(())
 }
@@ -15,9 +15,11 @@ mod log;
 #[tokio::main]
 async fn main() -> Result<()> {
 	dotenv().ok();
-	log::setup()?;
 
-	let raw_settings = Settings::new().await.unwrap_or_else(|e| {
+	let args = Settings::from_args();
+	log::setup(args.log_format)?;
+
+	let raw_settings = args.init().await.unwrap_or_else(|e| {
 		quit(match e.downcast_ref::<AppError>() {
 			Some(app_error) => app_error.clone(),
 			None => AppError::Unexpected { error: e.to_string().into() },